@@ -0,0 +1,580 @@
+//! Archive download of a tree at a commit.
+//!
+//! Archives are written entry by entry while blobs stream out of the object
+//! storage, so memory use doesn't grow with the size of the tree. Zip
+//! entries use data descriptors for the same reason: the compressed size of
+//! a file is only known once it has been written.
+
+use std::io::{self, Write};
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bytes::Bytes;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use futures::{Stream, StreamExt, stream};
+use git_internal::internal::object::tree::{Tree, TreeItemMode};
+use jupiter::object_storage::ObjectByteStream;
+use zip::write::{SimpleFileOptions, StreamWriter};
+use zip::{CompressionMethod, ZipWriter};
+
+use common::errors::MegaError;
+
+use crate::api_service::ApiHandler;
+
+/// Blobs this small are read whole to check for an LFS pointer.
+const LFS_POINTER_MAX_SIZE: i64 = 1024;
+const LFS_POINTER_VERSION: &str = "version https://git-lfs.github.com/spec/v1";
+/// Output is handed to the client once this much has piled up.
+const FLUSH_SIZE: usize = 64 * 1024;
+/// Deflate may grow incompressible data a little, so files this large get
+/// ZIP64 fields up front.
+const ZIP64_FILE_THRESHOLD: u64 = 0xF000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Splits the format off a requested name such as `src.tar.gz`.
+    pub fn from_name(name: &str) -> Option<(&str, ArchiveFormat)> {
+        if let Some(stem) = name.strip_suffix(".tar.gz") {
+            Some((stem, ArchiveFormat::TarGz))
+        } else if let Some(stem) = name.strip_suffix(".tgz") {
+            Some((stem, ArchiveFormat::TarGz))
+        } else {
+            name.strip_suffix(".zip")
+                .map(|stem| (stem, ArchiveFormat::Zip))
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "application/gzip",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+/// The tree an archive is built from.
+pub struct ArchiveSource {
+    pub tree: Tree,
+    /// Entries are placed under this directory, e.g. `src-1a2b3c4`.
+    pub prefix: String,
+    /// Commit time, given to every entry.
+    pub mtime: u64,
+}
+
+/// Finds the tree at `path` in `refs`, a commit hash or tag name.
+pub async fn find_archive_source<T: ApiHandler + ?Sized>(
+    handler: &T,
+    refs: &str,
+    path: &Path,
+) -> Result<Option<ArchiveSource>, MegaError> {
+    let commit = if refs.len() == 40 && refs.chars().all(|c| c.is_ascii_hexdigit()) {
+        handler.get_commit_by_hash(refs).await?
+    } else {
+        match handler.get_tag(None, refs.to_owned()).await? {
+            Some(tag) => handler.get_commit_by_hash(&tag.object_id).await?,
+            None => return Ok(None),
+        }
+    };
+
+    let mut tree = handler
+        .get_tree_by_hash(&commit.tree_id.to_string())
+        .await?;
+    for component in handler.strip_relative(path)?.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        let Some(item) = tree
+            .tree_items
+            .iter()
+            .find(|item| item.is_tree() && item.name == name.to_string_lossy())
+        else {
+            return Ok(None);
+        };
+        tree = handler.get_tree_by_hash(&item.id.to_string()).await?;
+    }
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "monorepo".to_owned());
+    let commit_id = commit.id.to_string();
+    Ok(Some(ArchiveSource {
+        tree,
+        prefix: format!("{name}-{}", &commit_id[..7]),
+        mtime: commit.committer.timestamp as u64,
+    }))
+}
+
+/// Streams `source` as an archive. LFS pointers are replaced by the files
+/// they point to when `resolve_lfs` is set and the file is stored here.
+pub fn archive_stream(
+    handler: Box<dyn ApiHandler>,
+    source: ArchiveSource,
+    format: ArchiveFormat,
+    resolve_lfs: bool,
+) -> impl Stream<Item = Result<Bytes, MegaError>> + Send + 'static {
+    async_stream::try_stream! {
+        let mut sink: Box<dyn ArchiveSink + Send> = match format {
+            ArchiveFormat::TarGz => Box::new(TarGzSink::new(source.mtime)),
+            ArchiveFormat::Zip => Box::new(ZipSink::new(source.mtime)),
+        };
+        let mut dirs = vec![(format!("{}/", source.prefix), source.tree)];
+        while let Some((dir, tree)) = dirs.pop() {
+            sink.add_dir(&dir)?;
+            let mut subtrees = vec![];
+            for item in tree.tree_items {
+                let path = format!("{dir}{}", item.name);
+                let id = item.id.to_string();
+                match item.mode {
+                    TreeItemMode::Tree => {
+                        subtrees.push((format!("{path}/"), handler.get_tree_by_hash(&id).await?));
+                    }
+                    TreeItemMode::Blob | TreeItemMode::BlobExecutable => {
+                        let mode = if item.mode == TreeItemMode::Blob { 0o644 } else { 0o755 };
+                        let (mut data, size) = open_blob(handler.as_ref(), &id, resolve_lfs).await?;
+                        sink.start_file(&path, mode, size)?;
+                        while let Some(chunk) = data.next().await {
+                            sink.write(&chunk?)?;
+                            if sink.pending() >= FLUSH_SIZE {
+                                yield sink.take();
+                            }
+                        }
+                        sink.finish_file()?;
+                    }
+                    TreeItemMode::Link => {
+                        let target = handler.get_raw_blob_by_hash(&id).await?;
+                        sink.add_symlink(&path, &target)?;
+                    }
+                    // submodules have no content here
+                    TreeItemMode::Commit => {}
+                }
+                if sink.pending() >= FLUSH_SIZE {
+                    yield sink.take();
+                }
+            }
+            // depth first, in tree order
+            dirs.extend(subtrees.into_iter().rev());
+        }
+        yield sink.finish()?;
+    }
+}
+
+async fn open_blob(
+    handler: &dyn ApiHandler,
+    id: &str,
+    resolve_lfs: bool,
+) -> Result<(ObjectByteStream, u64), MegaError> {
+    let storage = handler.get_context();
+    let (data, meta) = storage.git_service.get_object_stream(id).await?;
+    if !resolve_lfs || meta.size > LFS_POINTER_MAX_SIZE {
+        return Ok((data, meta.size as u64));
+    }
+
+    let pointer: Vec<u8> = data
+        .map(|chunk| chunk.map(|chunk| chunk.to_vec()))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
+    if let Some(oid) = lfs_oid(&pointer) {
+        match storage.lfs_file_storage().get_object_stream(oid).await {
+            Ok(object) => return Ok(object),
+            Err(e) => {
+                tracing::warn!("LFS object {oid} not available, archiving its pointer: {e}");
+            }
+        }
+    }
+    let size = pointer.len() as u64;
+    Ok((Box::pin(stream::iter([Ok(Bytes::from(pointer))])), size))
+}
+
+/// The object id of an LFS pointer file.
+fn lfs_oid(data: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut lines = text.lines();
+    if lines.next()? != LFS_POINTER_VERSION {
+        return None;
+    }
+    lines
+        .find_map(|line| line.strip_prefix("oid sha256:"))
+        .filter(|oid| oid.len() == 64 && oid.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Writes archive entries into an in-memory buffer that the caller drains
+/// with [`ArchiveSink::take`] as it goes.
+trait ArchiveSink {
+    fn add_dir(&mut self, path: &str) -> io::Result<()>;
+    fn add_symlink(&mut self, path: &str, target: &[u8]) -> io::Result<()>;
+    /// Starts a file of `size` bytes, whose content follows through
+    /// [`ArchiveSink::write`].
+    fn start_file(&mut self, path: &str, mode: u32, size: u64) -> io::Result<()>;
+    fn write(&mut self, data: &[u8]) -> io::Result<()>;
+    fn finish_file(&mut self) -> io::Result<()>;
+    /// Bytes written but not yet taken.
+    fn pending(&self) -> usize;
+    fn take(&mut self) -> Bytes;
+    fn finish(&mut self) -> io::Result<Bytes>;
+}
+
+fn size_mismatch(path: &str) -> io::Error {
+    io::Error::other(format!("{path} doesn't match the size of its object"))
+}
+
+struct TarGzSink {
+    gz: Option<GzEncoder<Vec<u8>>>,
+    mtime: u64,
+    path: String,
+    size: u64,
+    remaining: u64,
+}
+
+impl TarGzSink {
+    fn new(mtime: u64) -> Self {
+        Self {
+            gz: Some(GzEncoder::new(vec![], Compression::default())),
+            mtime,
+            path: String::new(),
+            size: 0,
+            remaining: 0,
+        }
+    }
+
+    fn gz(&mut self) -> &mut GzEncoder<Vec<u8>> {
+        self.gz.as_mut().expect("archive already finished")
+    }
+
+    fn header(
+        &mut self,
+        path: &str,
+        entry_type: tar::EntryType,
+        mode: u32,
+        size: u64,
+        link: &[u8],
+    ) -> io::Result<()> {
+        // names that don't fit the header go in GNU long name entries
+        if path.len() > 100 {
+            self.long_name(tar::EntryType::GNULongName, path.as_bytes())?;
+        }
+        if link.len() > 100 {
+            self.long_name(tar::EntryType::GNULongLink, link)?;
+        }
+        let mut header = tar::Header::new_gnu();
+        let gnu = header.as_gnu_mut().expect("GNU header");
+        let name = &path.as_bytes()[..path.len().min(100)];
+        gnu.name[..name.len()].copy_from_slice(name);
+        let link = &link[..link.len().min(100)];
+        gnu.linkname[..link.len()].copy_from_slice(link);
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(size);
+        header.set_mtime(self.mtime);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_cksum();
+        self.gz().write_all(header.as_bytes())
+    }
+
+    fn long_name(&mut self, entry_type: tar::EntryType, name: &[u8]) -> io::Result<()> {
+        let mut data = name.to_vec();
+        data.push(0);
+        self.header("././@LongLink", entry_type, 0o644, data.len() as u64, &[])?;
+        self.gz().write_all(&data)?;
+        self.pad(data.len() as u64)
+    }
+
+    fn pad(&mut self, size: u64) -> io::Result<()> {
+        let padding = (512 - size % 512) % 512;
+        self.gz().write_all(&vec![0; padding as usize])
+    }
+}
+
+impl ArchiveSink for TarGzSink {
+    fn add_dir(&mut self, path: &str) -> io::Result<()> {
+        self.header(path, tar::EntryType::Directory, 0o755, 0, &[])
+    }
+
+    fn add_symlink(&mut self, path: &str, target: &[u8]) -> io::Result<()> {
+        self.header(path, tar::EntryType::Symlink, 0o777, 0, target)
+    }
+
+    fn start_file(&mut self, path: &str, mode: u32, size: u64) -> io::Result<()> {
+        self.header(path, tar::EntryType::Regular, mode, size, &[])?;
+        self.path = path.to_owned();
+        self.size = size;
+        self.remaining = size;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() as u64 > self.remaining {
+            return Err(size_mismatch(&self.path));
+        }
+        self.remaining -= data.len() as u64;
+        self.gz().write_all(data)
+    }
+
+    fn finish_file(&mut self) -> io::Result<()> {
+        if self.remaining != 0 {
+            return Err(size_mismatch(&self.path));
+        }
+        self.pad(self.size)
+    }
+
+    fn pending(&self) -> usize {
+        self.gz.as_ref().map_or(0, |gz| gz.get_ref().len())
+    }
+
+    fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(self.gz().get_mut()))
+    }
+
+    fn finish(&mut self) -> io::Result<Bytes> {
+        self.gz().write_all(&[0; 1024])?;
+        let gz = self.gz.take().expect("archive already finished");
+        Ok(Bytes::from(gz.finish()?))
+    }
+}
+
+/// A buffer the zip writer writes into while the sink drains it.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct ZipSink {
+    zip: Option<ZipWriter<StreamWriter<SharedBuffer>>>,
+    out: SharedBuffer,
+    mtime: zip::DateTime,
+    path: String,
+    remaining: u64,
+}
+
+impl ZipSink {
+    fn new(mtime: u64) -> Self {
+        let out = SharedBuffer::default();
+        Self {
+            zip: Some(ZipWriter::new_stream(out.clone())),
+            out,
+            mtime: zip_datetime(mtime),
+            path: String::new(),
+            remaining: 0,
+        }
+    }
+
+    fn zip(&mut self) -> &mut ZipWriter<StreamWriter<SharedBuffer>> {
+        self.zip.as_mut().expect("archive already finished")
+    }
+
+    fn options(&self, mode: u32) -> SimpleFileOptions {
+        SimpleFileOptions::default()
+            .last_modified_time(self.mtime)
+            .unix_permissions(mode)
+    }
+}
+
+impl ArchiveSink for ZipSink {
+    fn add_dir(&mut self, path: &str) -> io::Result<()> {
+        let options = self.options(0o755);
+        Ok(self.zip().add_directory(path, options)?)
+    }
+
+    fn add_symlink(&mut self, path: &str, target: &[u8]) -> io::Result<()> {
+        let options = self.options(0o777);
+        let target = String::from_utf8_lossy(target).into_owned();
+        Ok(self.zip().add_symlink(path, target, options)?)
+    }
+
+    fn start_file(&mut self, path: &str, mode: u32, size: u64) -> io::Result<()> {
+        let options = self
+            .options(mode)
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(size >= ZIP64_FILE_THRESHOLD);
+        self.zip().start_file(path, options)?;
+        self.path = path.to_owned();
+        self.remaining = size;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() as u64 > self.remaining {
+            return Err(size_mismatch(&self.path));
+        }
+        self.remaining -= data.len() as u64;
+        self.zip().write_all(data)
+    }
+
+    fn finish_file(&mut self) -> io::Result<()> {
+        if self.remaining != 0 {
+            return Err(size_mismatch(&self.path));
+        }
+        Ok(())
+    }
+
+    fn pending(&self) -> usize {
+        self.out.lock().len()
+    }
+
+    fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(&mut *self.out.lock()))
+    }
+
+    fn finish(&mut self) -> io::Result<Bytes> {
+        let zip = self.zip.take().expect("archive already finished");
+        zip.finish()?;
+        Ok(self.take())
+    }
+}
+
+/// The zip timestamp of a unix timestamp, which can't go before 1980.
+fn zip_datetime(timestamp: u64) -> zip::DateTime {
+    use chrono::{Datelike, Timelike};
+
+    let time = chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .naive_utc();
+    zip::DateTime::from_date_and_time(
+        time.year().clamp(1980, 2107) as u16,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::{ArchiveFormat, ArchiveSink, TarGzSink, ZipSink, lfs_oid};
+
+    fn write_entries(sink: &mut dyn ArchiveSink, long_name: &str) -> Vec<u8> {
+        let mut out = vec![];
+        sink.add_dir("demo-1a2b3c4/").unwrap();
+        sink.start_file("demo-1a2b3c4/run.sh", 0o755, 10).unwrap();
+        sink.write(b"echo ").unwrap();
+        sink.write(b"hello").unwrap();
+        sink.finish_file().unwrap();
+        out.extend_from_slice(&sink.take());
+        sink.add_symlink("demo-1a2b3c4/link", b"run.sh").unwrap();
+        sink.start_file(long_name, 0o644, 3).unwrap();
+        sink.write(b"abc").unwrap();
+        sink.finish_file().unwrap();
+        out.extend_from_slice(&sink.finish().unwrap());
+        out
+    }
+
+    #[test]
+    fn test_tar_gz() {
+        let long_name = format!("demo-1a2b3c4/{}.rs", "a".repeat(120));
+        let data = write_entries(&mut TarGzSink::new(1_700_000_000), &long_name);
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&data[..]));
+        let mut entries = vec![];
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mode = entry.header().mode().unwrap();
+            let link = entry
+                .link_name()
+                .unwrap()
+                .map(|l| l.to_string_lossy().into_owned());
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            entries.push((path, mode, link, content));
+        }
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].0, "demo-1a2b3c4/");
+        assert_eq!(
+            entries[1],
+            (
+                "demo-1a2b3c4/run.sh".to_owned(),
+                0o755,
+                None,
+                "echo hello".to_owned()
+            )
+        );
+        assert_eq!(entries[2].2.as_deref(), Some("run.sh"));
+        assert_eq!(entries[3].0, long_name);
+        assert_eq!(entries[3].3, "abc");
+
+        // a blob must match the size it was announced with
+        let mut sink = TarGzSink::new(0);
+        sink.start_file("short", 0o644, 4).unwrap();
+        sink.write(b"abc").unwrap();
+        assert!(sink.finish_file().is_err());
+    }
+
+    #[test]
+    fn test_zip() {
+        let long_name = format!("demo-1a2b3c4/{}.rs", "a".repeat(120));
+        let data = write_entries(&mut ZipSink::new(1_700_000_000), &long_name);
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        assert_eq!(archive.len(), 4);
+        assert!(archive.by_index(0).unwrap().is_dir());
+        let mut run = archive.by_name("demo-1a2b3c4/run.sh").unwrap();
+        assert_eq!(run.unix_mode(), Some(0o100755));
+        let mut content = String::new();
+        run.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "echo hello");
+        drop(run);
+        let link = archive.by_name("demo-1a2b3c4/link").unwrap();
+        assert_eq!(link.unix_mode(), Some(0o120777));
+        drop(link);
+        let mut long = archive.by_name(&long_name).unwrap();
+        let mut content = String::new();
+        long.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abc");
+    }
+
+    #[test]
+    fn test_archive_names() {
+        assert_eq!(
+            ArchiveFormat::from_name("src.tar.gz"),
+            Some(("src", ArchiveFormat::TarGz))
+        );
+        assert_eq!(
+            ArchiveFormat::from_name("abc.zip"),
+            Some(("abc", ArchiveFormat::Zip))
+        );
+        assert_eq!(ArchiveFormat::from_name("src.tar"), None);
+
+        let pointer = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12\n",
+            "ab".repeat(32)
+        );
+        assert_eq!(lfs_oid(pointer.as_bytes()), Some("ab".repeat(32).as_str()));
+        assert_eq!(lfs_oid(b"fn main() {}"), None);
+    }
+}
@@ -454,7 +454,7 @@ impl BuckCommitBuilder {
     /// - Compare items byte-by-byte
     /// - Directories are treated as if they have a trailing '/'
     /// - This ensures directories sort after files with the same prefix
-    fn sort_tree_items_git_style(items: &mut [TreeItem]) {
+    pub(crate) fn sort_tree_items_git_style(items: &mut [TreeItem]) {
         items.sort_by(|a, b| {
             let a_name = a.name.as_bytes();
             let b_name = b.name.as_bytes();
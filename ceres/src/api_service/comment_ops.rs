@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use callisto::sea_orm_active_enums::DiffSideEnum;
+use common::errors::MegaError;
+use diffs::Diff;
+use git_internal::hash::ObjectHash;
+
+use crate::api_service::{ApiHandler, mono_api_service::MonoApiService};
+
+/// Carries the inline comments of a CL forward after it was updated.
+///
+/// A comment moves to the new revision when the lines it covers are still
+/// there, unchanged and in one piece. Otherwise it is marked outdated and
+/// keeps pointing at the revision it was made against.
+pub async fn reanchor_inline_comments(
+    service: &MonoApiService,
+    cl_link: &str,
+) -> Result<(), MegaError> {
+    let cl = service
+        .storage
+        .cl_storage()
+        .get_cl(cl_link)
+        .await?
+        .ok_or_else(|| MegaError::Other(format!("CL not found: {cl_link}")))?;
+    let from_hash = service.cl_diff_base(&cl).await?;
+    let to_hash = cl.to_hash.as_str();
+    let conv_stg = service.storage.conversation_storage();
+    let comments = conv_stg.get_inline_comments(cl_link).await?;
+
+    let mut commit_blobs: HashMap<String, HashMap<PathBuf, ObjectHash>> = HashMap::new();
+    let mut outdated = vec![];
+    for comment in comments.into_iter().map(|c| c.conversation) {
+        let (Some(file_path), Some(side), Some(start), Some(end), Some(commit_id)) = (
+            comment.file_path,
+            comment.diff_side,
+            comment.start_line,
+            comment.end_line,
+            comment.commit_id,
+        ) else {
+            continue;
+        };
+        let target = match side {
+            DiffSideEnum::Old => from_hash.as_str(),
+            DiffSideEnum::New => to_hash,
+        };
+        if comment.outdated || commit_id == target {
+            continue;
+        }
+
+        let path = Path::new(&file_path);
+        let old_blob = blob_at(service, &mut commit_blobs, &commit_id, path).await?;
+        let new_blob = blob_at(service, &mut commit_blobs, target, path).await?;
+        let moved = match (old_blob, new_blob) {
+            (Some(old), Some(new)) if old == new => Some((start, end)),
+            (Some(old), Some(new)) => {
+                let old = service.get_raw_blob_by_hash(&old.to_string()).await?;
+                let new = service.get_raw_blob_by_hash(&new.to_string()).await?;
+                remap_lines(
+                    &String::from_utf8_lossy(&old),
+                    &String::from_utf8_lossy(&new),
+                    start,
+                    end,
+                )
+            }
+            _ => None,
+        };
+        match moved {
+            Some((start, end)) => conv_stg.move_anchor(comment.id, start, end, target).await?,
+            None => outdated.push(comment.id),
+        }
+    }
+    conv_stg.mark_outdated(outdated).await
+}
+
+async fn blob_at(
+    service: &MonoApiService,
+    cache: &mut HashMap<String, HashMap<PathBuf, ObjectHash>>,
+    commit_id: &str,
+    path: &Path,
+) -> Result<Option<ObjectHash>, MegaError> {
+    if !cache.contains_key(commit_id) {
+        let blobs = service.get_commit_blobs(commit_id).await?;
+        cache.insert(commit_id.to_owned(), blobs.into_iter().collect());
+    }
+    Ok(cache[commit_id].get(path).copied())
+}
+
+/// Equal runs of lines between two versions of a file, as
+/// `(old_index, new_index, len)`.
+#[derive(Default)]
+struct EqualRuns(Vec<(usize, usize, usize)>);
+
+impl Diff for EqualRuns {
+    type Error = ();
+
+    fn equal(&mut self, old: usize, new: usize, len: usize) -> Result<(), ()> {
+        self.0.push((old, new, len));
+        Ok(())
+    }
+}
+
+/// Finds the 1-based line range `start..=end` of `old` in `new`.
+///
+/// Returns `None` if any line in the range was changed or removed, or if
+/// lines were inserted inside it.
+pub fn remap_lines(old: &str, new: &str, start: i32, end: i32) -> Option<(i32, i32)> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let first = usize::try_from(start).ok()?.checked_sub(1)?;
+    let last = usize::try_from(end).ok()?.checked_sub(1)?;
+    if first > last || last >= old_lines.len() {
+        return None;
+    }
+
+    let mut runs = EqualRuns::default();
+    diffs::myers::diff(
+        &mut runs,
+        &old_lines,
+        0,
+        old_lines.len(),
+        &new_lines,
+        0,
+        new_lines.len(),
+    )
+    .ok()?;
+    let (old_at, new_at, _) = runs
+        .0
+        .into_iter()
+        .find(|(old_at, _, len)| *old_at <= first && last < old_at + len)?;
+    let shift = |line: usize| i32::try_from(line - old_at + new_at + 1).ok();
+    Some((shift(first)?, shift(last)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::remap_lines;
+
+    #[test]
+    fn test_remap_lines() {
+        let old = "a\nb\nc\nd\ne\n";
+        // unchanged
+        assert_eq!(remap_lines(old, old, 2, 3), Some((2, 3)));
+        // lines added above move the range down
+        assert_eq!(
+            remap_lines(old, "x\ny\na\nb\nc\nd\ne\n", 2, 3),
+            Some((4, 5))
+        );
+        // lines removed above move it up
+        assert_eq!(remap_lines(old, "c\nd\ne\n", 4, 5), Some((2, 3)));
+        // changes below don't matter
+        assert_eq!(remap_lines(old, "a\nb\nc\n", 1, 2), Some((1, 2)));
+        // a changed line, or one inserted inside the range, outdates it
+        assert_eq!(remap_lines(old, "a\nB\nc\nd\ne\n", 2, 3), None);
+        assert_eq!(remap_lines(old, "a\nb\nx\nc\nd\ne\n", 2, 3), None);
+        // out of range
+        assert_eq!(remap_lines(old, old, 0, 1), None);
+        assert_eq!(remap_lines(old, old, 5, 6), None);
+        assert_eq!(remap_lines(old, old, 3, 2), None);
+    }
+}
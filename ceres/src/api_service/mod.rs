@@ -31,16 +31,22 @@ use crate::{
     },
 };
 
+pub mod archive_ops;
 pub mod blame_ops;
 pub mod blob_ops;
 pub mod buck_tree_builder;
 pub mod cache;
+pub mod comment_ops;
 pub mod commit_ops;
 pub mod history;
 pub mod import_api_service;
 pub mod mono_api_service;
+pub mod review_ops;
+pub mod search_ops;
 pub mod state;
+pub mod symbol_ops;
 pub mod tree_ops;
+pub mod webhook_ops;
 
 #[async_trait]
 pub trait ApiHandler: Send + Sync {
@@ -35,7 +35,7 @@
 //! API requests for monorepo operations. All operations are asynchronous and return
 //! appropriate error types for robust error handling.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -43,8 +43,13 @@ use std::time::Duration;
 
 use crate::api_service::buck_tree_builder::BuckCommitBuilder;
 use crate::api_service::cache::GitObjectCache;
+use crate::api_service::search_ops::CodeIndexer;
 use crate::api_service::state::ProtocolApiState;
-use crate::api_service::{ApiHandler, tree_ops};
+use crate::api_service::{ApiHandler, comment_ops, review_ops, tree_ops};
+use crate::merge_checker::branch_protection_checker::BranchProtection;
+use crate::merge_checker::ci_status_checker::summarize_builds;
+use crate::merge_checker::merge_conflict_checker::{MergeEntry, TreeMerge};
+use crate::merge_checker::{CheckerRegistry, ConditionResult};
 use crate::model::buck::{CompletePayload, CompleteResponse, ManifestPayload, ManifestResponse};
 use crate::model::buck::{DEFAULT_MODE, FileChange, FileToUpload as ApiFileToUpload};
 use crate::model::change_list::ClDiffFile;
@@ -57,6 +62,10 @@ use crate::pack::monorepo::MonoRepo;
 use crate::protocol::{SmartProtocol, TransportProtocol};
 
 use async_trait::async_trait;
+use bellatrix::Bellatrix;
+use bellatrix::orion_client::{
+    BuildInfo, BuildOrigin, OrionBuildRequest, ProjectRelativePath, Status,
+};
 use bytes::Bytes;
 use regex::Regex;
 
@@ -64,6 +73,7 @@ use callisto::sea_orm_active_enums::{
     ConvTypeEnum, MergeStatusEnum, QueueFailureTypeEnum, QueueStatusEnum,
 };
 use callisto::{mega_cl, mega_refs, mega_tag, mega_tree};
+use common::enums::WebhookEvent;
 use common::errors::{BuckError, MegaError};
 use common::model::{DiffItem, Pagination};
 use common::utils::MEGA_BRANCH_NAME;
@@ -74,6 +84,7 @@ use git_internal::internal::metadata::EntryMeta;
 use git_internal::internal::object::blob::Blob;
 use git_internal::internal::object::commit::Commit;
 use git_internal::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use jupiter::model::merge_queue_dto::BatchRun;
 use jupiter::service::buck_service::{
     CommitArtifacts, CompletePayload as SvcCompletePayload, CompleteResponse as SvcCompleteResponse,
 };
@@ -1170,7 +1181,32 @@ impl MonoApiService {
     }
     /// Merges a CL after checking for conflicts.
     /// This is the public API that includes conflict checking.
+    ///
+    /// If the main ref of `cl.path` has moved since the CL was created, the CL is
+    /// three-way merged onto it and rejected only when both sides touched the same paths.
+    /// Root CLs are never merged onto a moved trunk.
     pub async fn merge_cl(&self, username: &str, cl: mega_cl::Model) -> Result<(), GitError> {
+        if let Some(base) = self
+            .unmerged_ancestor(&cl)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+        {
+            return Err(GitError::CustomError(format!(
+                "Blocked by stacked CL: {} has to be merged first",
+                base.link
+            )));
+        }
+        BranchProtection::verify_merge(
+            &self.storage,
+            &cl.path,
+            &cl.from_hash,
+            &cl.to_hash,
+            &cl.link,
+            &cl.username,
+        )
+        .await
+        .map_err(|e| GitError::CustomError(format!("Blocked by branch protection: {e}")))?;
+
         let storage = self.storage.mono_storage();
         let refs = storage
             .get_main_ref(&cl.path)
@@ -1178,11 +1214,231 @@ impl MonoApiService {
             .map_err(|e| GitError::CustomError(format!("Failed to get main ref: {}", e)))?
             .ok_or_else(|| GitError::CustomError("Main ref not found".to_string()))?;
 
-        if cl.from_hash != refs.ref_commit_hash {
-            return Err(GitError::CustomError("ref hash conflict".to_owned()));
+        if cl.from_hash == refs.ref_commit_hash {
+            return self.merge_cl_unchecked(username, cl).await;
+        }
+        // the root has no parent tree to splice a merged tree into
+        if cl.path == "/" {
+            return Err(GitError::CustomError(format!(
+                "{} has moved since the CL was created, push the CL again on top of it",
+                MEGA_BRANCH_NAME
+            )));
+        }
+
+        let base = self
+            .cl_diff_base(&cl)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        let tree_id = self
+            .merge_cl_onto_ref(&cl, &base, &refs.ref_commit_hash)
+            .await?;
+        self.merge_cl_with_tree(username, cl, tree_id).await
+    }
+
+    /// The revision the changes of `cl` are on top of: the head of the CL
+    /// it is stacked on, or its `from_hash`.
+    pub async fn cl_diff_base(&self, cl: &mega_cl::Model) -> Result<String, MegaError> {
+        if let Some(link) = &cl.base_cl
+            && let Some(base) = self.storage.cl_storage().get_cl(link).await?
+        {
+            return Ok(base.to_hash);
+        }
+        Ok(cl.from_hash.clone())
+    }
+
+    /// Whether `commit` is a revision of `cl`: one of its patchsets, or a
+    /// speculative commit the merge queue built for the batch topped by it.
+    pub async fn is_cl_commit(&self, cl: &mega_cl::Model, commit: &str) -> Result<bool, MegaError> {
+        let cl_stg = self.storage.cl_storage();
+        if cl.to_hash == commit
+            || cl_stg
+                .list_patchsets(&cl.link)
+                .await?
+                .iter()
+                .any(|p| p.to_hash == commit)
+        {
+            return Ok(true);
+        }
+        Ok(cl_stg.get_latest_cl_task(cl.id, commit).await?.is_some())
+    }
+
+    /// The nearest CL below `cl` in its stack that hasn't been merged.
+    pub async fn unmerged_ancestor(
+        &self,
+        cl: &mega_cl::Model,
+    ) -> Result<Option<mega_cl::Model>, MegaError> {
+        let ancestors = self.storage.cl_storage().get_cl_ancestors(cl).await?;
+        Ok(ancestors
+            .into_iter()
+            .find(|a| a.status != MergeStatusEnum::Merged))
+    }
+
+    /// Stacks `link` on `base_cl`, or unstacks it with `None`.
+    ///
+    /// Both CLs must be on the same path, and the base must still be open
+    /// and not already stacked on `link`.
+    pub async fn set_base_cl(&self, link: &str, base_cl: Option<&str>) -> Result<(), MegaError> {
+        let cl_stg = self.storage.cl_storage();
+        let cl = cl_stg
+            .get_cl(link)
+            .await?
+            .ok_or_else(|| MegaError::Other(format!("CL not found: {link}")))?;
+        if matches!(cl.status, MergeStatusEnum::Merged | MergeStatusEnum::Closed) {
+            return Err(MegaError::Other(format!("CL {link} is no longer open")));
+        }
+        if let Some(base_link) = base_cl {
+            let base = cl_stg
+                .get_cl(base_link)
+                .await?
+                .ok_or_else(|| MegaError::Other(format!("CL not found: {base_link}")))?;
+            let ancestors = cl_stg.get_cl_ancestors(&base).await?;
+            check_stack_base(&cl, &base, &ancestors)?;
+        }
+        cl_stg.set_base_cl(link, base_cl).await
+    }
+
+    /// Rebases the CLs stacked on `merged` onto the trunk it just landed
+    /// on, and stacks them on whatever `merged` was stacked on.
+    ///
+    /// Rebased CLs go through the same steps as a CL updated by a push:
+    /// comments and approvals are carried forward, and the new revision is
+    /// built and checked.
+    async fn rebase_stacked_cls(
+        &self,
+        username: &str,
+        merged: &mega_cl::Model,
+    ) -> Result<(), MegaError> {
+        let cl_stg = self.storage.cl_storage();
+        for link in rebase_stack(&self.storage, username, merged).await? {
+            comment_ops::reanchor_inline_comments(self, &link).await?;
+            if let Err(e) = review_ops::refresh_approvals(self, &link, username).await {
+                tracing::warn!("Failed to refresh approvals of cl {link}: {e}");
+            }
+
+            let rebased = cl_stg
+                .get_cl(&link)
+                .await?
+                .ok_or_else(|| MegaError::Other(format!("CL not found: {link}")))?;
+            let bellatrix = Arc::new(Bellatrix::new(self.storage.config().build.clone()));
+            self.trigger_cl_build(bellatrix, &rebased).await?;
+            CheckerRegistry::new(self.storage.clone().into(), username.to_owned())
+                .run_checks(rebased.into())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Starts an Orion build of the latest revision of `cl_info`, unless no
+    /// build server is configured.
+    pub async fn trigger_cl_build(
+        &self,
+        bellatrix: Arc<Bellatrix>,
+        cl_info: &mega_cl::Model,
+    ) -> Result<(), MegaError> {
+        if !bellatrix.enable_build() {
+            return Ok(());
+        }
+        let old_files = self.get_commit_blobs(&cl_info.from_hash).await?;
+        let new_files = self.get_commit_blobs(&cl_info.to_hash).await?;
+        let cl_diff_files = self.cl_files_list(old_files, new_files.clone()).await?;
+
+        let cl_base = PathBuf::from(&cl_info.path);
+        let changes = cl_diff_files
+            .into_iter()
+            .map(|m| {
+                let mut item: crate::model::change_list::ClFilesRes = m.into();
+                item.path = cl_base.join(item.path).to_string_lossy().to_string();
+                item
+            })
+            .collect::<Vec<_>>();
+
+        let path_str = cl_base.to_str().ok_or_else(|| {
+            MegaError::Other(format!("CL base path is not valid UTF-8: {:?}", cl_base))
+        })?;
+        let counter_changes: Vec<_> = changes
+            .iter()
+            .filter(|&s| PathBuf::from(&s.path).starts_with(&cl_base))
+            .map(|s| {
+                let path = ProjectRelativePath::from_abs(&s.path, path_str).unwrap();
+                if s.action == "new" {
+                    Status::Added(path)
+                } else if s.action == "deleted" {
+                    Status::Removed(path)
+                } else if s.action == "modified" {
+                    Status::Modified(path)
+                } else {
+                    unreachable!()
+                }
+            })
+            .collect();
+
+        tracing::info!(
+            "Trigger bellatrix build for cl: {}, changes: {:?}, repo: {}",
+            cl_info.id,
+            counter_changes,
+            path_str
+        );
+
+        let labels = self
+            .storage
+            .cl_storage()
+            .get_cl_labels(&cl_info.link)
+            .await?
+            .map(|(_, labels)| labels.into_iter().map(|l| l.name).collect())
+            .unwrap_or_default();
+        let req: OrionBuildRequest = OrionBuildRequest {
+            cl_link: cl_info.link.clone(),
+            repo: path_str.to_string(),
+            cl: cl_info.id,
+            builds: vec![BuildInfo {
+                changes: counter_changes,
+            }],
+            commit_id: cl_info.to_hash.clone(),
+            origin: BuildOrigin::Cl,
+            labels,
+            username: cl_info.username.clone(),
+        };
+        tokio::spawn(async move {
+            let _ = bellatrix.on_post_receive(req).await;
+        });
+        Ok(())
+    }
+
+    /// Three-way merges the CL onto `current` and saves the resulting trees.
+    ///
+    /// `base` is the revision the CL's changes are on top of, see
+    /// [`Self::cl_diff_base`]. Returns the merged root tree id, or an error
+    /// listing the conflicting paths.
+    async fn merge_cl_onto_ref(
+        &self,
+        cl: &mega_cl::Model,
+        base: &str,
+        current: &str,
+    ) -> Result<ObjectHash, GitError> {
+        let storage = self.storage.mono_storage();
+        let outcome = TreeMerge::merge_commits(&storage, base, current, &cl.to_hash)
+            .await
+            .map_err(|e| GitError::CustomError(format!("Failed to merge trees: {}", e)))?;
+        if !outcome.is_clean() {
+            return Err(GitError::CustomError(format!(
+                "merge conflict with {}: {}",
+                MEGA_BRANCH_NAME,
+                outcome.conflict_message()
+            )));
         }
 
-        self.merge_cl_unchecked(username, cl).await
+        let trees = TreeMerge::build_trees(&outcome.merged)
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        let tree_id = trees
+            .last()
+            .map(|t| t.id)
+            .ok_or_else(|| GitError::CustomError("merged tree is empty".to_string()))?;
+        let commit_id = ObjectHash::from_str(&cl.to_hash).map_err(GitError::CustomError)?;
+        storage
+            .save_mega_trees(trees, commit_id, None)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(tree_id)
     }
 
     /// Merges a CL without checking for conflicts.
@@ -1197,12 +1453,23 @@ impl MonoApiService {
             .ok_or_else(|| GitError::CustomError(format!("Commit not found: {}", cl.to_hash)))?;
         let commit: Commit = Commit::from_mega_model(commit_model);
 
+        self.merge_cl_with_tree(username, cl, commit.tree_id).await
+    }
+
+    /// Points `cl.path` at `tree_id` on trunk and marks the CL merged.
+    async fn merge_cl_with_tree(
+        &self,
+        username: &str,
+        cl: mega_cl::Model,
+        tree_id: ObjectHash,
+    ) -> Result<(), GitError> {
+        let storage = self.storage.mono_storage();
+
         if cl.path != "/" {
             let path = PathBuf::from(cl.path.clone());
             // because only parent tree is needed so we skip current directory
             let update_chain = self.search_tree_for_update(path.parent().unwrap()).await?;
-            let result =
-                MonoServiceLogic::build_result_by_chain(path, update_chain, commit.tree_id)?;
+            let result = MonoServiceLogic::build_result_by_chain(path, update_chain, tree_id)?;
             self.apply_update_result(&result, "cl merge generated commit", Some(cl.link.as_str()))
                 .await?;
             storage
@@ -1224,6 +1491,9 @@ impl MonoApiService {
             .await
             .map_err(|e| GitError::CustomError(format!("Failed to update CL status: {}", e)))?;
 
+        if let Err(e) = self.rebase_stacked_cls(username, &cl).await {
+            tracing::warn!("Failed to rebase CLs stacked on {}: {e}", cl.link);
+        }
         Ok(())
     }
 
@@ -1290,6 +1560,20 @@ impl MonoApiService {
             .batch_save_model(save_trees)
             .await
             .map_err(|e| GitError::CustomError(e.to_string()))?;
+        CodeIndexer::spawn_sync(self.storage.clone());
+
+        // CL revisions are reported through the CL events instead
+        if cl_link.is_none() {
+            let webhook_stg = self.storage.webhook_storage();
+            for path in paths {
+                let data = serde_json::json!({
+                    "ref": MEGA_BRANCH_NAME,
+                    "after": new_commit_id,
+                    "message": commit_msg,
+                });
+                webhook_stg.emit(WebhookEvent::Push, path, data).await;
+            }
+        }
 
         Ok(new_commit_id)
     }
@@ -1306,21 +1590,58 @@ impl MonoApiService {
         cl_link: &str,
         page: Pagination,
     ) -> Result<(Vec<DiffItem>, u64), GitError> {
-        let per_page = page.per_page as usize;
-        let page_id = page.page as usize;
-
         // old and new blobs for comparison
         let stg = self.storage.cl_storage();
         let cl =
             stg.get_cl(cl_link).await.unwrap().ok_or_else(|| {
                 GitError::CustomError(format!("Merge request not found: {cl_link}"))
             })?;
+        let base = self
+            .cl_diff_base(&cl)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        self.paged_diff_between(&base, &cl.to_hash, page).await
+    }
+
+    /// Diffs two patchsets of a CL, showing what changed between the
+    /// revisions they point at.
+    pub async fn paged_interdiff(
+        &self,
+        cl_link: &str,
+        old: i32,
+        new: i32,
+        page: Pagination,
+    ) -> Result<(Vec<DiffItem>, u64), GitError> {
+        let stg = self.storage.cl_storage();
+        let mut hashes = vec![];
+        for number in [old, new] {
+            let patchset = stg
+                .get_patchset(cl_link, number)
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))?
+                .ok_or_else(|| {
+                    GitError::CustomError(format!("Patchset {number} not found in {cl_link}"))
+                })?;
+            hashes.push(patchset.to_hash);
+        }
+        self.paged_diff_between(&hashes[0], &hashes[1], page).await
+    }
+
+    async fn paged_diff_between(
+        &self,
+        old_hash: &str,
+        new_hash: &str,
+        page: Pagination,
+    ) -> Result<(Vec<DiffItem>, u64), GitError> {
+        let per_page = page.per_page as usize;
+        let page_id = page.page as usize;
+
         let old_blobs = self
-            .get_commit_blobs(&cl.from_hash)
+            .get_commit_blobs(old_hash)
             .await
             .map_err(|e| GitError::CustomError(format!("Failed to get old commit blobs: {e}")))?;
         let new_blobs = self
-            .get_commit_blobs(&cl.to_hash)
+            .get_commit_blobs(new_hash)
             .await
             .map_err(|e| GitError::CustomError(format!("Failed to get new commit blobs: {e}")))?;
 
@@ -1428,7 +1749,9 @@ impl MonoApiService {
             .unwrap()
             .ok_or_else(|| MegaError::Other("Error getting ".to_string()))?;
 
-        let old_files = self.get_commit_blobs(&cl.from_hash.clone()).await?;
+        let old_files = self
+            .get_commit_blobs(&self.cl_diff_base(&cl).await?)
+            .await?;
         let new_files = self.get_commit_blobs(&cl.to_hash.clone()).await?;
 
         // calculate pages
@@ -1485,6 +1808,20 @@ impl MonoApiService {
         Ok(res)
     }
 
+    /// The commit `commit_hash` was created on top of, its first parent.
+    pub async fn first_parent(&self, commit_hash: &str) -> Result<String, MegaError> {
+        let commit = self
+            .storage
+            .mono_storage()
+            .get_commit_by_hash(commit_hash)
+            .await?
+            .ok_or_else(|| MegaError::Other(format!("Commit not found: {commit_hash}")))?;
+        serde_json::from_value::<Vec<String>>(commit.parents_id)
+            .ok()
+            .and_then(|parents| parents.into_iter().next())
+            .ok_or_else(|| MegaError::Other(format!("Commit {commit_hash} has no parent")))
+    }
+
     pub async fn get_commit_blobs(
         &self,
         commit_hash: &str,
@@ -1599,6 +1936,15 @@ impl MonoApiService {
     /// Error backoff interval in seconds after processing failure
     const ERROR_BACKOFF_SECS: u64 = 30;
 
+    /// Maximum number of CLs tested together in one speculative batch
+    const MAX_BATCH_SIZE: u64 = 3;
+
+    /// Interval in seconds between checks of a speculative build
+    const BATCH_BUILD_POLL_SECS: u64 = 10;
+
+    /// Time in seconds after which a speculative build counts as timed out
+    const BATCH_BUILD_TIMEOUT_SECS: u64 = 2 * 60 * 60;
+
     /// Adds a CL to the merge queue and ensures the background processor is running.
     ///
     /// This method validates the CL status before adding to queue and automatically
@@ -1975,84 +2321,395 @@ impl MonoApiService {
         }
     }
 
-    /// Processes the next item in the merge queue.
+    /// Processes the next speculative batch of the merge queue.
+    ///
+    /// Up to `MAX_BATCH_SIZE` waiting CLs under the same path as the head of
+    /// the queue are stacked on trunk and built together by Orion. If the
+    /// stack passes, the CLs are merged in queue order. If it fails, the
+    /// batch is bisected: the CLs before the first failing one are merged,
+    /// the culprit is marked failed and the rest are requeued in place.
     ///
     /// # Returns
-    /// * `Ok(true)` - An item was processed (success or failure)
-    /// * `Ok(false)` - No items to process
+    /// * `Ok(true)` - A batch was processed (success or failure)
+    /// * `Ok(false)` - No items to process, or the batch hit a conflict
     /// * `Err(MegaError)` - System error occurred
     async fn process_next_queue_item(&self) -> Result<bool, MegaError> {
         let queue_service = &self.storage.merge_queue_service;
 
-        // Get next waiting item from queue
-        let next_item = queue_service.get_next_waiting_item().await?;
+        let items = queue_service
+            .get_next_waiting_items(Self::MAX_BATCH_SIZE)
+            .await?;
+        if items.is_empty() {
+            return Ok(false);
+        }
 
-        if let Some(item) = next_item {
-            let cl_link = item.cl_link.clone();
+        let batch_id = common::utils::generate_id();
+        let mut batch: Vec<mega_cl::Model> = Vec::new();
+        for item in items {
+            let cl = match self.validate_queued_cl(&item.cl_link).await {
+                Ok(cl) => cl,
+                Err((failure_type, message)) => {
+                    self.fail_queue_item(&item.cl_link, failure_type, message)
+                        .await;
+                    continue;
+                }
+            };
+            // CLs are only stacked on a single trunk
+            if batch.first().is_some_and(|head| head.path != cl.path) {
+                break;
+            }
+            // Item was cancelled before we could start processing
+            if queue_service.assign_batch(&cl.link, batch_id).await? {
+                batch.push(cl);
+            }
+        }
+        if batch.is_empty() {
+            return Ok(true);
+        }
 
-            // Update status to Testing
-            let updated = queue_service
-                .update_item_status(&cl_link, QueueStatusEnum::Testing)
-                .await?;
+        match self.test_stack(batch_id, &batch, batch.len()).await {
+            Ok(()) => {
+                self.merge_queued_cls(&batch).await;
+                Ok(true)
+            }
+            Err(StackFailure::Conflict(culprit, _)) => {
+                // Conflict - move to tail of queue for retry, after the rest of the batch
+                if let Err(e) = queue_service.move_item_to_tail(&batch[culprit].link).await {
+                    tracing::warn!(
+                        "Failed to move conflicting item {} to tail: {}",
+                        batch[culprit].link,
+                        e
+                    );
+                }
+                batch.remove(culprit);
+                self.requeue_queued_cls(&batch).await;
+                Ok(false)
+            }
+            Err(StackFailure::Build(failure_type, message)) => {
+                self.bisect_batch(batch_id, &batch, failure_type, message)
+                    .await;
+                Ok(true)
+            }
+            Err(StackFailure::Cancelled) => {
+                self.requeue_queued_cls(&batch).await;
+                Ok(true)
+            }
+            Err(StackFailure::System(message)) => {
+                for cl in &batch {
+                    self.fail_queue_item(
+                        &cl.link,
+                        QueueFailureTypeEnum::SystemError,
+                        message.clone(),
+                    )
+                    .await;
+                }
+                Ok(true)
+            }
+        }
+    }
 
-            // Item was cancelled before we could start processing
-            if !updated {
-                return Ok(false);
+    /// Narrows a failed batch down to the first CL that breaks the build.
+    ///
+    /// The CLs before the culprit passed together as a prefix of the stack
+    /// and are merged, the ones after it are requeued untested.
+    async fn bisect_batch(
+        &self,
+        batch_id: i64,
+        batch: &[mega_cl::Model],
+        mut failure_type: QueueFailureTypeEnum,
+        mut message: String,
+    ) {
+        let mut bisect = Bisect::new(batch.len());
+        while let Some(len) = bisect.next_len() {
+            match self.test_stack(batch_id, batch, len).await {
+                Ok(()) => bisect.record(len, true),
+                Err(StackFailure::Build(ft, msg)) => {
+                    (failure_type, message) = (ft, msg);
+                    bisect.record(len, false);
+                }
+                // Trunk moved or a CL was cancelled, start over with a fresh batch
+                Err(e) => {
+                    tracing::warn!("Bisecting merge queue batch {} aborted: {}", batch_id, e);
+                    self.requeue_queued_cls(batch).await;
+                    return;
+                }
             }
+        }
+
+        let culprit = bisect.culprit();
+        tracing::info!(
+            "Merge queue batch {} failed, culprit is {}",
+            batch_id,
+            batch[culprit].link
+        );
+        self.merge_queued_cls(&batch[..culprit]).await;
+        self.fail_queue_item(&batch[culprit].link, failure_type, message)
+            .await;
+        self.requeue_queued_cls(&batch[culprit + 1..]).await;
+    }
+
+    /// Builds the first `len` CLs of `batch` stacked on trunk and records
+    /// the run in the history of every CL in the batch.
+    async fn test_stack(
+        &self,
+        batch_id: i64,
+        batch: &[mega_cl::Model],
+        len: usize,
+    ) -> Result<(), StackFailure> {
+        let stack = &batch[..len];
+        let (commit_id, result) = self.build_stack(stack).await;
+
+        let run = BatchRun {
+            batch_id,
+            cl_links: stack.iter().map(|cl| cl.link.clone()).collect(),
+            commit_id,
+            passed: result.is_ok(),
+            message: match &result {
+                Ok(()) => "Passed".to_string(),
+                Err(e) => e.to_string(),
+            },
+            tested_at: chrono::Utc::now().naive_utc(),
+        };
+        let links: Vec<String> = batch.iter().map(|cl| cl.link.clone()).collect();
+        if let Err(e) = self
+            .storage
+            .merge_queue_service
+            .record_batch_run(&links, &run)
+            .await
+        {
+            tracing::warn!("Failed to record merge queue batch {}: {}", batch_id, e);
+        }
+        result
+    }
+
+    /// Creates the speculative commit for `stack`, triggers an Orion build
+    /// for it and waits for the result.
+    ///
+    /// Returns the speculative commit id alongside the result. Without a
+    /// build server the stack passes untested, as the serial queue did.
+    async fn build_stack(
+        &self,
+        stack: &[mega_cl::Model],
+    ) -> (Option<String>, Result<(), StackFailure>) {
+        let bellatrix = Bellatrix::new(self.storage.config().build.clone());
+        if !bellatrix.enable_build() {
+            return (None, Ok(()));
+        }
+
+        let (commit_id, changes) = match self.create_speculative_commit(stack).await {
+            Ok(commit) => commit,
+            Err(e) => return (None, Err(e)),
+        };
+        let top = &stack[stack.len() - 1];
+        let req = OrionBuildRequest {
+            cl_link: top.link.clone(),
+            repo: top.path.clone(),
+            cl: top.id,
+            builds: vec![BuildInfo { changes }],
+            commit_id: commit_id.clone(),
+            origin: BuildOrigin::MergeQueue,
+            labels: vec![],
+            username: top.username.clone(),
+        };
+        if let Err(e) = bellatrix.on_post_receive(req).await {
+            return (
+                Some(commit_id),
+                Err(StackFailure::System(format!(
+                    "Failed to trigger speculative build: {e}"
+                ))),
+            );
+        }
+
+        let result = self.wait_for_stack_build(stack, top.id, &commit_id).await;
+        (Some(commit_id), result)
+    }
 
-            // Execute the merge workflow
-            match self.execute_merge_workflow(&cl_link).await {
-                Ok(()) => {
-                    // Success - status already updated to Merged in workflow
-                    Ok(true)
+    /// Three-way merges each CL of `stack` in turn onto trunk and saves the
+    /// result as a commit on top of trunk, without moving any ref.
+    ///
+    /// Returns the commit id and the files it changes relative to trunk.
+    async fn create_speculative_commit(
+        &self,
+        stack: &[mega_cl::Model],
+    ) -> Result<(String, Vec<Status<ProjectRelativePath>>), StackFailure> {
+        let storage = self.storage.mono_storage();
+        let system = |e: MegaError| StackFailure::System(e.to_string());
+
+        let path = &stack[0].path;
+        let trunk = storage
+            .get_main_ref(path)
+            .await
+            .map_err(system)?
+            .ok_or_else(|| StackFailure::System(format!("Main ref not found: {path}")))?;
+        let trunk_snapshot = TreeMerge::flatten_commit(&storage, &trunk.ref_commit_hash)
+            .await
+            .map_err(system)?;
+
+        let mut snapshot = trunk_snapshot.clone();
+        for (idx, cl) in stack.iter().enumerate() {
+            let base_hash = self.cl_diff_base(cl).await.map_err(system)?;
+            let base = TreeMerge::flatten_commit(&storage, &base_hash)
+                .await
+                .map_err(system)?;
+            let theirs = TreeMerge::flatten_commit(&storage, &cl.to_hash)
+                .await
+                .map_err(system)?;
+            let outcome = TreeMerge::three_way(&base, &snapshot, &theirs);
+            if !outcome.is_clean() {
+                return Err(StackFailure::Conflict(idx, outcome.conflict_message()));
+            }
+            snapshot = outcome.merged;
+        }
+
+        let trees = TreeMerge::build_trees(&snapshot).map_err(system)?;
+        let tree_id = trees
+            .last()
+            .map(|t| t.id)
+            .ok_or_else(|| StackFailure::System("Speculative tree is empty".to_string()))?;
+        let parent = ObjectHash::from_str(&trunk.ref_commit_hash).map_err(StackFailure::System)?;
+        let links: Vec<&str> = stack.iter().map(|cl| cl.link.as_str()).collect();
+        let commit = Commit::from_tree_id(
+            tree_id,
+            vec![parent],
+            &format!("merge queue: speculative build of {}", links.join(", ")),
+        );
+        let commit_id = commit.id.to_string();
+
+        storage
+            .save_mega_trees(trees, commit.id, None)
+            .await
+            .map_err(system)?;
+        storage
+            .save_mega_commits(vec![commit], None)
+            .await
+            .map_err(system)?;
+
+        Ok((commit_id, stack_changes(&trunk_snapshot, &snapshot)))
+    }
+
+    /// Polls the Orion builds of a speculative commit until they finish.
+    ///
+    /// Gives up early when any CL of the stack is removed from the queue.
+    async fn wait_for_stack_build(
+        &self,
+        stack: &[mega_cl::Model],
+        cl_id: i64,
+        commit_id: &str,
+    ) -> Result<(), StackFailure> {
+        let queue_service = &self.storage.merge_queue_service;
+        let cl_storage = self.storage.cl_storage();
+        let system = |e: MegaError| StackFailure::System(e.to_string());
+        let deadline =
+            tokio::time::Instant::now() + Duration::from_secs(Self::BATCH_BUILD_TIMEOUT_SECS);
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(Self::BATCH_BUILD_POLL_SECS)).await;
+
+            for cl in stack {
+                let item = queue_service
+                    .get_cl_queue_status(&cl.link)
+                    .await
+                    .map_err(system)?;
+                if !item.is_some_and(|item| item.status == QueueStatusEnum::Testing) {
+                    return Err(StackFailure::Cancelled);
                 }
-                Err((failure_type, message)) => {
-                    if matches!(failure_type, QueueFailureTypeEnum::Conflict) {
-                        // Conflict - move to tail of queue for retry
-                        if let Err(e) = queue_service.move_item_to_tail(&cl_link).await {
-                            tracing::warn!(
-                                "Failed to move conflicting item {} to tail: {}",
-                                cl_link,
-                                e
-                            );
-                        }
-                        Ok(false)
-                    } else {
-                        // Other failure - mark as failed
-                        if let Err(e) = queue_service
-                            .update_item_status_with_error(&cl_link, failure_type, message)
-                            .await
-                        {
-                            tracing::error!(
-                                "Failed to update item {} status to failed: {}",
-                                cl_link,
-                                e
-                            );
-                        }
-                        Ok(true)
+            }
+
+            if let Some((_, builds)) = cl_storage
+                .get_latest_cl_task(cl_id, commit_id)
+                .await
+                .map_err(system)?
+            {
+                match summarize_builds(&builds) {
+                    (ConditionResult::PASSED, _) => return Ok(()),
+                    (ConditionResult::FAILED, message) => {
+                        return Err(StackFailure::Build(
+                            QueueFailureTypeEnum::BuildFailure,
+                            message,
+                        ));
                     }
+                    _ => {}
                 }
             }
-        } else {
-            Ok(false)
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(StackFailure::Build(
+                    QueueFailureTypeEnum::Timeout,
+                    format!("Speculative build of {commit_id} did not finish in time"),
+                ));
+            }
         }
     }
 
-    /// Executes the complete merge workflow for a CL.
-    ///
-    /// Workflow steps:
-    /// 1. Validate CL exists and is in valid status
-    /// 2. Run tests (TODO: Buck2 integration)
-    /// 3. Check for conflicts
-    /// 4. Execute merge
-    /// 5. Update statuses
-    async fn execute_merge_workflow(
+    /// Merges CLs that passed as a stack, one by one in queue order.
+    async fn merge_queued_cls(&self, cls: &[mega_cl::Model]) {
+        let queue_service = &self.storage.merge_queue_service;
+        for cl in cls {
+            match self.execute_merge_workflow(&cl.link).await {
+                Ok(()) => {}
+                Err((QueueFailureTypeEnum::Conflict, _)) => {
+                    // Conflict - move to tail of queue for retry
+                    if let Err(e) = queue_service.move_item_to_tail(&cl.link).await {
+                        tracing::warn!(
+                            "Failed to move conflicting item {} to tail: {}",
+                            cl.link,
+                            e
+                        );
+                    }
+                }
+                Err((failure_type, message)) => {
+                    self.fail_queue_item(&cl.link, failure_type, message).await;
+                }
+            }
+        }
+    }
+
+    /// Returns untested or innocent CLs of a batch to the queue in place.
+    async fn requeue_queued_cls(&self, cls: &[mega_cl::Model]) {
+        for cl in cls {
+            if let Err(e) = self
+                .storage
+                .merge_queue_service
+                .requeue_item(&cl.link)
+                .await
+            {
+                tracing::warn!("Failed to requeue item {}: {}", cl.link, e);
+            }
+        }
+    }
+
+    async fn fail_queue_item(
         &self,
         cl_link: &str,
-    ) -> Result<(), (QueueFailureTypeEnum, String)> {
-        let queue_service = &self.storage.merge_queue_service;
+        failure_type: QueueFailureTypeEnum,
+        message: String,
+    ) {
+        let data = serde_json::json!({
+            "link": cl_link,
+            "failure_type": failure_type,
+            "message": message,
+        });
+        if let Err(e) = self
+            .storage
+            .merge_queue_service
+            .update_item_status_with_error(cl_link, failure_type, message)
+            .await
+        {
+            tracing::error!("Failed to update item {} status to failed: {}", cl_link, e);
+        }
+        if let Ok(Some(cl)) = self.storage.cl_storage().get_cl(cl_link).await {
+            self.storage
+                .webhook_storage()
+                .emit(WebhookEvent::MergeQueueFailed, &cl.path, data)
+                .await;
+        }
+    }
 
-        // Step 1: Validate CL still exists and is not closed
+    /// Fetches a queued CL and checks it can still be merged.
+    async fn validate_queued_cl(
+        &self,
+        cl_link: &str,
+    ) -> Result<mega_cl::Model, (QueueFailureTypeEnum, String)> {
         let cl = self
             .storage
             .cl_storage()
@@ -2065,7 +2722,7 @@ impl MonoApiService {
                 )
             })?;
 
-        let cl_model = match cl {
+        match cl {
             Some(model) => {
                 if model.status == MergeStatusEnum::Closed {
                     return Err((
@@ -2079,23 +2736,37 @@ impl MonoApiService {
                         "CL is in draft status, cannot merge".to_string(),
                     ));
                 }
-                model
+                Ok(model)
             }
-            None => {
-                return Err((
-                    QueueFailureTypeEnum::SystemError,
-                    "CL no longer exists, cannot merge".to_string(),
-                ));
-            }
-        };
+            None => Err((
+                QueueFailureTypeEnum::SystemError,
+                "CL no longer exists, cannot merge".to_string(),
+            )),
+        }
+    }
 
-        // Step 2: Run tests (TODO: Buck2 integration)
-        // self.run_tests(&cl_model).await?;
+    /// Executes the complete merge workflow for a CL.
+    ///
+    /// Workflow steps:
+    /// 1. Validate CL exists and is in valid status
+    /// 2. Check for conflicts
+    /// 3. Execute merge
+    /// 4. Update statuses
+    ///
+    /// Tests have already run on the speculative stack the CL was part of.
+    async fn execute_merge_workflow(
+        &self,
+        cl_link: &str,
+    ) -> Result<(), (QueueFailureTypeEnum, String)> {
+        let queue_service = &self.storage.merge_queue_service;
 
-        // Step 3: Check for conflicts
+        // Step 1: Validate CL still exists and is not closed
+        let cl_model = self.validate_queued_cl(cl_link).await?;
+
+        // Step 2: Check for conflicts
         self.check_merge_conflicts(&cl_model).await?;
 
-        // Step 4: Update status to Merging
+        // Step 3: Update status to Merging
         let updated = queue_service
             .update_item_status(cl_link, QueueStatusEnum::Merging)
             .await
@@ -2113,8 +2784,8 @@ impl MonoApiService {
             ));
         }
 
-        // Step 5: Execute merge (conflict already checked in step 3)
-        self.merge_cl_unchecked("system", cl_model.clone())
+        // Step 4: Execute merge (re-merges onto trunk if it moved since step 2)
+        self.merge_cl("system", cl_model.clone())
             .await
             .map_err(|e| {
                 (
@@ -2123,7 +2794,7 @@ impl MonoApiService {
                 )
             })?;
 
-        // Step 6: Update queue status to Merged
+        // Step 5: Update queue status to Merged
         queue_service
             .update_item_status(cl_link, QueueStatusEnum::Merged)
             .await
@@ -2137,10 +2808,10 @@ impl MonoApiService {
         Ok(())
     }
 
-    /// Checks for merge conflicts by comparing CL base hash with current main ref.
+    /// Checks for merge conflicts by three-way merging the CL onto the current main ref.
     ///
-    /// A conflict occurs when the CL's from_hash differs from the current
-    /// main branch ref, indicating the base has changed since CL creation.
+    /// A conflict occurs when a path changed by the CL was also changed on
+    /// the main branch since the CL's from_hash.
     async fn check_merge_conflicts(
         &self,
         cl: &mega_cl::Model,
@@ -2161,12 +2832,27 @@ impl MonoApiService {
                 "Main ref not found".to_string(),
             ))?;
 
-        if cl.from_hash != refs.ref_commit_hash {
+        if cl.from_hash == refs.ref_commit_hash {
+            return Ok(());
+        }
+
+        let outcome =
+            TreeMerge::merge_commits(&storage, &cl.from_hash, &refs.ref_commit_hash, &cl.to_hash)
+                .await
+                .map_err(|e| {
+                    (
+                        QueueFailureTypeEnum::SystemError,
+                        format!("Failed to merge trees: {}", e),
+                    )
+                })?;
+
+        if !outcome.is_clean() {
             return Err((
                 QueueFailureTypeEnum::Conflict,
                 format!(
-                    "Conflict detected: CL base hash {} differs from current main ref {}",
-                    cl.from_hash, refs.ref_commit_hash
+                    "Conflict detected against main ref {}: {}",
+                    refs.ref_commit_hash,
+                    outcome.conflict_message()
                 ),
             ));
         }
@@ -2175,6 +2861,212 @@ impl MonoApiService {
     }
 }
 
+/// Why a speculative stack of queued CLs could not be verified.
+#[derive(Debug)]
+enum StackFailure {
+    /// The CL at this index does not merge cleanly onto trunk and the CLs before it.
+    Conflict(usize, String),
+    /// The speculative build failed or timed out.
+    Build(QueueFailureTypeEnum, String),
+    /// A CL of the stack was removed from the queue while it was tested.
+    Cancelled,
+    System(String),
+}
+
+impl std::fmt::Display for StackFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackFailure::Conflict(_, message)
+            | StackFailure::Build(_, message)
+            | StackFailure::System(message) => write!(f, "{message}"),
+            StackFailure::Cancelled => write!(f, "A CL of the batch was removed from the queue"),
+        }
+    }
+}
+
+/// Binary search for the shortest failing prefix of a speculative batch.
+///
+/// Assumes a CL that breaks the build breaks every longer stack too, and
+/// that trunk itself (the empty prefix) is green.
+#[derive(Debug)]
+struct Bisect {
+    /// Longest prefix known to pass.
+    passed: usize,
+    /// Shortest prefix known to fail.
+    failed: usize,
+}
+
+impl Bisect {
+    fn new(failed: usize) -> Self {
+        Self { passed: 0, failed }
+    }
+
+    /// Length of the next prefix to test, or `None` once the culprit is found.
+    fn next_len(&self) -> Option<usize> {
+        (self.failed - self.passed > 1).then_some((self.passed + self.failed) / 2)
+    }
+
+    fn record(&mut self, len: usize, passed: bool) {
+        if passed {
+            self.passed = len;
+        } else {
+            self.failed = len;
+        }
+    }
+
+    /// Index of the first failing CL. Every CL before it passed as a stack.
+    fn culprit(&self) -> usize {
+        self.failed - 1
+    }
+}
+
+/// Files that differ between trunk and a speculative stack, as Orion expects them.
+fn stack_changes(
+    trunk: &BTreeMap<PathBuf, MergeEntry>,
+    stack: &BTreeMap<PathBuf, MergeEntry>,
+) -> Vec<Status<ProjectRelativePath>> {
+    let paths: BTreeSet<&PathBuf> = trunk.keys().chain(stack.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let relative = ProjectRelativePath::new(&path.to_string_lossy());
+            match (trunk.get(path), stack.get(path)) {
+                (None, Some(_)) => Some(Status::Added(relative)),
+                (Some(_), None) => Some(Status::Removed(relative)),
+                (Some(old), Some(new)) if old != new => Some(Status::Modified(relative)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Rebases every CL stacked above `merged`, and returns the links of the
+/// rebased CLs from the bottom of the stack up.
+///
+/// Each CL is three-way merged with its old parent's revision as the merge
+/// base: CLs stacked directly on `merged` onto trunk, and the CLs above them
+/// onto their parent's rebased revision. A CL whose changes conflict is left
+/// as it is, with a note in its conversation, and so are the CLs above it.
+async fn rebase_stack(
+    storage: &Storage,
+    username: &str,
+    merged: &mega_cl::Model,
+) -> Result<Vec<String>, MegaError> {
+    let cl_stg = storage.cl_storage();
+    let mono_stg = storage.mono_storage();
+    let conv_stg = storage.conversation_storage();
+
+    // (parent link, parent's old revision, revision to rebase onto)
+    let mut pending = VecDeque::from([(merged.link.clone(), merged.to_hash.clone(), None)]);
+    let mut rebased = vec![];
+    while let Some((parent, old_base, onto)) = pending.pop_front() {
+        for child in cl_stg.get_stacked_cls(&parent).await? {
+            if child.status == MergeStatusEnum::Merged || child.to_hash.is_empty() {
+                continue;
+            }
+            let trunk = mono_stg
+                .get_main_ref(&child.path)
+                .await?
+                .ok_or_else(|| MegaError::Other(format!("Main ref not found: {}", child.path)))?;
+            let onto_hash = onto.as_ref().unwrap_or(&trunk.ref_commit_hash);
+            let outcome =
+                TreeMerge::merge_commits(&mono_stg, &old_base, onto_hash, &child.to_hash).await?;
+            if !outcome.is_clean() {
+                let onto_name = match onto {
+                    Some(_) => parent.as_str(),
+                    None => MEGA_BRANCH_NAME,
+                };
+                conv_stg
+                    .add_conversation(
+                        &child.link,
+                        username,
+                        Some(format!(
+                            "Could not rebase onto {} after {} was merged: {}",
+                            onto_name,
+                            merged.link,
+                            outcome.conflict_message()
+                        )),
+                        ConvTypeEnum::Comment,
+                    )
+                    .await?;
+                continue;
+            }
+
+            let trees = TreeMerge::build_trees(&outcome.merged)?;
+            let tree_id = trees
+                .last()
+                .map(|t| t.id)
+                .ok_or_else(|| MegaError::Other("Rebased tree is empty".to_string()))?;
+            let parent_commit = ObjectHash::from_str(onto_hash).map_err(MegaError::Other)?;
+            let commit = Commit::from_tree_id(tree_id, vec![parent_commit], &child.title);
+            let commit_id = commit.id.to_string();
+            mono_stg.save_mega_trees(trees, commit.id, None).await?;
+            mono_stg.save_mega_commits(vec![commit], None).await?;
+            mono_stg
+                .save_or_update_cl_ref(
+                    &child.path,
+                    &common::utils::cl_ref_name(&child.link),
+                    &commit_id,
+                    &tree_id.to_string(),
+                )
+                .await?;
+
+            conv_stg
+                .add_conversation(
+                    &child.link,
+                    username,
+                    Some(format!(
+                        "{} was merged, rebased the cl from {} to {}",
+                        merged.link,
+                        &child.to_hash[..6],
+                        &commit_id[..6]
+                    )),
+                    ConvTypeEnum::ForcePush,
+                )
+                .await?;
+            let link = child.link.clone();
+            let old_hash = child.to_hash.clone();
+            cl_stg
+                .update_cl_hash(child, &trunk.ref_commit_hash, &commit_id, username)
+                .await?;
+            if onto.is_none() {
+                cl_stg.set_base_cl(&link, merged.base_cl.as_deref()).await?;
+            }
+            pending.push_back((link.clone(), old_hash, Some(commit_id)));
+            rebased.push(link);
+        }
+    }
+    Ok(rebased)
+}
+
+/// Checks that `cl` may be stacked on `base`, whose own ancestors are
+/// `base_ancestors`.
+fn check_stack_base(
+    cl: &mega_cl::Model,
+    base: &mega_cl::Model,
+    base_ancestors: &[mega_cl::Model],
+) -> Result<(), MegaError> {
+    if base.path != cl.path {
+        return Err(MegaError::Other(format!(
+            "{} is on {}, a CL can only be stacked on a CL of the same path",
+            base.link, base.path
+        )));
+    }
+    if matches!(
+        base.status,
+        MergeStatusEnum::Merged | MergeStatusEnum::Closed
+    ) {
+        return Err(MegaError::Other(format!("{} is no longer open", base.link)));
+    }
+    if base.link == cl.link || base_ancestors.iter().any(|a| a.link == cl.link) {
+        return Err(MegaError::Other(format!(
+            "{} is already stacked on {}",
+            base.link, cl.link
+        )));
+    }
+    Ok(())
+}
+
 fn collect_page_blobs(
     items: &[ClDiffFile],
     old_out: &mut Vec<(PathBuf, ObjectHash)>,
@@ -2209,6 +3101,165 @@ mod test {
     use std::str::FromStr;
     use std::sync::Arc;
 
+    #[test]
+    fn test_bisect_finds_first_failing_cl() {
+        // the CL at `culprit` and every stack containing it fail
+        for len in 1..=5 {
+            for culprit in 0..len {
+                let mut bisect = Bisect::new(len);
+                let mut runs = 0;
+                while let Some(next) = bisect.next_len() {
+                    bisect.record(next, next <= culprit);
+                    runs += 1;
+                }
+                assert_eq!(bisect.culprit(), culprit);
+                assert!(runs <= len.ilog2() as usize + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stack_changes() {
+        use crate::merge_checker::merge_conflict_checker::MergeEntry;
+
+        let entry = |byte| MergeEntry {
+            id: ObjectHash::Sha1([byte; 20]),
+            mode: TreeItemMode::Blob,
+        };
+        let trunk = BTreeMap::from([
+            (PathBuf::from("kept"), entry(1)),
+            (PathBuf::from("src/changed"), entry(2)),
+            (PathBuf::from("removed"), entry(3)),
+        ]);
+        let stack = BTreeMap::from([
+            (PathBuf::from("kept"), entry(1)),
+            (PathBuf::from("src/changed"), entry(4)),
+            (PathBuf::from("added"), entry(5)),
+        ]);
+
+        assert_eq!(
+            stack_changes(&trunk, &stack),
+            vec![
+                Status::Added(ProjectRelativePath::new("added")),
+                Status::Removed(ProjectRelativePath::new("removed")),
+                Status::Modified(ProjectRelativePath::new("src/changed")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_stack_base() {
+        let cl = |link: &str, path: &str, status| {
+            let mut cl = mega_cl::Model::new(
+                path.to_string(),
+                String::new(),
+                link.to_string(),
+                "from".to_string(),
+                "to".to_string(),
+                "mega".to_string(),
+            );
+            cl.status = status;
+            cl
+        };
+        let child = cl("CHILD", "/project", MergeStatusEnum::Open);
+        let base = cl("BASE", "/project", MergeStatusEnum::Open);
+        assert!(check_stack_base(&child, &base, &[]).is_ok());
+
+        // stacking CHILD on BASE when BASE already sits on CHILD
+        assert!(check_stack_base(&child, &base, std::slice::from_ref(&child)).is_err());
+        assert!(check_stack_base(&child, &child, &[]).is_err());
+        let other_path = cl("OTHER", "/doc", MergeStatusEnum::Open);
+        assert!(check_stack_base(&child, &other_path, &[]).is_err());
+        let merged = cl("MERGED", "/project", MergeStatusEnum::Merged);
+        assert!(check_stack_base(&child, &merged, &[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rebase_three_level_stack() {
+        use callisto::mega_refs;
+        use jupiter::tests::test_storage;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = test_storage(temp_dir.path()).await;
+        let mono_stg = storage.mono_storage();
+        let cl_stg = storage.cl_storage();
+
+        // saves a single tree commit holding one blob per name
+        let commit = async |names: &[&str], parent: Option<&str>| {
+            let items = names
+                .iter()
+                .map(|name| {
+                    let id = ObjectHash::new(name.as_bytes());
+                    TreeItem::new(TreeItemMode::Blob, id, name.to_string())
+                })
+                .collect();
+            let tree = Tree::from_tree_items(items).unwrap();
+            let parents = parent
+                .map(|p| ObjectHash::from_str(p).unwrap())
+                .into_iter()
+                .collect();
+            let commit = Commit::from_tree_id(tree.id, parents, &names.join(","));
+            let id = commit.id;
+            mono_stg
+                .save_mega_trees(vec![tree], id, None)
+                .await
+                .unwrap();
+            mono_stg
+                .save_mega_commits(vec![commit], None)
+                .await
+                .unwrap();
+            id.to_string()
+        };
+        let trunk = commit(&["f"], None).await;
+        let a = commit(&["a", "f"], Some(&trunk)).await;
+        let b = commit(&["a", "b", "f"], Some(&a)).await;
+        let c = commit(&["a", "b", "c", "f"], Some(&b)).await;
+        // A landed next to an unrelated trunk change
+        let landed = commit(&["a", "f", "t"], Some(&trunk)).await;
+        let landed_ref = mega_refs::Model::new(
+            "/",
+            MEGA_BRANCH_NAME.to_string(),
+            landed.clone(),
+            String::new(),
+            false,
+        );
+        mono_stg.save_refs(landed_ref, None).await.unwrap();
+
+        for (link, to) in [("A", &a), ("B", &b), ("C", &c)] {
+            cl_stg
+                .new_cl("/", link, link, &trunk, to, "mega")
+                .await
+                .unwrap();
+        }
+        cl_stg.set_base_cl("B", Some("A")).await.unwrap();
+        cl_stg.set_base_cl("C", Some("B")).await.unwrap();
+        let merged = cl_stg.get_cl("A").await.unwrap().unwrap();
+
+        let rebased = rebase_stack(&storage, "mega", &merged).await.unwrap();
+        assert_eq!(rebased, vec!["B".to_string(), "C".to_string()]);
+
+        let b = cl_stg.get_cl("B").await.unwrap().unwrap();
+        let c = cl_stg.get_cl("C").await.unwrap().unwrap();
+        assert_eq!(b.base_cl, None);
+        assert_eq!(c.base_cl.as_deref(), Some("B"));
+        let names = async |hash: &str| {
+            TreeMerge::flatten_commit(&mono_stg, hash)
+                .await
+                .unwrap()
+                .into_keys()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&b.to_hash).await, ["a", "b", "f", "t"]);
+        assert_eq!(names(&c.to_hash).await, ["a", "b", "c", "f", "t"]);
+        let c_commit = mono_stg
+            .get_commit_by_hash(&c.to_hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(c_commit.parents_id, serde_json::json!([b.to_hash]));
+    }
+
     #[test]
     fn test_clean_path_str_edges() {
         assert_eq!(MonoServiceLogic::clean_path_str(""), "/");
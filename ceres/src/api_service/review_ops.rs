@@ -0,0 +1,199 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use callisto::sea_orm_active_enums::{ConvTypeEnum, ReviewStateEnum};
+use common::errors::MegaError;
+use git_internal::hash::ObjectHash;
+
+use crate::api_service::mono_api_service::MonoApiService;
+
+/// Applies the branch protection policy for stale approvals after a CL got
+/// a new patchset.
+///
+/// With `dismiss_stale_approvals` every approval is reset to pending. With
+/// `sticky_approvals` as well, an approval is kept when the update only
+/// touches files that were already part of the CL when it was approved.
+/// Both revisions are compared as the CL's own diff against its base, so
+/// rebasing onto a moved trunk doesn't count as touching trunk's files.
+pub async fn refresh_approvals(
+    service: &MonoApiService,
+    cl_link: &str,
+    username: &str,
+) -> Result<(), MegaError> {
+    let cl_stg = service.storage.cl_storage();
+    let cl = cl_stg
+        .get_cl(cl_link)
+        .await?
+        .ok_or_else(|| MegaError::Other(format!("CL not found: {cl_link}")))?;
+    let Some(rule) = cl_stg.get_branch_protection_rule(&cl.path).await? else {
+        return Ok(());
+    };
+    if !rule.dismiss_stale_approvals {
+        return Ok(());
+    }
+    let Some(latest) = cl_stg.get_latest_patchset(cl_link).await? else {
+        return Ok(());
+    };
+
+    let reviewer_stg = service.storage.reviewer_storage();
+    let reviewers = reviewer_stg.list_reviewers(cl_link).await?;
+
+    // Patchsets whose approvals survive the update. Reviewers often approved
+    // the same patchset, so each one's files are compared once.
+    let mut sticky_patchsets = HashSet::new();
+    if rule.sticky_approvals {
+        let approved: HashSet<i32> = reviewers
+            .iter()
+            .filter(|reviewer| reviewer.review_state == ReviewStateEnum::Approved)
+            .filter_map(|reviewer| reviewer.approved_patchset)
+            .filter(|&number| number != latest.number)
+            .collect();
+        if !approved.is_empty() {
+            let latest_diff = diff_files(
+                service
+                    .get_commit_blobs(&service.cl_diff_base(&cl).await?)
+                    .await?,
+                service.get_commit_blobs(&latest.to_hash).await?,
+            );
+            for number in approved {
+                let Some(patchset) = cl_stg.get_patchset(cl_link, number).await? else {
+                    continue;
+                };
+                let approved_diff = diff_files(
+                    service.get_commit_blobs(&patchset.from_hash).await?,
+                    service.get_commit_blobs(&patchset.to_hash).await?,
+                );
+                if changed_in_update(&approved_diff, &latest_diff)
+                    .iter()
+                    .all(|path| approved_diff.contains_key(path))
+                {
+                    sticky_patchsets.insert(number);
+                }
+            }
+        }
+    }
+
+    let mut dismissed = vec![];
+    for reviewer in reviewers {
+        if reviewer.review_state != ReviewStateEnum::Approved {
+            continue;
+        }
+        let sticky = match reviewer.approved_patchset {
+            Some(number) if number == latest.number => continue,
+            Some(number) => sticky_patchsets.contains(&number),
+            None => false,
+        };
+
+        if sticky {
+            reviewer_stg
+                .carry_approval(cl_link, &reviewer.username, latest.number)
+                .await?;
+        } else {
+            reviewer_stg
+                .set_review_state(cl_link, &reviewer.username, ReviewStateEnum::Pending)
+                .await?;
+            dismissed.push(reviewer.username);
+        }
+    }
+
+    if !dismissed.is_empty() {
+        service
+            .storage
+            .conversation_storage()
+            .add_conversation(
+                cl_link,
+                username,
+                Some(format!(
+                    "Approvals from {} were dismissed by patchset {}",
+                    dismissed.join(", "),
+                    latest.number
+                )),
+                ConvTypeEnum::Review,
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Files whose content differs between two file lists, with their new
+/// content, or `None` for deleted files.
+fn diff_files(
+    old: Vec<(PathBuf, ObjectHash)>,
+    new: Vec<(PathBuf, ObjectHash)>,
+) -> HashMap<PathBuf, Option<ObjectHash>> {
+    let mut old: HashMap<PathBuf, ObjectHash> = old.into_iter().collect();
+    let mut changed = HashMap::new();
+    for (path, hash) in new {
+        if old.remove(&path) != Some(hash) {
+            changed.insert(path, Some(hash));
+        }
+    }
+    changed.extend(old.into_keys().map(|path| (path, None)));
+    changed
+}
+
+/// Paths a new revision of a CL changes compared to an older one, given
+/// the diff of each against its base.
+fn changed_in_update(
+    old: &HashMap<PathBuf, Option<ObjectHash>>,
+    new: &HashMap<PathBuf, Option<ObjectHash>>,
+) -> HashSet<PathBuf> {
+    old.keys()
+        .chain(new.keys())
+        .filter(|path| old.get(*path) != new.get(*path))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn hash(n: u8) -> ObjectHash {
+        ObjectHash::from_str(&format!("{n:040x}")).unwrap()
+    }
+
+    #[test]
+    fn test_changed_in_update() {
+        let trunk = vec![
+            (PathBuf::from("a.rs"), hash(1)),
+            (PathBuf::from("b.rs"), hash(2)),
+            (PathBuf::from("c.rs"), hash(3)),
+        ];
+        let approved = vec![
+            (PathBuf::from("a.rs"), hash(4)),
+            (PathBuf::from("b.rs"), hash(2)),
+            (PathBuf::from("d.rs"), hash(5)),
+        ];
+        let approved_diff = diff_files(trunk.clone(), approved.clone());
+        let reviewed: HashSet<PathBuf> = approved_diff.keys().cloned().collect();
+        assert_eq!(
+            reviewed,
+            HashSet::from(["a.rs", "c.rs", "d.rs"].map(PathBuf::from))
+        );
+        let sticky = |trunk, update| {
+            changed_in_update(&approved_diff, &diff_files(trunk, update)).is_subset(&reviewed)
+        };
+
+        // touching a reviewed file again keeps the approval
+        let mut update = approved.clone();
+        update[0].1 = hash(6);
+        assert!(sticky(trunk.clone(), update));
+
+        // a file the reviewer hasn't seen in the CL doesn't
+        let mut update = approved.clone();
+        update[1].1 = hash(7);
+        assert!(!sticky(trunk.clone(), update));
+
+        // rebasing onto a trunk that changed another file keeps it too
+        let mut moved = trunk.clone();
+        moved.push((PathBuf::from("e.rs"), hash(8)));
+        let mut rebased = approved.clone();
+        rebased.push((PathBuf::from("e.rs"), hash(8)));
+        assert!(sticky(moved, rebased));
+    }
+}
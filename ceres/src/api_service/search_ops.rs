@@ -0,0 +1,485 @@
+//! Code search over trunk.
+//!
+//! Every text blob on trunk is indexed under its path together with the
+//! trigrams of its ASCII-lowercased content. A query is narrowed down to the
+//! files holding every trigram of the literals it cannot match without, and
+//! those files are then scanned line by line with the real pattern.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use async_recursion::async_recursion;
+use callisto::code_index_file;
+use common::errors::MegaError;
+use futures::{StreamExt, stream};
+use git_internal::hash::ObjectHash;
+use git_internal::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use jupiter::storage::Storage;
+use jupiter::utils::converter::{FromGitModel, FromMegaModel};
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Hir, HirKind};
+use tokio::sync::Mutex;
+
+use crate::model::search::{CodeSearchHit, CodeSearchQuery, CodeSearchResult, LineMatch};
+
+/// Larger blobs are not indexed.
+const MAX_INDEXED_SIZE: usize = 1024 * 1024;
+/// Most files scanned for a single query.
+const MAX_CANDIDATES: u64 = 1000;
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+/// Most matching lines reported per file.
+const MAX_LINE_MATCHES: usize = 20;
+/// Matched lines are cut to this many characters.
+const MAX_SNIPPET_LEN: usize = 300;
+/// Blobs read at once while scanning candidates.
+const SCAN_CONCURRENCY: usize = 8;
+
+/// Syncs diff trunk against the last indexed tree, so running two at once
+/// would index the same changes twice.
+static SYNC_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+/// Keeps the code index in step with trunk.
+#[derive(Clone)]
+pub struct CodeIndexer {
+    storage: Storage,
+}
+
+impl CodeIndexer {
+    pub fn new(storage: Storage) -> Self {
+        Self { storage }
+    }
+
+    /// Runs [`CodeIndexer::sync`] in the background, so pushes and merges
+    /// don't wait for indexing.
+    pub fn spawn_sync(storage: Storage) {
+        tokio::spawn(async move {
+            if let Err(e) = CodeIndexer::new(storage).sync().await {
+                tracing::warn!("Failed to update the code index: {e}");
+            }
+        });
+    }
+
+    /// Brings the index up to the current trunk, only visiting the subtrees
+    /// that changed since the last sync.
+    pub async fn sync(&self) -> Result<(), MegaError> {
+        let _guard = SYNC_LOCK.lock().await;
+        let Some(root_ref) = self.storage.mono_storage().get_main_ref("/").await? else {
+            return Ok(());
+        };
+        let index_stg = self.storage.code_index_storage();
+        let root = Path::new("/");
+        let old_tree = match index_stg.indexed_state().await? {
+            Some(state) if state.tree_id == root_ref.ref_tree_hash => return Ok(()),
+            Some(state) => self.load_tree(&state.tree_id, root, None).await?,
+            None => None,
+        };
+        let (new_tree, _) = self
+            .load_tree(&root_ref.ref_tree_hash, root, None)
+            .await?
+            .ok_or_else(|| {
+                MegaError::Other(format!("Root tree {} not found", root_ref.ref_tree_hash))
+            })?;
+        self.sync_tree(old_tree.map(|(tree, _)| tree), new_tree, root, None)
+            .await?;
+        index_stg
+            .save_state(&root_ref.ref_tree_hash, &root_ref.ref_commit_hash)
+            .await?;
+        tracing::info!("Code index synced to {}", root_ref.ref_commit_hash);
+        Ok(())
+    }
+
+    #[async_recursion]
+    async fn sync_tree(
+        &self,
+        old: Option<Tree>,
+        new: Tree,
+        path: &Path,
+        repo_id: Option<i64>,
+    ) -> Result<(), MegaError> {
+        let index_stg = self.storage.code_index_storage();
+        let mut old_items: HashMap<String, TreeItem> = old
+            .map(|tree| {
+                tree.tree_items
+                    .into_iter()
+                    .map(|item| (item.name.clone(), item))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for item in new.tree_items {
+            let item_path = path.join(&item.name);
+            let old_item = old_items.remove(&item.name);
+            if old_item
+                .as_ref()
+                .is_some_and(|old| old.id == item.id && old.mode == item.mode)
+            {
+                continue;
+            }
+            match item.mode {
+                TreeItemMode::Tree => {
+                    let old_tree = match old_item {
+                        Some(old) if old.mode == TreeItemMode::Tree => self
+                            .load_tree(&old.id.to_string(), &item_path, repo_id)
+                            .await?
+                            .map(|(tree, _)| tree),
+                        _ => None,
+                    };
+                    if old_tree.is_none() {
+                        index_stg.remove_path(&path_str(&item_path)).await?;
+                    }
+                    match self
+                        .load_tree(&item.id.to_string(), &item_path, repo_id)
+                        .await?
+                    {
+                        Some((tree, repo_id)) => {
+                            self.sync_tree(old_tree, tree, &item_path, repo_id).await?
+                        }
+                        None => tracing::warn!(
+                            "Tree {} at {} not found, not indexing it",
+                            item.id,
+                            item_path.display()
+                        ),
+                    }
+                }
+                TreeItemMode::Blob | TreeItemMode::BlobExecutable => {
+                    if old_item.is_some_and(|old| old.mode == TreeItemMode::Tree) {
+                        index_stg.remove_path(&path_str(&item_path)).await?;
+                    }
+                    self.index_blob(&item_path, &item.id).await?;
+                }
+                // symlinks and submodules have no content of their own
+                TreeItemMode::Link | TreeItemMode::Commit => {
+                    if old_item.is_some() {
+                        index_stg.remove_path(&path_str(&item_path)).await?;
+                    }
+                }
+            }
+        }
+
+        for name in old_items.into_keys() {
+            index_stg.remove_path(&path_str(&path.join(name))).await?;
+        }
+        Ok(())
+    }
+
+    async fn index_blob(&self, path: &Path, id: &ObjectHash) -> Result<(), MegaError> {
+        let index_stg = self.storage.code_index_storage();
+        let path = path_str(path);
+        let data = match self
+            .storage
+            .git_service
+            .get_object_as_bytes(&id.to_string())
+            .await
+        {
+            Ok(data) => data,
+            // one missing object shouldn't hold back the rest of trunk
+            Err(e) => {
+                tracing::warn!("Failed to read blob {id} at {path}: {e}");
+                return index_stg.remove_path(&path).await;
+            }
+        };
+        if data.len() > MAX_INDEXED_SIZE || is_binary(&data) {
+            return index_stg.remove_path(&path).await;
+        }
+        let file = code_index_file::Model::new(&path, &id.to_string(), language_of(&path));
+        index_stg.index_file(file, &trigrams(&data)).await
+    }
+
+    /// Trunk trees live in the mono tables, except under imported repos,
+    /// whose trees stay with the repo they were pushed to.
+    async fn load_tree(
+        &self,
+        hash: &str,
+        path: &Path,
+        repo_id: Option<i64>,
+    ) -> Result<Option<(Tree, Option<i64>)>, MegaError> {
+        let git_stg = self.storage.git_db_storage();
+        if let Some(repo_id) = repo_id {
+            return Ok(git_stg
+                .get_tree_by_hash(repo_id, hash)
+                .await?
+                .map(|model| (Tree::from_git_model(model), Some(repo_id))));
+        }
+        if let Some(model) = self.storage.mono_storage().get_tree_by_hash(hash).await? {
+            return Ok(Some((Tree::from_mega_model(model), None)));
+        }
+        let Some(repo) = git_stg.find_git_repo_exact_match(&path_str(path)).await? else {
+            return Ok(None);
+        };
+        Ok(git_stg
+            .get_tree_by_hash(repo.id, hash)
+            .await?
+            .map(|model| (Tree::from_git_model(model), Some(repo.id))))
+    }
+}
+
+/// Searches the indexed trunk. `scope` is the part of the monorepo the
+/// caller may read, if they are limited to one.
+pub async fn search_code(
+    storage: &Storage,
+    query: &CodeSearchQuery,
+    scope: Option<&str>,
+) -> Result<CodeSearchResult, MegaError> {
+    if query.q.is_empty() {
+        return Err(MegaError::Other(
+            "[code:400] Search query cannot be empty".to_string(),
+        ));
+    }
+    let pattern = if query.regex {
+        query.q.clone()
+    } else {
+        regex::escape(&query.q)
+    };
+    let matcher = RegexBuilder::new(&pattern)
+        .case_insensitive(!query.case_sensitive)
+        .size_limit(1 << 20)
+        .build()
+        .map_err(|e| MegaError::Other(format!("[code:400] Invalid pattern: {e}")))?;
+
+    let Some(path) = narrow_scope(query.path.as_deref(), scope) else {
+        return Ok(CodeSearchResult::default());
+    };
+    let literals = if query.regex {
+        required_literals(&query.q)
+    } else {
+        vec![query.q.as_bytes().to_vec()]
+    };
+    let mut needles: Vec<i32> = literals
+        .iter()
+        .flat_map(|literal| trigrams(literal))
+        // case-insensitive matching may fold non-ASCII text into other bytes
+        .filter(|&t| query.case_sensitive || t.to_be_bytes().iter().all(u8::is_ascii))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    needles.sort_unstable();
+
+    let candidates = storage
+        .code_index_storage()
+        .find_files(
+            &needles,
+            path.as_deref(),
+            query.lang.as_deref(),
+            MAX_CANDIDATES,
+        )
+        .await?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut truncated = candidates.len() as u64 >= MAX_CANDIDATES;
+
+    let mut scans = stream::iter(candidates)
+        .map(|file| scan_file(storage, file, &matcher))
+        .buffered(SCAN_CONCURRENCY);
+    let mut hits = vec![];
+    while let Some(hit) = scans.next().await {
+        if let Some(hit) = hit? {
+            if hits.len() == limit {
+                truncated = true;
+                break;
+            }
+            hits.push(hit);
+        }
+    }
+    Ok(CodeSearchResult { hits, truncated })
+}
+
+async fn scan_file(
+    storage: &Storage,
+    file: code_index_file::Model,
+    matcher: &Regex,
+) -> Result<Option<CodeSearchHit>, MegaError> {
+    let data = storage
+        .git_service
+        .get_object_as_bytes(&file.blob_id)
+        .await?;
+    let content = String::from_utf8_lossy(&data);
+    let matches: Vec<LineMatch> = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| matcher.is_match(line))
+        .take(MAX_LINE_MATCHES)
+        .map(|(i, line)| LineMatch {
+            line_number: i + 1,
+            line: line.chars().take(MAX_SNIPPET_LEN).collect(),
+        })
+        .collect();
+    if matches.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(CodeSearchHit {
+        path: file.path,
+        language: file.language,
+        blob_id: file.blob_id,
+        matches,
+    }))
+}
+
+/// The path to search under given the requested `path` and the caller's
+/// `scope`, `Some(None)` meaning everywhere. `None` when the two don't
+/// overlap.
+fn narrow_scope(path: Option<&str>, scope: Option<&str>) -> Option<Option<String>> {
+    match (path, scope) {
+        (None, None) => Some(None),
+        (Some(p), None) | (None, Some(p)) => Some(Some(p.to_owned())),
+        (Some(path), Some(scope)) => {
+            if Path::new(path).starts_with(scope) {
+                Some(Some(path.to_owned()))
+            } else if Path::new(scope).starts_with(path) {
+                Some(Some(scope.to_owned()))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Literal strings every match of `pattern` contains. Alternations,
+/// optional parts and classes end a literal, so `foo(bar|baz)+qux` requires
+/// `foo` and `qux`.
+fn required_literals(pattern: &str) -> Vec<Vec<u8>> {
+    fn collect(hir: &Hir, literals: &mut Vec<Vec<u8>>, run: &mut Vec<u8>) {
+        match hir.kind() {
+            HirKind::Literal(literal) => run.extend_from_slice(&literal.0),
+            HirKind::Capture(capture) => collect(&capture.sub, literals, run),
+            HirKind::Concat(subs) => subs.iter().for_each(|sub| collect(sub, literals, run)),
+            // zero-width, the bytes around it are still adjacent
+            HirKind::Look(_) | HirKind::Empty => {}
+            HirKind::Repetition(rep) if rep.min > 0 => {
+                literals.push(std::mem::take(run));
+                collect(&rep.sub, literals, run);
+                literals.push(std::mem::take(run));
+            }
+            _ => literals.push(std::mem::take(run)),
+        }
+    }
+
+    let Ok(hir) = regex_syntax::Parser::new().parse(pattern) else {
+        return vec![];
+    };
+    let mut literals = vec![];
+    let mut run = vec![];
+    collect(&hir, &mut literals, &mut run);
+    literals.push(run);
+    literals.retain(|literal| literal.len() >= 3);
+    literals
+}
+
+/// The distinct trigrams of `data` after ASCII lowercasing, each packed
+/// into the low three bytes of an `i32`.
+fn trigrams(data: &[u8]) -> Vec<i32> {
+    let mut trigrams: Vec<i32> = data
+        .windows(3)
+        .map(|w| {
+            i32::from_be_bytes([
+                0,
+                w[0].to_ascii_lowercase(),
+                w[1].to_ascii_lowercase(),
+                w[2].to_ascii_lowercase(),
+            ])
+        })
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    trigrams.sort_unstable();
+    trigrams
+}
+
+/// Same heuristic as git: a NUL byte near the start means binary.
+fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Language of a file from its name, as accepted by the `lang` filter.
+pub fn language_of(path: &str) -> Option<&'static str> {
+    let name = Path::new(path).file_name()?.to_str()?;
+    match name {
+        "BUCK" | "TARGETS" | "PACKAGE" | "BUILD" | "WORKSPACE" => return Some("starlark"),
+        "Dockerfile" => return Some("dockerfile"),
+        "Makefile" => return Some("makefile"),
+        _ => {}
+    }
+    let language = match PathBuf::from(name).extension()?.to_str()? {
+        "rs" => "rust",
+        "go" => "go",
+        "py" => "python",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "js" | "mjs" | "cjs" | "jsx" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "html" | "htm" => "html",
+        "css" | "scss" => "css",
+        "sh" | "bash" | "zsh" => "shell",
+        "bzl" | "star" => "starlark",
+        "proto" => "protobuf",
+        "sql" => "sql",
+        "md" | "markdown" => "markdown",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "json" => "json",
+        "xml" => "xml",
+        _ => return None,
+    };
+    Some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_literals() {
+        let literals = |pattern: &str| {
+            required_literals(pattern)
+                .into_iter()
+                .map(|l| String::from_utf8(l).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(literals("fn main"), ["fn main"]);
+        assert_eq!(literals(r"foo(bar|baz)+qux"), ["foo", "qux"]);
+        assert_eq!(literals(r"\bstruct\s+Storage"), ["struct", "Storage"]);
+        assert_eq!(literals(r"(token)+ab"), ["token"]);
+        assert!(literals("a.*b").is_empty());
+        assert!(literals("(").is_empty());
+    }
+
+    #[test]
+    fn test_trigrams() {
+        assert_eq!(trigrams(b"ab"), Vec::<i32>::new());
+        assert_eq!(trigrams(b"AbCabc"), trigrams(b"abcabc"));
+        assert_eq!(trigrams(b"abcd").len(), 2);
+        assert_eq!(trigrams(b"abc"), [0x616263]);
+    }
+
+    #[test]
+    fn test_narrow_scope() {
+        assert_eq!(narrow_scope(None, None), Some(None));
+        assert_eq!(
+            narrow_scope(Some("/project/mega/src"), Some("/project/mega")),
+            Some(Some("/project/mega/src".to_owned()))
+        );
+        assert_eq!(
+            narrow_scope(Some("/project"), Some("/project/mega")),
+            Some(Some("/project/mega".to_owned()))
+        );
+        assert_eq!(narrow_scope(Some("/third-party"), Some("/project")), None);
+    }
+
+    #[test]
+    fn test_language_of() {
+        assert_eq!(language_of("/project/src/main.rs"), Some("rust"));
+        assert_eq!(language_of("/project/BUCK"), Some("starlark"));
+        assert_eq!(language_of("/project/LICENSE"), None);
+    }
+}
@@ -0,0 +1,832 @@
+//! Symbol navigation: go-to-definition and find-references per commit.
+//!
+//! Occurrences come from SCIP or LSIF indexes that CI uploads for a commit,
+//! and from a built-in tree-sitter indexer for Rust that runs on every CL
+//! push. The built-in indexer only sees syntax, so it can't tell two items
+//! with the same name apart and uses the bare name as the symbol; an
+//! uploaded index for the same commit replaces its guesses.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use callisto::code_symbols;
+use common::errors::MegaError;
+use common::utils::generate_id;
+use git_internal::internal::object::tree::TreeItemMode;
+use protobuf::Message;
+use scip::types::descriptor::Suffix;
+use serde_json::Value;
+use tree_sitter::{Node, Parser};
+
+use crate::api_service::ApiHandler;
+use crate::api_service::tree_ops;
+use crate::model::symbol::{SymbolIndexFormat, SymbolLocation, SymbolQuery};
+
+pub const SOURCE_SCIP: &str = "scip";
+pub const SOURCE_LSIF: &str = "lsif";
+pub const SOURCE_TREE_SITTER: &str = "tree-sitter";
+
+/// Most Rust files the built-in indexer parses for one directory.
+const MAX_INDEXED_FILES: usize = 2000;
+/// Larger files are not parsed.
+const MAX_FILE_SIZE: usize = 1024 * 1024;
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+/// A definition or reference found by one of the indexers, before it is
+/// tied to a commit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    pub path: String,
+    pub symbol: String,
+    /// Left empty when the index doesn't say; filled in from the source.
+    pub name: String,
+    pub kind: Option<String>,
+    pub is_definition: bool,
+    /// Start line, start character, end line and end character, 0-based.
+    pub range: [i32; 4],
+}
+
+impl Occurrence {
+    fn into_model(self, commit_id: &str, source: &str) -> code_symbols::Model {
+        let now = chrono::Utc::now().naive_utc();
+        code_symbols::Model {
+            created_at: now,
+            updated_at: now,
+            id: generate_id(),
+            commit_id: commit_id.to_owned(),
+            path: self.path,
+            symbol: self.symbol,
+            name: self.name,
+            kind: self.kind,
+            is_definition: self.is_definition,
+            start_line: self.range[0],
+            start_character: self.range[1],
+            end_line: self.range[2],
+            end_character: self.range[3],
+            source: source.to_owned(),
+        }
+    }
+}
+
+/// Resolves the query to symbols and returns where they are defined.
+pub async fn find_definitions<T: ApiHandler + ?Sized>(
+    handler: &T,
+    query: &SymbolQuery,
+    scope: Option<&str>,
+) -> Result<Vec<SymbolLocation>, MegaError> {
+    let symbol_stg = handler.get_context().symbol_storage();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let Some((symbols, name)) = resolve(handler, query, scope).await? else {
+        return Ok(vec![]);
+    };
+    let mut found = symbol_stg
+        .find_occurrences(&query.commit, &symbols, true, limit)
+        .await?;
+    // a reference in an uploaded index may point at a symbol defined in
+    // code the index didn't cover
+    if found.is_empty() {
+        found = symbol_stg
+            .definitions_named(&query.commit, &name, limit)
+            .await?;
+    }
+    Ok(in_scope(found, scope))
+}
+
+/// Resolves the query to symbols and returns where they are referenced.
+pub async fn find_references<T: ApiHandler + ?Sized>(
+    handler: &T,
+    query: &SymbolQuery,
+    scope: Option<&str>,
+) -> Result<Vec<SymbolLocation>, MegaError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let Some((symbols, _)) = resolve(handler, query, scope).await? else {
+        return Ok(vec![]);
+    };
+    let found = handler
+        .get_context()
+        .symbol_storage()
+        .find_occurrences(&query.commit, &symbols, false, limit)
+        .await?;
+    Ok(in_scope(found, scope))
+}
+
+/// The symbols a query is about and their short name. A position resolves
+/// to the occurrence under it, a name to every symbol defined with it.
+async fn resolve<T: ApiHandler + ?Sized>(
+    handler: &T,
+    query: &SymbolQuery,
+    scope: Option<&str>,
+) -> Result<Option<(Vec<String>, String)>, MegaError> {
+    let symbol_stg = handler.get_context().symbol_storage();
+    if let (Some(path), Some(line), Some(character)) = (&query.path, query.line, query.character) {
+        if scope.is_some_and(|scope| !is_under(path, scope)) {
+            return Ok(None);
+        }
+        return Ok(symbol_stg
+            .occurrence_at(&query.commit, path, line, character)
+            .await?
+            .map(|occurrence| (vec![occurrence.symbol], occurrence.name)));
+    }
+    let Some(name) = &query.name else {
+        return Err(MegaError::Other(
+            "[code:400] Either a name or a path, line and character are required".to_string(),
+        ));
+    };
+    let mut symbols: Vec<String> = symbol_stg
+        .definitions_named(&query.commit, name, MAX_LIMIT)
+        .await?
+        .into_iter()
+        .map(|definition| definition.symbol)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    // symbols of the built-in indexer are their names
+    if !symbols.contains(name) {
+        symbols.push(name.clone());
+    }
+    Ok(Some((symbols, name.clone())))
+}
+
+fn in_scope(found: Vec<code_symbols::Model>, scope: Option<&str>) -> Vec<SymbolLocation> {
+    found
+        .into_iter()
+        .filter(|s| scope.is_none_or(|scope| is_under(&s.path, scope)))
+        .map(SymbolLocation::from)
+        .collect()
+}
+
+fn is_under(path: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    dir.is_empty()
+        || path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Stores an uploaded index built from `root` at `commit`, replacing what
+/// was indexed there before. Returns how many occurrences were stored.
+pub async fn upload_index<T: ApiHandler + ?Sized>(
+    handler: &T,
+    commit: &str,
+    root: &str,
+    format: SymbolIndexFormat,
+    data: &[u8],
+) -> Result<usize, MegaError> {
+    let root = normalize_root(root);
+    let (mut occurrences, source) = match format {
+        SymbolIndexFormat::Scip => (parse_scip(data, &root)?, SOURCE_SCIP),
+        SymbolIndexFormat::Lsif => (parse_lsif(data, &root)?, SOURCE_LSIF),
+    };
+    fill_names(handler, commit, &mut occurrences).await;
+    let count = occurrences.len();
+    let models = occurrences
+        .into_iter()
+        .map(|occurrence| occurrence.into_model(commit, source))
+        .collect();
+    handler
+        .get_context()
+        .symbol_storage()
+        .replace_symbols(commit, &root, source, models)
+        .await?;
+    Ok(count)
+}
+
+/// Runs the built-in Rust indexer over `path` at `commit`, returning how
+/// many occurrences were stored.
+pub async fn index_rust<T: ApiHandler + ?Sized>(
+    handler: &T,
+    commit: &str,
+    path: &str,
+) -> Result<usize, MegaError> {
+    let root = normalize_root(path);
+    let Some(tree) = tree_ops::search_tree_by_path(handler, Path::new(&root), Some(commit)).await?
+    else {
+        return Err(MegaError::Other(format!(
+            "[code:404] Directory {root} not found at {commit}"
+        )));
+    };
+
+    let mut sources = vec![];
+    let mut queue = VecDeque::from([(PathBuf::from(&root), tree)]);
+    'walk: while let Some((dir, tree)) = queue.pop_front() {
+        for item in tree.tree_items {
+            let item_path = dir.join(&item.name);
+            match item.mode {
+                TreeItemMode::Tree => {
+                    let subtree = handler.get_tree_by_hash(&item.id.to_string()).await?;
+                    queue.push_back((item_path, subtree));
+                }
+                TreeItemMode::Blob | TreeItemMode::BlobExecutable if item.name.ends_with(".rs") => {
+                    if sources.len() == MAX_INDEXED_FILES {
+                        tracing::warn!(
+                            "Only indexing the first {MAX_INDEXED_FILES} Rust files under {root}"
+                        );
+                        break 'walk;
+                    }
+                    let data = handler.get_raw_blob_by_hash(&item.id.to_string()).await?;
+                    if data.len() > MAX_FILE_SIZE {
+                        continue;
+                    }
+                    let Ok(source) = String::from_utf8(data) else {
+                        continue;
+                    };
+                    sources.push((item_path.to_string_lossy().into_owned(), source));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let (definitions, identifiers) = parse_rust_files(sources).await?;
+    store_rust_symbols(handler, commit, &root, definitions, identifiers, vec![]).await
+}
+
+/// Indexes `path` at `commit` by parsing only the files changed since
+/// `base`, and keeping what the built-in indexer found at `base` for the
+/// rest. Falls back to [`index_rust`] if `base` wasn't indexed.
+///
+/// References in unchanged files are kept as they were, so they miss items
+/// first defined in the changed files.
+pub async fn index_rust_changes<T: ApiHandler + ?Sized>(
+    handler: &T,
+    base: &str,
+    commit: &str,
+    path: &str,
+    changed: &[String],
+) -> Result<usize, MegaError> {
+    let root = normalize_root(path);
+    let previous = handler
+        .get_context()
+        .symbol_storage()
+        .symbols_under(base, &root, SOURCE_TREE_SITTER)
+        .await?;
+    if previous.is_empty() {
+        return index_rust(handler, commit, path).await;
+    }
+
+    let changed: HashSet<String> = changed
+        .iter()
+        .map(|path| normalize_root(path))
+        .filter(|path| is_under(path, &root))
+        .collect();
+    let mut sources = vec![];
+    for path in changed.iter().filter(|path| path.ends_with(".rs")) {
+        // deleted files are gone from the index at `commit`
+        if let Some(source) = read_file(handler, commit, path).await
+            && source.len() <= MAX_FILE_SIZE
+        {
+            sources.push((path.clone(), source));
+        }
+    }
+    let (definitions, identifiers) = parse_rust_files(sources).await?;
+    let kept = previous
+        .into_iter()
+        .filter(|symbol| !changed.contains(&symbol.path))
+        .collect();
+    store_rust_symbols(handler, commit, &root, definitions, identifiers, kept).await
+}
+
+/// Parses Rust sources on the blocking pool, since tree-sitter is CPU bound.
+async fn parse_rust_files(
+    sources: Vec<(String, String)>,
+) -> Result<(Vec<Occurrence>, Vec<Occurrence>), MegaError> {
+    tokio::task::spawn_blocking(move || {
+        let mut definitions = vec![];
+        let mut identifiers = vec![];
+        for (path, source) in sources {
+            let (defs, idents) = rust_occurrences(&path, &source);
+            definitions.extend(defs);
+            identifiers.extend(idents);
+        }
+        (definitions, identifiers)
+    })
+    .await
+    .map_err(|e| MegaError::Other(format!("Rust indexer failed: {e}")))
+}
+
+/// Replaces the symbols under `root` at `commit` with the parsed ones and
+/// the `kept` ones of an earlier commit, returning how many were stored.
+async fn store_rust_symbols<T: ApiHandler + ?Sized>(
+    handler: &T,
+    commit: &str,
+    root: &str,
+    definitions: Vec<Occurrence>,
+    identifiers: Vec<Occurrence>,
+    kept: Vec<code_symbols::Model>,
+) -> Result<usize, MegaError> {
+    // without name resolution, only identifiers naming something defined
+    // here are worth keeping as references
+    let defined: HashSet<&str> = definitions
+        .iter()
+        .map(|d| d.name.as_str())
+        .chain(
+            kept.iter()
+                .filter(|s| s.is_definition)
+                .map(|s| s.name.as_str()),
+        )
+        .collect();
+    let references: Vec<Occurrence> = identifiers
+        .into_iter()
+        .filter(|ident| defined.contains(ident.name.as_str()))
+        .collect();
+    let now = chrono::Utc::now().naive_utc();
+    let kept = kept.into_iter().map(|symbol| code_symbols::Model {
+        id: generate_id(),
+        commit_id: commit.to_owned(),
+        created_at: now,
+        updated_at: now,
+        ..symbol
+    });
+    let models: Vec<_> = definitions
+        .into_iter()
+        .chain(references)
+        .map(|occurrence| occurrence.into_model(commit, SOURCE_TREE_SITTER))
+        .chain(kept)
+        .collect();
+    let count = models.len();
+    handler
+        .get_context()
+        .symbol_storage()
+        .replace_symbols(commit, root, SOURCE_TREE_SITTER, models)
+        .await?;
+    Ok(count)
+}
+
+/// Runs the built-in indexer in the background, so pushes don't wait for
+/// it. With `changes`, a base commit and the files changed since, only
+/// those files are parsed.
+pub fn spawn_index_rust<T: ApiHandler + Clone + 'static>(
+    handler: T,
+    commit: String,
+    path: String,
+    changes: Option<(String, Vec<String>)>,
+) {
+    tokio::spawn(async move {
+        let res = match &changes {
+            Some((base, changed)) => {
+                index_rust_changes(&handler, base, &commit, &path, changed).await
+            }
+            None => index_rust(&handler, &commit, &path).await,
+        };
+        match res {
+            Ok(count) => tracing::debug!("Indexed {count} symbols under {path} at {commit}"),
+            Err(e) => tracing::warn!("Failed to index symbols under {path} at {commit}: {e}"),
+        }
+    });
+}
+
+fn normalize_root(root: &str) -> String {
+    let root = root.trim_end_matches('/');
+    if root.starts_with('/') {
+        root.to_owned()
+    } else {
+        format!("/{root}")
+    }
+}
+
+fn join_root(root: &str, relative: &str) -> String {
+    let relative = relative.trim_start_matches("./").trim_start_matches('/');
+    format!("{}/{relative}", root.trim_end_matches('/'))
+}
+
+/// Occurrences of a SCIP index whose paths are relative to `root`.
+pub fn parse_scip(data: &[u8], root: &str) -> Result<Vec<Occurrence>, MegaError> {
+    let index = scip::types::Index::parse_from_bytes(data)
+        .map_err(|e| MegaError::Other(format!("[code:400] Invalid SCIP index: {e}")))?;
+    let mut occurrences = vec![];
+    for document in index.documents {
+        let path = join_root(root, &document.relative_path);
+        for occurrence in document.occurrences {
+            if occurrence.symbol.is_empty() {
+                continue;
+            }
+            let range = match occurrence.range[..] {
+                [line, start, end] => [line, start, line, end],
+                [start_line, start, end_line, end] => [start_line, start, end_line, end],
+                _ => continue,
+            };
+            // local symbols are only unique within their document
+            let (symbol, name, kind) = if scip::symbol::is_local_symbol(&occurrence.symbol) {
+                (
+                    format!("{} {path}", occurrence.symbol),
+                    String::new(),
+                    Some("local".to_owned()),
+                )
+            } else {
+                let (name, kind) = scip_name(&occurrence.symbol);
+                (occurrence.symbol.clone(), name, kind)
+            };
+            occurrences.push(Occurrence {
+                path: path.clone(),
+                symbol,
+                name,
+                kind,
+                is_definition: occurrence.symbol_roles & scip::types::SymbolRole::Definition as i32
+                    != 0,
+                range,
+            });
+        }
+    }
+    Ok(occurrences)
+}
+
+/// Short name and kind from the last descriptor of a global SCIP symbol.
+fn scip_name(symbol: &str) -> (String, Option<String>) {
+    let Some(descriptor) = scip::symbol::parse_symbol(symbol)
+        .ok()
+        .and_then(|parsed| parsed.descriptors.into_iter().last())
+    else {
+        return (String::new(), None);
+    };
+    let kind = match descriptor.suffix.enum_value_or_default() {
+        Suffix::Namespace | Suffix::Package => Some("module"),
+        Suffix::Type => Some("type"),
+        Suffix::Term => Some("term"),
+        Suffix::Method => Some("method"),
+        Suffix::Macro => Some("macro"),
+        Suffix::TypeParameter => Some("type_parameter"),
+        Suffix::Parameter => Some("parameter"),
+        _ => None,
+    };
+    (descriptor.name, kind.map(str::to_owned))
+}
+
+/// Occurrences of an LSIF dump, either JSON lines or a JSON array. Paths are
+/// made relative to the dump's project root, or taken as relative to `root`
+/// if they aren't under it.
+pub fn parse_lsif(data: &[u8], root: &str) -> Result<Vec<Occurrence>, MegaError> {
+    let invalid = |e: serde_json::Error| MegaError::Other(format!("[code:400] Invalid LSIF: {e}"));
+    let entries: Vec<Value> = if data.trim_ascii_start().starts_with(b"[") {
+        serde_json::from_slice(data).map_err(invalid)?
+    } else {
+        data.split(|&b| b == b'\n')
+            .filter(|line| !line.trim_ascii().is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?
+    };
+
+    let id = |value: &Value| match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let mut project_root = String::new();
+    let mut documents = HashMap::new();
+    let mut ranges = HashMap::new();
+    let mut monikers = HashMap::new();
+    let mut next = HashMap::new();
+    let mut moniker_of = HashMap::new();
+    let mut definition_results = HashSet::new();
+    let mut range_document = HashMap::new();
+    let mut definition_ranges = HashSet::new();
+    let mut items = vec![];
+    for entry in &entries {
+        let label = entry["label"].as_str().unwrap_or_default();
+        let entry_id = id(&entry["id"]);
+        let out_v = id(&entry["outV"]);
+        let in_vs: Vec<String> = match &entry["inVs"] {
+            Value::Array(ids) => ids.iter().map(id).collect(),
+            _ if !entry["inV"].is_null() => vec![id(&entry["inV"])],
+            _ => vec![],
+        };
+        match (entry["type"].as_str(), label) {
+            (Some("vertex"), "metaData") => {
+                project_root = entry["projectRoot"].as_str().unwrap_or_default().to_owned();
+            }
+            (Some("vertex"), "document") => {
+                documents.insert(entry_id, entry["uri"].as_str().unwrap_or_default());
+            }
+            (Some("vertex"), "range") => {
+                let position = |p: &Value| {
+                    [
+                        p["line"].as_i64().unwrap_or_default() as i32,
+                        p["character"].as_i64().unwrap_or_default() as i32,
+                    ]
+                };
+                let [start_line, start] = position(&entry["start"]);
+                let [end_line, end] = position(&entry["end"]);
+                let name = entry["tag"]["text"].as_str().unwrap_or_default();
+                ranges.insert(entry_id, ([start_line, start, end_line, end], name));
+            }
+            (Some("vertex"), "moniker") => {
+                monikers.insert(entry_id, entry["identifier"].as_str().unwrap_or_default());
+            }
+            (Some("edge"), "next") => {
+                next.extend(in_vs.into_iter().next().map(|set| (out_v, set)));
+            }
+            (Some("edge"), "moniker") => {
+                moniker_of.extend(in_vs.into_iter().next().map(|m| (out_v, m)));
+            }
+            (Some("edge"), "textDocument/definition") => definition_results.extend(in_vs),
+            (Some("edge"), "contains") => {
+                range_document.extend(in_vs.into_iter().map(|range| (range, out_v.clone())));
+            }
+            (Some("edge"), "item") => {
+                if entry["property"].as_str() == Some("definitions") {
+                    definition_ranges.extend(in_vs.iter().cloned());
+                }
+                items.push((out_v, in_vs, id(&entry["document"])));
+            }
+            _ => {}
+        }
+    }
+    for (result, in_vs, document) in items {
+        if definition_results.contains(&result) {
+            definition_ranges.extend(in_vs.iter().cloned());
+        }
+        for range in in_vs {
+            range_document
+                .entry(range)
+                .or_insert_with(|| document.clone());
+        }
+    }
+
+    let project_root = project_root.trim_end_matches('/');
+    let mut occurrences = vec![];
+    for (range_id, (range, tag_name)) in ranges {
+        let Some(uri) = range_document
+            .get(&range_id)
+            .and_then(|doc| documents.get(doc))
+        else {
+            continue;
+        };
+        let relative = match uri.strip_prefix(project_root) {
+            Some(rest) if !project_root.is_empty() => rest,
+            _ => uri.strip_prefix("file://").unwrap_or(uri),
+        };
+        // follow the chain of result sets, the last one is the symbol
+        let mut set = range_id.clone();
+        let mut moniker = moniker_of.get(&set);
+        for _ in 0..16 {
+            let Some(following) = next.get(&set) else {
+                break;
+            };
+            set = following.clone();
+            moniker = moniker.or(moniker_of.get(&set));
+        }
+        let identifier = moniker.and_then(|m| monikers.get(m)).copied();
+        let symbol = match identifier {
+            Some(identifier) if !identifier.is_empty() => identifier.to_owned(),
+            // without a moniker a result set is only unique within the dump
+            _ => format!("lsif {set} {root}"),
+        };
+        occurrences.push(Occurrence {
+            path: join_root(root, relative),
+            symbol,
+            name: tag_name.to_owned(),
+            kind: None,
+            is_definition: definition_ranges.contains(&range_id),
+            range,
+        });
+    }
+    occurrences.sort_by(|a, b| (&a.path, a.range).cmp(&(&b.path, b.range)));
+    Ok(occurrences)
+}
+
+/// Reads the names the index left out from the source at `commit`.
+async fn fill_names<T: ApiHandler + ?Sized>(
+    handler: &T,
+    commit: &str,
+    occurrences: &mut [Occurrence],
+) {
+    let paths: HashSet<String> = occurrences
+        .iter()
+        .filter(|o| o.name.is_empty())
+        .map(|o| o.path.clone())
+        .collect();
+    let mut sources = HashMap::new();
+    for path in paths {
+        if let Some(source) = read_file(handler, commit, &path).await {
+            sources.insert(path, source);
+        }
+    }
+    for occurrence in occurrences.iter_mut().filter(|o| o.name.is_empty()) {
+        let [line, start, end_line, end] = occurrence.range;
+        occurrence.name = sources
+            .get(&occurrence.path)
+            .and_then(|source| source.lines().nth(line as usize))
+            .filter(|_| end_line == line)
+            .map(|text| {
+                text.chars()
+                    .skip(start as usize)
+                    .take((end - start).max(0) as usize)
+                    .collect()
+            })
+            .unwrap_or_else(|| occurrence.symbol.clone());
+    }
+}
+
+async fn read_file<T: ApiHandler + ?Sized>(
+    handler: &T,
+    commit: &str,
+    path: &str,
+) -> Option<String> {
+    let path = Path::new(path);
+    let name = path.file_name()?.to_str()?;
+    let tree = tree_ops::search_tree_by_path(handler, path.parent()?, Some(commit))
+        .await
+        .ok()??;
+    let item = tree.tree_items.into_iter().find(|item| item.name == name)?;
+    let data = handler
+        .get_raw_blob_by_hash(&item.id.to_string())
+        .await
+        .ok()?;
+    String::from_utf8(data).ok()
+}
+
+/// Definitions in a Rust file, and every identifier that may refer to one.
+pub fn rust_occurrences(path: &str, source: &str) -> (Vec<Occurrence>, Vec<Occurrence>) {
+    let mut parser = Parser::new();
+    if parser
+        .set_language(&tree_sitter_rust::LANGUAGE.into())
+        .is_err()
+    {
+        return (vec![], vec![]);
+    }
+    let Some(tree) = parser.parse(source, None) else {
+        return (vec![], vec![]);
+    };
+
+    let occurrence = |node: Node, kind: Option<&str>| {
+        let name = node.utf8_text(source.as_bytes()).unwrap_or_default();
+        let (start, end) = (node.start_position(), node.end_position());
+        Occurrence {
+            path: path.to_owned(),
+            symbol: name.to_owned(),
+            name: name.to_owned(),
+            kind: kind.map(str::to_owned),
+            is_definition: kind.is_some(),
+            range: [
+                start.row as i32,
+                start.column as i32,
+                end.row as i32,
+                end.column as i32,
+            ],
+        }
+    };
+    let mut definitions = vec![];
+    let mut identifiers = vec![];
+    let mut names = HashSet::new();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if let Some(kind) = definition_kind(node)
+            && let Some(name) = node.child_by_field_name("name")
+        {
+            names.insert(name.id());
+            definitions.push(occurrence(name, Some(kind)));
+        } else if matches!(
+            node.kind(),
+            "identifier" | "type_identifier" | "field_identifier"
+        ) && !names.contains(&node.id())
+        {
+            identifiers.push(occurrence(node, None));
+        }
+
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return (definitions, identifiers);
+            }
+        }
+    }
+}
+
+fn definition_kind(node: Node) -> Option<&'static str> {
+    Some(match node.kind() {
+        "function_item" | "function_signature_item" => {
+            let in_impl = node
+                .parent()
+                .filter(|parent| parent.kind() == "declaration_list")
+                .and_then(|list| list.parent())
+                .is_some_and(|owner| matches!(owner.kind(), "impl_item" | "trait_item"));
+            if in_impl { "method" } else { "function" }
+        }
+        "struct_item" => "struct",
+        "enum_item" => "enum",
+        "union_item" => "union",
+        "trait_item" => "trait",
+        "type_item" | "associated_type" => "type",
+        "const_item" => "const",
+        "static_item" => "static",
+        "mod_item" => "module",
+        "macro_definition" => "macro",
+        "enum_variant" => "variant",
+        "field_declaration" => "field",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use protobuf::Message;
+    use scip::types::{Document, Index, Occurrence as ScipOccurrence};
+
+    use super::{parse_lsif, parse_scip, rust_occurrences};
+
+    #[test]
+    fn test_rust_occurrences() {
+        let source = "struct Config { name: String }\n\
+                      impl Config {\n    fn parse() -> Config { todo!() }\n}\n\
+                      fn main() { let c = Config::parse(); }\n";
+        let (definitions, identifiers) = rust_occurrences("/p/src/main.rs", source);
+        let defs: Vec<_> = definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.kind.as_deref().unwrap(), d.range))
+            .collect();
+        assert_eq!(
+            defs,
+            [
+                ("Config", "struct", [0, 7, 0, 13]),
+                ("name", "field", [0, 16, 0, 20]),
+                ("parse", "method", [2, 7, 2, 12]),
+                ("main", "function", [4, 3, 4, 7]),
+            ]
+        );
+        let refs: Vec<_> = identifiers
+            .iter()
+            .filter(|i| i.name == "Config" || i.name == "parse")
+            .map(|i| (i.name.as_str(), i.range[0]))
+            .collect();
+        assert_eq!(
+            refs,
+            [("Config", 1), ("Config", 2), ("Config", 4), ("parse", 4)]
+        );
+        assert!(identifiers.iter().all(|i| !i.is_definition));
+    }
+
+    #[test]
+    fn test_parse_scip() {
+        let mut index = Index::new();
+        let mut document = Document::new();
+        document.relative_path = "src/lib.rs".to_owned();
+        for (symbol, range, roles) in [
+            (
+                "rust-analyzer cargo demo 0.1.0 config/parse().",
+                vec![3, 7, 12],
+                1,
+            ),
+            (
+                "rust-analyzer cargo demo 0.1.0 Config#",
+                vec![5, 0, 6, 1],
+                0,
+            ),
+            ("local 4", vec![8, 4, 5], 1),
+        ] {
+            let mut occurrence = ScipOccurrence::new();
+            occurrence.symbol = symbol.to_owned();
+            occurrence.range = range;
+            occurrence.symbol_roles = roles;
+            document.occurrences.push(occurrence);
+        }
+        index.documents.push(document);
+        let data = index.write_to_bytes().unwrap();
+
+        let occurrences = parse_scip(&data, "/project").unwrap();
+        assert_eq!(occurrences.len(), 3);
+        assert_eq!(occurrences[0].path, "/project/src/lib.rs");
+        assert_eq!(occurrences[0].name, "parse");
+        assert_eq!(occurrences[0].kind.as_deref(), Some("method"));
+        assert!(occurrences[0].is_definition);
+        assert_eq!(occurrences[0].range, [3, 7, 3, 12]);
+        assert_eq!(occurrences[1].name, "Config");
+        assert_eq!(occurrences[1].range, [5, 0, 6, 1]);
+        assert!(!occurrences[1].is_definition);
+        assert_eq!(occurrences[2].symbol, "local 4 /project/src/lib.rs");
+        assert!(parse_scip(b"\xff\xff", "/project").is_err());
+    }
+
+    #[test]
+    fn test_parse_lsif() {
+        let dump = r#"
+{"id":1,"type":"vertex","label":"metaData","projectRoot":"file:///work/demo"}
+{"id":2,"type":"vertex","label":"document","uri":"file:///work/demo/src/lib.rs"}
+{"id":3,"type":"vertex","label":"range","start":{"line":0,"character":3},"end":{"line":0,"character":8},"tag":{"type":"definition","text":"parse"}}
+{"id":4,"type":"vertex","label":"range","start":{"line":5,"character":4},"end":{"line":5,"character":9}}
+{"id":5,"type":"vertex","label":"resultSet"}
+{"id":6,"type":"edge","label":"next","outV":3,"inV":5}
+{"id":7,"type":"edge","label":"next","outV":4,"inV":5}
+{"id":8,"type":"vertex","label":"definitionResult"}
+{"id":9,"type":"edge","label":"textDocument/definition","outV":5,"inV":8}
+{"id":10,"type":"edge","label":"item","outV":8,"inVs":[3],"document":2}
+{"id":11,"type":"edge","label":"contains","outV":2,"inVs":[3,4]}
+{"id":12,"type":"vertex","label":"moniker","identifier":"demo::parse","scheme":"rust"}
+{"id":13,"type":"edge","label":"moniker","outV":5,"inV":12}
+"#;
+        let occurrences = parse_lsif(dump.as_bytes(), "/project").unwrap();
+        assert_eq!(occurrences.len(), 2);
+        assert_eq!(occurrences[0].path, "/project/src/lib.rs");
+        assert_eq!(occurrences[0].symbol, "demo::parse");
+        assert_eq!(occurrences[0].name, "parse");
+        assert!(occurrences[0].is_definition);
+        assert_eq!(occurrences[1].symbol, "demo::parse");
+        assert_eq!(occurrences[1].range, [5, 4, 5, 9]);
+        // the name of an untagged range is read from the source later
+        assert!(occurrences[1].name.is_empty());
+        assert!(!occurrences[1].is_definition);
+    }
+}
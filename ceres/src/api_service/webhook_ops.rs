@@ -0,0 +1,305 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{StreamExt, stream};
+use redis::aio::ConnectionManager;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, Url, redirect};
+use ring::hmac;
+
+use callisto::sea_orm_active_enums::DeliveryStatusEnum;
+use callisto::webhook_delivery;
+use common::errors::MegaError;
+use jupiter::redis::delay_queue::DelayQueue;
+use jupiter::storage::Storage;
+
+const QUEUE_KEY: &str = "webhook:deliveries";
+
+/// Attempts before a delivery is given up.
+const MAX_ATTEMPTS: i32 = 6;
+
+/// Delay before the first retry, doubled for each further one.
+const RETRY_BASE_SECS: i64 = 30;
+
+/// How long a queued delivery may be overdue before it is assumed lost
+/// from Redis and queued again.
+const STALE_SECS: i64 = 300;
+
+const BATCH_SIZE: u64 = 100;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Deliveries sent at the same time, so a slow endpoint doesn't hold up
+/// the rest of the batch.
+const MAX_CONCURRENT_DELIVERIES: usize = 16;
+
+/// Sends recorded webhook deliveries.
+///
+/// The database is the source of truth for deliveries, Redis only orders
+/// the attempts. Deliveries recorded by `WebhookStorage::emit` are moved
+/// onto the queue on each tick, so nothing is lost if Redis is flushed.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    storage: Storage,
+    queue: DelayQueue,
+    client: Client,
+}
+
+impl WebhookDispatcher {
+    pub fn new(storage: Storage, connection: ConnectionManager) -> Self {
+        Self {
+            storage,
+            queue: DelayQueue::new(connection, QUEUE_KEY),
+            // Connections only go to addresses the resolver checked, so a host
+            // can't pass `check_webhook_url` and then rebind to an internal
+            // address. A proxy would resolve the host itself.
+            client: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .redirect(redirect::Policy::none())
+                .dns_resolver(Arc::new(PublicResolver))
+                .no_proxy()
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub async fn tick(&self) -> Result<(), MegaError> {
+        let now = chrono::Utc::now();
+        let webhook_stg = self.storage.webhook_storage();
+
+        let stale_before = (now - chrono::Duration::seconds(STALE_SECS)).naive_utc();
+        for delivery in webhook_stg
+            .unscheduled_deliveries(stale_before, BATCH_SIZE)
+            .await?
+        {
+            self.queue.schedule(delivery.id, now.timestamp()).await?;
+            webhook_stg.mark_queued(delivery, now.naive_utc()).await?;
+        }
+
+        let due = self
+            .queue
+            .claim_due(now.timestamp(), BATCH_SIZE as isize)
+            .await?;
+        stream::iter(due)
+            .map(|id| self.deliver_claimed(id))
+            .buffer_unordered(MAX_CONCURRENT_DELIVERIES)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    async fn deliver_claimed(&self, id: i64) -> Result<(), MegaError> {
+        match self.storage.webhook_storage().get_delivery(id).await? {
+            Some(delivery) if delivery.status == DeliveryStatusEnum::Queued => {
+                self.deliver(delivery).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn deliver(&self, delivery: webhook_delivery::Model) -> Result<(), MegaError> {
+        let webhook_stg = self.storage.webhook_storage();
+        let hook = match webhook_stg.get_webhook(delivery.webhook_id).await? {
+            Some(hook) if hook.active => hook,
+            _ => {
+                return webhook_stg
+                    .record_failure(delivery, None, "Webhook is inactive".to_owned(), None)
+                    .await;
+            }
+        };
+
+        // The host may resolve elsewhere by now than when the webhook was
+        // created.
+        if let Err(e) = check_webhook_url(&hook.url).await {
+            return webhook_stg
+                .record_failure(delivery, None, e.to_string(), None)
+                .await;
+        }
+
+        let response = self
+            .client
+            .post(&hook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Mega-Event", &delivery.event)
+            .header("X-Mega-Delivery", delivery.id.to_string())
+            .header(
+                "X-Mega-Signature",
+                sign_payload(&hook.secret, &delivery.payload),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let (status, error) = match response {
+            Ok(res) if res.status().is_success() => {
+                return webhook_stg
+                    .record_success(delivery, res.status().as_u16() as i32)
+                    .await;
+            }
+            Ok(res) => (
+                Some(res.status().as_u16() as i32),
+                format!("Endpoint responded with {}", res.status()),
+            ),
+            Err(e) => (None, e.to_string()),
+        };
+
+        let id = delivery.id;
+        let retry_at = retry_delay(delivery.attempts + 1)
+            .map(|delay| chrono::Utc::now() + chrono::Duration::seconds(delay));
+        webhook_stg
+            .record_failure(delivery, status, error, retry_at.map(|at| at.naive_utc()))
+            .await?;
+        if let Some(at) = retry_at {
+            self.queue.schedule(id, at.timestamp()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a webhook URL is http(s) and that its host only resolves to
+/// public addresses, so webhooks can't be used to reach internal services.
+pub async fn check_webhook_url(url: &str) -> Result<(), MegaError> {
+    let invalid = |reason: &str| MegaError::Other(format!("Invalid webhook url {url}: {reason}"));
+    let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(invalid("only http and https are supported"));
+    }
+    let host = parsed.host_str().ok_or_else(|| invalid("missing host"))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| invalid("missing port"))?;
+    public_addrs(host.trim_matches(['[', ']']), port)
+        .await
+        .map_err(|reason| invalid(&reason))?;
+    Ok(())
+}
+
+/// Resolves `host`, failing unless every address it has is public.
+async fn public_addrs(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<_> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| e.to_string())?
+        .collect();
+    if addrs.is_empty() {
+        return Err("host does not resolve".to_owned());
+    }
+    if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err("host is not a public address".to_owned());
+    }
+    Ok(addrs)
+}
+
+/// DNS resolver of the delivery client, which refuses hosts with
+/// non-public addresses like `check_webhook_url` does.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        Box::pin(async move {
+            let addrs = public_addrs(&host, 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Shared address space, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7
+                    || (first & 0xfe00) == 0xfc00
+                    // Link local, fe80::/10
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// The `X-Mega-Signature` header: `sha256=` and the hex HMAC of the body
+/// keyed with the webhook secret.
+pub fn sign_payload(secret: &str, payload: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, payload.as_bytes());
+    format!("sha256={}", hex::encode(tag.as_ref()))
+}
+
+/// Seconds to wait after the `attempts`th failed attempt, or `None` once
+/// the delivery should be given up.
+fn retry_delay(attempts: i32) -> Option<i64> {
+    (attempts < MAX_ATTEMPTS).then(|| RETRY_BASE_SECS << (attempts - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_payload() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_payload("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        assert!(is_public("93.184.216.34".parse().unwrap()));
+        assert!(is_public("2606:4700::1111".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_check_webhook_url() {
+        assert!(check_webhook_url("ftp://example.com/hook").await.is_err());
+        assert!(
+            check_webhook_url("http://127.0.0.1:8000/hook")
+                .await
+                .is_err()
+        );
+        assert!(check_webhook_url("http://[::1]/hook").await.is_err());
+        assert!(check_webhook_url("http://localhost/hook").await.is_err());
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Some(30));
+        assert_eq!(retry_delay(2), Some(60));
+        assert_eq!(retry_delay(5), Some(480));
+        assert_eq!(retry_delay(MAX_ATTEMPTS), None);
+    }
+}
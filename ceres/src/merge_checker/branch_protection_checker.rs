@@ -0,0 +1,390 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use callisto::branch_protection_rules;
+use common::errors::MegaError;
+use git_internal::hash::ObjectHash;
+use git_internal::internal::object::tree::Tree;
+use jupiter::utils::converter::FromMegaModel;
+use jupiter::{model::cl_dto::ClInfoDto, storage::Storage};
+
+use crate::merge_checker::merge_conflict_checker::TreeMerge;
+use crate::merge_checker::{CheckResult, CheckType, Checker, ConditionResult};
+use crate::pack::RepoHandler;
+use crate::protocol::import_refs::{CommandType, RefCommand};
+
+pub struct BranchProtectionChecker {
+    pub storage: Arc<Storage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BranchProtectionParams {
+    path: String,
+    cl_link: String,
+    author: String,
+    from_hash: String,
+    to_hash: String,
+}
+
+impl BranchProtectionParams {
+    fn from_value(v: &serde_json::Value) -> anyhow::Result<Self> {
+        Ok(serde_json::from_value(v.clone())?)
+    }
+}
+
+#[async_trait]
+impl Checker for BranchProtectionChecker {
+    async fn run(&self, params: &Value) -> Result<CheckResult, MegaError> {
+        let params = BranchProtectionParams::from_value(params)?;
+        let mut res = CheckResult {
+            check_type_code: CheckType::BranchProtection,
+            status: ConditionResult::FAILED,
+            message: String::new(),
+        };
+
+        let rules = BranchProtection::rules_for_cl(
+            &self.storage,
+            &params.path,
+            &params.from_hash,
+            &params.to_hash,
+        )
+        .await?;
+        if rules.is_empty() {
+            res.status = ConditionResult::SKIPPED;
+            res.message = String::from("No branch protection rule applies to this path.");
+            return Ok(res);
+        }
+
+        let approvals =
+            BranchProtection::count_approvals(&self.storage, &params.cl_link, &params.author)
+                .await?;
+        let failures: Vec<String> = rules
+            .iter()
+            .filter_map(|rule| BranchProtection::check_approvals(rule, approvals).err())
+            .collect();
+        if failures.is_empty() {
+            let paths: Vec<&str> = rules.iter().map(|r| r.path.as_str()).collect();
+            res.status = ConditionResult::PASSED;
+            res.message = format!(
+                "Branch protection rules for {} are satisfied.",
+                paths.join(", ")
+            );
+        } else {
+            res.message = failures.join("\n");
+        }
+        Ok(res)
+    }
+
+    async fn build_params(&self, cl_info: &ClInfoDto) -> Result<Value, MegaError> {
+        Ok(serde_json::json!({
+            "path": cl_info.path,
+            "cl_link": cl_info.link,
+            "author": cl_info.username,
+            "from_hash": cl_info.from_hash,
+            "to_hash": cl_info.to_hash,
+        }))
+    }
+}
+
+/// Enforces the path-scoped rules stored in `branch_protection_rules`.
+///
+/// A change has to satisfy the rule with the longest path covering its
+/// target, and the rule of every protected path below the target whose
+/// content it changes. Paths without a rule are unprotected.
+pub struct BranchProtection;
+
+impl BranchProtection {
+    /// Checks that a CL has collected the approvals its rules require.
+    ///
+    /// Approvals given by the CL author are not counted. Returns the
+    /// applied rules.
+    pub async fn verify_merge(
+        storage: &Storage,
+        path: &str,
+        from_hash: &str,
+        to_hash: &str,
+        cl_link: &str,
+        author: &str,
+    ) -> Result<Vec<branch_protection_rules::Model>, MegaError> {
+        let rules = Self::rules_for_cl(storage, path, from_hash, to_hash).await?;
+        if rules.is_empty() {
+            return Ok(rules);
+        }
+
+        let approvals = Self::count_approvals(storage, cl_link, author).await?;
+        for rule in &rules {
+            Self::check_approvals(rule, approvals).map_err(MegaError::Other)?;
+        }
+        Ok(rules)
+    }
+
+    /// The rules a CL at `path` from `from_hash` to `to_hash` has to satisfy.
+    pub async fn rules_for_cl(
+        storage: &Storage,
+        path: &str,
+        from_hash: &str,
+        to_hash: &str,
+    ) -> Result<Vec<branch_protection_rules::Model>, MegaError> {
+        let mono = storage.mono_storage();
+        let load_tree = |id: ObjectHash| {
+            let mono = mono.clone();
+            async move {
+                mono.get_tree_by_hash(&id.to_string())
+                    .await?
+                    .map(Tree::from_mega_model)
+                    .ok_or_else(|| MegaError::Other(format!("Tree not found: {id}")))
+            }
+        };
+        let old = TreeMerge::root_tree(&mono, from_hash).await?;
+        let new = TreeMerge::root_tree(&mono, to_hash).await?;
+        Self::rules_for_change(storage, path, Some(old), Some(new), load_tree).await
+    }
+
+    /// The rules a ref update at `path` from `old_id` to `new_id` has to
+    /// satisfy. A zero id stands for a ref that is created or deleted.
+    pub async fn rules_for_ref_update(
+        storage: &Storage,
+        path: &str,
+        old_id: &str,
+        new_id: &str,
+        repo_handler: Arc<dyn RepoHandler>,
+    ) -> Result<Vec<branch_protection_rules::Model>, MegaError> {
+        let mut roots = [None, None];
+        for (root, id) in roots.iter_mut().zip([old_id, new_id]) {
+            if id.chars().all(|c| c == '0') {
+                continue;
+            }
+            let commit = repo_handler
+                .get_commit(id)
+                .await?
+                .ok_or_else(|| MegaError::Other(format!("Commit not found: {id}")))?;
+            *root = Some(commit.tree_id);
+        }
+        let load_tree = |id: ObjectHash| {
+            let repo_handler = repo_handler.clone();
+            async move {
+                repo_handler
+                    .get_trees_by_hashes(vec![id.to_string()])
+                    .await?
+                    .pop()
+                    .ok_or_else(|| MegaError::Other(format!("Tree not found: {id}")))
+            }
+        };
+        let [old, new] = roots;
+        Self::rules_for_change(storage, path, old, new, load_tree).await
+    }
+
+    /// The nearest rule covering `path`, followed by the rules of protected
+    /// paths below it whose subtree differs between the `old` and `new` root
+    /// trees of `path`.
+    async fn rules_for_change<F, Fut>(
+        storage: &Storage,
+        path: &str,
+        old: Option<ObjectHash>,
+        new: Option<ObjectHash>,
+        load_tree: F,
+    ) -> Result<Vec<branch_protection_rules::Model>, MegaError>
+    where
+        F: Fn(ObjectHash) -> Fut,
+        Fut: Future<Output = Result<Tree, MegaError>>,
+    {
+        let cl_stg = storage.cl_storage();
+        let mut rules: Vec<_> = cl_stg
+            .get_branch_protection_rule(path)
+            .await?
+            .into_iter()
+            .collect();
+        for rule in cl_stg.get_branch_protection_rules_below(path).await? {
+            let Ok(rel) = Path::new(&rule.path).strip_prefix(path) else {
+                continue;
+            };
+            let old_id = Self::subtree_id(&load_tree, old, rel).await?;
+            let new_id = Self::subtree_id(&load_tree, new, rel).await?;
+            if old_id != new_id {
+                rules.push(rule);
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Id of the entry at `rel` below the tree `root`, if there is one.
+    async fn subtree_id<F, Fut>(
+        load_tree: &F,
+        root: Option<ObjectHash>,
+        rel: &Path,
+    ) -> Result<Option<ObjectHash>, MegaError>
+    where
+        F: Fn(ObjectHash) -> Fut,
+        Fut: Future<Output = Result<Tree, MegaError>>,
+    {
+        let mut id = root;
+        for name in rel.iter() {
+            let Some(tree_id) = id else {
+                break;
+            };
+            id = load_tree(tree_id)
+                .await?
+                .tree_items
+                .into_iter()
+                .find(|item| item.is_tree() && name.to_str() == Some(item.name.as_str()))
+                .map(|item| item.id);
+        }
+        Ok(id)
+    }
+
+    /// Counts approvals on a CL, excluding the author's own.
+    async fn count_approvals(
+        storage: &Storage,
+        cl_link: &str,
+        author: &str,
+    ) -> Result<usize, MegaError> {
+        Ok(storage
+            .reviewer_storage()
+            .list_reviewers(cl_link)
+            .await?
+            .iter()
+            .filter(|r| r.approved && r.username != author)
+            .count())
+    }
+
+    /// Checks a single ref update received by `git-receive-pack`.
+    ///
+    /// `is_monorepo` pushes always open or update a CL, so only import
+    /// repositories can update a protected branch directly.
+    pub async fn verify_ref_update(
+        rule: &branch_protection_rules::Model,
+        username: &str,
+        is_monorepo: bool,
+        command: &RefCommand,
+        repo_handler: &dyn RepoHandler,
+    ) -> Result<(), String> {
+        if command.command_type == CommandType::Delete {
+            if !rule.allow_deletion {
+                return Err(format!(
+                    "deleting {} is not allowed by the branch protection rule of {}",
+                    command.ref_name, rule.path
+                ));
+            }
+            return Ok(());
+        }
+
+        if command.command_type == CommandType::Update && !rule.allow_force_push {
+            let fast_forward = repo_handler
+                .is_fast_forward(&command.old_id, &command.new_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            if !fast_forward {
+                return Err(format!(
+                    "force-push to {} is not allowed by the branch protection rule of {}",
+                    command.ref_name, rule.path
+                ));
+            }
+        }
+
+        if !is_monorepo && !rule.allows_direct_push(username) {
+            return Err(format!(
+                "{username} is not allowed to push directly to {}, changes must go through a CL",
+                rule.path
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_approvals(
+        rule: &branch_protection_rules::Model,
+        approvals: usize,
+    ) -> Result<(), String> {
+        let required = usize::try_from(rule.min_approvals).unwrap_or(0);
+        if approvals < required {
+            return Err(format!(
+                "{approvals} of {required} required approvals for {}.",
+                rule.path
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::PathBuf;
+
+    use git_internal::internal::object::tree::TreeItemMode;
+    use git_internal::internal::object::types::ObjectType;
+
+    use super::*;
+    use crate::merge_checker::merge_conflict_checker::MergeEntry;
+
+    fn build(files: &[(&str, &str)]) -> (ObjectHash, HashMap<ObjectHash, Tree>) {
+        let entries: BTreeMap<PathBuf, MergeEntry> = files
+            .iter()
+            .map(|(path, content)| {
+                let entry = MergeEntry {
+                    id: ObjectHash::from_type_and_data(ObjectType::Blob, content.as_bytes()),
+                    mode: TreeItemMode::Blob,
+                };
+                (PathBuf::from(path), entry)
+            })
+            .collect();
+        let trees = TreeMerge::build_trees(&entries).unwrap();
+        let root = trees.last().unwrap().id;
+        (root, trees.into_iter().map(|t| (t.id, t)).collect())
+    }
+
+    #[tokio::test]
+    async fn test_subtree_id() {
+        let (old, mut trees) = build(&[("project/a.rs", "a"), ("doc/x.md", "x")]);
+        let (new, new_trees) = build(&[("project/a.rs", "a2"), ("doc/x.md", "x")]);
+        trees.extend(new_trees);
+        let load_tree = |id: ObjectHash| {
+            let tree = trees.get(&id).cloned();
+            async move { tree.ok_or_else(|| MegaError::Other(format!("Tree not found: {id}"))) }
+        };
+
+        let subtree = |root, rel: &'static str| {
+            BranchProtection::subtree_id(&load_tree, root, Path::new(rel))
+        };
+        assert_ne!(
+            subtree(Some(old), "project").await.unwrap(),
+            subtree(Some(new), "project").await.unwrap()
+        );
+        assert_eq!(
+            subtree(Some(old), "doc").await.unwrap(),
+            subtree(Some(new), "doc").await.unwrap()
+        );
+        assert_eq!(subtree(Some(old), "missing/dir").await.unwrap(), None);
+        assert_eq!(subtree(None, "doc").await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_check_approvals() {
+        let rule = branch_protection_rules::Model::new("/project", vec![], true, 2, false, false);
+        assert!(BranchProtection::check_approvals(&rule, 2).is_ok());
+        assert_eq!(
+            BranchProtection::check_approvals(&rule, 1).unwrap_err(),
+            "1 of 2 required approvals for /project."
+        );
+    }
+
+    #[test]
+    fn test_allows_direct_push() {
+        let rule = branch_protection_rules::Model::new(
+            "/third-party",
+            vec![String::from("release-bot")],
+            true,
+            0,
+            false,
+            false,
+        );
+        assert!(rule.allows_direct_push("release-bot"));
+        assert!(!rule.allows_direct_push("alice"));
+
+        let open = branch_protection_rules::Model::new("/doc", vec![], false, 0, false, false);
+        assert!(open.allows_direct_push("alice"));
+    }
+}
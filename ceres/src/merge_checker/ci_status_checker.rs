@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use callisto::builds;
+use common::errors::MegaError;
+use jupiter::{model::cl_dto::ClInfoDto, storage::Storage};
+
+use crate::merge_checker::{CheckResult, CheckType, Checker, ConditionResult};
+
+/// Reports the Orion build status of the CL's current revision.
+///
+/// Orion stores the `to_hash` a task was triggered for, so builds of
+/// earlier pushes to the same CL are ignored. Skipped when Orion builds are
+/// disabled, since no build will ever be triggered.
+pub struct CiStatusChecker {
+    pub storage: Arc<Storage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CiStatusParams {
+    cl_id: i64,
+    commit_id: String,
+}
+
+impl CiStatusParams {
+    fn from_value(v: &serde_json::Value) -> anyhow::Result<Self> {
+        Ok(serde_json::from_value(v.clone())?)
+    }
+}
+
+#[async_trait]
+impl Checker for CiStatusChecker {
+    async fn run(&self, params: &Value) -> Result<CheckResult, MegaError> {
+        let params = CiStatusParams::from_value(params)?;
+        let mut res = CheckResult {
+            check_type_code: CheckType::CiStatus,
+            status: ConditionResult::PENDING,
+            message: String::new(),
+        };
+        if !self.storage.config().build.enable_build {
+            res.status = ConditionResult::SKIPPED;
+            res.message = String::from("Orion builds are disabled.");
+            return Ok(res);
+        }
+
+        let task = self
+            .storage
+            .cl_storage()
+            .get_latest_cl_task(params.cl_id, &params.commit_id)
+            .await?;
+        match task {
+            Some((_, builds)) => {
+                (res.status, res.message) = summarize_builds(&builds);
+            }
+            None => {
+                res.message = format!("No build has been triggered for {}.", params.commit_id);
+            }
+        }
+        Ok(res)
+    }
+
+    async fn build_params(&self, cl_info: &ClInfoDto) -> Result<Value, MegaError> {
+        let cl = self
+            .storage
+            .cl_storage()
+            .get_cl(&cl_info.link)
+            .await?
+            .ok_or_else(|| MegaError::Other(format!("CL not found: {}", cl_info.link)))?;
+        Ok(serde_json::json!({
+            "cl_id": cl.id,
+            "commit_id": cl_info.to_hash,
+        }))
+    }
+}
+
+/// Folds the builds of a task into a single check status.
+///
+/// Any finished build with a non-zero or missing exit code fails the check,
+/// even while other builds are still running.
+pub(crate) fn summarize_builds(builds: &[builds::Model]) -> (ConditionResult, String) {
+    if builds.is_empty() {
+        return (
+            ConditionResult::PENDING,
+            String::from("Waiting for builds to be scheduled."),
+        );
+    }
+
+    let failed: Vec<String> = builds
+        .iter()
+        .filter(|b| b.end_at.is_some() && b.exit_code != Some(0))
+        .map(|b| {
+            let target = if b.target.is_empty() {
+                &b.repo
+            } else {
+                &b.target
+            };
+            match b.exit_code {
+                Some(code) => format!("- {target} (build {}): exit code {code}", b.id),
+                None => format!("- {target} (build {}): interrupted", b.id),
+            }
+        })
+        .collect();
+    if !failed.is_empty() {
+        return (
+            ConditionResult::FAILED,
+            format!(
+                "{} of {} builds failed:\n{}",
+                failed.len(),
+                builds.len(),
+                failed.join("\n")
+            ),
+        );
+    }
+
+    let finished = builds.iter().filter(|b| b.end_at.is_some()).count();
+    if finished < builds.len() {
+        return (
+            ConditionResult::PENDING,
+            format!("{finished} of {} builds finished.", builds.len()),
+        );
+    }
+    (
+        ConditionResult::PASSED,
+        format!("All {} builds passed.", builds.len()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn build(target: &str, finished: bool, exit_code: Option<i32>) -> builds::Model {
+        let now = Utc::now().fixed_offset();
+        builds::Model {
+            id: Uuid::new_v4(),
+            task_id: Uuid::nil(),
+            exit_code,
+            start_at: now,
+            end_at: finished.then_some(now),
+            repo: String::from("/project"),
+            target: target.to_owned(),
+            args: None,
+            output_file: String::new(),
+            created_at: now,
+        }
+    }
+
+    #[test]
+    fn test_summarize_builds_pending_until_all_finish() {
+        let builds = vec![build("//a", true, Some(0)), build("//b", false, None)];
+        let (status, message) = summarize_builds(&builds);
+        assert_eq!(status, ConditionResult::PENDING);
+        assert_eq!(message, "1 of 2 builds finished.");
+    }
+
+    #[test]
+    fn test_summarize_builds_reports_failing_targets() {
+        let builds = vec![
+            build("//a", true, Some(0)),
+            build("//b", true, Some(3)),
+            build("", true, None),
+            build("//c", false, None),
+        ];
+        let (status, message) = summarize_builds(&builds);
+        assert_eq!(status, ConditionResult::FAILED);
+        assert!(message.starts_with("2 of 4 builds failed:"));
+        assert!(message.contains("- //b (build "));
+        assert!(message.contains("exit code 3"));
+        assert!(message.contains("- /project (build "));
+        assert!(message.contains("interrupted"));
+    }
+
+    #[test]
+    fn test_summarize_builds_passed() {
+        let builds = vec![build("//a", true, Some(0)), build("//b", true, Some(0))];
+        assert_eq!(summarize_builds(&builds).0, ConditionResult::PASSED);
+        assert_eq!(summarize_builds(&[]).0, ConditionResult::PENDING);
+    }
+}
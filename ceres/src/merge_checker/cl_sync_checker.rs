@@ -3,6 +3,7 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use serde::Deserialize;
 
+use callisto::sea_orm_active_enums::MergeStatusEnum;
 use common::errors::MegaError;
 use jupiter::{model::cl_dto::ClInfoDto, storage::Storage};
 
@@ -16,6 +17,9 @@ pub struct ClSyncChecker {
 pub(crate) struct ClSyncParams {
     cl_from: String,
     current: String,
+    /// The nearest CL below this one in its stack that hasn't been merged.
+    #[serde(default)]
+    unmerged_base: Option<String>,
 }
 
 impl ClSyncParams {
@@ -26,20 +30,22 @@ impl ClSyncParams {
 
 #[async_trait]
 impl Checker for ClSyncChecker {
-    async fn run(&self, params: &serde_json::Value) -> CheckResult {
-        let params = ClSyncParams::from_value(params).expect("parse params err");
+    async fn run(&self, params: &serde_json::Value) -> Result<CheckResult, MegaError> {
+        let params = ClSyncParams::from_value(params)?;
         let mut res = CheckResult {
             check_type_code: CheckType::ClSync,
             status: ConditionResult::FAILED,
             message: String::new(),
         };
-        if params.cl_from == params.current {
+        if let Some(base) = params.unmerged_base {
+            res.message = format!("The CL is stacked on {base}, which has to be merged first");
+        } else if params.cl_from == params.current {
             res.status = ConditionResult::PASSED;
         } else {
             res.message =
                 String::from("The pull request must not have any unresolved merge conflicts");
         }
-        res
+        Ok(res)
     }
 
     async fn build_params(&self, cl_info: &ClInfoDto) -> Result<serde_json::Value, MegaError> {
@@ -48,10 +54,21 @@ impl Checker for ClSyncChecker {
             .mono_storage()
             .get_main_ref(&cl_info.path)
             .await?
-            .expect("Err: CL Related Refs Not Found");
+            .ok_or_else(|| MegaError::Other(format!("Main ref not found: {}", cl_info.path)))?;
+        let cl_stg = self.storage.cl_storage();
+        let mut unmerged_base = None;
+        if let Some(cl) = cl_stg.get_cl(&cl_info.link).await? {
+            unmerged_base = cl_stg
+                .get_cl_ancestors(&cl)
+                .await?
+                .into_iter()
+                .find(|a| a.status != MergeStatusEnum::Merged)
+                .map(|a| a.link);
+        }
         Ok(serde_json::json!({
             "cl_from": cl_info.from_hash,
-            "current": refs.ref_commit_hash
+            "current": refs.ref_commit_hash,
+            "unmerged_base": unmerged_base,
         }))
     }
 }
@@ -1,5 +1,6 @@
 use crate::merge_checker::{CheckResult, Checker};
 use async_trait::async_trait;
+use callisto::sea_orm_active_enums::ReviewStateEnum;
 use common::errors::MegaError;
 use jupiter::model::cl_dto::ClInfoDto;
 use jupiter::storage::Storage;
@@ -24,8 +25,8 @@ impl CodeReviewParams {
 
 #[async_trait]
 impl Checker for CodeReviewChecker {
-    async fn run(&self, params: &Value) -> CheckResult {
-        let params = CodeReviewParams::from_value(params).expect("parse params err");
+    async fn run(&self, params: &Value) -> Result<CheckResult, MegaError> {
+        let params = CodeReviewParams::from_value(params)?;
         let mut res = CheckResult {
             check_type_code: crate::merge_checker::CheckType::CodeReview,
             status: crate::merge_checker::ConditionResult::FAILED,
@@ -45,7 +46,7 @@ impl Checker for CodeReviewChecker {
             }
         }
 
-        res
+        Ok(res)
     }
 
     async fn build_params(&self, cl_info: &ClInfoDto) -> Result<Value, MegaError> {
@@ -65,10 +66,16 @@ impl CodeReviewChecker {
 
         let mut err_message = String::new();
         for reviewer in reviewers {
-            if !reviewer.approved {
-                let msg = format!("Reviewer {} has not approved the CL.\n", reviewer.id);
-                err_message = err_message + &msg;
-            }
+            let msg = match reviewer.review_state {
+                ReviewStateEnum::Approved => continue,
+                ReviewStateEnum::ChangesRequested => {
+                    format!("Reviewer {} requested changes.\n", reviewer.username)
+                }
+                ReviewStateEnum::Pending | ReviewStateEnum::Commented => {
+                    format!("Reviewer {} has not approved the CL.\n", reviewer.username)
+                }
+            };
+            err_message = err_message + &msg;
         }
 
         if !err_message.is_empty() {
@@ -9,7 +9,7 @@ pub struct CommitMessageChecker;
 
 #[async_trait]
 impl Checker for CommitMessageChecker {
-    async fn run(&self, params: &Value) -> CheckResult {
+    async fn run(&self, params: &Value) -> Result<CheckResult, MegaError> {
         let title = params["title"].as_str().unwrap_or_default();
         let status = if check_conventional_commits_message(title) {
             ConditionResult::PASSED
@@ -22,11 +22,11 @@ impl Checker for CommitMessageChecker {
             "Commit message does not follow conventional commits. Please make sure your CL title follows the Conventional Commits specification.".to_string()
         };
 
-        CheckResult {
+        Ok(CheckResult {
             check_type_code: CheckType::CommitMessage,
             status,
             message,
-        }
+        })
     }
 
     async fn build_params(&self, cl_info: &ClInfoDto) -> Result<Value, MegaError> {
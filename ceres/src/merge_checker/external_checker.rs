@@ -0,0 +1,214 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use globset::{GlobBuilder, GlobSetBuilder};
+use serde_json::Value;
+use subtle::ConstantTimeEq;
+
+use callisto::check_result;
+use common::{config::ExternalCheckConfig, errors::MegaError};
+use jupiter::{model::cl_dto::ClInfoDto, storage::Storage};
+
+use crate::merge_checker::ConditionResult;
+
+const DISPATCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checks declared under `external_checks` in the config.
+///
+/// Unlike the built-in checkers they do not compute a result themselves:
+/// the CL is POSTed to the check's URL and recorded as pending until the
+/// service reports back through [`ExternalChecks::report`].
+#[derive(Clone)]
+pub struct ExternalChecks {
+    storage: Arc<Storage>,
+    client: reqwest::Client,
+}
+
+impl ExternalChecks {
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self {
+            storage,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Looks up a configured check by name.
+    pub fn find(&self, name: &str) -> Option<ExternalCheckConfig> {
+        self.storage
+            .config()
+            .external_checks
+            .iter()
+            .find(|c| c.name == name)
+            .cloned()
+    }
+
+    /// Records every matching check that has not seen the current revision
+    /// of the CL yet as pending, and sends the CL to them.
+    ///
+    /// The requests go out in the background so a slow service does not hold
+    /// up the push; a check that cannot be reached is recorded as an error.
+    /// Re-running the checks for an unchanged revision keeps the reported
+    /// results instead of dispatching again.
+    pub async fn dispatch(&self, cl_info: &ClInfoDto) -> Result<(), MegaError> {
+        let config = self.storage.config();
+        let mut checks = vec![];
+        for check in matching_checks(&config.external_checks, &cl_info.path) {
+            let existing = self
+                .storage
+                .cl_storage()
+                .get_external_check_result(&cl_info.link, &check.name)
+                .await?;
+            if existing.is_some_and(|m| m.commit_id == cl_info.to_hash) {
+                continue;
+            }
+            checks.push(check.clone());
+        }
+        if checks.is_empty() {
+            return Ok(());
+        }
+
+        let models = checks
+            .iter()
+            .map(|check| {
+                Self::result(
+                    check,
+                    cl_info,
+                    ConditionResult::PENDING,
+                    &format!("Waiting for {} to report.", check.name),
+                )
+            })
+            .collect();
+        self.storage.cl_storage().save_check_results(models).await?;
+
+        let body = serde_json::to_value(cl_info)?;
+        for check in checks {
+            let this = self.clone();
+            let body = body.clone();
+            let failed = Self::result(&check, cl_info, ConditionResult::ERROR, "");
+            tokio::spawn(async move {
+                let Err(e) = this.post(&check, &body).await else {
+                    return;
+                };
+                tracing::warn!("Failed to dispatch check {}: {e}", check.name);
+                let model = check_result::Model {
+                    message: format!("Failed to reach {}: {e}", check.name),
+                    ..failed
+                };
+                // The CL may have moved on, or the service reported anyway
+                if let Err(e) = this.storage.cl_storage().resolve_pending_check(model).await {
+                    tracing::warn!("Failed to record check {}: {e}", check.name);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether `token` is the token of `check`, compared in constant time.
+    pub fn verify_token(check: &ExternalCheckConfig, token: Option<&str>) -> bool {
+        token.is_some_and(|token| bool::from(token.as_bytes().ct_eq(check.token.as_bytes())))
+    }
+
+    /// Records the status an external check reported for a CL revision.
+    pub async fn report(
+        &self,
+        check: &ExternalCheckConfig,
+        cl_info: &ClInfoDto,
+        status: ConditionResult,
+        message: &str,
+    ) -> Result<(), MegaError> {
+        self.storage
+            .cl_storage()
+            .save_check_results(vec![Self::result(check, cl_info, status, message)])
+            .await
+    }
+
+    fn result(
+        check: &ExternalCheckConfig,
+        cl_info: &ClInfoDto,
+        status: ConditionResult,
+        message: &str,
+    ) -> check_result::Model {
+        check_result::Model::new_external(
+            &cl_info.path,
+            &cl_info.link,
+            &cl_info.to_hash,
+            &check.name,
+            check.required,
+            status.into(),
+            message,
+        )
+    }
+
+    async fn post(&self, check: &ExternalCheckConfig, body: &Value) -> Result<(), MegaError> {
+        self.client
+            .post(&check.url)
+            .timeout(DISPATCH_TIMEOUT)
+            .bearer_auth(&check.token)
+            .json(body)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| MegaError::Other(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Returns the checks with a path glob matching `path`.
+///
+/// `*` does not cross `/`, so use `**` to cover a whole subtree.
+fn matching_checks<'a>(
+    checks: &'a [ExternalCheckConfig],
+    path: &str,
+) -> Vec<&'a ExternalCheckConfig> {
+    checks
+        .iter()
+        .filter(|check| {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in &check.paths {
+                match GlobBuilder::new(pattern).literal_separator(true).build() {
+                    Ok(glob) => {
+                        builder.add(glob);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Invalid path glob {pattern} of check {}: {e}", check.name)
+                    }
+                }
+            }
+            builder.build().is_ok_and(|set| set.is_match(path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, paths: &[&str]) -> ExternalCheckConfig {
+        ExternalCheckConfig {
+            name: name.to_owned(),
+            url: String::from("http://localhost/check"),
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            required: true,
+            token: String::from("secret"),
+        }
+    }
+
+    #[test]
+    fn test_matching_checks() {
+        let checks = vec![
+            check("subtree", &["/project/**"]),
+            check("direct", &["/project/*"]),
+            check("doc", &["/doc", "/doc/**"]),
+        ];
+        let names = |path| {
+            matching_checks(&checks, path)
+                .into_iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("/project/mega"), vec!["subtree", "direct"]);
+        assert_eq!(names("/project/mega/src"), vec!["subtree"]);
+        assert_eq!(names("/doc"), vec!["doc"]);
+        assert!(names("/third-party/x").is_empty());
+    }
+}
@@ -27,8 +27,8 @@ impl GpgSignatureParams {
 
 #[async_trait]
 impl Checker for GpgSignatureChecker {
-    async fn run(&self, params: &serde_json::Value) -> crate::merge_checker::CheckResult {
-        let params = GpgSignatureParams::from_value(params).expect("parse params err");
+    async fn run(&self, params: &serde_json::Value) -> Result<CheckResult, MegaError> {
+        let params = GpgSignatureParams::from_value(params)?;
         let mut res = CheckResult {
             check_type_code: CheckType::GpgSignature,
             status: ConditionResult::FAILED,
@@ -48,7 +48,7 @@ impl Checker for GpgSignatureChecker {
             }
         };
 
-        res
+        Ok(res)
     }
 
     async fn build_params(&self, cl_info: &ClInfoDto) -> Result<Value, MegaError> {
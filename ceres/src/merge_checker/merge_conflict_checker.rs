@@ -0,0 +1,436 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use common::errors::MegaError;
+use common::utils::MEGA_BRANCH_NAME;
+use git_internal::hash::ObjectHash;
+use git_internal::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use jupiter::storage::mono_storage::MonoStorage;
+use jupiter::utils::converter::FromMegaModel;
+use jupiter::{model::cl_dto::ClInfoDto, storage::Storage};
+
+use crate::api_service::buck_tree_builder::BuckCommitBuilder;
+use crate::merge_checker::{CheckResult, CheckType, Checker, ConditionResult};
+
+pub struct MergeConflictChecker {
+    pub storage: Arc<Storage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct MergeConflictParams {
+    #[serde(default)]
+    path: String,
+    base: String,
+    ours: String,
+    theirs: String,
+}
+
+impl MergeConflictParams {
+    fn from_value(v: &serde_json::Value) -> anyhow::Result<Self> {
+        Ok(serde_json::from_value(v.clone())?)
+    }
+}
+
+#[async_trait]
+impl Checker for MergeConflictChecker {
+    async fn run(&self, params: &Value) -> Result<CheckResult, MegaError> {
+        let params = MergeConflictParams::from_value(params)?;
+        let mut res = CheckResult {
+            check_type_code: CheckType::MergeConflict,
+            status: ConditionResult::FAILED,
+            message: String::new(),
+        };
+
+        // `merge_cl` can't merge a root CL onto a moved trunk, so don't offer it
+        if params.path == "/" && params.base != params.ours {
+            res.message = format!(
+                "{MEGA_BRANCH_NAME} has moved since the CL was created, push the CL again on top of it"
+            );
+            return Ok(res);
+        }
+
+        let outcome = TreeMerge::merge_commits(
+            &self.storage.mono_storage(),
+            &params.base,
+            &params.ours,
+            &params.theirs,
+        )
+        .await?;
+        if outcome.is_clean() {
+            res.status = ConditionResult::PASSED;
+            res.message = String::from("No conflicts with the target branch.");
+        } else {
+            res.message = outcome.conflict_message();
+        }
+        Ok(res)
+    }
+
+    async fn build_params(&self, cl_info: &ClInfoDto) -> Result<Value, MegaError> {
+        let refs = self
+            .storage
+            .mono_storage()
+            .get_main_ref(&cl_info.path)
+            .await?
+            .ok_or_else(|| MegaError::Other(format!("Main ref not found: {}", cl_info.path)))?;
+        Ok(serde_json::json!({
+            "path": cl_info.path,
+            "base": cl_info.from_hash,
+            "ours": refs.ref_commit_hash,
+            "theirs": cl_info.to_hash,
+        }))
+    }
+}
+
+/// A single non-tree entry of a flattened tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeEntry {
+    pub id: ObjectHash,
+    pub mode: TreeItemMode,
+}
+
+/// Result of a three-way tree merge.
+#[derive(Debug, Default)]
+pub struct MergeOutcome {
+    /// Merged snapshot, only meaningful when `conflicts` is empty.
+    pub merged: BTreeMap<PathBuf, MergeEntry>,
+    /// Paths changed differently on both sides, sorted.
+    pub conflicts: Vec<PathBuf>,
+}
+
+impl MergeOutcome {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    pub fn conflict_message(&self) -> String {
+        let paths: Vec<String> = self
+            .conflicts
+            .iter()
+            .map(|p| format!("- {}", p.display()))
+            .collect();
+        format!("Conflicting paths:\n{}", paths.join("\n"))
+    }
+}
+
+/// Path-level three-way merge of mega commit trees.
+///
+/// Blobs are compared by hash only, so a file modified on both sides is
+/// always reported as a conflict even if the edits touch different lines.
+pub struct TreeMerge;
+
+impl TreeMerge {
+    /// Merges `theirs` into `ours` using `base` as the common ancestor.
+    ///
+    /// The three trees are walked together and a subtree that resolves by
+    /// hash alone is kept as a single entry instead of being descended into,
+    /// so only directories changed on both sides are loaded.
+    pub async fn merge_commits(
+        storage: &MonoStorage,
+        base: &str,
+        ours: &str,
+        theirs: &str,
+    ) -> Result<MergeOutcome, MegaError> {
+        let roots = [
+            Some(Self::root_tree(storage, base).await?),
+            Some(Self::root_tree(storage, ours).await?),
+            Some(Self::root_tree(storage, theirs).await?),
+        ];
+        let mut snapshots: [BTreeMap<PathBuf, MergeEntry>; 3] = Default::default();
+        let mut level = vec![(PathBuf::new(), roots)];
+        while !level.is_empty() {
+            let ids = level
+                .iter()
+                .flat_map(|(_, ids)| ids.iter().flatten().copied());
+            let trees = Self::load_trees(storage, ids).await?;
+
+            let mut next = Vec::new();
+            for (dir, ids) in level {
+                let items = ids.map(|id| id.map(|id| &trees[&id].tree_items));
+                let names: BTreeSet<&str> = items
+                    .iter()
+                    .flatten()
+                    .flat_map(|items| items.iter().map(|i| i.name.as_str()))
+                    .collect();
+                for name in names {
+                    let found =
+                        items.map(|items| items.and_then(|v| v.iter().find(|i| i.name == name)));
+                    let [b, o, t] = found.map(|i| {
+                        i.map(|i| MergeEntry {
+                            id: i.id,
+                            mode: i.mode,
+                        })
+                    });
+                    let path = dir.join(name);
+                    let has_tree = found.iter().flatten().any(|i| i.is_tree());
+                    if has_tree && (o == t || t == b || o == b) {
+                        for (snapshot, entry) in snapshots.iter_mut().zip([b, o, t]) {
+                            if let Some(entry) = entry {
+                                snapshot.insert(path.clone(), entry);
+                            }
+                        }
+                        continue;
+                    }
+
+                    let mut children = [None; 3];
+                    for (i, item) in found.iter().enumerate() {
+                        match item {
+                            Some(item) if item.is_tree() => children[i] = Some(item.id),
+                            Some(item) => {
+                                snapshots[i].insert(
+                                    path.clone(),
+                                    MergeEntry {
+                                        id: item.id,
+                                        mode: item.mode,
+                                    },
+                                );
+                            }
+                            None => {}
+                        }
+                    }
+                    if children.iter().any(Option::is_some) {
+                        next.push((path, children));
+                    }
+                }
+            }
+            level = next;
+        }
+
+        let [base, ours, theirs] = snapshots;
+        Ok(Self::three_way(&base, &ours, &theirs))
+    }
+
+    pub fn three_way(
+        base: &BTreeMap<PathBuf, MergeEntry>,
+        ours: &BTreeMap<PathBuf, MergeEntry>,
+        theirs: &BTreeMap<PathBuf, MergeEntry>,
+    ) -> MergeOutcome {
+        let paths: BTreeSet<&PathBuf> = base
+            .keys()
+            .chain(ours.keys())
+            .chain(theirs.keys())
+            .collect();
+        let mut outcome = MergeOutcome::default();
+
+        for path in paths {
+            let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+            let resolved = if o == t || t == b {
+                o
+            } else if o == b {
+                t
+            } else {
+                outcome.conflicts.push(path.clone());
+                continue;
+            };
+            if let Some(entry) = resolved {
+                outcome.merged.insert(path.clone(), *entry);
+            }
+        }
+
+        // A file on one side may now sit where the other side created a directory.
+        let file_dir_conflicts: Vec<PathBuf> = outcome
+            .merged
+            .keys()
+            .filter(|p| {
+                p.ancestors()
+                    .skip(1)
+                    .any(|a| outcome.merged.contains_key(a))
+            })
+            .cloned()
+            .collect();
+        outcome.conflicts.extend(file_dir_conflicts);
+        outcome.conflicts.sort();
+        outcome.conflicts.dedup();
+        outcome
+    }
+
+    /// Collects every non-tree entry reachable from the commit's root tree.
+    pub async fn flatten_commit(
+        storage: &MonoStorage,
+        commit_hash: &str,
+    ) -> Result<BTreeMap<PathBuf, MergeEntry>, MegaError> {
+        let mut result = BTreeMap::new();
+        let mut level = vec![(PathBuf::new(), Self::root_tree(storage, commit_hash).await?)];
+        while !level.is_empty() {
+            let trees = Self::load_trees(storage, level.iter().map(|(_, id)| *id)).await?;
+            let mut next = Vec::new();
+            for (dir, id) in level {
+                for item in &trees[&id].tree_items {
+                    let path = dir.join(&item.name);
+                    if item.is_tree() {
+                        next.push((path, item.id));
+                    } else {
+                        result.insert(
+                            path,
+                            MergeEntry {
+                                id: item.id,
+                                mode: item.mode,
+                            },
+                        );
+                    }
+                }
+            }
+            level = next;
+        }
+        Ok(result)
+    }
+
+    pub(crate) async fn root_tree(
+        storage: &MonoStorage,
+        commit_hash: &str,
+    ) -> Result<ObjectHash, MegaError> {
+        let commit = storage
+            .get_commit_by_hash(commit_hash)
+            .await?
+            .ok_or_else(|| MegaError::Other(format!("Commit not found: {commit_hash}")))?;
+        ObjectHash::from_str(&commit.tree).map_err(MegaError::Other)
+    }
+
+    /// Loads one directory level of trees with a single query.
+    async fn load_trees(
+        storage: &MonoStorage,
+        ids: impl Iterator<Item = ObjectHash>,
+    ) -> Result<HashMap<ObjectHash, Tree>, MegaError> {
+        let wanted: BTreeSet<String> = ids.map(|id| id.to_string()).collect();
+        let trees: HashMap<ObjectHash, Tree> = storage
+            .get_trees_by_hashes(wanted.iter().cloned().collect())
+            .await?
+            .into_iter()
+            .map(|model| {
+                let tree = Tree::from_mega_model(model);
+                (tree.id, tree)
+            })
+            .collect();
+        if trees.len() < wanted.len() {
+            let found: BTreeSet<String> = trees.keys().map(|id| id.to_string()).collect();
+            let missing = wanted
+                .difference(&found)
+                .next()
+                .cloned()
+                .unwrap_or_default();
+            return Err(MegaError::Other(format!("Tree not found: {missing}")));
+        }
+        Ok(trees)
+    }
+
+    /// Rebuilds tree objects for a flattened snapshot.
+    ///
+    /// Returns every tree (root last) so callers can persist them before
+    /// pointing a ref at the root.
+    pub fn build_trees(entries: &BTreeMap<PathBuf, MergeEntry>) -> Result<Vec<Tree>, MegaError> {
+        let mut dirs: BTreeMap<PathBuf, Vec<TreeItem>> = BTreeMap::new();
+        dirs.insert(PathBuf::new(), vec![]);
+        for (path, entry) in entries {
+            let parent = path.parent().unwrap_or(Path::new("")).to_path_buf();
+            let name = Self::file_name(path)?;
+            for dir in parent.ancestors() {
+                dirs.entry(dir.to_path_buf()).or_default();
+            }
+            dirs.entry(parent)
+                .or_default()
+                .push(TreeItem::new(entry.mode, entry.id, name));
+        }
+
+        // Deepest directories first so parents can reference their children.
+        let mut ordered: Vec<PathBuf> = dirs.keys().cloned().collect();
+        ordered.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+        let mut trees = Vec::with_capacity(ordered.len());
+        for dir in ordered {
+            let mut items = dirs.remove(&dir).unwrap_or_default();
+            BuckCommitBuilder::sort_tree_items_git_style(&mut items);
+            let tree = Tree::from_tree_items(items)
+                .map_err(|e| MegaError::Other(format!("Failed to build tree {dir:?}: {e}")))?;
+            if let Some(parent) = dir.parent() {
+                let name = Self::file_name(&dir)?;
+                dirs.entry(parent.to_path_buf())
+                    .or_default()
+                    .push(TreeItem::new(TreeItemMode::Tree, tree.id, name));
+            }
+            trees.push(tree);
+        }
+        Ok(trees)
+    }
+
+    fn file_name(path: &Path) -> Result<String, MegaError> {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .map(str::to_owned)
+            .ok_or_else(|| MegaError::Other(format!("Invalid path: {path:?}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git_internal::internal::object::types::ObjectType;
+
+    fn entry(content: &str) -> MergeEntry {
+        MergeEntry {
+            id: ObjectHash::from_type_and_data(ObjectType::Blob, content.as_bytes()),
+            mode: TreeItemMode::Blob,
+        }
+    }
+
+    fn snapshot(files: &[(&str, &str)]) -> BTreeMap<PathBuf, MergeEntry> {
+        files
+            .iter()
+            .map(|(p, c)| (PathBuf::from(p), entry(c)))
+            .collect()
+    }
+
+    #[test]
+    fn test_three_way_disjoint_changes_merge_cleanly() {
+        let base = snapshot(&[("a.txt", "a"), ("src/b.rs", "b")]);
+        let ours = snapshot(&[("a.txt", "a2"), ("src/b.rs", "b")]);
+        let theirs = snapshot(&[("a.txt", "a"), ("src/b.rs", "b"), ("src/c.rs", "c")]);
+
+        let outcome = TreeMerge::three_way(&base, &ours, &theirs);
+        assert!(outcome.is_clean());
+        assert_eq!(
+            outcome.merged,
+            snapshot(&[("a.txt", "a2"), ("src/b.rs", "b"), ("src/c.rs", "c")])
+        );
+    }
+
+    #[test]
+    fn test_three_way_reports_conflicting_paths() {
+        let base = snapshot(&[("a.txt", "a"), ("b.txt", "b")]);
+        let ours = snapshot(&[("a.txt", "ours"), ("b.txt", "b")]);
+        let theirs = snapshot(&[("a.txt", "theirs")]);
+
+        let outcome = TreeMerge::three_way(&base, &ours, &theirs);
+        assert_eq!(outcome.conflicts, vec![PathBuf::from("a.txt")]);
+        assert!(!outcome.merged.contains_key(&PathBuf::from("b.txt")));
+    }
+
+    #[test]
+    fn test_three_way_file_directory_conflict() {
+        let base = snapshot(&[("x.txt", "x")]);
+        let ours = snapshot(&[("x.txt", "x"), ("lib", "file")]);
+        let theirs = snapshot(&[("x.txt", "x"), ("lib/mod.rs", "dir")]);
+
+        let outcome = TreeMerge::three_way(&base, &ours, &theirs);
+        assert_eq!(outcome.conflicts, vec![PathBuf::from("lib/mod.rs")]);
+    }
+
+    #[test]
+    fn test_build_trees_root_is_last() {
+        let files = snapshot(&[
+            ("a.txt", "a"),
+            ("src/lib.rs", "lib"),
+            ("src/bin/main.rs", "m"),
+        ]);
+        let trees = TreeMerge::build_trees(&files).unwrap();
+        assert_eq!(trees.len(), 3);
+
+        let root = trees.last().unwrap();
+        let names: Vec<&str> = root.tree_items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "src"]);
+    }
+}
@@ -1,24 +1,40 @@
 use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
 
 use async_trait::async_trait;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::merge_checker::branch_protection_checker::BranchProtectionChecker;
+use crate::merge_checker::ci_status_checker::CiStatusChecker;
 use crate::merge_checker::cl_sync_checker::ClSyncChecker;
 use crate::merge_checker::commit_message_checker::CommitMessageChecker;
+use crate::merge_checker::external_checker::ExternalChecks;
 use crate::merge_checker::gpg_signature_checker::GpgSignatureChecker;
-use callisto::{check_result, sea_orm_active_enums::CheckTypeEnum};
+use crate::merge_checker::merge_conflict_checker::MergeConflictChecker;
+use callisto::{
+    check_result,
+    sea_orm_active_enums::{CheckStatusEnum, CheckTypeEnum},
+};
 use common::errors::MegaError;
 use jupiter::{model::cl_dto::ClInfoDto, storage::Storage};
 
+pub mod branch_protection_checker;
+pub(crate) mod ci_status_checker;
 pub mod cl_sync_checker;
 mod code_review_checker;
 mod commit_message_checker;
+pub mod external_checker;
 pub(crate) mod gpg_signature_checker;
+pub mod merge_conflict_checker;
 
 #[async_trait]
 pub trait Checker: Send + Sync {
-    async fn run(&self, params: &serde_json::Value) -> CheckResult;
+    /// Runs the check against params produced by `build_params`.
+    ///
+    /// Returns `Err` when the check itself could not be evaluated, e.g. on
+    /// malformed params or a storage failure; the registry records such
+    /// runs as `ERROR` instead of `FAILED`.
+    async fn run(&self, params: &serde_json::Value) -> Result<CheckResult, MegaError>;
 
     async fn build_params(&self, cl_info: &ClInfoDto) -> Result<serde_json::Value, MegaError>;
 }
@@ -32,12 +48,26 @@ pub enum CheckType {
     MergeConflict,
     CiStatus,
     CodeReview,
+    External,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub enum ConditionResult {
     FAILED,
     PASSED,
+    /// The outcome is not known yet, e.g. CI is still running.
+    PENDING,
+    /// The check does not apply to this CL and does not block merging.
+    SKIPPED,
+    /// The check could not be evaluated.
+    ERROR,
+}
+
+impl ConditionResult {
+    /// Whether this result allows the CL to be merged.
+    pub fn is_satisfied(&self) -> bool {
+        matches!(self, ConditionResult::PASSED | ConditionResult::SKIPPED)
+    }
 }
 
 impl fmt::Display for ConditionResult {
@@ -45,6 +75,9 @@ impl fmt::Display for ConditionResult {
         let s = match self {
             ConditionResult::FAILED => "FAILED",
             ConditionResult::PASSED => "PASSED",
+            ConditionResult::PENDING => "PENDING",
+            ConditionResult::SKIPPED => "SKIPPED",
+            ConditionResult::ERROR => "ERROR",
         };
         write!(f, "{}", s)
     }
@@ -57,11 +90,38 @@ impl FromStr for ConditionResult {
         match s {
             "PASSED" => Ok(ConditionResult::PASSED),
             "FAILED" => Ok(ConditionResult::FAILED),
+            "PENDING" => Ok(ConditionResult::PENDING),
+            "SKIPPED" => Ok(ConditionResult::SKIPPED),
+            "ERROR" => Ok(ConditionResult::ERROR),
             _ => Err(()),
         }
     }
 }
 
+impl From<CheckStatusEnum> for ConditionResult {
+    fn from(value: CheckStatusEnum) -> Self {
+        match value {
+            CheckStatusEnum::Failed => ConditionResult::FAILED,
+            CheckStatusEnum::Passed => ConditionResult::PASSED,
+            CheckStatusEnum::Pending => ConditionResult::PENDING,
+            CheckStatusEnum::Skipped => ConditionResult::SKIPPED,
+            CheckStatusEnum::Error => ConditionResult::ERROR,
+        }
+    }
+}
+
+impl From<ConditionResult> for CheckStatusEnum {
+    fn from(value: ConditionResult) -> Self {
+        match value {
+            ConditionResult::FAILED => CheckStatusEnum::Failed,
+            ConditionResult::PASSED => CheckStatusEnum::Passed,
+            ConditionResult::PENDING => CheckStatusEnum::Pending,
+            ConditionResult::SKIPPED => CheckStatusEnum::Skipped,
+            ConditionResult::ERROR => CheckStatusEnum::Error,
+        }
+    }
+}
+
 impl CheckType {
     pub fn display_name(&self) -> &'static str {
         match self {
@@ -72,6 +132,7 @@ impl CheckType {
             CheckType::MergeConflict => "Merge conflict",
             CheckType::CiStatus => "Ci status",
             CheckType::CodeReview => "Code review",
+            CheckType::External => "External check",
         }
     }
 
@@ -81,7 +142,7 @@ impl CheckType {
                 "Verify whether the commit has a valid GPG signature and the key is trusted"
             }
             CheckType::BranchProtection => {
-                "Ensure the merge target complies with branch protection policies, such as no direct merges to main and requiring squash or rebase"
+                "Ensure the merge target complies with the branch protection rule of its path, such as the minimum number of approvals"
             }
             CheckType::CommitMessage => {
                 "Verify whether the commit message follows Conventional Commits or the internal agreed-upon format"
@@ -98,6 +159,9 @@ impl CheckType {
             CheckType::CodeReview => {
                 "Ensure the required reviewers have approved the merge request"
             }
+            CheckType::External => {
+                "Result reported by an external service configured for this path"
+            }
         }
     }
 }
@@ -112,6 +176,7 @@ impl From<CheckTypeEnum> for CheckType {
             CheckTypeEnum::MergeConflict => CheckType::MergeConflict,
             CheckTypeEnum::CiStatus => CheckType::CiStatus,
             CheckTypeEnum::CodeReview => CheckType::CodeReview,
+            CheckTypeEnum::External => CheckType::External,
         }
     }
 }
@@ -126,6 +191,7 @@ impl From<CheckType> for CheckTypeEnum {
             CheckType::MergeConflict => CheckTypeEnum::MergeConflict,
             CheckType::CiStatus => CheckTypeEnum::CiStatus,
             CheckType::CodeReview => CheckTypeEnum::CodeReview,
+            CheckType::External => CheckTypeEnum::External,
         }
     }
 }
@@ -170,6 +236,24 @@ impl CheckerRegistry {
             }),
         );
         r.register(CheckType::CommitMessage, Box::new(CommitMessageChecker));
+        r.register(
+            CheckType::MergeConflict,
+            Box::new(MergeConflictChecker {
+                storage: storage.clone(),
+            }),
+        );
+        r.register(
+            CheckType::BranchProtection,
+            Box::new(BranchProtectionChecker {
+                storage: storage.clone(),
+            }),
+        );
+        r.register(
+            CheckType::CiStatus,
+            Box::new(CiStatusChecker {
+                storage: storage.clone(),
+            }),
+        );
 
         r
     }
@@ -187,15 +271,26 @@ impl CheckerRegistry {
         let mut save_models = vec![];
 
         for c_config in check_configs {
-            if let Some(checker) = self.checkers.get(&c_config.check_type_code.into()) {
-                let params = checker.build_params(&cl_info).await?;
-                let res = checker.run(&params).await;
+            let check_type: CheckType = c_config.check_type_code.into();
+            if let Some(checker) = self.checkers.get(&check_type) {
+                let res = match checker.build_params(&cl_info).await {
+                    Ok(params) => checker.run(&params).await,
+                    Err(e) => Err(e),
+                }
+                .unwrap_or_else(|e| {
+                    tracing::warn!("{check_type:?} check errored on {}: {e}", cl_info.link);
+                    CheckResult {
+                        check_type_code: check_type,
+                        status: ConditionResult::ERROR,
+                        message: format!("The check could not be evaluated: {e}"),
+                    }
+                });
                 let model = check_result::Model::new(
                     &cl_info.path,
                     &cl_info.link,
                     &cl_info.to_hash,
                     res.check_type_code.into(),
-                    &res.status.to_string(),
+                    res.status.into(),
                     &res.message,
                 );
                 save_models.push(model);
@@ -205,6 +300,8 @@ impl CheckerRegistry {
             .cl_storage()
             .save_check_results(save_models)
             .await?;
-        Ok(())
+        ExternalChecks::new(self.storage.clone())
+            .dispatch(&cl_info)
+            .await
     }
 }
@@ -0,0 +1,66 @@
+use callisto::branch_protection_rules;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// A branch protection rule, applying to its path and everything below it.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BranchProtectionRule {
+    pub path: String,
+    /// Users allowed to push without a CL when `require_cl` is set.
+    #[serde(default)]
+    pub direct_push_users: Vec<String>,
+    #[serde(default)]
+    pub require_cl: bool,
+    #[serde(default)]
+    pub min_approvals: i32,
+    #[serde(default)]
+    pub allow_force_push: bool,
+    #[serde(default)]
+    pub allow_deletion: bool,
+    #[serde(default)]
+    pub require_signed_push: bool,
+    /// Dismiss approvals when new changes are pushed to the CL.
+    #[serde(default)]
+    pub dismiss_stale_approvals: bool,
+    /// Keep approvals across pushes that leave the approved changes as they were.
+    #[serde(default)]
+    pub sticky_approvals: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct BranchProtectionQuery {
+    pub path: String,
+}
+
+impl From<branch_protection_rules::Model> for BranchProtectionRule {
+    fn from(value: branch_protection_rules::Model) -> Self {
+        Self {
+            direct_push_users: serde_json::from_value(value.direct_push_users).unwrap_or_default(),
+            path: value.path,
+            require_cl: value.require_cl,
+            min_approvals: value.min_approvals,
+            allow_force_push: value.allow_force_push,
+            allow_deletion: value.allow_deletion,
+            require_signed_push: value.require_signed_push,
+            dismiss_stale_approvals: value.dismiss_stale_approvals,
+            sticky_approvals: value.sticky_approvals,
+        }
+    }
+}
+
+impl From<BranchProtectionRule> for branch_protection_rules::Model {
+    fn from(value: BranchProtectionRule) -> Self {
+        let mut model = branch_protection_rules::Model::new(
+            &value.path,
+            value.direct_push_users,
+            value.require_cl,
+            value.min_approvals,
+            value.allow_force_push,
+            value.allow_deletion,
+        );
+        model.require_signed_push = value.require_signed_push;
+        model.dismiss_stale_approvals = value.dismiss_stale_approvals;
+        model.sticky_approvals = value.sticky_approvals;
+        model
+    }
+}
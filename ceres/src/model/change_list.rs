@@ -2,10 +2,13 @@ use std::path::PathBuf;
 use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-use callisto::{check_result, sea_orm_active_enums::MergeStatusEnum};
+use callisto::{
+    check_result, mega_cl, mega_cl_patchset,
+    sea_orm_active_enums::{MergeStatusEnum, ReviewStateEnum},
+};
 use common::model::CommonPage;
 use common::model::DiffItem;
 use git_internal::hash::ObjectHash;
@@ -13,7 +16,10 @@ use jupiter::model::cl_dto::CLDetails;
 use jupiter::model::common::ListParams;
 
 use crate::merge_checker::{CheckType, ConditionResult};
-use crate::model::{conversation::ConversationItem, label::LabelItem};
+use crate::model::{
+    conversation::{ConversationItem, InlineThread},
+    label::LabelItem,
+};
 
 #[derive(Deserialize, ToSchema)]
 pub struct AssigneeUpdatePayload {
@@ -57,6 +63,37 @@ pub struct CLDetailRes {
     pub labels: Vec<LabelItem>,
     pub assignees: Vec<String>,
     pub path: String,
+    /// The CL this one is stacked on.
+    pub base_cl: Option<String>,
+    /// Every CL in the same stack, including this one, bottom of the stack
+    /// first.
+    pub stack: Vec<StackedCl>,
+}
+
+/// A CL in a stack. `base_cl` links it to the CL below it.
+#[derive(Serialize, ToSchema)]
+pub struct StackedCl {
+    pub link: String,
+    pub title: String,
+    pub status: MergeStatus,
+    pub base_cl: Option<String>,
+}
+
+impl From<mega_cl::Model> for StackedCl {
+    fn from(value: mega_cl::Model) -> Self {
+        Self {
+            link: value.link,
+            title: value.title,
+            status: value.status.into(),
+            base_cl: value.base_cl,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateBaseClPayload {
+    /// Link of the CL to stack on, or `None` to unstack.
+    pub base_cl: Option<String>,
 }
 
 impl From<CLDetails> for CLDetailRes {
@@ -80,6 +117,8 @@ impl From<CLDetails> for CLDetailRes {
                 .map(|x| x.assignnee_id)
                 .collect(),
             path: value.cl.path,
+            base_cl: value.cl.base_cl,
+            stack: value.stack.into_iter().map(StackedCl::from).collect(),
         }
     }
 }
@@ -87,6 +126,8 @@ impl From<CLDetails> for CLDetailRes {
 #[derive(Serialize, ToSchema)]
 pub struct FilesChangedPage {
     pub page: CommonPage<DiffItem>,
+    /// Inline comment threads on the files in this page.
+    pub comments: Vec<InlineThread>,
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
@@ -133,6 +174,43 @@ impl MuiTreeNode {
     }
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct PatchsetItem {
+    pub number: i32,
+    pub from_hash: String,
+    pub to_hash: String,
+    pub pusher: String,
+    pub created_at: i64,
+}
+
+impl From<mega_cl_patchset::Model> for PatchsetItem {
+    fn from(value: mega_cl_patchset::Model) -> Self {
+        Self {
+            number: value.number,
+            from_hash: value.from_hash,
+            to_hash: value.to_hash,
+            pusher: value.pusher,
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct InterdiffPayload {
+    /// Patchset number to diff from.
+    pub old: i32,
+    /// Patchset number to diff to.
+    pub new: i32,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ClFilesQuery {
+    /// Commit to list instead of the latest revision, compared to its
+    /// parent. Set by merge queue builds of a speculative commit; it must be
+    /// a patchset of the CL or a commit built for it.
+    pub commit: Option<String>,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct ClFilesRes {
     pub path: String,
@@ -200,12 +278,16 @@ pub struct MergeBoxRes {
 
 impl MergeBoxRes {
     pub fn from_condition(conditions: Vec<Condition>) -> Self {
-        let mut state = RequirementsState::MERGEABLE;
-        for cond in &conditions {
-            if cond.result == ConditionResult::FAILED {
-                state = RequirementsState::UNMERGEABLE
-            }
-        }
+        let required = || conditions.iter().filter(|c| c.required);
+        let state = if required().all(|c| c.result.is_satisfied()) {
+            RequirementsState::MERGEABLE
+        } else if required()
+            .all(|c| c.result.is_satisfied() || c.result == ConditionResult::PENDING)
+        {
+            RequirementsState::PENDING
+        } else {
+            RequirementsState::UNMERGEABLE
+        };
         MergeBoxRes {
             merge_requirements: Some(MergeRequirements { conditions, state }),
         }
@@ -226,17 +308,25 @@ pub struct Condition {
     pub description: String,
     pub message: String,
     pub result: ConditionResult,
+    /// Optional conditions are shown but do not block merging.
+    pub required: bool,
 }
 
 impl From<check_result::Model> for Condition {
     fn from(value: check_result::Model) -> Self {
         let check_type: CheckType = value.check_type_code.into();
+        let display_name = if value.check_name.is_empty() {
+            check_type.display_name().to_string()
+        } else {
+            value.check_name
+        };
         Self {
             condition_type: check_type.clone(),
-            display_name: check_type.clone().display_name().to_string(),
+            display_name,
             description: check_type.description().to_string(),
             message: value.message,
-            result: value.status.parse().unwrap(),
+            result: value.status.into(),
+            required: value.required,
         }
     }
 }
@@ -245,6 +335,8 @@ impl From<check_result::Model> for Condition {
 pub enum RequirementsState {
     UNMERGEABLE,
     MERGEABLE,
+    /// Nothing has failed, but some checks have not reported yet.
+    PENDING,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
@@ -258,6 +350,16 @@ pub struct UpdateClStatusPayload {
     pub status: String,
 }
 
+/// Status reported by an external check for a CL revision.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+pub struct ExternalCheckPayload {
+    /// The revision the check ran on, normally the `to_hash` it was sent
+    pub commit_id: String,
+    pub status: ConditionResult,
+    #[serde(default)]
+    pub message: String,
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 pub struct ReviewerPayload {
     pub reviewer_usernames: Vec<String>,
@@ -273,6 +375,44 @@ pub struct ChangeReviewerStatePayload {
     pub approved: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewState {
+    Pending,
+    Approved,
+    ChangesRequested,
+    Commented,
+}
+
+impl From<ReviewStateEnum> for ReviewState {
+    fn from(value: ReviewStateEnum) -> Self {
+        match value {
+            ReviewStateEnum::Pending => ReviewState::Pending,
+            ReviewStateEnum::Approved => ReviewState::Approved,
+            ReviewStateEnum::ChangesRequested => ReviewState::ChangesRequested,
+            ReviewStateEnum::Commented => ReviewState::Commented,
+        }
+    }
+}
+
+impl From<ReviewState> for ReviewStateEnum {
+    fn from(value: ReviewState) -> Self {
+        match value {
+            ReviewState::Pending => ReviewStateEnum::Pending,
+            ReviewState::Approved => ReviewStateEnum::Approved,
+            ReviewState::ChangesRequested => ReviewStateEnum::ChangesRequested,
+            ReviewState::Commented => ReviewStateEnum::Commented,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+pub struct SubmitReviewPayload {
+    /// `pending` withdraws an earlier verdict.
+    pub state: ReviewState,
+    pub comment: Option<String>,
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 pub struct ChangeReviewStatePayload {
     pub conversation_id: i64,
@@ -290,6 +430,9 @@ pub struct ReviewerInfo {
     pub username: String,
     pub approved: bool,
     pub system_required: bool,
+    /// The patchset the approval was given on.
+    pub approved_patchset: Option<i32>,
+    pub review_state: ReviewState,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
@@ -331,3 +474,42 @@ pub struct BuckFile {
     pub buck_config: ObjectHash,
     pub path: PathBuf,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(result: ConditionResult, required: bool) -> Condition {
+        Condition {
+            condition_type: CheckType::CiStatus,
+            display_name: String::new(),
+            description: String::new(),
+            message: String::new(),
+            result,
+            required,
+        }
+    }
+
+    fn state(results: Vec<ConditionResult>) -> RequirementsState {
+        MergeBoxRes::from_condition(results.into_iter().map(|r| condition(r, true)).collect())
+            .merge_requirements
+            .unwrap()
+            .state
+    }
+
+    #[test]
+    fn test_merge_box_state() {
+        use ConditionResult::*;
+        assert_eq!(state(vec![PASSED, SKIPPED]), RequirementsState::MERGEABLE);
+        assert_eq!(state(vec![PASSED, PENDING]), RequirementsState::PENDING);
+        assert_eq!(state(vec![PENDING, FAILED]), RequirementsState::UNMERGEABLE);
+        assert_eq!(state(vec![PASSED, ERROR]), RequirementsState::UNMERGEABLE);
+
+        let optional =
+            MergeBoxRes::from_condition(vec![condition(PASSED, true), condition(FAILED, false)]);
+        assert_eq!(
+            optional.merge_requirements.unwrap().state,
+            RequirementsState::MERGEABLE
+        );
+    }
+}
@@ -3,7 +3,11 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use callisto::{mega_conversation, reactions, sea_orm_active_enums::ConvTypeEnum};
+use callisto::{
+    mega_conversation, reactions,
+    sea_orm_active_enums::{ConvTypeEnum, DiffSideEnum},
+};
+use jupiter::model::conv_dto::{CommentAnchor, ConvWithReactions};
 
 #[derive(Serialize, ToSchema)]
 pub struct ConversationItem {
@@ -15,6 +19,10 @@ pub struct ConversationItem {
     pub created_at: i64,
     pub updated_at: i64,
     pub grouped_reactions: Vec<ReactionItem>,
+    /// Set on replies to an inline comment.
+    pub parent_id: Option<i64>,
+    /// Set on inline comments.
+    pub anchor: Option<CommentAnchorItem>,
 }
 
 impl ConversationItem {
@@ -23,6 +31,7 @@ impl ConversationItem {
         reactions: Vec<reactions::Model>,
         viewer: &str,
     ) -> Self {
+        let anchor = CommentAnchorItem::from_model(&conversation);
         let mut item = Self {
             id: conversation.id,
             username: conversation.username,
@@ -32,6 +41,8 @@ impl ConversationItem {
             created_at: conversation.created_at.and_utc().timestamp(),
             updated_at: conversation.updated_at.and_utc().timestamp(),
             grouped_reactions: vec![],
+            parent_id: conversation.parent_id,
+            anchor,
         };
         item.grouped_emoji(viewer, reactions);
         item
@@ -63,6 +74,115 @@ impl ConversationItem {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffSide {
+    Old,
+    New,
+}
+
+impl From<DiffSideEnum> for DiffSide {
+    fn from(value: DiffSideEnum) -> Self {
+        match value {
+            DiffSideEnum::Old => DiffSide::Old,
+            DiffSideEnum::New => DiffSide::New,
+        }
+    }
+}
+
+impl From<DiffSide> for DiffSideEnum {
+    fn from(value: DiffSide) -> Self {
+        match value {
+            DiffSide::Old => DiffSideEnum::Old,
+            DiffSide::New => DiffSideEnum::New,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CommentAnchorItem {
+    pub file_path: String,
+    pub side: DiffSide,
+    pub start_line: i32,
+    pub end_line: i32,
+    /// The revision the lines refer to. It stays at the revision the
+    /// comment was made against once the comment is outdated.
+    pub commit_id: String,
+    pub outdated: bool,
+}
+
+impl CommentAnchorItem {
+    fn from_model(conversation: &mega_conversation::Model) -> Option<Self> {
+        Some(Self {
+            file_path: conversation.file_path.clone()?,
+            side: conversation.diff_side.clone()?.into(),
+            start_line: conversation.start_line?,
+            end_line: conversation.end_line?,
+            commit_id: conversation.commit_id.clone()?,
+            outdated: conversation.outdated,
+        })
+    }
+}
+
+/// An inline comment and its replies.
+#[derive(Serialize, ToSchema)]
+pub struct InlineThread {
+    pub anchor: CommentAnchorItem,
+    /// The comment that started the thread comes first.
+    pub comments: Vec<ConversationItem>,
+}
+
+impl InlineThread {
+    /// Groups inline comments and replies, as returned by
+    /// `get_inline_comments`, into threads.
+    pub fn group(comments: Vec<ConvWithReactions>, viewer: &str) -> Vec<Self> {
+        let mut threads: Vec<Self> = vec![];
+        let mut replies: HashMap<i64, Vec<ConversationItem>> = HashMap::new();
+        for c in comments {
+            let item = ConversationItem::from_model(c.conversation, c.reactions, viewer);
+            match (item.parent_id, item.anchor.clone()) {
+                (Some(parent_id), _) => replies.entry(parent_id).or_default().push(item),
+                (None, Some(anchor)) => threads.push(Self {
+                    anchor,
+                    comments: vec![item],
+                }),
+                (None, None) => {}
+            }
+        }
+        for thread in &mut threads {
+            if let Some(replies) = replies.remove(&thread.comments[0].id) {
+                thread.comments.extend(replies);
+            }
+        }
+        threads
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct InlineCommentPayload {
+    pub content: String,
+    pub file_path: String,
+    pub side: DiffSide,
+    /// 1-based, inclusive.
+    pub start_line: i32,
+    pub end_line: i32,
+    /// The CL's current `from_hash` for the old side, or `to_hash` for the
+    /// new side.
+    pub commit_id: String,
+}
+
+impl InlineCommentPayload {
+    pub fn anchor(&self) -> CommentAnchor {
+        CommentAnchor {
+            file_path: self.file_path.clone(),
+            side: self.side.into(),
+            start_line: self.start_line,
+            end_line: self.end_line,
+            commit_id: self.commit_id.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, Default, ToSchema)]
 pub struct ReactionItem {
     pub viewer_reaction_id: String,
@@ -1,5 +1,6 @@
 use callisto::merge_queue::Model;
 use callisto::sea_orm_active_enums::{QueueFailureTypeEnum, QueueStatusEnum};
+use jupiter::model::merge_queue_dto::BatchRun;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -32,6 +33,18 @@ pub struct QueueError {
     pub occurred_at: String,
 }
 
+/// Speculative test run of a batch for API
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QueueBatchRun {
+    pub batch_id: i64,
+    /// CLs stacked on trunk for this run, in queue order
+    pub cl_links: Vec<String>,
+    pub commit_id: Option<String>,
+    pub passed: bool,
+    pub message: String,
+    pub tested_at: String,
+}
+
 /// Queue item for API
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct QueueItem {
@@ -43,6 +56,10 @@ pub struct QueueItem {
     pub updated_at: String,
     pub retry_count: i32,
     pub error: Option<QueueError>,
+    /// Last speculative batch the CL was tested in
+    pub batch_id: Option<i64>,
+    /// Every batch run the CL took part in, including bisect steps
+    pub batch_runs: Vec<QueueBatchRun>,
 }
 
 /// Queue statistics for API
@@ -116,6 +133,24 @@ impl From<QueueFailureTypeEnum> for FailureType {
     }
 }
 
+impl From<BatchRun> for QueueBatchRun {
+    fn from(run: BatchRun) -> Self {
+        QueueBatchRun {
+            batch_id: run.batch_id,
+            cl_links: run.cl_links,
+            commit_id: run.commit_id,
+            passed: run.passed,
+            message: run.message,
+            tested_at: run
+                .tested_at
+                .and_utc()
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+        }
+    }
+}
+
 impl From<Model> for QueueItem {
     fn from(item: Model) -> Self {
         let error = item.failure_type.map(|ft| {
@@ -152,6 +187,14 @@ impl From<Model> for QueueItem {
                 .to_string(),
             retry_count: item.retry_count,
             error,
+            batch_id: item.batch_id,
+            batch_runs: item
+                .batch_history
+                .and_then(|history| serde_json::from_value::<Vec<BatchRun>>(history).ok())
+                .unwrap_or_default()
+                .into_iter()
+                .map(QueueBatchRun::from)
+                .collect(),
         }
     }
 }
@@ -1,4 +1,5 @@
 pub mod blame;
+pub mod branch_protection;
 pub mod buck;
 pub mod change_list;
 pub mod cl;
@@ -10,6 +11,10 @@ pub mod gpg;
 pub mod issue;
 pub mod label;
 pub mod merge_queue;
+pub mod notification;
+pub mod search;
+pub mod symbol;
 pub mod tag;
 pub mod third_party;
 pub mod user;
+pub mod webhook;
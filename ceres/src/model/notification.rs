@@ -0,0 +1,146 @@
+use callisto::sea_orm_active_enums::{
+    NotificationReasonEnum, NotificationStateEnum, SubscriptionTargetEnum,
+};
+use callisto::{notification_subscriptions, notifications};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationReason {
+    Mention,
+    Assigned,
+    ReviewRequested,
+    Subscribed,
+}
+
+impl From<NotificationReasonEnum> for NotificationReason {
+    fn from(value: NotificationReasonEnum) -> Self {
+        match value {
+            NotificationReasonEnum::Mention => NotificationReason::Mention,
+            NotificationReasonEnum::Assigned => NotificationReason::Assigned,
+            NotificationReasonEnum::ReviewRequested => NotificationReason::ReviewRequested,
+            NotificationReasonEnum::Subscribed => NotificationReason::Subscribed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationState {
+    Unread,
+    Read,
+    Done,
+}
+
+impl From<NotificationStateEnum> for NotificationState {
+    fn from(value: NotificationStateEnum) -> Self {
+        match value {
+            NotificationStateEnum::Unread => NotificationState::Unread,
+            NotificationStateEnum::Read => NotificationState::Read,
+            NotificationStateEnum::Done => NotificationState::Done,
+        }
+    }
+}
+
+impl From<NotificationState> for NotificationStateEnum {
+    fn from(value: NotificationState) -> Self {
+        match value {
+            NotificationState::Unread => NotificationStateEnum::Unread,
+            NotificationState::Read => NotificationStateEnum::Read,
+            NotificationState::Done => NotificationStateEnum::Done,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionTarget {
+    /// Every CL under a path.
+    Path,
+    /// A single issue or CL, by link.
+    Item,
+}
+
+impl From<SubscriptionTargetEnum> for SubscriptionTarget {
+    fn from(value: SubscriptionTargetEnum) -> Self {
+        match value {
+            SubscriptionTargetEnum::Path => SubscriptionTarget::Path,
+            SubscriptionTargetEnum::Item => SubscriptionTarget::Item,
+        }
+    }
+}
+
+impl From<SubscriptionTarget> for SubscriptionTargetEnum {
+    fn from(value: SubscriptionTarget) -> Self {
+        match value {
+            SubscriptionTarget::Path => SubscriptionTargetEnum::Path,
+            SubscriptionTarget::Item => SubscriptionTargetEnum::Item,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct NotificationQuery {
+    /// Without a state, every notification not marked done is listed.
+    pub state: Option<NotificationState>,
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NotificationInfo {
+    pub id: i64,
+    pub reason: NotificationReason,
+    /// Link of the issue or CL the notification is about.
+    pub link: String,
+    pub actor: Option<String>,
+    pub summary: String,
+    pub state: NotificationState,
+    pub created_at: i64,
+}
+
+impl From<notifications::Model> for NotificationInfo {
+    fn from(value: notifications::Model) -> Self {
+        Self {
+            id: value.id,
+            reason: value.reason.into(),
+            link: value.link,
+            actor: value.actor,
+            summary: value.summary,
+            state: value.state.into(),
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NotificationList {
+    pub total: u64,
+    pub unread: u64,
+    pub items: Vec<NotificationInfo>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MarkNotifications {
+    /// Leave empty to mark every unread notification.
+    #[serde(default)]
+    pub ids: Vec<i64>,
+    pub state: NotificationState,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Subscription {
+    pub target_type: SubscriptionTarget,
+    /// A path, or the link of an issue or CL.
+    pub target: String,
+}
+
+impl From<notification_subscriptions::Model> for Subscription {
+    fn from(value: notification_subscriptions::Model) -> Self {
+        Self {
+            target_type: value.target_type.into(),
+            target: value.target,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CodeSearchQuery {
+    /// Text to look for, or a regular expression when `regex` is set.
+    pub q: String,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Only search under this path.
+    pub path: Option<String>,
+    /// Only search files in this language, e.g. `rust`.
+    pub lang: Option<String>,
+    /// Most files to return, 50 by default and at most 200.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LineMatch {
+    /// 1-based.
+    pub line_number: usize,
+    pub line: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CodeSearchHit {
+    pub path: String,
+    pub language: Option<String>,
+    pub blob_id: String,
+    pub matches: Vec<LineMatch>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CodeSearchResult {
+    pub hits: Vec<CodeSearchHit>,
+    /// Set when more files may match than were returned.
+    pub truncated: bool,
+}
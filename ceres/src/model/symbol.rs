@@ -0,0 +1,88 @@
+use callisto::code_symbols;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Looks a symbol up either by name or by a position in a file, as when
+/// clicking on an identifier in a preview or CL diff.
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SymbolQuery {
+    /// Full hash of the commit the code is read at, e.g. the head of a CL.
+    pub commit: String,
+    /// Short name of the symbol, e.g. `parse`.
+    pub name: Option<String>,
+    /// File holding the position to resolve.
+    pub path: Option<String>,
+    /// 0-based line of the position.
+    pub line: Option<i32>,
+    /// 0-based character of the position.
+    pub character: Option<i32>,
+    /// Most locations to return, 100 by default and at most 1000.
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolIndexFormat {
+    Scip,
+    /// JSON lines, as written by `lsif` dumpers.
+    Lsif,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SymbolUploadQuery {
+    /// Full hash of the commit the index was built from.
+    pub commit: String,
+    /// Monorepo directory the index's paths are relative to.
+    #[serde(default = "default_root")]
+    pub root: String,
+    pub format: SymbolIndexFormat,
+}
+
+fn default_root() -> String {
+    "/".to_owned()
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct IndexSymbolsPayload {
+    /// Full hash of the commit to index.
+    pub commit: String,
+    /// Directory whose Rust files are indexed.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SymbolLocation {
+    pub path: String,
+    pub symbol: String,
+    pub name: String,
+    pub kind: Option<String>,
+    /// 0-based, like every position below.
+    pub start_line: i32,
+    pub start_character: i32,
+    pub end_line: i32,
+    pub end_character: i32,
+    /// What produced the location: `scip`, `lsif` or `tree-sitter`.
+    pub source: String,
+}
+
+impl From<code_symbols::Model> for SymbolLocation {
+    fn from(value: code_symbols::Model) -> Self {
+        Self {
+            path: value.path,
+            symbol: value.symbol,
+            name: value.name,
+            kind: value.kind,
+            start_line: value.start_line,
+            start_character: value.start_character,
+            end_line: value.end_line,
+            end_character: value.end_character,
+            source: value.source,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SymbolIndexResult {
+    /// Definitions and references stored.
+    pub occurrences: usize,
+}
@@ -1,4 +1,6 @@
 use callisto::{access_token, ssh_keys};
+use common::enums::TokenScope;
+use jupiter::model::token_dto::parse_scopes;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -29,20 +31,49 @@ impl From<ssh_keys::Model> for ListSSHKey {
     }
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GenerateToken {
+    pub scopes: Vec<TokenScope>,
+    /// Limits the token to a part of the monorepo.
+    pub path_prefix: Option<String>,
+    /// Defaults to 90 days, at most 366.
+    pub expires_in_days: Option<u32>,
+}
+
+/// A newly issued token. The token itself is only returned here.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct GeneratedToken {
+    pub id: i64,
+    pub token: String,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RevokeTokens {
+    pub path_prefix: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ListToken {
     pub id: i64,
+    /// The leading part of the token, enough to recognise it.
     pub token: String,
+    pub scopes: Vec<TokenScope>,
+    pub path_prefix: Option<String>,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
     pub created_at: i64,
 }
 
 impl From<access_token::Model> for ListToken {
     fn from(value: access_token::Model) -> Self {
-        let mut mask_token = value.token;
-        mask_token.replace_range(7..32, "-******-");
         Self {
             id: value.id,
-            token: mask_token,
+            token: format!("{}******", value.token_prefix),
+            scopes: parse_scopes(&value.scopes),
+            path_prefix: value.path_prefix,
+            expires_at: value.expires_at.map(|t| t.and_utc().timestamp()),
+            last_used_at: value.last_used_at.map(|t| t.and_utc().timestamp()),
             created_at: value.created_at.and_utc().timestamp(),
         }
     }
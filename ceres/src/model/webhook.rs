@@ -0,0 +1,102 @@
+use callisto::sea_orm_active_enums::DeliveryStatusEnum;
+use callisto::{webhook, webhook_delivery};
+use common::enums::WebhookEvent;
+use jupiter::storage::webhook_storage::parse_events;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhook {
+    /// Only events under this path are sent. Issue events aren't tied to
+    /// a path and are sent regardless.
+    pub path_prefix: String,
+    pub url: String,
+    /// Key of the HMAC in the `X-Mega-Signature` header.
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeliveryQuery {
+    /// Defaults to the latest 50 deliveries.
+    pub limit: Option<u64>,
+}
+
+/// A webhook, without its secret.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookInfo {
+    pub id: i64,
+    pub path_prefix: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub active: bool,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+impl From<webhook::Model> for WebhookInfo {
+    fn from(value: webhook::Model) -> Self {
+        Self {
+            id: value.id,
+            path_prefix: value.path_prefix,
+            url: value.url,
+            events: parse_events(&value.events),
+            active: value.active,
+            created_by: value.created_by,
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Queued,
+    Delivered,
+    Failed,
+}
+
+impl From<DeliveryStatusEnum> for DeliveryStatus {
+    fn from(value: DeliveryStatusEnum) -> Self {
+        match value {
+            DeliveryStatusEnum::Pending => DeliveryStatus::Pending,
+            DeliveryStatusEnum::Queued => DeliveryStatus::Queued,
+            DeliveryStatusEnum::Delivered => DeliveryStatus::Delivered,
+            DeliveryStatusEnum::Failed => DeliveryStatus::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeliveryInfo {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<i64>,
+    pub delivered_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl From<webhook_delivery::Model> for DeliveryInfo {
+    fn from(value: webhook_delivery::Model) -> Self {
+        Self {
+            id: value.id,
+            webhook_id: value.webhook_id,
+            event: value.event,
+            payload: value.payload,
+            status: value.status.into(),
+            attempts: value.attempts,
+            response_status: value.response_status,
+            last_error: value.last_error,
+            next_attempt_at: value.next_attempt_at.map(|t| t.and_utc().timestamp()),
+            delivered_at: value.delivered_at.map(|t| t.and_utc().timestamp()),
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
@@ -32,9 +32,12 @@ use jupiter::{
 use jupiter::{redis::lock::RedLock, storage::Storage};
 
 use crate::{
-    api_service::{cache::GitObjectCache, mono_api_service::MonoApiService, tree_ops},
+    api_service::{
+        cache::GitObjectCache, mono_api_service::MonoApiService, search_ops::CodeIndexer, tree_ops,
+    },
     pack::RepoHandler,
     protocol::{
+        filter::ObjectFilter,
         import_refs::{CommandType, RefCommand, Refs},
         repo::Repo,
     },
@@ -118,6 +121,8 @@ impl RepoHandler for ImportRepo {
         &self,
         want: Vec<String>,
         have: Vec<String>,
+        shallow: HashSet<String>,
+        filter: Option<ObjectFilter>,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
         let mut want_clone = want.clone();
         let pack_config = &self.storage.config().pack;
@@ -133,10 +138,14 @@ impl RepoHandler for ImportRepo {
             .into_iter()
             .map(Commit::from_git_model)
             .collect();
+        let (wanted_trees, wanted_blobs) = self.wanted_objects(&want, &want_commits).await?;
         let mut traversal_list: Vec<Commit> = want_commits.clone();
 
         // traverse commit's all parents to find the commit that client does not have
         while let Some(temp) = traversal_list.pop() {
+            if shallow.contains(&temp.id.to_string()) {
+                continue;
+            }
             for p_commit_id in temp.parent_commit_ids {
                 let p_commit_id = p_commit_id.to_string();
 
@@ -184,8 +193,14 @@ impl RepoHandler for ImportRepo {
             .unwrap();
         // traverse to get exist_objs
         for have_tree in have_trees {
-            self.traverse(Tree::from_git_model(have_tree), &mut exist_objs, None)
-                .await?;
+            self.traverse(
+                Tree::from_git_model(have_tree),
+                &mut exist_objs,
+                None,
+                None,
+                0,
+            )
+            .await?;
         }
 
         let mut counted_obj = HashSet::new();
@@ -196,9 +211,16 @@ impl RepoHandler for ImportRepo {
                 &exist_objs,
                 &mut counted_obj,
                 &obj_num,
+                filter,
+                0,
             )
             .await;
         }
+        for t in wanted_trees.clone() {
+            self.traverse_for_count(t, &exist_objs, &mut counted_obj, &obj_num, filter, 0)
+                .await;
+        }
+        obj_num.fetch_add(wanted_blobs.len(), Ordering::SeqCst);
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
         let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
@@ -209,6 +231,8 @@ impl RepoHandler for ImportRepo {
                 want_trees.get(&c.tree_id).unwrap().clone(),
                 &mut exist_objs,
                 Some(&entry_tx),
+                filter,
+                0,
             )
             .await?;
             entry_tx
@@ -219,6 +243,11 @@ impl RepoHandler for ImportRepo {
                 .await
                 .unwrap();
         }
+        for t in wanted_trees {
+            self.traverse(t, &mut exist_objs, Some(&entry_tx), filter, 0)
+                .await?;
+        }
+        self.send_blobs(wanted_blobs, None, &entry_tx).await?;
         drop(entry_tx);
 
         Ok(ReceiverStream::new(stream_rx))
@@ -318,6 +347,15 @@ impl RepoHandler for ImportRepo {
             .unwrap()
     }
 
+    async fn get_commit(&self, hash: &str) -> Result<Option<Commit>, MegaError> {
+        let commit = self
+            .storage
+            .git_db_storage()
+            .get_commit_by_hash(self.repo.repo_id, hash)
+            .await?;
+        Ok(commit.map(Commit::from_git_model))
+    }
+
     async fn traverses_tree_and_update_filepath(&self) -> Result<(), MegaError> {
         //let (current_head, refs) = self.head_hash().await;
         let (current_head, _refs) = self.refs_with_head_hash().await;
@@ -424,6 +462,7 @@ impl ImportRepo {
         storage
             .attach_to_monorepo_parent_with_txn(root_ref, new_commit, save_trees.into())
             .await?;
+        CodeIndexer::spawn_sync(self.storage.clone());
         Ok(())
     }
 }
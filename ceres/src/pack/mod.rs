@@ -1,14 +1,14 @@
 use async_trait::async_trait;
 use bytes::Bytes;
-use futures::TryStreamExt;
 use futures::{Stream, future::join_all};
+use futures::{StreamExt, TryStreamExt};
 use jupiter::object_storage::MultiObjectByteStream;
 use std::collections::HashMap;
 use std::{
-    collections::HashSet,
+    collections::{HashSet, VecDeque},
     pin::Pin,
     sync::{
-        Arc,
+        Arc, LazyLock, Mutex,
         atomic::{AtomicUsize, Ordering},
     },
 };
@@ -16,6 +16,7 @@ use sysinfo::System;
 use tokio::sync::{Semaphore, mpsc::UnboundedReceiver};
 use tokio_stream::wrappers::ReceiverStream;
 
+use crate::protocol::filter::ObjectFilter;
 use crate::protocol::import_refs::{RefCommand, Refs};
 use common::{
     config::PackConfig,
@@ -29,7 +30,9 @@ use git_internal::{
     errors::GitError,
     internal::{
         object::{
+            ObjectTrait,
             blob::Blob,
+            commit::Commit,
             tree::{Tree, TreeItemMode},
         },
         pack::entry::Entry,
@@ -39,6 +42,94 @@ use git_internal::{
 pub mod import_repo;
 pub mod monorepo;
 
+/// Most commits walked when checking that a ref update is a fast-forward.
+const FAST_FORWARD_WALK_LIMIT: usize = 10_000;
+/// Seconds a descendant may be committed before its ancestor due to clock skew.
+const FAST_FORWARD_CLOCK_SKEW: usize = 24 * 60 * 60;
+/// Ref tip sets whose reachability walk is kept between fetches.
+const CACHED_REACHABILITY_WALKS: usize = 16;
+
+/// Objects found reachable from a set of ref tips, resumed by later fetches.
+///
+/// The walk only ever grows while the tips stay the same, so each object of
+/// the history is loaded at most once however many fetches ask for it.
+#[derive(Default)]
+struct ReachabilityWalk {
+    /// Commits, trees and blobs found reachable so far.
+    seen: HashSet<String>,
+    /// Commits whose trees and parents are not walked yet.
+    commits: VecDeque<String>,
+}
+
+impl ReachabilityWalk {
+    fn new(tips: &[String]) -> Self {
+        Self {
+            seen: HashSet::new(),
+            commits: tips.iter().cloned().collect(),
+        }
+    }
+}
+
+type SharedWalk = Arc<tokio::sync::Mutex<ReachabilityWalk>>;
+
+/// Most recently used reachability walks, keyed by their sorted ref tips.
+type CachedWalks = VecDeque<(Vec<String>, SharedWalk)>;
+
+static REACHABILITY_WALKS: LazyLock<Mutex<CachedWalks>> =
+    LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+/// Returns the cached walk of `tips`, starting a new one if there is none.
+fn reachability_walk(tips: &[String]) -> SharedWalk {
+    let mut walks = REACHABILITY_WALKS.lock().unwrap();
+    let walk = match walks.iter().position(|(key, _)| key == tips) {
+        Some(index) => walks.remove(index).unwrap().1,
+        None => Arc::new(tokio::sync::Mutex::new(ReachabilityWalk::new(tips))),
+    };
+    walks.push_front((tips.to_vec(), walk.clone()));
+    walks.truncate(CACHED_REACHABILITY_WALKS);
+    walk
+}
+
+/// Adds a commit with its whole tree to `walk`, queueing its parents.
+async fn walk_commit<R: RepoHandler + ?Sized>(
+    repo: &R,
+    walk: &mut ReachabilityWalk,
+    hash: String,
+    pending: &mut HashSet<&str>,
+) -> Result<(), MegaError> {
+    if !walk.seen.insert(hash.clone()) {
+        return Ok(());
+    }
+    pending.remove(hash.as_str());
+    let Some(commit) = repo.get_commit(&hash).await? else {
+        return Ok(());
+    };
+    let mut trees = vec![commit.tree_id.to_string()];
+    while !trees.is_empty() {
+        trees.retain(|id| walk.seen.insert(id.clone()));
+        for id in &trees {
+            pending.remove(id.as_str());
+        }
+        for item in repo
+            .get_trees_by_hashes(std::mem::take(&mut trees))
+            .await?
+            .into_iter()
+            .flat_map(|t| t.tree_items)
+        {
+            let id = item.id.to_string();
+            if item.is_tree() {
+                trees.push(id);
+            } else {
+                pending.remove(id.as_str());
+                walk.seen.insert(id);
+            }
+        }
+    }
+    walk.commits
+        .extend(commit.parent_commit_ids.iter().map(ToString::to_string));
+    Ok(())
+}
+
 #[async_trait]
 pub trait RepoHandler: Send + Sync + 'static {
     fn is_monorepo(&self) -> bool;
@@ -141,10 +232,17 @@ pub trait RepoHandler: Send + Sync + 'static {
     ///
     async fn full_pack(&self, want: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError>;
 
+    /// Packs the objects reachable from `want` but not from `have`.
+    ///
+    /// The parents of commits in `shallow` are left out, for clients that
+    /// only keep history down to those commits, and so are the trees and
+    /// blobs excluded by `filter`.
     async fn incremental_pack(
         &self,
         want: Vec<String>,
         have: Vec<String>,
+        shallow: HashSet<String>,
+        filter: Option<ObjectFilter>,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError>;
 
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError>;
@@ -159,12 +257,73 @@ pub trait RepoHandler: Send + Sync + 'static {
         hashes: Vec<String>,
     ) -> Result<HashMap<String, EntryMeta>, MegaError>;
 
+    /// Returns the size of each tree or blob found among `hashes`.
+    ///
+    /// Unknown hashes, and objects of other types, are left out of the map.
+    async fn object_sizes(&self, hashes: Vec<String>) -> Result<HashMap<String, usize>, MegaError> {
+        let mut sizes = HashMap::new();
+        for tree in self.get_trees_by_hashes(hashes.clone()).await? {
+            sizes.insert(tree.id.to_string(), tree.to_data()?.len());
+        }
+        let rest: Vec<String> = hashes
+            .into_iter()
+            .filter(|hash| !sizes.contains_key(hash))
+            .collect();
+        if rest.is_empty() {
+            return Ok(sizes);
+        }
+        let mut blobs = self.get_blobs_by_hashes(rest).await?;
+        while let Some(item) = blobs.next().await {
+            if let Ok((key, _, meta)) = item {
+                sizes.insert(key.key, meta.size as usize);
+            }
+        }
+        Ok(sizes)
+    }
+
     async fn update_refs(&self, refs: &RefCommand) -> Result<(), GitError>;
 
     async fn check_commit_exist(&self, hash: &str) -> bool;
 
     async fn check_default_branch(&self) -> bool;
 
+    async fn get_commit(&self, hash: &str) -> Result<Option<Commit>, MegaError>;
+
+    /// Whether `new_id` descends from `old_id`, i.e. the update is not a force-push.
+    ///
+    /// Commits committed well before `old_id` cannot descend from it, so the walk
+    /// stops there instead of going through the whole history.
+    async fn is_fast_forward(&self, old_id: &str, new_id: &str) -> Result<bool, MegaError> {
+        let Some(old) = self.get_commit(old_id).await? else {
+            return Ok(false);
+        };
+        let horizon = old
+            .committer
+            .timestamp
+            .saturating_sub(FAST_FORWARD_CLOCK_SKEW);
+        let mut queue = VecDeque::from([new_id.to_owned()]);
+        let mut visited = HashSet::new();
+        while let Some(hash) = queue.pop_front() {
+            if hash == old_id {
+                return Ok(true);
+            }
+            if !visited.insert(hash.clone()) {
+                continue;
+            }
+            if visited.len() > FAST_FORWARD_WALK_LIMIT {
+                return Err(MegaError::Other(format!(
+                    "{new_id} is more than {FAST_FORWARD_WALK_LIMIT} commits ahead of {old_id}"
+                )));
+            }
+            if let Some(commit) = self.get_commit(&hash).await?
+                && commit.committer.timestamp >= horizon
+            {
+                queue.extend(commit.parent_commit_ids.iter().map(ToString::to_string));
+            }
+        }
+        Ok(false)
+    }
+
     fn find_head_hash(&self, refs: Vec<Refs>) -> (String, Vec<Refs>) {
         let mut head_hash = ZERO_ID.to_string();
         for git_ref in refs.iter() {
@@ -216,12 +375,22 @@ pub trait RepoHandler: Send + Sync + 'static {
         exist_objs: &HashSet<String>,
         counted_obj: &mut HashSet<String>,
         obj_num: &AtomicUsize,
+        filter: Option<ObjectFilter>,
+        depth: usize,
     ) {
+        if !filter.is_none_or(|f| f.includes_tree(depth)) {
+            return;
+        }
         let mut search_tree_ids = vec![];
         let mut search_blob_ids = vec![];
         for item in &tree.tree_items {
             let hash = item.id.to_string();
-            if !exist_objs.contains(&hash) && counted_obj.insert(hash.clone()) {
+            let included = if item.mode == TreeItemMode::Tree {
+                filter.is_none_or(|f| f.includes_tree(depth + 1))
+            } else {
+                filter.is_none_or(|f| f.includes_blob_at(depth + 1))
+            };
+            if included && !exist_objs.contains(&hash) && counted_obj.insert(hash.clone()) {
                 if item.mode == TreeItemMode::Tree {
                     search_tree_ids.push(hash.clone())
                 } else {
@@ -229,15 +398,103 @@ pub trait RepoHandler: Send + Sync + 'static {
                 }
             }
         }
-        obj_num.fetch_add(search_blob_ids.len(), Ordering::SeqCst);
+        let blob_num = match filter.and_then(|f| f.blob_limit()) {
+            Some(limit) => self.count_blobs_below(search_blob_ids, limit).await,
+            None => search_blob_ids.len(),
+        };
+        obj_num.fetch_add(blob_num, Ordering::SeqCst);
         let trees = self.get_trees_by_hashes(search_tree_ids).await.unwrap();
         for t in trees {
-            self.traverse_for_count(t, exist_objs, counted_obj, obj_num)
+            self.traverse_for_count(t, exist_objs, counted_obj, obj_num, filter, depth + 1)
                 .await;
         }
         obj_num.fetch_add(1, Ordering::SeqCst);
     }
 
+    /// Counts the blobs smaller than `limit` bytes, for `blob:limit` filters.
+    async fn count_blobs_below(&self, hashes: Vec<String>, limit: u64) -> usize {
+        if hashes.is_empty() {
+            return 0;
+        }
+        self.get_blobs_by_hashes(hashes)
+            .await
+            .unwrap()
+            .filter(|item| {
+                futures::future::ready(
+                    matches!(item, Ok((_, _, meta)) if (meta.size as u64) < limit),
+                )
+            })
+            .count()
+            .await
+    }
+
+    /// Checks that every want is reachable from one of the advertised refs.
+    ///
+    /// Objects are shared by every path of the monorepo, so knowing a hash
+    /// must not be enough to fetch it from outside the requested repository.
+    /// Ref tips are accepted directly; other wants are looked up in the walk
+    /// cached for the current tips, which is only extended as far as needed.
+    async fn verify_wants(&self, want: &[String]) -> Result<(), ProtocolError> {
+        let (_, refs) = self.refs_with_head_hash().await;
+        let mut tips: Vec<String> = refs.into_iter().map(|r| r.ref_hash).collect();
+        tips.sort();
+        tips.dedup();
+        let mut pending: HashSet<&str> = want
+            .iter()
+            .map(String::as_str)
+            .filter(|hash| tips.binary_search_by(|tip| tip.as_str().cmp(hash)).is_err())
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let walk = reachability_walk(&tips);
+        let mut walk = walk.lock().await;
+        pending.retain(|hash| !walk.seen.contains(*hash));
+        while !pending.is_empty()
+            && let Some(hash) = walk.commits.pop_front()
+        {
+            if let Err(e) = walk_commit(self, &mut walk, hash, &mut pending).await {
+                // A commit walked halfway would hide its unvisited objects.
+                *walk = ReachabilityWalk::new(&tips);
+                return Err(e.into());
+            }
+        }
+        match pending.into_iter().next() {
+            Some(hash) => Err(ProtocolError::InvalidInput(format!("not our ref {hash}"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Splits the wants that are not commits into trees and blobs.
+    ///
+    /// A partial clone asks for them directly when it needs an object its
+    /// filter left out.
+    async fn wanted_objects(
+        &self,
+        want: &[String],
+        want_commits: &[Commit],
+    ) -> Result<(Vec<Tree>, Vec<String>), MegaError> {
+        let commit_ids: HashSet<String> = want_commits.iter().map(|c| c.id.to_string()).collect();
+        let others: Vec<String> = want
+            .iter()
+            .filter(|hash| !commit_ids.contains(*hash))
+            .cloned()
+            .collect();
+        if others.is_empty() {
+            return Ok((vec![], vec![]));
+        }
+        let trees = self.get_trees_by_hashes(others.clone()).await?;
+        let tree_ids: HashSet<String> = trees.iter().map(|t| t.id.to_string()).collect();
+        let blobs = others
+            .into_iter()
+            .filter(|hash| !tree_ids.contains(hash))
+            .collect();
+        // drop unknown hashes, the pack header must count only what is sent
+        let sizes = self.object_sizes(blobs).await?;
+        Ok((trees, sizes.into_keys().collect()))
+    }
+
     /// Traverse a tree structure asynchronously.
     ///
     /// This function traverses a given tree, keeps track of processed objects, and optionally sends
@@ -261,56 +518,39 @@ pub trait RepoHandler: Send + Sync + 'static {
         tree: Tree,
         exist_objs: &mut HashSet<String>,
         sender: Option<&tokio::sync::mpsc::Sender<MetaAttached<Entry, EntryMeta>>>,
+        filter: Option<ObjectFilter>,
+        depth: usize,
     ) -> Result<(), MegaError> {
+        if !filter.is_none_or(|f| f.includes_tree(depth)) {
+            return Ok(());
+        }
         let mut search_tree_ids = vec![];
         let mut search_blob_ids = vec![];
 
         for item in &tree.tree_items {
             let hash = item.id.to_string();
-            if exist_objs.insert(hash.clone()) {
-                if item.mode == TreeItemMode::Tree {
+            if item.mode == TreeItemMode::Tree {
+                if filter.is_none_or(|f| f.includes_tree(depth + 1))
+                    && exist_objs.insert(hash.clone())
+                {
                     search_tree_ids.push(hash);
-                } else {
-                    search_blob_ids.push(hash);
                 }
+            } else if filter.is_none_or(|f| f.includes_blob_at(depth + 1))
+                && exist_objs.insert(hash.clone())
+            {
+                search_blob_ids.push(hash);
             }
         }
 
         if let Some(sender) = sender {
-            let blobs = self.get_blobs_by_hashes(search_blob_ids.clone()).await?;
-            let blobs_ext_data = self
-                .get_blob_metadata_by_hashes(search_blob_ids.clone())
-                .await?;
-
-            let default_meta = EntryMeta::default();
-            blobs
-                .try_for_each_concurrent(16, |(_, stream, _)| async {
-                    let data = stream
-                        .try_fold(Vec::new(), |mut acc, bytes| async move {
-                            acc.extend_from_slice(&bytes);
-                            Ok(acc)
-                        })
-                        .await?;
-                    let blob = Blob::from_content_bytes(data);
-                    let ext_data = blobs_ext_data
-                        .get(&blob.id.to_string())
-                        .unwrap_or(&default_meta);
-                    sender
-                        .send(MetaAttached {
-                            inner: blob.into(),
-                            meta: ext_data.to_owned(),
-                        })
-                        .await
-                        .unwrap();
-
-                    Ok(())
-                })
+            self.send_blobs(search_blob_ids, filter.and_then(|f| f.blob_limit()), sender)
                 .await?;
         }
 
         let trees = self.get_trees_by_hashes(search_tree_ids).await?;
         for t in trees {
-            self.traverse(t, exist_objs, sender).await?;
+            self.traverse(t, exist_objs, sender, filter, depth + 1)
+                .await?;
         }
 
         if let Some(sender) = sender {
@@ -325,5 +565,70 @@ pub trait RepoHandler: Send + Sync + 'static {
         Ok(())
     }
 
+    /// Sends the blobs smaller than `limit` bytes, or all of them without a limit.
+    async fn send_blobs(
+        &self,
+        hashes: Vec<String>,
+        limit: Option<u64>,
+        sender: &tokio::sync::mpsc::Sender<MetaAttached<Entry, EntryMeta>>,
+    ) -> Result<(), MegaError> {
+        let blobs = self.get_blobs_by_hashes(hashes.clone()).await?;
+        let blobs_ext_data = self.get_blob_metadata_by_hashes(hashes).await?;
+
+        let default_meta = EntryMeta::default();
+        let (blobs_ext_data, default_meta) = (&blobs_ext_data, &default_meta);
+        blobs
+            .try_for_each_concurrent(16, |(_, stream, meta)| async move {
+                if limit.is_some_and(|limit| meta.size as u64 >= limit) {
+                    return Ok(());
+                }
+                let data = stream
+                    .try_fold(Vec::new(), |mut acc, bytes| async move {
+                        acc.extend_from_slice(&bytes);
+                        Ok(acc)
+                    })
+                    .await?;
+                let blob = Blob::from_content_bytes(data);
+                let ext_data = blobs_ext_data
+                    .get(&blob.id.to_string())
+                    .unwrap_or(default_meta);
+                sender
+                    .send(MetaAttached {
+                        inner: blob.into(),
+                        meta: ext_data.to_owned(),
+                    })
+                    .await
+                    .unwrap();
+
+                Ok(())
+            })
+            .await?;
+        Ok(())
+    }
+
     async fn traverses_tree_and_update_filepath(&self) -> Result<(), MegaError>;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{CACHED_REACHABILITY_WALKS, reachability_walk};
+
+    #[test]
+    fn test_reachability_walk_is_reused_for_the_same_tips() {
+        let tips = vec!["reachability-test-a".to_string()];
+        let walk = reachability_walk(&tips);
+        assert!(Arc::ptr_eq(&walk, &reachability_walk(&tips)));
+        assert_eq!(
+            walk.try_lock().unwrap().commits.front(),
+            Some(&tips[0]),
+            "a new walk starts from the ref tips"
+        );
+
+        for i in 0..CACHED_REACHABILITY_WALKS {
+            reachability_walk(&[format!("reachability-test-other-{i}")]);
+        }
+        assert!(!Arc::ptr_eq(&walk, &reachability_walk(&tips)));
+    }
+}
@@ -13,11 +13,7 @@ use std::{
 use tokio::sync::{RwLock, mpsc};
 use tokio_stream::wrappers::ReceiverStream;
 
-use bellatrix::{
-    Bellatrix,
-    orion_client::OrionBuildRequest,
-    orion_client::{BuildInfo, ProjectRelativePath, Status},
-};
+use bellatrix::Bellatrix;
 use callisto::{
     entity_ext::generate_link, mega_cl, mega_commit, mega_refs, sea_orm_active_enums::ConvTypeEnum,
 };
@@ -42,11 +38,17 @@ use jupiter::utils::converter::FromMegaModel;
 use jupiter::{object_storage::MultiObjectByteStream, service::reviewer_service::ReviewerService};
 
 use crate::{
-    api_service::{ApiHandler, cache::GitObjectCache, mono_api_service::MonoApiService, tree_ops},
+    api_service::{
+        ApiHandler, cache::GitObjectCache, comment_ops, mono_api_service::MonoApiService,
+        review_ops, symbol_ops, tree_ops,
+    },
     merge_checker::CheckerRegistry,
     model::change_list::BuckFile,
     pack::RepoHandler,
-    protocol::import_refs::{RefCommand, Refs},
+    protocol::{
+        filter::ObjectFilter,
+        import_refs::{RefCommand, Refs},
+    },
 };
 
 pub struct MonoRepo {
@@ -217,13 +219,16 @@ impl RepoHandler for MonoRepo {
 
     // monorepo full pack should follow the shallow clone command 'git clone --depth=1'
     async fn full_pack(&self, want: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError> {
-        self.incremental_pack(want, Vec::new()).await
+        self.incremental_pack(want, Vec::new(), HashSet::new(), None)
+            .await
     }
 
     async fn incremental_pack(
         &self,
         want: Vec<String>,
         have: Vec<String>,
+        shallow: HashSet<String>,
+        filter: Option<ObjectFilter>,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
         let mut want_clone = want.clone();
         let pack_config = &self.storage.config().pack;
@@ -239,10 +244,14 @@ impl RepoHandler for MonoRepo {
             .into_iter()
             .map(Commit::from_mega_model)
             .collect();
+        let (wanted_trees, wanted_blobs) = self.wanted_objects(&want, &want_commits).await?;
         let mut traversal_list: Vec<Commit> = want_commits.clone();
 
         // traverse commit's all parents to find the commit that client does not have
         while let Some(temp) = traversal_list.pop() {
+            if shallow.contains(&temp.id.to_string()) {
+                continue;
+            }
             for p_commit_id in temp.parent_commit_ids {
                 let p_commit_id = p_commit_id.to_string();
 
@@ -283,8 +292,14 @@ impl RepoHandler for MonoRepo {
             .await
             .unwrap();
         for have_tree in have_trees {
-            self.traverse(Tree::from_mega_model(have_tree), &mut exist_objs, None)
-                .await?;
+            self.traverse(
+                Tree::from_mega_model(have_tree),
+                &mut exist_objs,
+                None,
+                None,
+                0,
+            )
+            .await?;
         }
 
         let mut counted_obj = HashSet::new();
@@ -295,9 +310,16 @@ impl RepoHandler for MonoRepo {
                 &exist_objs,
                 &mut counted_obj,
                 &obj_num,
+                filter,
+                0,
             )
             .await;
         }
+        for t in wanted_trees.clone() {
+            self.traverse_for_count(t, &exist_objs, &mut counted_obj, &obj_num, filter, 0)
+                .await;
+        }
+        obj_num.fetch_add(wanted_blobs.len(), Ordering::SeqCst);
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
         let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
@@ -308,6 +330,8 @@ impl RepoHandler for MonoRepo {
                 want_trees.get(&c.tree_id).unwrap().clone(),
                 &mut exist_objs,
                 Some(&entry_tx),
+                filter,
+                0,
             )
             .await?;
             entry_tx
@@ -318,6 +342,11 @@ impl RepoHandler for MonoRepo {
                 .await
                 .unwrap();
         }
+        for t in wanted_trees {
+            self.traverse(t, &mut exist_objs, Some(&entry_tx), filter, 0)
+                .await?;
+        }
+        self.send_blobs(wanted_blobs, None, &entry_tx).await?;
         drop(entry_tx);
 
         Ok(ReceiverStream::new(stream_rx))
@@ -412,6 +441,11 @@ impl RepoHandler for MonoRepo {
         true
     }
 
+    async fn get_commit(&self, hash: &str) -> Result<Option<Commit>, MegaError> {
+        let commit = self.storage.mono_storage().get_commit_by_hash(hash).await?;
+        Ok(commit.map(Commit::from_mega_model))
+    }
+
     async fn traverses_tree_and_update_filepath(&self) -> Result<(), MegaError> {
         let commit_guard = self.current_commit.read().await;
         let commit_opt = match commit_guard.as_ref() {
@@ -600,12 +634,27 @@ impl MonoRepo {
                 )
                 .await?;
 
-            cl_stg.update_cl_to_hash(cl, &self.to_hash).await?;
+            cl_stg
+                .update_cl_to_hash(cl.clone(), &self.to_hash, &username)
+                .await?;
         } else {
             cl_stg
-                .update_cl_hash(cl, &self.from_hash, &self.to_hash)
+                .update_cl_hash(cl.clone(), &self.from_hash, &self.to_hash, &self.username())
                 .await?;
         }
+
+        let api_service: MonoApiService = self.into();
+        if let Err(e) = comment_ops::reanchor_inline_comments(&api_service, &cl.link).await {
+            tracing::warn!(
+                "Failed to carry inline comments of cl {} forward: {e}",
+                cl.link
+            );
+        }
+        if let Err(e) =
+            review_ops::refresh_approvals(&api_service, &cl.link, &self.username()).await
+        {
+            tracing::warn!("Failed to refresh approvals of cl {}: {e}", cl.link);
+        }
         Ok(())
     }
 
@@ -744,6 +793,21 @@ impl MonoRepo {
             tracing::warn!("Failed to resync Cedar reviewers: {}", e);
         }
 
+        // so reviewers can jump between definitions in the CL diff
+        let changes = match self.changed_paths().await {
+            Ok(changed) => Some((self.from_hash.clone(), changed)),
+            Err(e) => {
+                tracing::warn!("Failed to list changed files, indexing all of them: {}", e);
+                None
+            }
+        };
+        symbol_ops::spawn_index_rust(
+            MonoApiService::from(self),
+            self.to_hash.clone(),
+            path_str.into_owned(),
+            changes,
+        );
+
         Ok(())
     }
 
@@ -772,27 +836,22 @@ impl MonoRepo {
     /// Get list of files changed between from_hash and to_hash commits.
     /// Returns paths relative to the CL root directory with forward slashes.
     async fn get_changed_files(&self) -> Result<Vec<String>, MegaError> {
-        let mono_api_service: MonoApiService = self.into();
-
-        let old_files = mono_api_service.get_commit_blobs(&self.from_hash).await?;
-        let new_files = mono_api_service.get_commit_blobs(&self.to_hash).await?;
-        let changed = mono_api_service.cl_files_list(old_files, new_files).await?;
-
         // Normalize CL root path to use forward slashes
         let cl_root = self.path.to_string_lossy().replace('\\', "/");
         let cl_root_normalized = cl_root.trim_start_matches('/');
 
-        let file_paths: Vec<String> = changed
-            .iter()
-            .map(|f| {
-                let full_path = f.path().to_string_lossy().replace('\\', "/");
+        let file_paths: Vec<String> = self
+            .changed_paths()
+            .await?
+            .into_iter()
+            .map(|full_path| {
                 let full_path_normalized = full_path.trim_start_matches('/');
 
                 // Strip CL root prefix to get relative path
                 if let Some(rel) = full_path_normalized.strip_prefix(cl_root_normalized) {
                     rel.trim_start_matches('/').to_string()
                 } else {
-                    full_path.to_string()
+                    full_path.clone()
                 }
             })
             .collect();
@@ -800,6 +859,19 @@ impl MonoRepo {
         Ok(file_paths)
     }
 
+    /// Paths of the files changed by this push, with forward slashes.
+    async fn changed_paths(&self) -> Result<Vec<String>, MegaError> {
+        let mono_api_service: MonoApiService = self.into();
+
+        let old_files = mono_api_service.get_commit_blobs(&self.from_hash).await?;
+        let new_files = mono_api_service.get_commit_blobs(&self.to_hash).await?;
+        let changed = mono_api_service.cl_files_list(old_files, new_files).await?;
+        Ok(changed
+            .iter()
+            .map(|f| f.path().to_string_lossy().replace('\\', "/"))
+            .collect())
+    }
+
     /// Collect Cedar policy files from directories of all changed files.
     /// Also collects policies from parent directories up to Monorepo root for inheritance.
     /// Tries from_hash first for security, then falls back to to_hash for new directories.
@@ -1021,61 +1093,10 @@ impl MonoRepo {
             .await?
             .ok_or_else(|| MegaError::Other(format!("CL not found for link: {}", link)))?;
 
-        if self.bellatrix.enable_build() {
-            let old_files = self.get_commit_blobs(&cl_info.from_hash).await?;
-            let new_files = self.get_commit_blobs(&cl_info.to_hash).await?;
-            let cl_diff_files = self.cl_files_list(old_files, new_files.clone()).await?;
-
-            let cl_base = PathBuf::from(&cl_info.path);
-            let changes = cl_diff_files
-                .into_iter()
-                .map(|m| {
-                    let mut item: crate::model::change_list::ClFilesRes = m.into();
-                    item.path = cl_base.join(item.path).to_string_lossy().to_string();
-                    item
-                })
-                .collect::<Vec<_>>();
-
-            let path_str = cl_base.to_str().ok_or_else(|| {
-                MegaError::Other(format!("CL base path is not valid UTF-8: {:?}", cl_base))
-            })?;
-            let counter_changes: Vec<_> = changes
-                .iter()
-                .filter(|&s| PathBuf::from(&s.path).starts_with(&cl_base))
-                .map(|s| {
-                    let path = ProjectRelativePath::from_abs(&s.path, path_str).unwrap();
-                    if s.action == "new" {
-                        Status::Added(path)
-                    } else if s.action == "deleted" {
-                        Status::Removed(path)
-                    } else if s.action == "modified" {
-                        Status::Modified(path)
-                    } else {
-                        unreachable!()
-                    }
-                })
-                .collect();
-
-            tracing::info!(
-                "Trigger bellatrix build for cl: {}, changes: {:?}, repo: {}",
-                cl_info.id,
-                counter_changes,
-                path_str
-            );
-
-            let req: OrionBuildRequest = OrionBuildRequest {
-                cl_link: link.clone(),
-                repo: path_str.to_string(),
-                cl: cl_info.id,
-                builds: vec![BuildInfo {
-                    changes: counter_changes,
-                }],
-            };
-            let bellatrix = self.bellatrix.clone();
-            tokio::spawn(async move {
-                let _ = bellatrix.on_post_receive(req).await;
-            });
-        }
+        let api_service: MonoApiService = self.into();
+        api_service
+            .trigger_cl_build(self.bellatrix.clone(), &cl_info)
+            .await?;
 
         let check_reg = CheckerRegistry::new(self.storage.clone().into(), self.username());
         check_reg.run_checks(cl_info.clone().into()).await?;
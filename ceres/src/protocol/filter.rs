@@ -0,0 +1,125 @@
+//! Object filters for partial clones.
+//!
+//! see https://git-scm.com/docs/partial-clone
+//!
+//! With `filter <spec>` the client asks the server to leave some trees and
+//! blobs out of the pack. It fetches them later by asking for them directly
+//! when they are needed.
+
+use std::str::FromStr;
+
+use common::errors::MegaError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectFilter {
+    /// `blob:none`, no blobs at all.
+    BlobNone,
+    /// `blob:limit=<n>`, no blobs of `n` bytes or more.
+    BlobLimit(u64),
+    /// `tree:<depth>`, no trees or blobs `depth` levels or more below the
+    /// root tree.
+    TreeDepth(usize),
+}
+
+impl ObjectFilter {
+    /// Whether a tree `depth` levels below the root tree is sent. The root
+    /// tree itself is at depth 0.
+    pub fn includes_tree(&self, depth: usize) -> bool {
+        match self {
+            ObjectFilter::TreeDepth(max) => depth < *max,
+            ObjectFilter::BlobNone | ObjectFilter::BlobLimit(_) => true,
+        }
+    }
+
+    /// Whether a blob `depth` levels below the root tree may be sent,
+    /// before looking at its size.
+    pub fn includes_blob_at(&self, depth: usize) -> bool {
+        match self {
+            ObjectFilter::BlobNone => false,
+            ObjectFilter::BlobLimit(_) => true,
+            ObjectFilter::TreeDepth(max) => depth < *max,
+        }
+    }
+
+    pub fn blob_limit(&self) -> Option<u64> {
+        match self {
+            ObjectFilter::BlobLimit(limit) => Some(*limit),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ObjectFilter {
+    type Err = MegaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MegaError::Other(format!("Unsupported filter: {s}"));
+        if s == "blob:none" {
+            return Ok(ObjectFilter::BlobNone);
+        }
+        if let Some(limit) = s.strip_prefix("blob:limit=") {
+            return parse_size(limit)
+                .map(ObjectFilter::BlobLimit)
+                .ok_or_else(invalid);
+        }
+        if let Some(depth) = s.strip_prefix("tree:") {
+            return depth
+                .parse()
+                .map(ObjectFilter::TreeDepth)
+                .map_err(|_| invalid());
+        }
+        Err(invalid())
+    }
+}
+
+/// Parses a size with an optional `k`, `m` or `g` suffix, as git does.
+fn parse_size(s: &str) -> Option<u64> {
+    let (digits, unit) = match s.char_indices().last()? {
+        (i, 'k' | 'K') => (&s[..i], 1 << 10),
+        (i, 'm' | 'M') => (&s[..i], 1 << 20),
+        (i, 'g' | 'G') => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(
+            "blob:none".parse::<ObjectFilter>().unwrap(),
+            ObjectFilter::BlobNone
+        );
+        assert_eq!(
+            "blob:limit=512".parse::<ObjectFilter>().unwrap(),
+            ObjectFilter::BlobLimit(512)
+        );
+        assert_eq!(
+            "blob:limit=1k".parse::<ObjectFilter>().unwrap(),
+            ObjectFilter::BlobLimit(1024)
+        );
+        assert_eq!(
+            "tree:0".parse::<ObjectFilter>().unwrap(),
+            ObjectFilter::TreeDepth(0)
+        );
+        assert!("sparse:oid=abc".parse::<ObjectFilter>().is_err());
+        assert!("blob:limit=".parse::<ObjectFilter>().is_err());
+    }
+
+    #[test]
+    fn test_filter_depth() {
+        let filter = ObjectFilter::TreeDepth(1);
+        assert!(filter.includes_tree(0));
+        assert!(!filter.includes_tree(1));
+        assert!(!filter.includes_blob_at(1));
+
+        let filter = ObjectFilter::TreeDepth(0);
+        assert!(!filter.includes_tree(0));
+
+        assert!(ObjectFilter::BlobNone.includes_tree(5));
+        assert!(!ObjectFilter::BlobNone.includes_blob_at(1));
+    }
+}
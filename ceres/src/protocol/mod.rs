@@ -9,11 +9,13 @@ use tokio::sync::RwLock;
 use bellatrix::Bellatrix;
 use callisto::sea_orm_active_enums::RefTypeEnum;
 use common::{
+    enums::TokenScope,
     errors::{MegaError, ProtocolError},
     utils::ZERO_ID,
 };
 use import_refs::RefCommand;
 use jupiter::redis::lock::RedLock;
+use push_cert::PushCertificate;
 use repo::Repo;
 
 use crate::{
@@ -21,9 +23,13 @@ use crate::{
     pack::{RepoHandler, import_repo::ImportRepo, monorepo::MonoRepo},
 };
 
+pub mod filter;
 pub mod import_refs;
+pub mod push_cert;
 pub mod repo;
+pub mod shallow;
 pub mod smart;
+pub mod v2;
 
 #[derive(Clone, Debug)]
 pub struct PushUserInfo {
@@ -39,6 +45,8 @@ pub struct SmartProtocol {
     pub service_type: Option<ServiceType>,
     pub username: Option<String>,
     pub authenticated_user: Option<PushUserInfo>,
+    pub protocol_version: ProtocolVersion,
+    pub push_cert: Option<PushCertificate>,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
@@ -51,6 +59,35 @@ pub enum TransportProtocol {
     P2p,
 }
 
+/// Wire protocol version requested by the client.
+///
+/// Sent in the `Git-Protocol` header over HTTP and the `GIT_PROTOCOL`
+/// environment variable over SSH, e.g. `version=2`.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ProtocolVersion {
+    #[default]
+    V0,
+    V1,
+    V2,
+}
+
+impl ProtocolVersion {
+    /// Parses a colon-separated `Git-Protocol` value. Unknown or missing
+    /// versions fall back to v0, as git itself does.
+    pub fn from_git_protocol(value: &str) -> Self {
+        value
+            .split(':')
+            .filter_map(|param| param.trim().strip_prefix("version="))
+            .map(|version| match version {
+                "2" => ProtocolVersion::V2,
+                "1" => ProtocolVersion::V1,
+                _ => ProtocolVersion::V0,
+            })
+            .max_by_key(|version| *version as u8)
+            .unwrap_or_default()
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ServiceType {
     UploadPack,
@@ -89,8 +126,19 @@ pub enum Capability {
     ReportStatus,
     ReportStatusv2,
     OfsDelta,
+    Filter,
+    Shallow,
     DeepenSince,
     DeepenNot,
+    ThinPack,
+    NoProgress,
+    IncludeTag,
+    // protocol v2 commands and fetch arguments
+    LsRefs,
+    Fetch,
+    ObjectInfo,
+    ServerOption,
+    WaitForDone,
 }
 
 impl FromStr for Capability {
@@ -106,13 +154,51 @@ impl FromStr for Capability {
             "multi_ack" => Ok(Capability::MultiAck),
             "multi_ack_detailed" => Ok(Capability::MultiAckDetailed),
             "no-done" => Ok(Capability::NoDone),
+            "filter" => Ok(Capability::Filter),
+            "shallow" => Ok(Capability::Shallow),
             "deepen-since" => Ok(Capability::DeepenSince),
             "deepen-not" => Ok(Capability::DeepenNot),
+            "thin-pack" => Ok(Capability::ThinPack),
+            "no-progress" => Ok(Capability::NoProgress),
+            "include-tag" => Ok(Capability::IncludeTag),
+            "ls-refs" => Ok(Capability::LsRefs),
+            "fetch" => Ok(Capability::Fetch),
+            "object-info" => Ok(Capability::ObjectInfo),
+            "server-option" => Ok(Capability::ServerOption),
+            "wait-for-done" => Ok(Capability::WaitForDone),
             _ => Err(()),
         }
     }
 }
 
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Capability::MultiAck => "multi_ack",
+            Capability::MultiAckDetailed => "multi_ack_detailed",
+            Capability::NoDone => "no-done",
+            Capability::SideBand => "side-band",
+            Capability::SideBand64k => "side-band-64k",
+            Capability::ReportStatus => "report-status",
+            Capability::ReportStatusv2 => "report-status-v2",
+            Capability::OfsDelta => "ofs-delta",
+            Capability::Filter => "filter",
+            Capability::Shallow => "shallow",
+            Capability::DeepenSince => "deepen-since",
+            Capability::DeepenNot => "deepen-not",
+            Capability::ThinPack => "thin-pack",
+            Capability::NoProgress => "no-progress",
+            Capability::IncludeTag => "include-tag",
+            Capability::LsRefs => "ls-refs",
+            Capability::Fetch => "fetch",
+            Capability::ObjectInfo => "object-info",
+            Capability::ServerOption => "server-option",
+            Capability::WaitForDone => "wait-for-done",
+        };
+        write!(f, "{name}")
+    }
+}
+
 pub enum SideBind {
     // sideband 1 will contain packfile data,
     PackfileData,
@@ -145,6 +231,8 @@ impl SmartProtocol {
             service_type: None,
             username: None,
             authenticated_user: None,
+            protocol_version: ProtocolVersion::default(),
+            push_cert: None,
         }
     }
 
@@ -157,6 +245,8 @@ impl SmartProtocol {
             service_type: None,
             username: None,
             authenticated_user: None,
+            protocol_version: ProtocolVersion::default(),
+            push_cert: None,
         }
     }
 
@@ -226,10 +316,13 @@ impl SmartProtocol {
         state.storage.config().enable_http_auth()
     }
 
+    /// Checks the Basic credentials of a Git HTTP request. The token must
+    /// belong to the user, carry `scope` and cover the repository path.
     pub async fn http_auth(
         &mut self,
         state: &ProtocolApiState,
         header: &HeaderMap<HeaderValue>,
+        scope: TokenScope,
     ) -> bool {
         for (k, v) in header {
             if k == http::header::AUTHORIZATION {
@@ -257,12 +350,18 @@ impl SmartProtocol {
                     });
                     return true;
                 }
-                let token_valid = state
-                    .storage
-                    .user_storage()
-                    .check_token(username, token)
-                    .await
-                    .unwrap_or(false);
+                let token_valid = match state.storage.user_storage().verify_token(token).await {
+                    Ok(Some(authorized)) => {
+                        authorized.username == username
+                            && authorized.allows(scope)
+                            && authorized.covers_path(&self.path)
+                    }
+                    Ok(None) => false,
+                    Err(e) => {
+                        tracing::error!("Error validating token: {e}");
+                        false
+                    }
+                };
 
                 if token_valid {
                     // Valid token: set minimal authenticated user info
@@ -280,4 +379,27 @@ impl SmartProtocol {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_version_from_git_protocol() {
+        assert_eq!(
+            ProtocolVersion::from_git_protocol("version=2"),
+            ProtocolVersion::V2
+        );
+        assert_eq!(
+            ProtocolVersion::from_git_protocol("object-format=sha1:version=2"),
+            ProtocolVersion::V2
+        );
+        assert_eq!(
+            ProtocolVersion::from_git_protocol("version=1"),
+            ProtocolVersion::V1
+        );
+        assert_eq!(
+            ProtocolVersion::from_git_protocol("version=3"),
+            ProtocolVersion::V0
+        );
+        assert_eq!(ProtocolVersion::from_git_protocol(""), ProtocolVersion::V0);
+    }
+}
@@ -0,0 +1,326 @@
+//! Signed pushes.
+//!
+//! see https://git-scm.com/docs/pack-protocol#_push_certificate
+//!
+//! With `git push --signed` the client sends the ref updates inside a
+//! certificate signed with the pusher's GPG key instead of as plain
+//! commands. The certificate echoes the nonce the server advertised in
+//! `push-cert=<nonce>`, and each nonce is accepted only once, so an old
+//! certificate cannot be replayed.
+
+use std::sync::OnceLock;
+
+use pgp::composed::{Deserializable, SignedPublicKey, StandaloneSignature};
+use rand::RngCore;
+use ring::hmac;
+
+use common::config::AuthConfig;
+use common::errors::MegaError;
+use jupiter::storage::Storage;
+
+use crate::api_service::cache::GitObjectCache;
+
+pub const PUSH_CERT_END: &str = "push-cert-end";
+
+const SIGNATURE_BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// How long a nonce stays valid after it was handed out, in seconds.
+///
+/// Over HTTP the nonce comes from `info/refs` and the certificate arrives
+/// in a later request, so it can't be tied to a single connection.
+const NONCE_SLOP: i64 = 300;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PushCertificate {
+    pub pusher: String,
+    pub pushee: Option<String>,
+    pub nonce: String,
+    /// `<old-id> <new-id> <ref-name>` lines, as in an unsigned push.
+    pub commands: Vec<String>,
+    /// Everything before the signature, which is what the client signed.
+    pub payload: String,
+    pub signature: Option<String>,
+}
+
+/// Who signed a verified certificate.
+#[derive(Debug, Clone)]
+pub struct SignedPusher {
+    pub pusher: String,
+    pub user_id: String,
+    pub key_id: String,
+    pub nonce: String,
+}
+
+impl PushCertificate {
+    /// Parses the certificate lines between `push-cert` and `push-cert-end`.
+    ///
+    /// Missing headers are left empty rather than rejected, so the ref
+    /// updates can still be reported on. [`verify_push_cert`] fails for
+    /// such a certificate.
+    pub fn parse(lines: &[String]) -> Self {
+        let mut cert = PushCertificate::default();
+        let mut in_header = true;
+        let mut signature: Option<String> = None;
+
+        for line in lines {
+            if let Some(sig) = signature.as_mut() {
+                sig.push_str(line);
+                continue;
+            }
+            if line.starts_with(SIGNATURE_BEGIN) {
+                signature = Some(line.clone());
+                continue;
+            }
+            cert.payload.push_str(line);
+
+            let content = line.trim_end_matches('\n');
+            if in_header {
+                if content.is_empty() {
+                    in_header = false;
+                } else if let Some(pusher) = content.strip_prefix("pusher ") {
+                    cert.pusher = pusher.to_owned();
+                } else if let Some(pushee) = content.strip_prefix("pushee ") {
+                    cert.pushee = Some(pushee.to_owned());
+                } else if let Some(nonce) = content.strip_prefix("nonce ") {
+                    cert.nonce = nonce.to_owned();
+                }
+            } else if !content.is_empty() {
+                cert.commands.push(content.to_owned());
+            }
+        }
+        cert.signature = signature;
+        cert
+    }
+
+    /// Key IDs named as issuer in the signature, in the `gpg_key.key_id`
+    /// format.
+    fn issuer_key_ids(&self) -> Result<Vec<String>, MegaError> {
+        Ok(self
+            .parse_signature()?
+            .signature
+            .issuer()
+            .into_iter()
+            .map(hex::encode_upper)
+            .collect())
+    }
+
+    fn parse_signature(&self) -> Result<StandaloneSignature, MegaError> {
+        let signature = self
+            .signature
+            .as_deref()
+            .ok_or_else(|| MegaError::Other("Push certificate is not signed".to_string()))?;
+        Ok(StandaloneSignature::from_string(signature)
+            .map_err(|e| MegaError::Other(format!("Failed to parse signature: {e}")))?
+            .0)
+    }
+
+    /// Checks the signature against `public_key` or any of its subkeys.
+    pub fn verify_signature(&self, public_key: &str) -> Result<(), MegaError> {
+        let pub_key = SignedPublicKey::from_string(public_key)
+            .map_err(|e| MegaError::Other(format!("Failed to parse public key: {e}")))?
+            .0;
+        let sig = self.parse_signature()?;
+        let payload = self.payload.as_bytes();
+        if sig.verify(&pub_key, payload).is_ok()
+            || pub_key
+                .public_subkeys
+                .iter()
+                .any(|subkey| sig.verify(subkey, payload).is_ok())
+        {
+            return Ok(());
+        }
+        Err(MegaError::Other(
+            "Push certificate signature verification failed".to_string(),
+        ))
+    }
+}
+
+/// The secret nonces are signed with, from config or generated once per
+/// process.
+pub fn nonce_seed(config: &AuthConfig) -> Vec<u8> {
+    static RANDOM_SEED: OnceLock<[u8; 32]> = OnceLock::new();
+    match &config.push_cert_seed {
+        Some(seed) => seed.as_bytes().to_vec(),
+        None => RANDOM_SEED
+            .get_or_init(|| {
+                let mut seed = [0u8; 32];
+                rand::rng().fill_bytes(&mut seed);
+                seed
+            })
+            .to_vec(),
+    }
+}
+
+/// Builds a `<timestamp>-<salt>-<hmac>` nonce for `path`. Nothing is stored
+/// when it is handed out: the HMAC lets the server check later that it
+/// issued the nonce, and the random salt keeps nonces of concurrent pushes
+/// apart.
+pub fn generate_nonce(seed: &[u8], path: &str, timestamp: i64) -> String {
+    let mut salt = [0u8; 8];
+    rand::rng().fill_bytes(&mut salt);
+    sign_nonce(seed, path, timestamp, &hex::encode(salt))
+}
+
+fn sign_nonce(seed: &[u8], path: &str, timestamp: i64, salt: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, seed);
+    let tag = hmac::sign(&key, format!("{path}:{timestamp}:{salt}").as_bytes());
+    format!("{timestamp}-{salt}-{}", hex::encode(&tag.as_ref()[..20]))
+}
+
+pub fn verify_nonce(seed: &[u8], path: &str, nonce: &str, now: i64) -> Result<(), MegaError> {
+    let invalid = || MegaError::Other(format!("Invalid push certificate nonce: {nonce}"));
+    let mut parts = nonce.splitn(3, '-');
+    let (Some(timestamp), Some(salt), Some(_)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let timestamp: i64 = timestamp.parse().map_err(|_| invalid())?;
+    if sign_nonce(seed, path, timestamp, salt) != nonce {
+        return Err(invalid());
+    }
+    if now - timestamp > NONCE_SLOP || timestamp > now {
+        return Err(MegaError::Other(format!(
+            "Push certificate nonce has expired: {nonce}"
+        )));
+    }
+    Ok(())
+}
+
+/// Marks `nonce` as used, failing if a certificate has already used it.
+///
+/// The mark expires together with the nonce, after which [`verify_nonce`]
+/// rejects it anyway.
+pub async fn claim_nonce(cache: &GitObjectCache, nonce: &str) -> Result<(), MegaError> {
+    let key = format!("{}:push-cert-nonce:{nonce}", cache.prefix);
+    let mut conn = cache.connection.clone();
+    let claimed: Option<String> = redis::cmd("SET")
+        .arg(&key)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(NONCE_SLOP)
+        .query_async(&mut conn)
+        .await?;
+    if claimed.is_none() {
+        return Err(MegaError::Other(format!(
+            "Push certificate nonce has already been used: {nonce}"
+        )));
+    }
+    Ok(())
+}
+
+/// Verifies the nonce and signature of `cert` against the GPG keys the
+/// authenticated `username` has uploaded, and uses up the nonce.
+///
+/// The key is looked up by the issuer key ID first. Certificates signed
+/// with a subkey carry the subkey's ID, which isn't stored, so all keys of
+/// the user are tried after that.
+pub async fn verify_push_cert(
+    storage: &Storage,
+    cache: &GitObjectCache,
+    cert: &PushCertificate,
+    path: &str,
+    username: Option<&str>,
+) -> Result<SignedPusher, MegaError> {
+    let username = username
+        .ok_or_else(|| MegaError::Other("Signed pushes have to be authenticated".to_string()))?;
+    let seed = nonce_seed(&storage.config().authentication);
+    verify_nonce(&seed, path, &cert.nonce, chrono::Utc::now().timestamp())?;
+
+    let gpg_storage = storage.gpg_storage();
+    let mut candidates = Vec::new();
+    for key_id in cert.issuer_key_ids()? {
+        if let Some(key) = gpg_storage.find_gpg_key_by_key_id(&key_id).await?
+            && key.user_id == username
+        {
+            candidates.push(key);
+        }
+    }
+    candidates.extend(gpg_storage.list_user_gpg(username.to_owned()).await?);
+
+    let pusher = candidates
+        .into_iter()
+        .find(|key| cert.verify_signature(&key.public_key).is_ok())
+        .map(|key| SignedPusher {
+            pusher: cert.pusher.clone(),
+            user_id: key.user_id,
+            key_id: key.key_id,
+            nonce: cert.nonce.clone(),
+        })
+        .ok_or_else(|| {
+            MegaError::Other(format!(
+                "No GPG key of {username} verifies the push certificate"
+            ))
+        })?;
+    claim_nonce(cache, &cert.nonce).await?;
+    Ok(pusher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cert_lines() -> Vec<String> {
+        [
+            "certificate version 0.1\n",
+            "pusher Mega <mega@example.com> 1760000000 +0000\n",
+            "pushee https://git.example.com/project\n",
+            "nonce 1760000000-abc\n",
+            "\n",
+            "0000000000000000000000000000000000000000 7bdc783132575d5b3e78400ace9971970ff43a18 refs/heads/main\n",
+            "-----BEGIN PGP SIGNATURE-----\n",
+            "\n",
+            "iQGzBAABCAAdFiEE\n",
+            "-----END PGP SIGNATURE-----\n",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn test_parse_push_cert() {
+        let cert = PushCertificate::parse(&cert_lines());
+        assert_eq!(cert.pusher, "Mega <mega@example.com> 1760000000 +0000");
+        assert_eq!(
+            cert.pushee.as_deref(),
+            Some("https://git.example.com/project")
+        );
+        assert_eq!(cert.nonce, "1760000000-abc");
+        assert_eq!(
+            cert.commands,
+            vec![
+                "0000000000000000000000000000000000000000 7bdc783132575d5b3e78400ace9971970ff43a18 refs/heads/main"
+            ]
+        );
+        assert!(cert.payload.starts_with("certificate version 0.1\n"));
+        assert!(cert.payload.ends_with("refs/heads/main\n"));
+        assert_eq!(
+            cert.signature.as_deref(),
+            Some(
+                "-----BEGIN PGP SIGNATURE-----\n\niQGzBAABCAAdFiEE\n-----END PGP SIGNATURE-----\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_unsigned_push_cert() {
+        let lines = cert_lines();
+        let cert = PushCertificate::parse(&lines[..6]);
+        assert!(cert.signature.is_none());
+        assert!(cert.issuer_key_ids().is_err());
+    }
+
+    #[test]
+    fn test_nonce() {
+        let seed = b"seed";
+        let nonce = generate_nonce(seed, "/project", 1_760_000_000);
+        assert!(nonce.starts_with("1760000000-"));
+        assert!(verify_nonce(seed, "/project", &nonce, 1_760_000_010).is_ok());
+        assert_ne!(nonce, generate_nonce(seed, "/project", 1_760_000_000));
+        // issued for another path, with another seed, or too long ago
+        assert!(verify_nonce(seed, "/doc", &nonce, 1_760_000_010).is_err());
+        assert!(verify_nonce(b"other", "/project", &nonce, 1_760_000_010).is_err());
+        assert!(verify_nonce(seed, "/project", &nonce, 1_760_001_000).is_err());
+        assert!(verify_nonce(seed, "/project", "garbage", 1_760_000_010).is_err());
+    }
+}
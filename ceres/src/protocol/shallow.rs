@@ -0,0 +1,449 @@
+//! Shallow clones and fetches.
+//!
+//! see https://git-scm.com/docs/shallow
+//!
+//! The client asks for history down to a depth (`deepen`), a date
+//! (`deepen-since`), or up to some refs (`deepen-not`), and tells the server
+//! which commits it already has as its `shallow` boundary. The server walks
+//! the commit graph from the wants, answers with the new boundary, and packs
+//! nothing behind it.
+
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bytes::BytesMut;
+use git_internal::internal::object::commit::Commit;
+
+use common::errors::MegaError;
+
+use crate::pack::RepoHandler;
+use crate::protocol::smart::add_pkt_line_string;
+
+/// The `shallow` and `deepen*` lines of an upload-pack request.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShallowRequest {
+    /// Commits the client already has without their parents.
+    pub client_shallow: Vec<String>,
+    pub depth: Option<usize>,
+    /// Unix timestamp, commits older than it are left out.
+    pub deepen_since: Option<usize>,
+    /// Refs whose history is left out.
+    pub deepen_not: Vec<String>,
+}
+
+impl ShallowRequest {
+    /// Records a `shallow`/`deepen*` line, returns false for any other line.
+    pub fn parse_line(&mut self, line: &str) -> bool {
+        let line = line.trim_end();
+        let Some((key, value)) = line.split_once(' ') else {
+            return false;
+        };
+        match key {
+            "shallow" => self.client_shallow.push(value.to_owned()),
+            "deepen" => self.depth = value.parse().ok(),
+            "deepen-since" => self.deepen_since = value.parse().ok(),
+            "deepen-not" => self.deepen_not.push(value.to_owned()),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Whether the client asked to move its shallow boundary.
+    pub fn is_deepen(&self) -> bool {
+        self.depth.is_some() || self.deepen_since.is_some() || !self.deepen_not.is_empty()
+    }
+
+    /// Whether the client is shallow or wants to become so.
+    pub fn is_shallow(&self) -> bool {
+        self.is_deepen() || !self.client_shallow.is_empty()
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ShallowUpdate {
+    /// New boundary commits to send as `shallow` lines.
+    pub shallow: Vec<String>,
+    /// Boundary commits of the client that now get their parents.
+    pub unshallow: Vec<String>,
+    /// Every commit whose parents are left out of the pack.
+    pub boundary: HashSet<String>,
+}
+
+impl ShallowUpdate {
+    /// Keeps the client's boundary when it is not deepening.
+    fn keep(request: &ShallowRequest) -> Self {
+        ShallowUpdate {
+            boundary: request.client_shallow.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn write_pkt_lines(&self, pkt_line_stream: &mut BytesMut) {
+        for hash in &self.shallow {
+            add_pkt_line_string(pkt_line_stream, format!("shallow {hash}\n"));
+        }
+        for hash in &self.unshallow {
+            add_pkt_line_string(pkt_line_stream, format!("unshallow {hash}\n"));
+        }
+    }
+}
+
+/// Computes the shallow boundary for `want`.
+///
+/// Unshallowed commits must be added to the wants, so that the pack
+/// walk goes on to their parents.
+pub async fn compute_shallow(
+    repo_handler: &dyn RepoHandler,
+    want: &[String],
+    request: &ShallowRequest,
+) -> Result<ShallowUpdate, MegaError> {
+    if !request.is_deepen() {
+        return Ok(ShallowUpdate::keep(request));
+    }
+    let mut deepen_not = DeepenNot::start(repo_handler, &request.deepen_not).await?;
+    let mut walk = ShallowWalk::new(request, load(repo_handler, want).await?);
+    while let Some(parents) = walk.parents_to_load() {
+        let parents = load(repo_handler, &parents).await?;
+        if let Some(oldest) = parents.values().map(|c| c.committer.timestamp).min() {
+            deepen_not.walk_back_to(repo_handler, oldest).await?;
+        }
+        walk.advance(parents, &deepen_not.excluded);
+    }
+    Ok(walk.finish(request))
+}
+
+async fn load(
+    repo_handler: &dyn RepoHandler,
+    hashes: &[String],
+) -> Result<HashMap<String, Commit>, MegaError> {
+    let mut commits = HashMap::new();
+    for hash in hashes {
+        if let Some(commit) = repo_handler.get_commit(hash).await? {
+            commits.insert(hash.clone(), commit);
+        }
+    }
+    Ok(commits)
+}
+
+/// Walks back from the `deepen-not` refs, newest commit first, and only as
+/// far as the commits the shallow walk has reached.
+///
+/// Like git, this trusts commit dates: a commit dated after its descendants
+/// may be missed.
+struct DeepenNot {
+    queue: BinaryHeap<(usize, String, Vec<String>)>,
+    excluded: HashSet<String>,
+}
+
+impl DeepenNot {
+    /// Starts from the refs given as full or short ref names.
+    async fn start(
+        repo_handler: &dyn RepoHandler,
+        deepen_not: &[String],
+    ) -> Result<Self, MegaError> {
+        let mut walk = DeepenNot {
+            queue: BinaryHeap::new(),
+            excluded: HashSet::new(),
+        };
+        if deepen_not.is_empty() {
+            return Ok(walk);
+        }
+        let (_, refs) = repo_handler.refs_with_head_hash().await;
+        let tips = refs
+            .into_iter()
+            .filter(|r| {
+                deepen_not.iter().any(|name| {
+                    r.ref_name == *name
+                        || r.ref_name == format!("refs/heads/{name}")
+                        || r.ref_name == format!("refs/tags/{name}")
+                })
+            })
+            .map(|r| r.ref_hash)
+            .collect();
+        walk.push(repo_handler, tips).await?;
+        Ok(walk)
+    }
+
+    async fn push(
+        &mut self,
+        repo_handler: &dyn RepoHandler,
+        hashes: Vec<String>,
+    ) -> Result<(), MegaError> {
+        for hash in hashes {
+            if !self.excluded.insert(hash.clone()) {
+                continue;
+            }
+            if let Some(commit) = repo_handler.get_commit(&hash).await? {
+                let parents = commit
+                    .parent_commit_ids
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                self.queue.push((commit.committer.timestamp, hash, parents));
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks on until every excluded commit dated `since` or later is known.
+    async fn walk_back_to(
+        &mut self,
+        repo_handler: &dyn RepoHandler,
+        since: usize,
+    ) -> Result<(), MegaError> {
+        while let Some((timestamp, ..)) = self.queue.peek()
+            && *timestamp >= since
+        {
+            if let Some((_, _, parents)) = self.queue.pop() {
+                self.push(repo_handler, parents).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Breadth-first walk from the wants, one generation at a time, so that
+/// `depth` counts the shortest path to each commit.
+struct ShallowWalk {
+    depth: Option<usize>,
+    deepen_since: Option<usize>,
+    included: HashSet<String>,
+    frontier: Vec<Commit>,
+    generation: usize,
+    shallow: Vec<String>,
+}
+
+impl ShallowWalk {
+    /// The wants are always sent, whatever their age.
+    fn new(request: &ShallowRequest, want: HashMap<String, Commit>) -> Self {
+        ShallowWalk {
+            depth: request.depth,
+            deepen_since: request.deepen_since,
+            included: want.keys().cloned().collect(),
+            frontier: want.into_values().collect(),
+            generation: 1,
+            shallow: Vec::new(),
+        }
+    }
+
+    /// Parents of the current generation that are not included yet, or
+    /// `None` once the walk is over.
+    fn parents_to_load(&mut self) -> Option<Vec<String>> {
+        if self.frontier.is_empty() {
+            return None;
+        }
+        if self.depth.is_some_and(|depth| self.generation >= depth) {
+            for commit in self.frontier.drain(..) {
+                if !commit.parent_commit_ids.is_empty() {
+                    self.shallow.push(commit.id.to_string());
+                }
+            }
+            return None;
+        }
+        let mut parents: Vec<String> = self
+            .frontier
+            .iter()
+            .flat_map(|c| c.parent_commit_ids.iter().map(ToString::to_string))
+            .filter(|p| !self.included.contains(p))
+            .collect();
+        parents.sort();
+        parents.dedup();
+        Some(parents)
+    }
+
+    /// Moves on to the parents in `loaded` that pass the deepen limits and
+    /// are not `excluded`. A commit with any parent left out becomes shallow.
+    fn advance(&mut self, loaded: HashMap<String, Commit>, excluded: &HashSet<String>) {
+        let mut next = Vec::new();
+        for commit in std::mem::take(&mut self.frontier) {
+            let mut cut = false;
+            for parent_id in commit.parent_commit_ids.iter().map(ToString::to_string) {
+                if self.included.contains(&parent_id) {
+                    continue;
+                }
+                match loaded.get(&parent_id) {
+                    Some(parent)
+                        if !excluded.contains(&parent_id)
+                            && self
+                                .deepen_since
+                                .is_none_or(|since| parent.committer.timestamp >= since) =>
+                    {
+                        self.included.insert(parent_id);
+                        next.push(parent.clone());
+                    }
+                    _ => cut = true,
+                }
+            }
+            if cut {
+                self.shallow.push(commit.id.to_string());
+            }
+        }
+        self.frontier = next;
+        self.generation += 1;
+    }
+
+    fn finish(self, request: &ShallowRequest) -> ShallowUpdate {
+        let client_shallow: HashSet<&String> = request.client_shallow.iter().collect();
+        let new_shallow: HashSet<&String> = self.shallow.iter().collect();
+        let unshallow: Vec<String> = request
+            .client_shallow
+            .iter()
+            .filter(|hash| self.included.contains(*hash) && !new_shallow.contains(hash))
+            .cloned()
+            .collect();
+        let mut boundary: HashSet<String> = self.shallow.iter().cloned().collect();
+        boundary.extend(
+            request
+                .client_shallow
+                .iter()
+                .filter(|hash| !unshallow.contains(hash))
+                .cloned(),
+        );
+        ShallowUpdate {
+            shallow: self
+                .shallow
+                .iter()
+                .filter(|hash| !client_shallow.contains(hash))
+                .cloned()
+                .collect(),
+            unshallow,
+            boundary,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use git_internal::hash::ObjectHash;
+    use git_internal::internal::object::signature::{Signature, SignatureType};
+
+    use super::*;
+
+    fn hash(n: u8) -> String {
+        format!("{n:040x}")
+    }
+
+    fn commit(n: u8, parents: &[u8], timestamp: usize) -> Commit {
+        let signature = Signature {
+            signature_type: SignatureType::Committer,
+            name: String::from("mega"),
+            email: String::from("mega@example.com"),
+            timestamp,
+            timezone: String::from("+0000"),
+        };
+        Commit {
+            id: ObjectHash::from_str(&hash(n)).unwrap(),
+            tree_id: ObjectHash::from_str(&hash(0)).unwrap(),
+            parent_commit_ids: parents
+                .iter()
+                .map(|p| ObjectHash::from_str(&hash(*p)).unwrap())
+                .collect(),
+            author: signature.clone(),
+            committer: signature,
+            message: String::new(),
+        }
+    }
+
+    /// 1 <- 2 <- 3 <- 5 (merge) and 1 <- 4 <- 5, timestamps follow the ids.
+    fn graph() -> HashMap<String, Commit> {
+        [
+            commit(1, &[], 1),
+            commit(2, &[1], 2),
+            commit(3, &[2], 3),
+            commit(4, &[1], 4),
+            commit(5, &[3, 4], 5),
+        ]
+        .into_iter()
+        .map(|c| (c.id.to_string(), c))
+        .collect()
+    }
+
+    fn walk(request: &ShallowRequest, excluded: &[u8]) -> ShallowUpdate {
+        let graph = graph();
+        let load = |hashes: &[String]| {
+            hashes
+                .iter()
+                .filter_map(|h| graph.get(h).map(|c| (h.clone(), c.clone())))
+                .collect::<HashMap<_, _>>()
+        };
+        let excluded = excluded.iter().map(|n| hash(*n)).collect();
+        let mut walk = ShallowWalk::new(request, load(&[hash(5)]));
+        while let Some(parents) = walk.parents_to_load() {
+            walk.advance(load(&parents), &excluded);
+        }
+        walk.finish(request)
+    }
+
+    fn sorted(mut hashes: Vec<String>) -> Vec<String> {
+        hashes.sort();
+        hashes
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let mut request = ShallowRequest::default();
+        assert!(request.parse_line(&format!("shallow {}\n", hash(1))));
+        assert!(request.parse_line("deepen 3"));
+        assert!(request.parse_line("deepen-since 1700000000"));
+        assert!(request.parse_line("deepen-not refs/heads/release"));
+        assert!(!request.parse_line("done"));
+        assert_eq!(request.client_shallow, vec![hash(1)]);
+        assert_eq!(request.depth, Some(3));
+        assert_eq!(request.deepen_since, Some(1700000000));
+        assert_eq!(request.deepen_not, vec!["refs/heads/release"]);
+        assert!(request.is_deepen());
+    }
+
+    #[test]
+    fn test_depth() {
+        let request = ShallowRequest {
+            depth: Some(1),
+            ..Default::default()
+        };
+        let update = walk(&request, &[]);
+        assert_eq!(update.shallow, vec![hash(5)]);
+
+        let request = ShallowRequest {
+            depth: Some(2),
+            ..Default::default()
+        };
+        let update = walk(&request, &[]);
+        assert_eq!(sorted(update.shallow), vec![hash(3), hash(4)]);
+
+        // deepening an existing depth=1 clone
+        let request = ShallowRequest {
+            client_shallow: vec![hash(5)],
+            depth: Some(3),
+            ..Default::default()
+        };
+        let update = walk(&request, &[]);
+        assert_eq!(update.shallow, vec![hash(2)]);
+        assert_eq!(update.unshallow, vec![hash(5)]);
+        assert_eq!(update.boundary, HashSet::from([hash(2)]));
+    }
+
+    #[test]
+    fn test_deepen_since_and_not() {
+        let request = ShallowRequest {
+            deepen_since: Some(3),
+            ..Default::default()
+        };
+        let update = walk(&request, &[]);
+        assert_eq!(sorted(update.shallow), vec![hash(3), hash(4)]);
+
+        let request = ShallowRequest {
+            deepen_not: vec![String::from("release")],
+            ..Default::default()
+        };
+        let update = walk(&request, &[1, 2]);
+        assert_eq!(sorted(update.shallow), vec![hash(3), hash(4)]);
+
+        // the whole history fits
+        let request = ShallowRequest {
+            depth: Some(10),
+            ..Default::default()
+        };
+        assert!(walk(&request, &[]).shallow.is_empty());
+    }
+}
@@ -6,13 +6,23 @@ use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::Stream;
 use tokio_stream::wrappers::ReceiverStream;
 
+use callisto::push_certificates;
 use callisto::sea_orm_active_enums::RefTypeEnum;
-use common::errors::ProtocolError;
+use common::enums::WebhookEvent;
+use common::errors::{MegaError, ProtocolError};
 
 use crate::api_service::state::ProtocolApiState;
+use crate::merge_checker::branch_protection_checker::BranchProtection;
 use crate::protocol::ZERO_ID;
+use crate::protocol::filter::ObjectFilter;
 use crate::protocol::import_refs::RefCommand;
-use crate::protocol::{Capability, ServiceType, SideBind, SmartProtocol, TransportProtocol};
+use crate::protocol::push_cert::{
+    PUSH_CERT_END, PushCertificate, SignedPusher, generate_nonce, nonce_seed, verify_push_cert,
+};
+use crate::protocol::shallow::{ShallowRequest, compute_shallow};
+use crate::protocol::{
+    Capability, ProtocolVersion, ServiceType, SideBind, SmartProtocol, TransportProtocol,
+};
 
 const LF: char = '\n';
 
@@ -32,7 +42,8 @@ const RECEIVE_CAP_LIST: &str = "report-status report-status-v2 delete-refs quiet
 const COMMON_CAP_LIST: &str = "side-band-64k ofs-delta agent=mega/0.1.0";
 
 // All other capabilities are only recognized by the upload-pack (fetch from server) process.
-const UPLOAD_CAP_LIST: &str = "multi_ack_detailed no-done include-tag ";
+const UPLOAD_CAP_LIST: &str = "multi_ack_detailed no-done include-tag shallow deepen-since deepen-not \
+     filter allow-tip-sha1-in-want allow-reachable-sha1-in-want ";
 
 impl SmartProtocol {
     /// # Retrieves the information about Git references (refs) for the specified service type.
@@ -63,6 +74,10 @@ impl SmartProtocol {
         let repo_handler = self.repo_handler(state).await?;
 
         let service_type = self.service_type.unwrap();
+        if self.protocol_version == ProtocolVersion::V2 && service_type == ServiceType::UploadPack {
+            // v2 advertises capabilities only, refs are listed by `ls-refs` on demand
+            return Ok(self.v2_capability_advertisement());
+        }
 
         // The stream MUST include capability declarations behind a NUL on the first ref.
         let (head_hash, git_refs) = repo_handler.refs_with_head_hash().await;
@@ -73,7 +88,15 @@ impl SmartProtocol {
         };
         let cap_list = match service_type {
             ServiceType::UploadPack => format!("{UPLOAD_CAP_LIST}{COMMON_CAP_LIST}"),
-            ServiceType::ReceivePack => format!("{RECEIVE_CAP_LIST}{COMMON_CAP_LIST}"),
+            ServiceType::ReceivePack => {
+                let seed = nonce_seed(&state.storage.config().authentication);
+                let nonce = generate_nonce(
+                    &seed,
+                    &self.path.to_string_lossy(),
+                    chrono::Utc::now().timestamp(),
+                );
+                format!("{RECEIVE_CAP_LIST}push-cert={nonce} {COMMON_CAP_LIST}")
+            }
         };
         let pkt_line = format!("{head_hash}{SP}{name}{NUL}{cap_list}{LF}");
         let mut ref_list = vec![pkt_line];
@@ -92,10 +115,15 @@ impl SmartProtocol {
         state: &ProtocolApiState,
         upload_request: &mut Bytes,
     ) -> Result<(ReceiverStream<Vec<u8>>, BytesMut), ProtocolError> {
+        if self.protocol_version == ProtocolVersion::V2 {
+            return self.git_upload_pack_v2(state, upload_request).await;
+        }
         let repo_handler = self.repo_handler(state).await?;
 
         let mut want: HashSet<String> = HashSet::new();
         let mut have: HashSet<String> = HashSet::new();
+        let mut shallow_request = ShallowRequest::default();
+        let mut filter = None;
         let mut last_common_commit = String::new();
 
         let mut read_first_line = false;
@@ -120,6 +148,15 @@ impl SmartProtocol {
                     have.insert(String::from_utf8(dst[5..45].to_vec()).unwrap());
                 }
                 b"done" => break,
+                b"shal" | b"deep" => {
+                    shallow_request.parse_line(&String::from_utf8_lossy(&dst));
+                }
+                b"filt" => {
+                    let line = String::from_utf8_lossy(&dst);
+                    if let Some(spec) = line.trim_end().strip_prefix("filter ") {
+                        filter = Some(spec.parse::<ObjectFilter>()?);
+                    }
+                }
                 other => {
                     tracing::error!(
                         "unsupported command: {:?}",
@@ -144,11 +181,28 @@ impl SmartProtocol {
         let pack_data;
         let mut protocol_buf = BytesMut::new();
 
-        let want: Vec<String> = want.into_iter().collect();
+        let mut want: Vec<String> = want.into_iter().collect();
         let have: Vec<String> = have.into_iter().collect();
+        repo_handler.verify_wants(&want).await?;
+
+        let shallow_update =
+            compute_shallow(repo_handler.as_ref(), &want, &shallow_request).await?;
+        if shallow_request.is_shallow() {
+            shallow_update.write_pkt_lines(&mut protocol_buf);
+            protocol_buf.put(&PKT_LINE_END_MARKER[..]);
+        }
+        want.extend(shallow_update.unshallow);
+        let shallow = shallow_update.boundary;
 
         if have.is_empty() {
-            pack_data = repo_handler.full_pack(want).await.unwrap();
+            pack_data = if shallow.is_empty() && filter.is_none() {
+                repo_handler.full_pack(want).await.unwrap()
+            } else {
+                repo_handler
+                    .incremental_pack(want, have, shallow, filter)
+                    .await
+                    .unwrap()
+            };
             add_pkt_line_string(&mut protocol_buf, String::from("NAK\n"));
         } else {
             if self.capabilities.contains(&Capability::MultiAckDetailed) {
@@ -165,7 +219,7 @@ impl SmartProtocol {
                     }
                 }
                 pack_data = repo_handler
-                    .incremental_pack(want.clone(), have)
+                    .incremental_pack(want.clone(), have, shallow, filter)
                     .await
                     .unwrap();
 
@@ -194,10 +248,33 @@ impl SmartProtocol {
         Ok((pack_data, protocol_buf))
     }
 
+    /// Parses the ref update commands, either sent as plain pkt-lines or
+    /// wrapped in a push certificate for `git push --signed`.
     pub fn parse_receive_pack_commands(&mut self, mut protocol_bytes: Bytes) {
+        let mut cert_lines: Option<Vec<String>> = None;
         while !protocol_bytes.is_empty() {
             let (bytes_take, mut pkt_line) = read_pkt_line(&mut protocol_bytes);
-            if bytes_take != 0 {
+            if bytes_take == 0 {
+                continue;
+            }
+            if let Some(lines) = cert_lines.as_mut() {
+                let line = String::from_utf8_lossy(&pkt_line).into_owned();
+                if line.trim_end() != PUSH_CERT_END {
+                    lines.push(line);
+                    continue;
+                }
+                let cert = PushCertificate::parse(lines);
+                for command in &cert.commands {
+                    let command = self.parse_ref_command(&mut Bytes::from(command.clone()));
+                    tracing::debug!("parse signed ref_command: {:?}", command);
+                    self.command_list.push(command);
+                }
+                self.push_cert = Some(cert);
+                cert_lines = None;
+            } else if let Some(caps) = pkt_line.strip_prefix(b"push-cert\0") {
+                self.parse_capabilities(core::str::from_utf8(caps).unwrap());
+                cert_lines = Some(Vec::new());
+            } else {
                 let command = self.parse_ref_command(&mut pkt_line);
                 self.parse_capabilities(core::str::from_utf8(&pkt_line).unwrap());
                 tracing::debug!(
@@ -234,41 +311,110 @@ impl SmartProtocol {
         let mut default_exist = repo_handler.check_default_branch().await;
 
         let mut unpack_failed = false;
+        let mut rejected = false;
+
+        let username = self.username.as_deref().unwrap_or("Anonymous");
+        let path = self.path.to_string_lossy().into_owned();
+
+        let signed_pusher = match &self.push_cert {
+            Some(cert) => {
+                verify_push_cert(
+                    &state.storage,
+                    &state.git_object_cache,
+                    cert,
+                    &path,
+                    self.username.as_deref(),
+                )
+                .await
+            }
+            None => Err(MegaError::Other(
+                "Signed push required, push with --signed".to_string(),
+            )),
+        };
+        if let (Some(_), Err(e)) = (&self.push_cert, &signed_pusher) {
+            tracing::warn!("Push certificate for {path} rejected: {e}");
+        }
 
-        //2. update each refs and build report
+        //2. check every command before any ref is updated, so the push is applied all or nothing
         for command in &mut self.command_list {
-            if command.ref_type == RefTypeEnum::Tag {
-                // just update if refs type is tag
-                repo_handler.update_refs(command).await.unwrap();
+            if let Err(err) = &unpack_result {
+                command.failed(err.to_string());
+                unpack_failed = true;
+                continue;
+            }
+            // Rules of protected paths below the push path apply to the files it changes there
+            let rules = match BranchProtection::rules_for_ref_update(
+                &state.storage,
+                &path,
+                &command.old_id,
+                &command.new_id,
+                repo_handler.clone(),
+            )
+            .await
+            {
+                Ok(rules) => rules,
+                Err(e) => {
+                    command.failed(e.to_string());
+                    rejected = true;
+                    continue;
+                }
+            };
+            for rule in &rules {
+                if rule.require_signed_push
+                    && let Err(e) = &signed_pusher
+                {
+                    command.failed(e.to_string());
+                    rejected = true;
+                    break;
+                }
+                if let Err(msg) = BranchProtection::verify_ref_update(
+                    rule,
+                    username,
+                    repo_handler.is_monorepo(),
+                    command,
+                    repo_handler.as_ref(),
+                )
+                .await
+                {
+                    command.failed(msg);
+                    rejected = true;
+                    break;
+                }
+            }
+        }
+
+        //3. update each refs and build report
+        for command in &mut self.command_list {
+            if unpack_failed || rejected {
+                if command.status == "ok" {
+                    command.failed("atomic push failure".to_owned());
+                }
             } else {
-                // Updates can be unsuccessful for a number of reasons.
-                // a.The reference can have changed since the reference discovery phase was originally sent, meaning someone pushed in the meantime.
-                // b.The reference being pushed could be a non-fast-forward reference and the update hooks or configuration could be set to not allow that, etc.
-                // c.Also, some references can be updated while others can be rejected.
-                match unpack_result {
-                    Ok(_) => {
-                        if !default_exist {
-                            command.default_branch = true;
-                            default_exist = true;
-                        }
-                        if let Err(e) = repo_handler.update_refs(command).await {
-                            command.failed(e.to_string());
-                        }
-                    }
-                    Err(ref err) => {
-                        command.failed(err.to_string());
-                        unpack_failed = true;
-                    }
+                if command.ref_type != RefTypeEnum::Tag && !default_exist {
+                    command.default_branch = true;
+                    default_exist = true;
+                }
+                if let Err(e) = repo_handler.update_refs(command).await {
+                    command.failed(e.to_string());
                 }
             }
             add_pkt_line_string(&mut report_status, command.get_status());
         }
-        if !unpack_failed {
-            //3. post_receive_pack
+        if !unpack_failed && !rejected {
+            //4. post_receive_pack
             repo_handler.post_receive_pack().await?;
 
-            // 4. Process commit bindings for successful ref updates
+            if let Ok(pusher) = &signed_pusher
+                && let Err(e) = self.record_push_cert(state, &path, pusher).await
+            {
+                tracing::warn!("Failed to record push certificate for {path}: {e}");
+            }
+
+            // 5. Process commit bindings for successful ref updates
             self.process_commit_bindings(state).await;
+
+            // 6. Notify webhooks of the updated refs
+            self.emit_push_events(state, &path).await;
         }
 
         report_status.put(&PKT_LINE_END_MARKER[..]);
@@ -343,6 +489,37 @@ impl SmartProtocol {
         )
     }
 
+    /// Records the verified pusher of each ref that was updated.
+    async fn record_push_cert(
+        &self,
+        state: &ProtocolApiState,
+        path: &str,
+        pusher: &SignedPusher,
+    ) -> Result<(), MegaError> {
+        let models = self
+            .command_list
+            .iter()
+            .filter(|command| command.status == "ok")
+            .map(|command| {
+                push_certificates::Model::new(
+                    path,
+                    &command.ref_name,
+                    &command.old_id,
+                    &command.new_id,
+                    &pusher.pusher,
+                    &pusher.user_id,
+                    &pusher.key_id,
+                    &pusher.nonce,
+                )
+            })
+            .collect();
+        state
+            .storage
+            .gpg_storage()
+            .save_push_certificates(models)
+            .await
+    }
+
     /// Process commit bindings for successfully pushed commits
     async fn process_commit_bindings(&self, state: &ProtocolApiState) {
         for command in &self.command_list {
@@ -358,6 +535,19 @@ impl SmartProtocol {
         }
     }
 
+    async fn emit_push_events(&self, state: &ProtocolApiState, path: &str) {
+        let webhook_stg = state.storage.webhook_storage();
+        for command in self.command_list.iter().filter(|c| c.status == "ok") {
+            let data = serde_json::json!({
+                "ref": command.ref_name,
+                "before": command.old_id,
+                "after": command.new_id,
+                "pusher": self.username,
+            });
+            webhook_stg.emit(WebhookEvent::Push, path, data).await;
+        }
+    }
+
     /// Bind a single commit to a user based on authenticated user only (username-only model)
     async fn bind_commit_to_user(
         &self,
@@ -0,0 +1,445 @@
+//! Git wire protocol version 2 for upload-pack.
+//!
+//! see https://git-scm.com/docs/protocol-v2
+//!
+//! Instead of advertising every ref up front, the server only advertises its
+//! capabilities and the client sends one command request at a time:
+//! `ls-refs` (optionally filtered by `ref-prefix`), `fetch` and `object-info`.
+//! Pushes keep using the v0 receive-pack exchange.
+
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_stream::wrappers::ReceiverStream;
+
+use common::errors::{MegaError, ProtocolError};
+
+use crate::api_service::state::ProtocolApiState;
+use crate::pack::RepoHandler;
+use crate::protocol::ZERO_ID;
+use crate::protocol::filter::ObjectFilter;
+use crate::protocol::import_refs::Refs;
+use crate::protocol::shallow::{ShallowRequest, compute_shallow};
+use crate::protocol::smart::{PKT_LINE_END_MARKER, add_pkt_line_string};
+use crate::protocol::{Capability, SmartProtocol};
+
+/// Separates the capabilities of a command request from its arguments, and
+/// the sections of a fetch response.
+pub const PKT_LINE_DELIM_MARKER: &[u8; 4] = b"0001";
+
+const AGENT: &str = "agent=mega/0.1.0";
+
+const OBJECT_FORMAT: &str = "object-format=sha1";
+
+#[derive(Debug, PartialEq)]
+pub enum PktLine {
+    /// `0000`, ends a request or response.
+    Flush,
+    /// `0001`, separates sections.
+    Delim,
+    /// `0002`, ends a stateless response.
+    ResponseEnd,
+    Data(Bytes),
+}
+
+/// Reads one pkt-line from `bytes`, including the special packets v2 adds.
+///
+/// Returns `None` if `bytes` does not start with a whole pkt-line.
+pub fn read_pkt_line_v2(bytes: &mut Bytes) -> Option<PktLine> {
+    if bytes.len() < 4 {
+        return None;
+    }
+    let length = core::str::from_utf8(&bytes[..4])
+        .ok()
+        .and_then(|len| usize::from_str_radix(len, 16).ok())?;
+    let pkt_line = match length {
+        0 => PktLine::Flush,
+        1 => PktLine::Delim,
+        2 => PktLine::ResponseEnd,
+        3 => return None,
+        len if len > bytes.len() => return None,
+        len => PktLine::Data(bytes.slice(4..len)),
+    };
+    bytes.advance(length.max(4));
+    Some(pkt_line)
+}
+
+/// Returns the length of the first complete command request in `buf`, up to
+/// and including the flush packet that ends it.
+///
+/// Over SSH a request may arrive split across several data packets, so it is
+/// buffered until this returns `Some`.
+pub fn request_len(buf: &[u8]) -> Option<usize> {
+    let mut bytes = Bytes::copy_from_slice(buf);
+    while let Some(pkt_line) = read_pkt_line_v2(&mut bytes) {
+        if pkt_line == PktLine::Flush {
+            return Some(buf.len() - bytes.len());
+        }
+    }
+    None
+}
+
+/// A command request: `command=<name>` and capabilities, then the
+/// arguments after a delim packet.
+#[derive(Debug, Default, PartialEq)]
+pub struct CommandRequest {
+    pub command: String,
+    pub capabilities: Vec<String>,
+    pub args: Vec<String>,
+}
+
+impl CommandRequest {
+    /// Parses the next request from `bytes`.
+    ///
+    /// A lone flush packet means the client has nothing more to ask, and
+    /// parses as `None`.
+    pub fn parse(bytes: &mut Bytes) -> Result<Option<Self>, ProtocolError> {
+        let mut request = CommandRequest::default();
+        let mut in_args = false;
+        loop {
+            match read_pkt_line_v2(bytes) {
+                Some(PktLine::Data(pkt_line)) => {
+                    let line = String::from_utf8(pkt_line.to_vec())
+                        .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+                    let line = line.trim_end_matches('\n').to_owned();
+                    if in_args {
+                        request.args.push(line);
+                    } else if let Some(command) = line.strip_prefix("command=") {
+                        request.command = command.to_owned();
+                    } else {
+                        request.capabilities.push(line);
+                    }
+                }
+                Some(PktLine::Delim) => in_args = true,
+                Some(PktLine::Flush) | None => break,
+                Some(PktLine::ResponseEnd) => {
+                    return Err(ProtocolError::InvalidInput(
+                        "unexpected response-end packet in request".to_owned(),
+                    ));
+                }
+            }
+        }
+        if request.command.is_empty() {
+            if request.capabilities.is_empty() && request.args.is_empty() {
+                return Ok(None);
+            }
+            return Err(ProtocolError::InvalidInput(
+                "command request without a command".to_owned(),
+            ));
+        }
+        Ok(Some(request))
+    }
+}
+
+impl SmartProtocol {
+    /// Sent by upload-pack in place of the ref advertisement when the
+    /// client asks for v2.
+    pub fn v2_capability_advertisement(&self) -> BytesMut {
+        let mut pkt_line_stream = BytesMut::new();
+        add_pkt_line_string(&mut pkt_line_stream, "version 2\n".to_owned());
+        let cap_list = [
+            AGENT.to_owned(),
+            Capability::LsRefs.to_string(),
+            format!(
+                "{}={} {}",
+                Capability::Fetch,
+                Capability::Shallow,
+                Capability::Filter
+            ),
+            Capability::ServerOption.to_string(),
+            OBJECT_FORMAT.to_owned(),
+            Capability::ObjectInfo.to_string(),
+        ];
+        for cap in cap_list {
+            add_pkt_line_string(&mut pkt_line_stream, format!("{cap}\n"));
+        }
+        pkt_line_stream.put(&PKT_LINE_END_MARKER[..]);
+        pkt_line_stream
+    }
+
+    /// Serves one v2 command request.
+    ///
+    /// Like [`SmartProtocol::git_upload_pack`], the returned buffer leaves out
+    /// the final flush packet, which callers write after the pack data.
+    pub async fn git_upload_pack_v2(
+        &mut self,
+        state: &ProtocolApiState,
+        upload_request: &mut Bytes,
+    ) -> Result<(ReceiverStream<Vec<u8>>, BytesMut), ProtocolError> {
+        // Capabilities are negotiated per command request, unlike in v0
+        self.capabilities.clear();
+        let Some(request) = CommandRequest::parse(upload_request)? else {
+            return Ok((empty_pack_data(), BytesMut::new()));
+        };
+        tracing::info!(
+            "v2 command: {}, caps: {:?}, args: {:?}",
+            request.command,
+            request.capabilities,
+            request.args
+        );
+
+        let repo_handler = self.repo_handler(state).await?;
+        match request.command.parse::<Capability>() {
+            Ok(Capability::LsRefs) => {
+                let (head_hash, refs) = repo_handler.refs_with_head_hash().await;
+                Ok((empty_pack_data(), ls_refs(&head_hash, &refs, &request.args)))
+            }
+            Ok(Capability::Fetch) => self.fetch(repo_handler.as_ref(), &request.args).await,
+            Ok(Capability::ObjectInfo) => Ok((
+                empty_pack_data(),
+                object_info(repo_handler.as_ref(), &request.args).await?,
+            )),
+            _ => Err(ProtocolError::InvalidInput(format!(
+                "unsupported command: {}",
+                request.command
+            ))),
+        }
+    }
+
+    async fn fetch(
+        &mut self,
+        repo_handler: &dyn RepoHandler,
+        args: &[String],
+    ) -> Result<(ReceiverStream<Vec<u8>>, BytesMut), ProtocolError> {
+        let mut want = Vec::new();
+        let mut have = Vec::new();
+        let mut done = false;
+        let mut shallow_request = ShallowRequest::default();
+        let mut filter = None;
+        for arg in args {
+            if let Some(hash) = arg.strip_prefix("want ") {
+                want.push(hash.to_owned());
+            } else if let Some(hash) = arg.strip_prefix("have ") {
+                have.push(hash.to_owned());
+            } else if arg == "done" {
+                done = true;
+            } else if let Some(spec) = arg.strip_prefix("filter ") {
+                filter = Some(spec.parse::<ObjectFilter>()?);
+            } else if let Ok(cap) = arg.parse::<Capability>() {
+                self.capabilities.push(cap);
+            } else if !shallow_request.parse_line(arg) {
+                tracing::warn!("unsupported fetch argument: {arg}");
+            }
+        }
+        // The packfile section is always multiplexed, there is no capability
+        // to negotiate it in v2.
+        self.capabilities.push(Capability::SideBand64k);
+        repo_handler.verify_wants(&want).await?;
+
+        let mut common = Vec::new();
+        for hash in &have {
+            if repo_handler.check_commit_exist(hash).await {
+                common.push(hash.clone());
+            }
+        }
+
+        let mut protocol_buf = BytesMut::new();
+        if !done {
+            add_pkt_line_string(&mut protocol_buf, "acknowledgments\n".to_owned());
+            if common.is_empty() {
+                add_pkt_line_string(&mut protocol_buf, "NAK\n".to_owned());
+            }
+            for hash in &common {
+                add_pkt_line_string(&mut protocol_buf, format!("ACK {hash}\n"));
+            }
+            // Without a common commit the client may still find one in its
+            // next round, unless it had nothing to offer in the first place.
+            let ready = (!common.is_empty() || have.is_empty())
+                && !self.capabilities.contains(&Capability::WaitForDone);
+            if !ready {
+                return Ok((empty_pack_data(), protocol_buf));
+            }
+            add_pkt_line_string(&mut protocol_buf, "ready\n".to_owned());
+            protocol_buf.put(&PKT_LINE_DELIM_MARKER[..]);
+        }
+
+        let shallow_update = compute_shallow(repo_handler, &want, &shallow_request).await?;
+        if shallow_request.is_shallow() {
+            add_pkt_line_string(&mut protocol_buf, "shallow-info\n".to_owned());
+            shallow_update.write_pkt_lines(&mut protocol_buf);
+            protocol_buf.put(&PKT_LINE_DELIM_MARKER[..]);
+        }
+        want.extend(shallow_update.unshallow);
+        let shallow = shallow_update.boundary;
+
+        add_pkt_line_string(&mut protocol_buf, "packfile\n".to_owned());
+        let pack_data = if common.is_empty() && shallow.is_empty() && filter.is_none() {
+            repo_handler.full_pack(want).await
+        } else {
+            repo_handler
+                .incremental_pack(want, common, shallow, filter)
+                .await
+        }
+        .map_err(MegaError::from)?;
+        Ok((pack_data, protocol_buf))
+    }
+}
+
+/// Lists HEAD and the refs matching any `ref-prefix` argument, or all refs
+/// if none was given.
+///
+/// Refs are stored unpeeled, so the `peel` argument adds nothing.
+fn ls_refs(head_hash: &str, refs: &[Refs], args: &[String]) -> BytesMut {
+    let prefixes: Vec<&str> = args
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("ref-prefix "))
+        .collect();
+    let symrefs = args.iter().any(|arg| arg == "symrefs");
+    let matches =
+        |name: &str| prefixes.is_empty() || prefixes.iter().any(|prefix| name.starts_with(prefix));
+
+    let mut pkt_line_stream = BytesMut::new();
+    if head_hash != ZERO_ID && matches("HEAD") {
+        let mut pkt_line = format!("{head_hash} HEAD");
+        if symrefs && let Some(default_ref) = refs.iter().find(|r| r.default_branch) {
+            pkt_line.push_str(&format!(" symref-target:{}", default_ref.ref_name));
+        }
+        add_pkt_line_string(&mut pkt_line_stream, format!("{pkt_line}\n"));
+    }
+    for git_ref in refs.iter().filter(|r| matches(&r.ref_name)) {
+        add_pkt_line_string(
+            &mut pkt_line_stream,
+            format!("{} {}\n", git_ref.ref_hash, git_ref.ref_name),
+        );
+    }
+    pkt_line_stream
+}
+
+/// Answers the `size` attribute of the requested objects. Unknown objects
+/// get an empty size, as git does.
+async fn object_info(
+    repo_handler: &dyn RepoHandler,
+    args: &[String],
+) -> Result<BytesMut, ProtocolError> {
+    let size = args.iter().any(|arg| arg == "size");
+    let oids: Vec<String> = args
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("oid "))
+        .map(str::to_owned)
+        .collect();
+    let sizes = if size {
+        repo_handler.object_sizes(oids.clone()).await?
+    } else {
+        HashMap::new()
+    };
+
+    let mut pkt_line_stream = BytesMut::new();
+    if size {
+        add_pkt_line_string(&mut pkt_line_stream, "size\n".to_owned());
+    }
+    for oid in oids {
+        let pkt_line = match (size, sizes.get(&oid)) {
+            (true, Some(len)) => format!("{oid} {len}\n"),
+            (true, None) => format!("{oid} \n"),
+            (false, _) => format!("{oid}\n"),
+        };
+        add_pkt_line_string(&mut pkt_line_stream, pkt_line);
+    }
+    Ok(pkt_line_stream)
+}
+
+fn empty_pack_data() -> ReceiverStream<Vec<u8>> {
+    let (_, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_ref(name: &str, hash: &str, default_branch: bool) -> Refs {
+        Refs {
+            id: 0,
+            ref_name: name.to_owned(),
+            ref_hash: hash.to_owned(),
+            default_branch,
+        }
+    }
+
+    #[test]
+    fn test_read_pkt_line_v2() {
+        let mut bytes = Bytes::from_static(b"0014command=ls-refs\n00010000000");
+        assert_eq!(
+            read_pkt_line_v2(&mut bytes),
+            Some(PktLine::Data(Bytes::from_static(b"command=ls-refs\n")))
+        );
+        assert_eq!(read_pkt_line_v2(&mut bytes), Some(PktLine::Delim));
+        assert_eq!(read_pkt_line_v2(&mut bytes), Some(PktLine::Flush));
+        // an incomplete line is left in the buffer
+        assert_eq!(read_pkt_line_v2(&mut bytes), None);
+        assert_eq!(&bytes[..], b"000");
+    }
+
+    #[test]
+    fn test_parse_command_request() {
+        let raw = b"0012command=fetch\n0015agent=git/2.45.0\n00010032want 7bdc783132575d5b3e78400ace9971970ff43a18\n0009done\n0000";
+        assert_eq!(request_len(raw), Some(raw.len()));
+        assert_eq!(request_len(&raw[..raw.len() - 1]), None);
+
+        let mut bytes = Bytes::from_static(raw);
+        let request = CommandRequest::parse(&mut bytes).unwrap().unwrap();
+        assert_eq!(request.command, "fetch");
+        assert_eq!(request.capabilities, vec!["agent=git/2.45.0"]);
+        assert_eq!(
+            request.args,
+            vec!["want 7bdc783132575d5b3e78400ace9971970ff43a18", "done"]
+        );
+        assert!(bytes.is_empty());
+
+        let mut bytes = Bytes::from_static(b"0000");
+        assert_eq!(CommandRequest::parse(&mut bytes).unwrap(), None);
+    }
+
+    #[test]
+    fn test_capability_advertisement() {
+        let buf = SmartProtocol::mock().v2_capability_advertisement();
+        assert_eq!(
+            &buf[..],
+            b"000eversion 2\n0015agent=mega/0.1.0\n000cls-refs\n0019fetch=shallow filter\n0012server-option\n0017object-format=sha1\n0010object-info\n0000"
+        );
+    }
+
+    #[test]
+    fn test_ls_refs() {
+        let refs = vec![
+            git_ref(
+                "refs/heads/main",
+                "7bdc783132575d5b3e78400ace9971970ff43a18",
+                true,
+            ),
+            git_ref(
+                "refs/cl/ABCD1234",
+                "27dd8d4cf39f3868c6eee38b601bc9e9939304f5",
+                false,
+            ),
+        ];
+        let head = "7bdc783132575d5b3e78400ace9971970ff43a18";
+
+        let expected = |lines: &[&str]| {
+            let mut buf = BytesMut::new();
+            for line in lines {
+                add_pkt_line_string(&mut buf, format!("{line}\n"));
+            }
+            buf
+        };
+
+        let all = ls_refs(head, &refs, &["symrefs".to_owned()]);
+        assert_eq!(
+            all,
+            expected(&[
+                "7bdc783132575d5b3e78400ace9971970ff43a18 HEAD symref-target:refs/heads/main",
+                "7bdc783132575d5b3e78400ace9971970ff43a18 refs/heads/main",
+                "27dd8d4cf39f3868c6eee38b601bc9e9939304f5 refs/cl/ABCD1234",
+            ])
+        );
+
+        let heads = ls_refs(head, &refs, &["ref-prefix refs/heads/".to_owned()]);
+        assert_eq!(
+            heads,
+            expected(&["7bdc783132575d5b3e78400ace9971970ff43a18 refs/heads/main"])
+        );
+
+        let empty = ls_refs(ZERO_ID, &[], &[]);
+        assert!(empty.is_empty());
+    }
+}
@@ -96,6 +96,8 @@ pub struct Config {
     #[serde(default)]
     pub oauth: Option<OauthConfig>,
     pub build: BuildConfig,
+    #[serde(default)]
+    pub external_checks: Vec<ExternalCheckConfig>,
     pub redis: RedisConfig,
     #[serde(default)]
     pub buck: Option<BuckConfig>,
@@ -129,6 +131,7 @@ impl Config {
             blame: BlameConfig::default(),
             oauth: None,
             build: BuildConfig::default(),
+            external_checks: Vec::new(),
             redis: RedisConfig::default(),
             buck: None,
             s3: S3Config::default(),
@@ -355,6 +358,11 @@ pub struct AuthConfig {
     pub enable_test_user: bool,
     pub test_user_name: String,
     pub test_user_token: String,
+    /// Secret used to sign push certificate nonces. Instances serving the
+    /// same repositories must share it; when unset each process picks a
+    /// random one at startup.
+    #[serde(default)]
+    pub push_cert_seed: Option<String>,
 }
 
 impl Default for AuthConfig {
@@ -364,6 +372,7 @@ impl Default for AuthConfig {
             enable_test_user: false,
             test_user_name: String::from("mega"),
             test_user_token: String::from("mega"),
+            push_cert_seed: None,
         }
     }
 }
@@ -674,6 +683,25 @@ pub struct BuildConfig {
     pub orion_server: String,
 }
 
+/// A merge check served by an external service.
+///
+/// For every open CL whose path matches one of `paths`, mono POSTs the CL
+/// info to `url` and records the check as pending until the service reports
+/// back on `/api/v1/cl/{link}/checks/{name}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExternalCheckConfig {
+    /// Unique name of the check, used in the callback route
+    pub name: String,
+    pub url: String,
+    /// Glob patterns of monorepo paths the check applies to, e.g. "/project/**"
+    pub paths: Vec<String>,
+    /// Whether a non-passing result blocks merging
+    #[serde(default)]
+    pub required: bool,
+    /// Bearer token sent with the request and expected on the callback
+    pub token: String,
+}
+
 /// Buck upload API configuration
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BuckConfig {
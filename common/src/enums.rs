@@ -9,6 +9,9 @@
 
 use std::str::FromStr;
 
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
 /// An enum representing different oauth types.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum SupportOauthType {
@@ -25,3 +28,137 @@ impl FromStr for SupportOauthType {
         }
     }
 }
+
+/// What a personal access token may be used for.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize, ToSchema)]
+pub enum TokenScope {
+    #[serde(rename = "repo:read")]
+    RepoRead,
+    #[serde(rename = "repo:write")]
+    RepoWrite,
+    #[serde(rename = "cl:write")]
+    ClWrite,
+    #[serde(rename = "admin")]
+    Admin,
+    #[serde(rename = "lfs")]
+    Lfs,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::RepoRead => "repo:read",
+            TokenScope::RepoWrite => "repo:write",
+            TokenScope::ClWrite => "cl:write",
+            TokenScope::Admin => "admin",
+            TokenScope::Lfs => "lfs",
+        }
+    }
+
+    /// Whether `granted` covers this scope. `admin` covers everything and
+    /// `repo:write` covers `repo:read`.
+    pub fn granted_by(&self, granted: &[TokenScope]) -> bool {
+        granted.iter().any(|scope| {
+            scope == self
+                || *scope == TokenScope::Admin
+                || (*scope == TokenScope::RepoWrite && *self == TokenScope::RepoRead)
+        })
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repo:read" => Ok(Self::RepoRead),
+            "repo:write" => Ok(Self::RepoWrite),
+            "cl:write" => Ok(Self::ClWrite),
+            "admin" => Ok(Self::Admin),
+            "lfs" => Ok(Self::Lfs),
+            _ => Err(format!("'{s}' is not a valid token scope")),
+        }
+    }
+}
+
+/// Events a webhook can subscribe to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize, ToSchema)]
+pub enum WebhookEvent {
+    #[serde(rename = "push")]
+    Push,
+    #[serde(rename = "cl.opened")]
+    ClOpened,
+    #[serde(rename = "cl.updated")]
+    ClUpdated,
+    #[serde(rename = "cl.reviewed")]
+    ClReviewed,
+    #[serde(rename = "cl.merged")]
+    ClMerged,
+    #[serde(rename = "cl.closed")]
+    ClClosed,
+    #[serde(rename = "cl.reopened")]
+    ClReopened,
+    #[serde(rename = "issue.opened")]
+    IssueOpened,
+    #[serde(rename = "issue.edited")]
+    IssueEdited,
+    #[serde(rename = "issue.closed")]
+    IssueClosed,
+    #[serde(rename = "issue.reopened")]
+    IssueReopened,
+    #[serde(rename = "merge_queue.failed")]
+    MergeQueueFailed,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::Push => "push",
+            WebhookEvent::ClOpened => "cl.opened",
+            WebhookEvent::ClUpdated => "cl.updated",
+            WebhookEvent::ClReviewed => "cl.reviewed",
+            WebhookEvent::ClMerged => "cl.merged",
+            WebhookEvent::ClClosed => "cl.closed",
+            WebhookEvent::ClReopened => "cl.reopened",
+            WebhookEvent::IssueOpened => "issue.opened",
+            WebhookEvent::IssueEdited => "issue.edited",
+            WebhookEvent::IssueClosed => "issue.closed",
+            WebhookEvent::IssueReopened => "issue.reopened",
+            WebhookEvent::MergeQueueFailed => "merge_queue.failed",
+        }
+    }
+
+    /// Whether the event happens under a monorepo path. Issues aren't tied
+    /// to one, so issue events go to webhooks under any path prefix.
+    pub fn is_path_scoped(&self) -> bool {
+        !matches!(
+            self,
+            WebhookEvent::IssueOpened
+                | WebhookEvent::IssueEdited
+                | WebhookEvent::IssueClosed
+                | WebhookEvent::IssueReopened
+        )
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "push" => Ok(Self::Push),
+            "cl.opened" => Ok(Self::ClOpened),
+            "cl.updated" => Ok(Self::ClUpdated),
+            "cl.reviewed" => Ok(Self::ClReviewed),
+            "cl.merged" => Ok(Self::ClMerged),
+            "cl.closed" => Ok(Self::ClClosed),
+            "cl.reopened" => Ok(Self::ClReopened),
+            "issue.opened" => Ok(Self::IssueOpened),
+            "issue.edited" => Ok(Self::IssueEdited),
+            "issue.closed" => Ok(Self::IssueClosed),
+            "issue.reopened" => Ok(Self::IssueReopened),
+            "merge_queue.failed" => Ok(Self::MergeQueueFailed),
+            _ => Err(format!("'{s}' is not a valid webhook event")),
+        }
+    }
+}
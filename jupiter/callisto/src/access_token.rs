@@ -9,9 +9,15 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: i64,
     pub username: String,
-    #[sea_orm(column_type = "Text")]
-    pub token: String,
     pub created_at: DateTime,
+    pub token_prefix: String,
+    pub token_hash: String,
+    pub salt: String,
+    #[sea_orm(column_type = "Text")]
+    pub scopes: String,
+    pub path_prefix: Option<String>,
+    pub expires_at: Option<DateTime>,
+    pub last_used_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
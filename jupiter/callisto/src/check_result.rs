@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
 
+use super::sea_orm_active_enums::CheckStatusEnum;
 use super::sea_orm_active_enums::CheckTypeEnum;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -15,8 +16,10 @@ pub struct Model {
     pub cl_link: String,
     pub commit_id: String,
     pub check_type_code: CheckTypeEnum,
-    pub status: String,
+    pub status: CheckStatusEnum,
     pub message: String,
+    pub check_name: String,
+    pub required: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
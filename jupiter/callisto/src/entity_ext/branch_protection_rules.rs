@@ -0,0 +1,39 @@
+use sea_orm::prelude::Json;
+
+use crate::{branch_protection_rules, entity_ext::generate_id};
+
+impl branch_protection_rules::Model {
+    pub fn new(
+        path: &str,
+        direct_push_users: Vec<String>,
+        require_cl: bool,
+        min_approvals: i32,
+        allow_force_push: bool,
+        allow_deletion: bool,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            path: path.to_owned(),
+            direct_push_users: Json::from(direct_push_users),
+            require_cl,
+            min_approvals,
+            allow_force_push,
+            allow_deletion,
+            require_signed_push: false,
+            dismiss_stale_approvals: false,
+            sticky_approvals: false,
+        }
+    }
+
+    /// Whether `username` may update refs under this path without a CL.
+    pub fn allows_direct_push(&self, username: &str) -> bool {
+        !self.require_cl
+            || self
+                .direct_push_users
+                .as_array()
+                .is_some_and(|users| users.iter().any(|u| u.as_str() == Some(username)))
+    }
+}
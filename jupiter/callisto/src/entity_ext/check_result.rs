@@ -1,4 +1,8 @@
-use crate::{check_result, entity_ext::generate_id, sea_orm_active_enums::CheckTypeEnum};
+use crate::{
+    check_result,
+    entity_ext::generate_id,
+    sea_orm_active_enums::{CheckStatusEnum, CheckTypeEnum},
+};
 
 impl check_result::Model {
     pub fn new(
@@ -6,7 +10,7 @@ impl check_result::Model {
         cl_link: &str,
         commit_id: &str,
         check_type_code: CheckTypeEnum,
-        status: &str,
+        status: CheckStatusEnum,
         message: &str,
     ) -> Self {
         let now = chrono::Utc::now().naive_utc();
@@ -18,8 +22,34 @@ impl check_result::Model {
             cl_link: cl_link.to_owned(),
             commit_id: commit_id.to_owned(),
             check_type_code,
-            status: status.to_owned(),
+            status,
             message: message.to_owned(),
+            check_name: String::new(),
+            required: true,
+        }
+    }
+
+    /// Result of an external check, identified by its configured name.
+    pub fn new_external(
+        path: &str,
+        cl_link: &str,
+        commit_id: &str,
+        check_name: &str,
+        required: bool,
+        status: CheckStatusEnum,
+        message: &str,
+    ) -> Self {
+        Self {
+            check_name: check_name.to_owned(),
+            required,
+            ..Self::new(
+                path,
+                cl_link,
+                commit_id,
+                CheckTypeEnum::External,
+                status,
+                message,
+            )
         }
     }
 }
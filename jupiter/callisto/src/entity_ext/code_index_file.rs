@@ -0,0 +1,15 @@
+use crate::{code_index_file, entity_ext::generate_id};
+
+impl code_index_file::Model {
+    pub fn new(path: &str, blob_id: &str, language: Option<&str>) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            path: path.to_owned(),
+            blob_id: blob_id.to_owned(),
+            language: language.map(str::to_owned),
+        }
+    }
+}
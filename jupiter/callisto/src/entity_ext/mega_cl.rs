@@ -73,6 +73,7 @@ impl mega_cl::Model {
             from_hash,
             to_hash,
             username,
+            base_cl: None,
         }
     }
 
@@ -97,6 +98,7 @@ impl mega_cl::Model {
             from_hash,
             to_hash: String::new(),
             username,
+            base_cl: None,
         }
     }
 }
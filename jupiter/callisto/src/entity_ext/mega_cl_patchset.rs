@@ -0,0 +1,17 @@
+use crate::{entity_ext::generate_id, mega_cl_patchset};
+
+impl mega_cl_patchset::Model {
+    pub fn new(cl_link: &str, number: i32, from_hash: &str, to_hash: &str, pusher: &str) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            cl_link: cl_link.to_owned(),
+            number,
+            from_hash: from_hash.to_owned(),
+            to_hash: to_hash.to_owned(),
+            pusher: pusher.to_owned(),
+        }
+    }
+}
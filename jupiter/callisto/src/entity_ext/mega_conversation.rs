@@ -73,6 +73,13 @@ impl mega_conversation::Model {
             updated_at: now,
             username: username.to_owned(),
             resolved,
+            parent_id: None,
+            file_path: None,
+            diff_side: None,
+            start_line: None,
+            end_line: None,
+            commit_id: None,
+            outdated: false,
         }
     }
 }
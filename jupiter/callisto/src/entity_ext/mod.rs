@@ -1,14 +1,22 @@
+pub mod branch_protection_rules;
 pub mod buck_session;
 pub mod buck_session_file;
 pub mod check_result;
+pub mod code_index_file;
 pub mod item_assignees;
 pub mod item_labels;
 pub mod label;
 pub mod mega_cl;
+pub mod mega_cl_patchset;
 pub mod mega_conversation;
 pub mod mega_issue;
 pub mod mega_refs;
+pub mod notification_subscriptions;
+pub mod notifications;
+pub mod push_certificates;
 pub mod reactions;
+pub mod webhook;
+pub mod webhook_delivery;
 
 use idgenerator::IdInstance;
 use rand::Rng;
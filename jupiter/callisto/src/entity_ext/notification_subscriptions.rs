@@ -0,0 +1,18 @@
+use crate::{
+    entity_ext::generate_id, notification_subscriptions,
+    sea_orm_active_enums::SubscriptionTargetEnum,
+};
+
+impl notification_subscriptions::Model {
+    pub fn new(username: &str, target_type: SubscriptionTargetEnum, target: &str) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            username: username.to_owned(),
+            target_type,
+            target: target.to_owned(),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{
+    entity_ext::generate_id,
+    notifications,
+    sea_orm_active_enums::{NotificationReasonEnum, NotificationStateEnum},
+};
+
+impl notifications::Model {
+    pub fn new(
+        username: &str,
+        reason: NotificationReasonEnum,
+        link: &str,
+        actor: Option<&str>,
+        summary: String,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            username: username.to_owned(),
+            reason,
+            link: link.to_owned(),
+            actor: actor.map(str::to_owned),
+            summary,
+            state: NotificationStateEnum::Unread,
+        }
+    }
+}
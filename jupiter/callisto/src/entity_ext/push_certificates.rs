@@ -0,0 +1,34 @@
+use crate::{entity_ext::generate_id, push_certificates};
+
+impl push_certificates::Model {
+    /// Records a ref update made under a verified push certificate.
+    ///
+    /// `pusher` is the identity written in the certificate, `user_id` and
+    /// `key_id` belong to the stored GPG key that signed it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        path: &str,
+        ref_name: &str,
+        old_id: &str,
+        new_id: &str,
+        pusher: &str,
+        user_id: &str,
+        key_id: &str,
+        nonce: &str,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            path: path.to_owned(),
+            ref_name: ref_name.to_owned(),
+            old_id: old_id.to_owned(),
+            new_id: new_id.to_owned(),
+            pusher: pusher.to_owned(),
+            user_id: user_id.to_owned(),
+            key_id: key_id.to_owned(),
+            nonce: nonce.to_owned(),
+        }
+    }
+}
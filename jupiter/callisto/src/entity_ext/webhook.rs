@@ -0,0 +1,24 @@
+use crate::{entity_ext::generate_id, webhook};
+
+impl webhook::Model {
+    pub fn new(
+        path_prefix: &str,
+        url: &str,
+        secret: &str,
+        events: String,
+        created_by: &str,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            path_prefix: path_prefix.to_owned(),
+            url: url.to_owned(),
+            secret: secret.to_owned(),
+            events,
+            active: true,
+            created_by: created_by.to_owned(),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use crate::{entity_ext::generate_id, sea_orm_active_enums::DeliveryStatusEnum, webhook_delivery};
+
+impl webhook_delivery::Model {
+    pub fn new(webhook_id: i64, event: &str, payload: String) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: generate_id(),
+            created_at: now,
+            updated_at: now,
+            webhook_id,
+            event: event.to_owned(),
+            payload,
+            status: DeliveryStatusEnum::Pending,
+            attempts: 0,
+            response_status: None,
+            last_error: None,
+            next_attempt_at: None,
+            delivered_at: None,
+        }
+    }
+}
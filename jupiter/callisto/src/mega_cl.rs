@@ -21,6 +21,7 @@ pub struct Model {
     pub created_at: DateTime,
     pub updated_at: DateTime,
     pub username: String,
+    pub base_cl: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
 
+use super::sea_orm_active_enums::ReviewStateEnum;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +15,8 @@ pub struct Model {
     pub created_at: DateTime,
     pub updated_at: DateTime,
     pub system_required: bool,
+    pub approved_patchset: Option<i32>,
+    pub review_state: ReviewStateEnum,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,6 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
 
-use super::sea_orm_active_enums::ConvTypeEnum;
+use super::sea_orm_active_enums::{ConvTypeEnum, DiffSideEnum};
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -17,6 +17,13 @@ pub struct Model {
     pub updated_at: DateTime,
     pub username: String,
     pub resolved: Option<bool>,
+    pub parent_id: Option<i64>,
+    pub file_path: Option<String>,
+    pub diff_side: Option<DiffSideEnum>,
+    pub start_line: Option<i32>,
+    pub end_line: Option<i32>,
+    pub commit_id: Option<String>,
+    pub outdated: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
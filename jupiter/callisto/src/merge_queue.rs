@@ -21,6 +21,9 @@ pub struct Model {
     pub error_message: Option<String>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
+    pub batch_id: Option<i64>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub batch_history: Option<Json>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -3,10 +3,15 @@
 pub mod prelude;
 
 pub mod access_token;
+pub mod branch_protection_rules;
 pub mod buck_session;
 pub mod buck_session_file;
 pub mod builds;
 pub mod check_result;
+pub mod code_index_file;
+pub mod code_index_state;
+pub mod code_index_trigram;
+pub mod code_symbols;
 pub mod commit_auths;
 pub mod dynamic_sidebar;
 pub mod entity_ext;
@@ -28,6 +33,7 @@ pub mod lfs_objects;
 pub mod mega_blob;
 pub mod mega_cl;
 pub mod mega_cl_commits;
+pub mod mega_cl_patchset;
 pub mod mega_cl_reviewer;
 pub mod mega_commit;
 pub mod mega_conversation;
@@ -37,9 +43,14 @@ pub mod mega_tag;
 pub mod mega_tree;
 pub mod merge_queue;
 pub mod notes;
+pub mod notification_subscriptions;
+pub mod notifications;
 pub mod path_check_configs;
+pub mod push_certificates;
 pub mod reactions;
 pub mod sea_orm_active_enums;
 pub mod ssh_keys;
 pub mod tasks;
 pub mod vault;
+pub mod webhook;
+pub mod webhook_delivery;
@@ -1,10 +1,15 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
 
 pub use super::access_token::Entity as AccessToken;
+pub use super::branch_protection_rules::Entity as BranchProtectionRules;
 pub use super::buck_session::Entity as BuckSession;
 pub use super::buck_session_file::Entity as BuckSessionFile;
 pub use super::builds::Entity as Builds;
 pub use super::check_result::Entity as CheckResult;
+pub use super::code_index_file::Entity as CodeIndexFile;
+pub use super::code_index_state::Entity as CodeIndexState;
+pub use super::code_index_trigram::Entity as CodeIndexTrigram;
+pub use super::code_symbols::Entity as CodeSymbols;
 pub use super::commit_auths::Entity as CommitAuths;
 pub use super::dynamic_sidebar::Entity as DynamicSidebar;
 pub use super::git_blob::Entity as GitBlob;
@@ -25,6 +30,7 @@ pub use super::lfs_objects::Entity as LfsObjects;
 pub use super::mega_blob::Entity as MegaBlob;
 pub use super::mega_cl::Entity as MegaCl;
 pub use super::mega_cl_commits::Entity as MegaClCommits;
+pub use super::mega_cl_patchset::Entity as MegaClPatchset;
 pub use super::mega_cl_reviewer::Entity as MegaClReviewer;
 pub use super::mega_commit::Entity as MegaCommit;
 pub use super::mega_conversation::Entity as MegaConversation;
@@ -34,8 +40,13 @@ pub use super::mega_tag::Entity as MegaTag;
 pub use super::mega_tree::Entity as MegaTree;
 pub use super::merge_queue::Entity as MergeQueue;
 pub use super::notes::Entity as Notes;
+pub use super::notification_subscriptions::Entity as NotificationSubscriptions;
+pub use super::notifications::Entity as Notifications;
 pub use super::path_check_configs::Entity as PathCheckConfigs;
+pub use super::push_certificates::Entity as PushCertificates;
 pub use super::reactions::Entity as Reactions;
 pub use super::ssh_keys::Entity as SshKeys;
 pub use super::tasks::Entity as Tasks;
 pub use super::vault::Entity as Vault;
+pub use super::webhook::Entity as Webhook;
+pub use super::webhook_delivery::Entity as WebhookDelivery;
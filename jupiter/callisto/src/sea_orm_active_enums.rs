@@ -3,6 +3,20 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "check_status_enum")]
+pub enum CheckStatusEnum {
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    #[sea_orm(string_value = "passed")]
+    Passed,
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "skipped")]
+    Skipped,
+    #[sea_orm(string_value = "error")]
+    Error,
+}
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "check_type_enum")]
 pub enum CheckTypeEnum {
@@ -20,6 +34,8 @@ pub enum CheckTypeEnum {
     CiStatus,
     #[sea_orm(string_value = "code_review")]
     CodeReview,
+    #[sea_orm(string_value = "external")]
+    External,
 }
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "conv_type_enum")]
@@ -56,6 +72,30 @@ pub enum ConvTypeEnum {
     Draft,
 }
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "delivery_status_enum"
+)]
+pub enum DeliveryStatusEnum {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "queued")]
+    Queued,
+    #[sea_orm(string_value = "delivered")]
+    Delivered,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "diff_side_enum")]
+pub enum DiffSideEnum {
+    #[sea_orm(string_value = "old")]
+    Old,
+    #[sea_orm(string_value = "new")]
+    New,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "merge_status_enum")]
 pub enum MergeStatusEnum {
     #[sea_orm(string_value = "open")]
@@ -68,6 +108,36 @@ pub enum MergeStatusEnum {
     Draft,
 }
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "notification_reason_enum"
+)]
+pub enum NotificationReasonEnum {
+    #[sea_orm(string_value = "mention")]
+    Mention,
+    #[sea_orm(string_value = "assigned")]
+    Assigned,
+    #[sea_orm(string_value = "review_requested")]
+    ReviewRequested,
+    #[sea_orm(string_value = "subscribed")]
+    Subscribed,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "notification_state_enum"
+)]
+pub enum NotificationStateEnum {
+    #[sea_orm(string_value = "unread")]
+    Unread,
+    #[sea_orm(string_value = "read")]
+    Read,
+    #[sea_orm(string_value = "done")]
+    Done,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(
     rs_type = "String",
     db_type = "Enum",
@@ -124,6 +194,18 @@ pub enum ReferenceTypeEnum {
     Blocks,
 }
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "review_state_enum")]
+pub enum ReviewStateEnum {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "approved")]
+    Approved,
+    #[sea_orm(string_value = "changes_requested")]
+    ChangesRequested,
+    #[sea_orm(string_value = "commented")]
+    Commented,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "storage_type_enum")]
 pub enum StorageTypeEnum {
     #[sea_orm(string_value = "database")]
@@ -133,3 +215,15 @@ pub enum StorageTypeEnum {
     #[sea_orm(string_value = "aws_s3")]
     AwsS3,
 }
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "subscription_target_enum"
+)]
+pub enum SubscriptionTargetEnum {
+    #[sea_orm(string_value = "path")]
+    Path,
+    #[sea_orm(string_value = "item")]
+    Item,
+}
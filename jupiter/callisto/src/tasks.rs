@@ -13,6 +13,7 @@ pub struct Model {
     #[sea_orm(column_type = "JsonBinary", nullable)]
     pub template: Option<Json>,
     pub created_at: DateTimeWithTimeZone,
+    pub commit_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -13,6 +13,8 @@ use common::config::S3Config;
 use common::errors::MegaError;
 
 use crate::lfs_storage::{LfsFileStorage, transform_path};
+use crate::object_storage::ObjectByteStream;
+use crate::object_storage::rustfs_object_storage::aws_byte_stream_to_object_stream;
 
 pub struct AwsS3Storage {
     client: Client,
@@ -68,6 +70,23 @@ impl LfsFileStorage for AwsS3Storage {
         unimplemented!("Aws_s3 mode using presigned url instead direct download")
     }
 
+    async fn get_object_stream(
+        &self,
+        object_id: &str,
+    ) -> Result<(ObjectByteStream, u64), MegaError> {
+        let key = format!("{}/{}", "objects", transform_path(object_id));
+        let res = self
+            .client
+            .get_object()
+            .bucket(self.bucket_name.clone())
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| convert_s3_error(&e))?;
+        let size = res.content_length().unwrap_or_default().max(0) as u64;
+        Ok((aws_byte_stream_to_object_stream(res.body), size))
+    }
+
     async fn download_url(&self, object_id: &str, _: &str) -> Result<String, MegaError> {
         let key = format!("{}/{}", "objects", transform_path(object_id));
         let expires_in = Duration::from_secs(3600);
@@ -9,8 +9,10 @@ use bytes::Bytes;
 use common::config::LFSLocalConfig;
 use common::errors::MegaError;
 use sea_orm::DatabaseConnection;
+use tokio_util::io::ReaderStream;
 
 use crate::lfs_storage::{LfsFileStorage, transform_path};
+use crate::object_storage::ObjectByteStream;
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
 use crate::storage::lfs_db_storage::LfsDbStorage;
 
@@ -54,6 +56,18 @@ impl LfsFileStorage for LocalStorage {
         Ok(Bytes::from(buffer))
     }
 
+    async fn get_object_stream(
+        &self,
+        object_id: &str,
+    ) -> Result<(ObjectByteStream, u64), MegaError> {
+        let path = Path::new(&self.config.lfs_file_path)
+            .join("objects")
+            .join(transform_path(object_id));
+        let file = tokio::fs::File::open(&path).await?;
+        let size = file.metadata().await?.len();
+        Ok((Box::pin(ReaderStream::new(file)), size))
+    }
+
     async fn download_url(&self, object_id: &str, hostname: &str) -> Result<String, MegaError> {
         Ok(self.action_href(object_id, hostname))
     }
@@ -11,6 +11,7 @@ use common::{
 use sea_orm::DatabaseConnection;
 
 use crate::lfs_storage::local_storage::LocalStorage;
+use crate::object_storage::ObjectByteStream;
 
 mod aws_s3_storage;
 pub mod local_storage;
@@ -19,6 +20,13 @@ pub mod local_storage;
 pub trait LfsFileStorage: Sync + Send {
     async fn get_object(&self, object_id: &str) -> Result<Bytes, MegaError>;
 
+    /// Streams an object along with its size, for objects too large to
+    /// hold in memory.
+    async fn get_object_stream(
+        &self,
+        object_id: &str,
+    ) -> Result<(ObjectByteStream, u64), MegaError>;
+
     fn action_href(&self, object_id: &str, hostname: &str) -> String {
         format!("{}/info/lfs/objects/{}", hostname, object_id)
     }
@@ -0,0 +1,56 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::migration::pk_bigint;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(BranchProtectionRules::Table)
+                    .col(pk_bigint(BranchProtectionRules::Id))
+                    .col(string(BranchProtectionRules::Path))
+                    .col(json_binary(BranchProtectionRules::DirectPushUsers))
+                    .col(boolean(BranchProtectionRules::RequireCl).default(true))
+                    .col(integer(BranchProtectionRules::MinApprovals).default(0))
+                    .col(boolean(BranchProtectionRules::AllowForcePush).default(false))
+                    .col(boolean(BranchProtectionRules::AllowDeletion).default(false))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("branch_protection_path_unique")
+                    .table(BranchProtectionRules::Table)
+                    .col(BranchProtectionRules::Path)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BranchProtectionRules::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum BranchProtectionRules {
+    Table,
+    Id,
+    Path,
+    DirectPushUsers,
+    RequireCl,
+    MinApprovals,
+    AllowForcePush,
+    AllowDeletion,
+}
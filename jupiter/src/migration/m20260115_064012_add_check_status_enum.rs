@@ -0,0 +1,74 @@
+use extension::postgres::Type;
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{DatabaseBackend, EnumIter, Iterable},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .create_type(
+                        Type::create()
+                            .as_enum(CheckStatusEnum)
+                            .values(CheckStatus::iter())
+                            .to_owned(),
+                    )
+                    .await?;
+                manager
+                    .get_connection()
+                    .execute_unprepared(
+                        r#"ALTER TABLE check_result ALTER COLUMN status TYPE check_status_enum USING LOWER(status)::check_status_enum;"#,
+                    )
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {
+                manager
+                    .get_connection()
+                    .execute_unprepared(r#"UPDATE check_result SET status = LOWER(status);"#)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .get_connection()
+                    .execute_unprepared(
+                        r#"ALTER TABLE check_result ALTER COLUMN status TYPE varchar USING UPPER(status::text);"#,
+                    )
+                    .await?;
+                manager
+                    .drop_type(Type::drop().name(CheckStatusEnum).to_owned())
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {
+                manager
+                    .get_connection()
+                    .execute_unprepared(r#"UPDATE check_result SET status = UPPER(status);"#)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+struct CheckStatusEnum;
+
+#[derive(Iden, EnumIter)]
+pub enum CheckStatus {
+    Failed,
+    Passed,
+    Pending,
+    Skipped,
+    Error,
+}
@@ -0,0 +1,119 @@
+use sea_orm_migration::{prelude::*, sea_orm::DatabaseBackend};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .get_connection()
+                    .execute_unprepared(
+                        r#"ALTER TYPE check_type_enum ADD VALUE IF NOT EXISTS 'external';"#,
+                    )
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+
+        // Built-in checks keep an empty name; external checks are told apart by it.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CheckResult::Table)
+                    .add_column(
+                        ColumnDef::new(CheckResult::CheckName)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CheckResult::Table)
+                    .add_column(
+                        ColumnDef::new(CheckResult::Required)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("check_res_unique")
+                    .table(CheckResult::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("check_res_unique")
+                    .table(CheckResult::Table)
+                    .col(CheckResult::ClLink)
+                    .col(CheckResult::CheckTypeCode)
+                    .col(CheckResult::CheckName)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("check_res_unique")
+                    .table(CheckResult::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CheckResult::Table)
+                    .drop_column(CheckResult::Required)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CheckResult::Table)
+                    .drop_column(CheckResult::CheckName)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("check_res_unique")
+                    .table(CheckResult::Table)
+                    .col(CheckResult::ClLink)
+                    .col(CheckResult::CheckTypeCode)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum CheckResult {
+    Table,
+    ClLink,
+    CheckTypeCode,
+    CheckName,
+    Required,
+}
@@ -0,0 +1,88 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::migration::pk_bigint;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(BranchProtectionRules::Table)
+                    .add_column(
+                        ColumnDef::new(BranchProtectionRules::RequireSignedPush)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                table_auto(PushCertificates::Table)
+                    .col(pk_bigint(PushCertificates::Id))
+                    .col(string(PushCertificates::Path))
+                    .col(string(PushCertificates::RefName))
+                    .col(string(PushCertificates::OldId))
+                    .col(string(PushCertificates::NewId))
+                    .col(string(PushCertificates::Pusher))
+                    .col(string(PushCertificates::UserId))
+                    .col(string(PushCertificates::KeyId))
+                    .col(string(PushCertificates::Nonce))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_push_cert_path_ref")
+                    .table(PushCertificates::Table)
+                    .col(PushCertificates::Path)
+                    .col(PushCertificates::RefName)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PushCertificates::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(BranchProtectionRules::Table)
+                    .drop_column(BranchProtectionRules::RequireSignedPush)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum BranchProtectionRules {
+    Table,
+    RequireSignedPush,
+}
+
+#[derive(DeriveIden)]
+enum PushCertificates {
+    Table,
+    Id,
+    Path,
+    RefName,
+    OldId,
+    NewId,
+    Pusher,
+    UserId,
+    KeyId,
+    Nonce,
+}
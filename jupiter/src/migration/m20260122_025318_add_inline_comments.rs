@@ -0,0 +1,122 @@
+use sea_orm::{DatabaseBackend, EnumIter, Iterable, sea_query::extension::postgres::Type};
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .create_type(
+                        Type::create()
+                            .as_enum(DiffSideEnum)
+                            .values(DiffSide::iter())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+
+        // SQLite can only add one column per ALTER TABLE
+        let columns = [
+            big_integer_null(MegaConversation::ParentId),
+            string_null(MegaConversation::FilePath),
+            enumeration_null(
+                MegaConversation::DiffSide,
+                Alias::new("diff_side_enum"),
+                DiffSide::iter(),
+            ),
+            integer_null(MegaConversation::StartLine),
+            integer_null(MegaConversation::EndLine),
+            string_null(MegaConversation::CommitId),
+            boolean(MegaConversation::Outdated)
+                .default(false)
+                .to_owned(),
+        ];
+        for mut column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(MegaConversation::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_conversation_parent_id")
+                    .table(MegaConversation::Table)
+                    .col(MegaConversation::ParentId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_conversation_parent_id")
+                    .table(MegaConversation::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            MegaConversation::Outdated,
+            MegaConversation::CommitId,
+            MegaConversation::EndLine,
+            MegaConversation::StartLine,
+            MegaConversation::DiffSide,
+            MegaConversation::FilePath,
+            MegaConversation::ParentId,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(MegaConversation::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .drop_type(Type::drop().name(DiffSideEnum).to_owned())
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum MegaConversation {
+    Table,
+    ParentId,
+    FilePath,
+    DiffSide,
+    StartLine,
+    EndLine,
+    CommitId,
+    Outdated,
+}
+
+#[derive(DeriveIden)]
+struct DiffSideEnum;
+
+#[derive(Iden, EnumIter)]
+pub enum DiffSide {
+    Old,
+    New,
+}
@@ -0,0 +1,93 @@
+use sea_orm::DatabaseBackend;
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::migration::pk_bigint;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(MegaClPatchset::Table)
+                    .col(pk_bigint(MegaClPatchset::Id))
+                    .col(string(MegaClPatchset::ClLink))
+                    .col(integer(MegaClPatchset::Number))
+                    .col(string(MegaClPatchset::FromHash))
+                    .col(string(MegaClPatchset::ToHash))
+                    .col(string(MegaClPatchset::Pusher))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cl_patchset_link_number")
+                    .unique()
+                    .table(MegaClPatchset::Table)
+                    .col(MegaClPatchset::ClLink)
+                    .col(MegaClPatchset::Number)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MegaClReviewer::Table)
+                    .add_column(integer_null(MegaClReviewer::ApprovedPatchset))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Existing CLs start their history at the current revision. SQLite
+        // never got the `username` column on `mega_cl`.
+        let pusher = match manager.get_database_backend() {
+            DatabaseBackend::Postgres | DatabaseBackend::MySql => "username",
+            DatabaseBackend::Sqlite => "''",
+        };
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                "INSERT INTO mega_cl_patchset (id, cl_link, number, from_hash, to_hash, pusher, created_at, updated_at) \
+                SELECT id, link, 1, from_hash, to_hash, {pusher}, updated_at, updated_at FROM mega_cl;"
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MegaClReviewer::Table)
+                    .drop_column(MegaClReviewer::ApprovedPatchset)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(MegaClPatchset::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum MegaClPatchset {
+    Table,
+    Id,
+    ClLink,
+    Number,
+    FromHash,
+    ToHash,
+    Pusher,
+}
+
+#[derive(DeriveIden)]
+enum MegaClReviewer {
+    Table,
+    ApprovedPatchset,
+}
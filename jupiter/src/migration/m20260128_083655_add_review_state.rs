@@ -0,0 +1,119 @@
+use sea_orm::{DatabaseBackend, EnumIter, Iterable, sea_query::extension::postgres::Type};
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .create_type(
+                        Type::create()
+                            .as_enum(ReviewStateEnum)
+                            .values(ReviewState::iter())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MegaClReviewer::Table)
+                    .add_column(
+                        enumeration(
+                            MegaClReviewer::ReviewState,
+                            Alias::new("review_state_enum"),
+                            ReviewState::iter(),
+                        )
+                        .default("pending"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE mega_cl_reviewer SET review_state = 'approved' WHERE approved = true;"#,
+            )
+            .await?;
+
+        for column in [
+            BranchProtectionRules::DismissStaleApprovals,
+            BranchProtectionRules::StickyApprovals,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(BranchProtectionRules::Table)
+                        .add_column(boolean(column).default(false))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            BranchProtectionRules::StickyApprovals,
+            BranchProtectionRules::DismissStaleApprovals,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(BranchProtectionRules::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MegaClReviewer::Table)
+                    .drop_column(MegaClReviewer::ReviewState)
+                    .to_owned(),
+            )
+            .await?;
+
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .drop_type(Type::drop().name(ReviewStateEnum).to_owned())
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum MegaClReviewer {
+    Table,
+    ReviewState,
+}
+
+#[derive(DeriveIden)]
+enum BranchProtectionRules {
+    Table,
+    DismissStaleApprovals,
+    StickyApprovals,
+}
+
+#[derive(DeriveIden)]
+struct ReviewStateEnum;
+
+#[derive(Iden, EnumIter)]
+pub enum ReviewState {
+    Pending,
+    Approved,
+    ChangesRequested,
+    Commented,
+}
@@ -0,0 +1,192 @@
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+use sea_orm::{FromQueryResult, Statement};
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Tokens created before scopes existed could do anything but
+/// administration.
+const LEGACY_SCOPES: &str = "repo:read,repo:write,cl:write,lfs";
+
+/// Days left to rotate a token created before tokens expired.
+const LEGACY_LIFETIME_DAYS: i64 = 90;
+
+// A copy of how tokens were hashed when this migration was written, so
+// later changes to the token storage don't change what it does.
+const TOKEN_PREFIX_LEN: usize = 12;
+
+fn generate_salt() -> String {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random source failed");
+    hex::encode(bytes)
+}
+
+fn hash_token(salt: &str, token: &str) -> String {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(salt.as_bytes());
+    ctx.update(token.as_bytes());
+    hex::encode(ctx.finish())
+}
+
+#[derive(FromQueryResult)]
+struct LegacyToken {
+    id: i64,
+    token: String,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite can only add one column per ALTER TABLE
+        let columns = [
+            string(AccessToken::TokenPrefix).default("").to_owned(),
+            string(AccessToken::TokenHash).default("").to_owned(),
+            string(AccessToken::Salt).default("").to_owned(),
+            text(AccessToken::Scopes).default("").to_owned(),
+            string_null(AccessToken::PathPrefix),
+            timestamp_null(AccessToken::ExpiresAt),
+            timestamp_null(AccessToken::LastUsedAt),
+        ];
+        for mut column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(AccessToken::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // hash the tokens stored so far, then forget them
+        let db = manager.get_connection();
+        let legacy = LegacyToken::find_by_statement(Statement::from_string(
+            manager.get_database_backend(),
+            "SELECT id, token FROM access_token",
+        ))
+        .all(db)
+        .await?;
+        let expires_at =
+            chrono::Utc::now().naive_utc() + chrono::Duration::days(LEGACY_LIFETIME_DAYS);
+        for token in legacy {
+            let salt = generate_salt();
+            let prefix: String = token.token.chars().take(TOKEN_PREFIX_LEN).collect();
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(AccessToken::Table)
+                        .values([
+                            (AccessToken::TokenPrefix, prefix.into()),
+                            (
+                                AccessToken::TokenHash,
+                                hash_token(&salt, &token.token).into(),
+                            ),
+                            (AccessToken::Salt, salt.into()),
+                            (AccessToken::Scopes, LEGACY_SCOPES.into()),
+                            (AccessToken::ExpiresAt, expires_at.into()),
+                        ])
+                        .and_where(Expr::col(AccessToken::Id).eq(token.id))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_token")
+                    .table(AccessToken::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AccessToken::Table)
+                    .drop_column(AccessToken::Token)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_access_token_prefix")
+                    .table(AccessToken::Table)
+                    .col(AccessToken::TokenPrefix)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_access_token_prefix")
+                    .table(AccessToken::Table)
+                    .to_owned(),
+            )
+            .await?;
+        // the raw tokens are gone, so every token has to be issued again
+        manager
+            .exec_stmt(Query::delete().from_table(AccessToken::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AccessToken::Table)
+                    .add_column(text(AccessToken::Token).default(""))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_token")
+                    .table(AccessToken::Table)
+                    .col(AccessToken::Token)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            AccessToken::TokenPrefix,
+            AccessToken::TokenHash,
+            AccessToken::Salt,
+            AccessToken::Scopes,
+            AccessToken::PathPrefix,
+            AccessToken::ExpiresAt,
+            AccessToken::LastUsedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(AccessToken::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AccessToken {
+    Table,
+    Id,
+    Token,
+    TokenPrefix,
+    TokenHash,
+    Salt,
+    Scopes,
+    PathPrefix,
+    ExpiresAt,
+    LastUsedAt,
+}
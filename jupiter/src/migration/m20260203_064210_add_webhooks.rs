@@ -0,0 +1,141 @@
+use sea_orm::{DatabaseBackend, EnumIter, Iterable, sea_query::extension::postgres::Type};
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::migration::pk_bigint;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .create_type(
+                        Type::create()
+                            .as_enum(DeliveryStatusEnum)
+                            .values(DeliveryStatus::iter())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+
+        manager
+            .create_table(
+                table_auto(Webhook::Table)
+                    .col(pk_bigint(Webhook::Id))
+                    .col(string(Webhook::PathPrefix))
+                    .col(string(Webhook::Url))
+                    .col(string(Webhook::Secret))
+                    .col(text(Webhook::Events))
+                    .col(boolean(Webhook::Active).default(true))
+                    .col(string(Webhook::CreatedBy))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                table_auto(WebhookDelivery::Table)
+                    .col(pk_bigint(WebhookDelivery::Id))
+                    .col(big_integer(WebhookDelivery::WebhookId))
+                    .col(string(WebhookDelivery::Event))
+                    .col(text(WebhookDelivery::Payload))
+                    .col(
+                        enumeration(
+                            WebhookDelivery::Status,
+                            Alias::new("delivery_status_enum"),
+                            DeliveryStatus::iter(),
+                        )
+                        .default("pending"),
+                    )
+                    .col(integer(WebhookDelivery::Attempts).default(0))
+                    .col(integer_null(WebhookDelivery::ResponseStatus))
+                    .col(text_null(WebhookDelivery::LastError))
+                    .col(timestamp_null(WebhookDelivery::NextAttemptAt))
+                    .col(timestamp_null(WebhookDelivery::DeliveredAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webhook_delivery_webhook_id")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::WebhookId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webhook_delivery_status")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::Status)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDelivery::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Webhook::Table).to_owned())
+            .await?;
+
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .drop_type(Type::drop().name(DeliveryStatusEnum).to_owned())
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Webhook {
+    Table,
+    Id,
+    PathPrefix,
+    Url,
+    Secret,
+    Events,
+    Active,
+    CreatedBy,
+}
+
+#[derive(DeriveIden)]
+enum WebhookDelivery {
+    Table,
+    Id,
+    WebhookId,
+    Event,
+    Payload,
+    Status,
+    Attempts,
+    ResponseStatus,
+    LastError,
+    NextAttemptAt,
+    DeliveredAt,
+}
+
+#[derive(DeriveIden)]
+struct DeliveryStatusEnum;
+
+#[derive(Iden, EnumIter)]
+pub enum DeliveryStatus {
+    Pending,
+    Queued,
+    Delivered,
+    Failed,
+}
@@ -0,0 +1,187 @@
+use sea_orm::{DatabaseBackend, EnumIter, Iterable, sea_query::extension::postgres::Type};
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::migration::pk_bigint;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .create_type(
+                        Type::create()
+                            .as_enum(NotificationReasonEnum)
+                            .values(NotificationReason::iter())
+                            .to_owned(),
+                    )
+                    .await?;
+                manager
+                    .create_type(
+                        Type::create()
+                            .as_enum(NotificationStateEnum)
+                            .values(NotificationState::iter())
+                            .to_owned(),
+                    )
+                    .await?;
+                manager
+                    .create_type(
+                        Type::create()
+                            .as_enum(SubscriptionTargetEnum)
+                            .values(SubscriptionTarget::iter())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+
+        manager
+            .create_table(
+                table_auto(Notifications::Table)
+                    .col(pk_bigint(Notifications::Id))
+                    .col(string(Notifications::Username))
+                    .col(enumeration(
+                        Notifications::Reason,
+                        Alias::new("notification_reason_enum"),
+                        NotificationReason::iter(),
+                    ))
+                    .col(string(Notifications::Link))
+                    .col(string_null(Notifications::Actor))
+                    .col(text(Notifications::Summary))
+                    .col(
+                        enumeration(
+                            Notifications::State,
+                            Alias::new("notification_state_enum"),
+                            NotificationState::iter(),
+                        )
+                        .default("unread"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_notifications_username_state")
+                    .table(Notifications::Table)
+                    .col(Notifications::Username)
+                    .col(Notifications::State)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                table_auto(NotificationSubscriptions::Table)
+                    .col(pk_bigint(NotificationSubscriptions::Id))
+                    .col(string(NotificationSubscriptions::Username))
+                    .col(enumeration(
+                        NotificationSubscriptions::TargetType,
+                        Alias::new("subscription_target_enum"),
+                        SubscriptionTarget::iter(),
+                    ))
+                    .col(string(NotificationSubscriptions::Target))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_notification_subscriptions_target")
+                    .table(NotificationSubscriptions::Table)
+                    .col(NotificationSubscriptions::Username)
+                    .col(NotificationSubscriptions::TargetType)
+                    .col(NotificationSubscriptions::Target)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(NotificationSubscriptions::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Notifications::Table).to_owned())
+            .await?;
+
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                manager
+                    .drop_type(Type::drop().name(SubscriptionTargetEnum).to_owned())
+                    .await?;
+                manager
+                    .drop_type(Type::drop().name(NotificationStateEnum).to_owned())
+                    .await?;
+                manager
+                    .drop_type(Type::drop().name(NotificationReasonEnum).to_owned())
+                    .await?;
+            }
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Notifications {
+    Table,
+    Id,
+    Username,
+    Reason,
+    Link,
+    Actor,
+    Summary,
+    State,
+}
+
+#[derive(DeriveIden)]
+enum NotificationSubscriptions {
+    Table,
+    Id,
+    Username,
+    TargetType,
+    Target,
+}
+
+#[derive(DeriveIden)]
+struct NotificationReasonEnum;
+
+#[derive(Iden, EnumIter)]
+pub enum NotificationReason {
+    Mention,
+    Assigned,
+    ReviewRequested,
+    Subscribed,
+}
+
+#[derive(DeriveIden)]
+struct NotificationStateEnum;
+
+#[derive(Iden, EnumIter)]
+pub enum NotificationState {
+    Unread,
+    Read,
+    Done,
+}
+
+#[derive(DeriveIden)]
+struct SubscriptionTargetEnum;
+
+#[derive(Iden, EnumIter)]
+pub enum SubscriptionTarget {
+    Path,
+    Item,
+}
@@ -0,0 +1,107 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::migration::pk_bigint;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(CodeIndexFile::Table)
+                    .col(pk_bigint(CodeIndexFile::Id))
+                    .col(string(CodeIndexFile::Path))
+                    .col(string(CodeIndexFile::BlobId))
+                    .col(string_null(CodeIndexFile::Language))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_code_index_file_path")
+                    .table(CodeIndexFile::Table)
+                    .col(CodeIndexFile::Path)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(CodeIndexTrigram::Table)
+                    .if_not_exists()
+                    .col(integer(CodeIndexTrigram::Trigram))
+                    .col(big_integer(CodeIndexTrigram::FileId))
+                    .primary_key(
+                        Index::create()
+                            .col(CodeIndexTrigram::Trigram)
+                            .col(CodeIndexTrigram::FileId),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_code_index_trigram_file_id")
+                    .table(CodeIndexTrigram::Table)
+                    .col(CodeIndexTrigram::FileId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                table_auto(CodeIndexState::Table)
+                    .col(pk_bigint(CodeIndexState::Id))
+                    .col(string(CodeIndexState::TreeId))
+                    .col(string(CodeIndexState::CommitId))
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CodeIndexState::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(CodeIndexTrigram::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(CodeIndexFile::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum CodeIndexFile {
+    Table,
+    Id,
+    Path,
+    BlobId,
+    Language,
+}
+
+#[derive(DeriveIden)]
+enum CodeIndexTrigram {
+    Table,
+    Trigram,
+    FileId,
+}
+
+#[derive(DeriveIden)]
+enum CodeIndexState {
+    Table,
+    Id,
+    TreeId,
+    CommitId,
+}
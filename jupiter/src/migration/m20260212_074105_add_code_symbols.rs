@@ -0,0 +1,71 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::migration::pk_bigint;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(CodeSymbols::Table)
+                    .col(pk_bigint(CodeSymbols::Id))
+                    .col(string(CodeSymbols::CommitId))
+                    .col(string(CodeSymbols::Path))
+                    .col(text(CodeSymbols::Symbol))
+                    .col(string(CodeSymbols::Name))
+                    .col(string_null(CodeSymbols::Kind))
+                    .col(boolean(CodeSymbols::IsDefinition))
+                    .col(integer(CodeSymbols::StartLine))
+                    .col(integer(CodeSymbols::StartCharacter))
+                    .col(integer(CodeSymbols::EndLine))
+                    .col(integer(CodeSymbols::EndCharacter))
+                    .col(string(CodeSymbols::Source))
+                    .to_owned(),
+            )
+            .await?;
+
+        for (name, col) in [
+            ("idx_code_symbols_commit_path", CodeSymbols::Path),
+            ("idx_code_symbols_commit_name", CodeSymbols::Name),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(name)
+                        .table(CodeSymbols::Table)
+                        .col(CodeSymbols::CommitId)
+                        .col(col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CodeSymbols::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum CodeSymbols {
+    Table,
+    Id,
+    CommitId,
+    Path,
+    Symbol,
+    Name,
+    Kind,
+    IsDefinition,
+    StartLine,
+    StartCharacter,
+    EndLine,
+    EndCharacter,
+    Source,
+}
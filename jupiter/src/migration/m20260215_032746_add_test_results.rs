@@ -0,0 +1,92 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TestResults::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TestResults::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(TestResults::TaskId).uuid().not_null())
+                    .col(ColumnDef::new(TestResults::BuildId).uuid().not_null())
+                    .col(ColumnDef::new(TestResults::Target).string().not_null())
+                    .col(ColumnDef::new(TestResults::Name).text().not_null())
+                    .col(ColumnDef::new(TestResults::Status).string().not_null())
+                    .col(ColumnDef::new(TestResults::DurationMs).big_integer())
+                    .col(ColumnDef::new(TestResults::Message).text())
+                    .col(
+                        ColumnDef::new(TestResults::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_test_results_build_id")
+                            .from(TestResults::Table, TestResults::BuildId)
+                            .to(Builds::Table, Builds::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_test_results_task_id")
+                    .table(TestResults::Table)
+                    .col(TestResults::TaskId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_test_results_build_id")
+                    .table(TestResults::Table)
+                    .col(TestResults::BuildId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TestResults::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum TestResults {
+    Table,
+    Id,
+    TaskId,
+    BuildId,
+    Target,
+    Name,
+    Status,
+    DurationMs,
+    Message,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Builds {
+    Table,
+    Id,
+}
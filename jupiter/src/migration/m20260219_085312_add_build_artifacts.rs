@@ -0,0 +1,92 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Artifacts::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Artifacts::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Artifacts::BuildId).uuid().not_null())
+                    .col(ColumnDef::new(Artifacts::Target).string().not_null())
+                    .col(ColumnDef::new(Artifacts::Name).string().not_null())
+                    .col(ColumnDef::new(Artifacts::Size).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Artifacts::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(Artifacts::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_artifacts_build_id")
+                            .from(Artifacts::Table, Artifacts::BuildId)
+                            .to(Builds::Table, Builds::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_artifacts_build_id")
+                    .table(Artifacts::Table)
+                    .col(Artifacts::BuildId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_artifacts_expires_at")
+                    .table(Artifacts::Table)
+                    .col(Artifacts::ExpiresAt)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Artifacts::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Artifacts {
+    Table,
+    Id,
+    BuildId,
+    Target,
+    Name,
+    Size,
+    CreatedAt,
+    ExpiresAt,
+}
+
+#[derive(DeriveIden)]
+enum Builds {
+    Table,
+    Id,
+}
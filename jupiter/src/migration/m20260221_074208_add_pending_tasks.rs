@@ -0,0 +1,101 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PendingTasks::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PendingTasks::BuildId)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PendingTasks::TaskId).uuid().not_null())
+                    .col(ColumnDef::new(PendingTasks::Repo).string().not_null())
+                    .col(ColumnDef::new(PendingTasks::Cl).big_integer().not_null())
+                    .col(ColumnDef::new(PendingTasks::ClLink).string().not_null())
+                    .col(
+                        ColumnDef::new(PendingTasks::Changes)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PendingTasks::Priority).string().not_null())
+                    .col(ColumnDef::new(PendingTasks::Username).string())
+                    .col(ColumnDef::new(PendingTasks::CommitId).string())
+                    .col(
+                        ColumnDef::new(PendingTasks::Origin)
+                            .string()
+                            .not_null()
+                            .default("cl"),
+                    )
+                    .col(
+                        ColumnDef::new(PendingTasks::Requirements)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PendingTasks::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_pending_tasks_task_id")
+                            .from(PendingTasks::Table, PendingTasks::TaskId)
+                            .to(Tasks::Table, Tasks::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_pending_tasks_created_at")
+                    .table(PendingTasks::Table)
+                    .col(PendingTasks::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PendingTasks::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PendingTasks {
+    Table,
+    BuildId,
+    TaskId,
+    Repo,
+    Cl,
+    ClLink,
+    Changes,
+    Priority,
+    Username,
+    CommitId,
+    Origin,
+    Requirements,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Tasks {
+    Table,
+    Id,
+}
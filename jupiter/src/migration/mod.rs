@@ -28,7 +28,7 @@
 //! // Refresh all migrations (development only)
 //! apply_migrations(&db, true).await?;
 //! ```
-use sea_orm::DatabaseConnection;
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, TransactionTrait};
 use sea_orm_migration::prelude::*;
 use sea_orm_migration::schema::big_integer;
 use tracing::log;
@@ -76,6 +76,25 @@ mod m20251203_013745_add_dynamic_sidebar;
 mod m20251210_113942_remove_unique_constraint_from_order_index;
 mod m20260106_070511_add_retry_time;
 mod m20260106_070515_remove_relay_mq_lfs_raw_table;
+mod m20260112_031522_add_commit_id_to_tasks;
+mod m20260114_082310_add_branch_protection_rules;
+mod m20260115_064012_add_check_status_enum;
+mod m20260116_021847_add_external_checks;
+mod m20260118_093015_add_push_certificates;
+mod m20260120_041233_add_merge_queue_batches;
+mod m20260122_025318_add_inline_comments;
+mod m20260124_061742_add_cl_patchsets;
+mod m20260126_020411_add_base_cl;
+mod m20260128_083655_add_review_state;
+mod m20260130_021544_hash_access_tokens;
+mod m20260203_064210_add_webhooks;
+mod m20260206_031207_add_notifications;
+mod m20260209_052318_add_code_index;
+mod m20260212_074105_add_code_symbols;
+mod m20260215_032746_add_test_results;
+mod m20260217_061455_add_build_cancelled;
+mod m20260219_085312_add_build_artifacts;
+mod m20260221_074208_add_pending_tasks;
 
 /// Creates a primary key column definition with big integer type.
 ///
@@ -140,6 +159,25 @@ impl MigratorTrait for Migrator {
             Box::new(m20251210_113942_remove_unique_constraint_from_order_index::Migration),
             Box::new(m20260106_070511_add_retry_time::Migration),
             Box::new(m20260106_070515_remove_relay_mq_lfs_raw_table::Migration),
+            Box::new(m20260112_031522_add_commit_id_to_tasks::Migration),
+            Box::new(m20260114_082310_add_branch_protection_rules::Migration),
+            Box::new(m20260115_064012_add_check_status_enum::Migration),
+            Box::new(m20260116_021847_add_external_checks::Migration),
+            Box::new(m20260118_093015_add_push_certificates::Migration),
+            Box::new(m20260120_041233_add_merge_queue_batches::Migration),
+            Box::new(m20260122_025318_add_inline_comments::Migration),
+            Box::new(m20260124_061742_add_cl_patchsets::Migration),
+            Box::new(m20260126_020411_add_base_cl::Migration),
+            Box::new(m20260128_083655_add_review_state::Migration),
+            Box::new(m20260130_021544_hash_access_tokens::Migration),
+            Box::new(m20260203_064210_add_webhooks::Migration),
+            Box::new(m20260206_031207_add_notifications::Migration),
+            Box::new(m20260209_052318_add_code_index::Migration),
+            Box::new(m20260212_074105_add_code_symbols::Migration),
+            Box::new(m20260215_032746_add_test_results::Migration),
+            Box::new(m20260217_061455_add_build_cancelled::Migration),
+            Box::new(m20260219_085312_add_build_artifacts::Migration),
+            Box::new(m20260221_074208_add_pending_tasks::Migration),
         ]
     }
 }
@@ -162,14 +200,33 @@ impl MigratorTrait for Migrator {
 /// - Migration SQL execution fails
 /// - Schema validation errors occur
 pub async fn apply_migrations(db: &DatabaseConnection, refresh: bool) -> Result<(), MegaError> {
+    let result = match db.get_database_backend() {
+        // The migrator only wraps Postgres migrations in a transaction. Without
+        // one, consecutive SQLite statements may run on different pooled
+        // connections, and one that hasn't seen the latest schema change fails.
+        DatabaseBackend::Sqlite => match db.begin().await {
+            Ok(txn) => match run_migrations(&txn, refresh).await {
+                Ok(()) => txn.commit().await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        },
+        _ => run_migrations(db, refresh).await,
+    };
+    result.map_err(|e| {
+        log::error!("Failed to apply migrations: {e}");
+        e.into()
+    })
+}
+
+async fn run_migrations<'c, C>(db: C, refresh: bool) -> Result<(), DbErr>
+where
+    C: IntoSchemaManagerConnection<'c>,
+{
     match refresh {
         true => Migrator::refresh(db).await,
         false => Migrator::up(db, None).await,
     }
-    .map_err(|e| {
-        log::error!("Failed to apply migrations: {e}");
-        e.into()
-    })
 }
 
 #[cfg(test)]
@@ -1,5 +1,6 @@
 use callisto::{item_assignees, label, mega_cl, sea_orm_active_enums::MergeStatusEnum};
 use sea_orm::entity::prelude::*;
+use serde::Serialize;
 
 use crate::model::conv_dto::ConvWithReactions;
 
@@ -9,8 +10,11 @@ pub struct CLDetails {
     pub conversations: Vec<ConvWithReactions>,
     pub labels: Vec<label::Model>,
     pub assignees: Vec<item_assignees::Model>,
+    /// The stack the CL belongs to, see `ClStorage::get_cl_stack`.
+    pub stack: Vec<mega_cl::Model>,
 }
 
+#[derive(Serialize)]
 pub struct ClInfoDto {
     pub link: String,
     pub title: String,
@@ -22,6 +26,7 @@ pub struct ClInfoDto {
     pub created_at: DateTime,
     pub updated_at: DateTime,
     pub username: String,
+    pub base_cl: Option<String>,
 }
 
 impl From<mega_cl::Model> for ClInfoDto {
@@ -37,6 +42,7 @@ impl From<mega_cl::Model> for ClInfoDto {
             created_at: value.created_at,
             updated_at: value.updated_at,
             username: value.username,
+            base_cl: value.base_cl,
         }
     }
 }
@@ -1,6 +1,19 @@
+use callisto::sea_orm_active_enums::DiffSideEnum;
 use callisto::{mega_conversation, reactions};
 
 pub struct ConvWithReactions {
     pub conversation: mega_conversation::Model,
     pub reactions: Vec<reactions::Model>,
 }
+
+/// Where an inline comment points: a line range on one side of a file's
+/// diff, at the commit the range refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommentAnchor {
+    pub file_path: String,
+    pub side: DiffSideEnum,
+    /// 1-based, inclusive.
+    pub start_line: i32,
+    pub end_line: i32,
+    pub commit_id: String,
+}
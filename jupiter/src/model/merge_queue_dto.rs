@@ -10,3 +10,19 @@ pub struct QueueStats {
     pub merged_count: usize,
     pub failed_count: usize,
 }
+
+/// One speculative test of a batch, or of a prefix of it while bisecting.
+///
+/// Appended to the `batch_history` of every CL in the batch, so innocent
+/// CLs that were requeued can see which run found the culprit.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchRun {
+    pub batch_id: i64,
+    /// CLs stacked on trunk for this run, in queue order.
+    pub cl_links: Vec<String>,
+    /// Speculative commit that was built, if the stack merged cleanly.
+    pub commit_id: Option<String>,
+    pub passed: bool,
+    pub message: String,
+    pub tested_at: chrono::NaiveDateTime,
+}
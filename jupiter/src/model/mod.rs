@@ -4,3 +4,4 @@ pub mod conv_dto;
 pub mod issue_dto;
 pub mod merge_queue_dto;
 pub mod sidebar_dto;
+pub mod token_dto;
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use callisto::access_token;
+use common::enums::TokenScope;
+
+/// A personal access token that passed verification.
+#[derive(Debug, Clone)]
+pub struct AuthorizedToken {
+    pub id: i64,
+    pub username: String,
+    pub scopes: Vec<TokenScope>,
+    /// The part of the monorepo the token is limited to, if any.
+    pub path_prefix: Option<String>,
+}
+
+impl AuthorizedToken {
+    pub fn allows(&self, scope: TokenScope) -> bool {
+        scope.granted_by(&self.scopes)
+    }
+
+    pub fn covers_path(&self, path: &Path) -> bool {
+        self.path_prefix
+            .as_ref()
+            .is_none_or(|prefix| path.starts_with(prefix))
+    }
+}
+
+impl From<access_token::Model> for AuthorizedToken {
+    fn from(value: access_token::Model) -> Self {
+        Self {
+            id: value.id,
+            scopes: parse_scopes(&value.scopes),
+            username: value.username,
+            path_prefix: value.path_prefix,
+        }
+    }
+}
+
+/// Scopes are stored comma separated. Unknown ones are dropped.
+pub fn parse_scopes(scopes: &str) -> Vec<TokenScope> {
+    scopes
+        .split(',')
+        .filter_map(|scope| scope.parse().ok())
+        .collect()
+}
+
+pub fn join_scopes(scopes: &[TokenScope]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -29,6 +29,7 @@ impl FsObjectStorage {
                 ObjectNamespace::Git => "git",
                 ObjectNamespace::Lfs => "lfs",
                 ObjectNamespace::Log => "log",
+                ObjectNamespace::Artifact => "artifact",
             })
             .join(&key.key)
     }
@@ -93,4 +94,11 @@ impl ObjectStorage for FsObjectStorage {
             },
         ))
     }
+
+    async fn delete(&self, key: &ObjectKey) -> Result<(), MegaError> {
+        match fs::remove_file(self.object_path(key)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
 }
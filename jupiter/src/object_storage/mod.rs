@@ -21,6 +21,7 @@ pub struct ObjectKey {
     /// - git: sha1/sha256
     /// - lfs: sha256
     /// - log: path like 2025/03/worker.log
+    /// - artifact: id of the build artifact
     pub key: String,
 }
 
@@ -29,6 +30,7 @@ pub enum ObjectNamespace {
     Git,
     Lfs,
     Log,
+    Artifact,
 }
 
 impl ObjectNamespace {
@@ -37,6 +39,7 @@ impl ObjectNamespace {
             ObjectNamespace::Git => "git",
             ObjectNamespace::Lfs => "lfs",
             ObjectNamespace::Log => "log",
+            ObjectNamespace::Artifact => "artifact",
         }
     }
 }
@@ -130,6 +133,15 @@ pub trait ObjectStorage: Send + Sync {
     /// - Backend I/O fails
     async fn get(&self, key: &ObjectKey) -> Result<(ObjectByteStream, ObjectMeta), MegaError>;
 
+    /// Remove a single object from the storage backend.
+    ///
+    /// # Semantics
+    /// - Removing an object that does not exist is not an error.
+    ///
+    /// # Errors
+    /// Returns an error if access is denied or backend I/O fails.
+    async fn delete(&self, key: &ObjectKey) -> Result<(), MegaError>;
+
     /// Upload multiple objects concurrently.
     ///
     /// Objects are provided as a stream, allowing the caller to:
@@ -181,6 +181,17 @@ impl ObjectStorage for RustfsObjectStorage {
             }
         }
     }
+
+    async fn delete(&self, key: &ObjectKey) -> Result<(), MegaError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(s3_key(key))
+            .send()
+            .await
+            .map_err(|e| MegaError::Other(dump_error_chain(&e)))?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
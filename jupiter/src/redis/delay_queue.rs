@@ -0,0 +1,52 @@
+use common::errors::MegaError;
+use redis::{AsyncCommands, aio::ConnectionManager};
+
+/// A queue of ids that become due at a point in time, kept in a Redis
+/// sorted set scored by unix timestamp.
+///
+/// Several processes may poll the same queue: an id is handed to whoever
+/// removes it from the set first.
+#[derive(Clone)]
+pub struct DelayQueue {
+    connection: ConnectionManager,
+    key: String,
+}
+
+impl DelayQueue {
+    pub fn new(connection: ConnectionManager, key: impl Into<String>) -> Self {
+        Self {
+            connection,
+            key: key.into(),
+        }
+    }
+
+    /// Schedules `id` at `due_at`, unless it is already waiting in the queue.
+    pub async fn schedule(&self, id: i64, due_at: i64) -> Result<(), MegaError> {
+        let mut conn = self.connection.clone();
+        let _: i64 = redis::cmd("ZADD")
+            .arg(&self.key)
+            .arg("NX")
+            .arg(due_at)
+            .arg(id)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Takes up to `limit` ids that are due at `now` off the queue.
+    pub async fn claim_due(&self, now: i64, limit: isize) -> Result<Vec<i64>, MegaError> {
+        let mut conn = self.connection.clone();
+        let due: Vec<i64> = conn
+            .zrangebyscore_limit(&self.key, "-inf", now, 0, limit)
+            .await?;
+        let mut claimed = Vec::with_capacity(due.len());
+        for id in due {
+            // another poller got it first
+            let removed: i64 = conn.zrem(&self.key, id).await?;
+            if removed == 1 {
+                claimed.push(id);
+            }
+        }
+        Ok(claimed)
+    }
+}
@@ -1,3 +1,4 @@
+pub mod delay_queue;
 pub mod lock;
 
 use common::config::RedisConfig;
@@ -57,12 +57,15 @@ impl CLService {
             .await?
             .unwrap_or((cl.clone(), vec![]));
 
+        let stack = self.cl_storage.get_cl_stack(&cl).await?;
+
         let res = CLDetails {
             cl,
             labels,
             conversations,
             assignees,
             username,
+            stack,
         };
         Ok(res)
     }
@@ -98,6 +98,18 @@ impl GitService {
         Ok(data)
     }
 
+    /// Opens a stored object for reading without loading it into memory.
+    pub async fn get_object_stream(
+        &self,
+        hash: &str,
+    ) -> Result<(ObjectByteStream, ObjectMeta), MegaError> {
+        let key = ObjectKey {
+            namespace: ObjectNamespace::Git,
+            key: hash.to_string(),
+        };
+        self.obj_storage.get(&key).await
+    }
+
     pub fn get_objects_stream(&self, hashes: Vec<String>) -> MultiObjectByteStream<'_> {
         self.obj_storage.get_many(
             hashes
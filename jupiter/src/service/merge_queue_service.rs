@@ -1,4 +1,4 @@
-use crate::model::merge_queue_dto::QueueStats;
+use crate::model::merge_queue_dto::{BatchRun, QueueStats};
 use crate::storage::{
     base_storage::{BaseStorage, StorageConnector},
     cl_storage::ClStorage,
@@ -106,6 +106,50 @@ impl MergeQueueService {
             .map_err(MegaError::Other)
     }
 
+    /// Gets up to `limit` waiting items, head of the queue first.
+    ///
+    /// Called by MonoApiService's background processor to build a
+    /// speculative batch.
+    pub async fn get_next_waiting_items(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<callisto::merge_queue::Model>, MegaError> {
+        self.merge_queue_storage
+            .get_next_waiting_items(limit)
+            .await
+            .map_err(MegaError::Other)
+    }
+
+    /// Moves a waiting item into speculative batch `batch_id`.
+    ///
+    /// Returns false if the item was cancelled before the batch started.
+    pub async fn assign_batch(&self, cl_link: &str, batch_id: i64) -> Result<bool, MegaError> {
+        self.merge_queue_storage
+            .assign_batch(cl_link, batch_id)
+            .await
+            .map_err(MegaError::Other)
+    }
+
+    /// Records a speculative test run in the history of each CL of the batch.
+    pub async fn record_batch_run(
+        &self,
+        cl_links: &[String],
+        run: &BatchRun,
+    ) -> Result<(), MegaError> {
+        self.merge_queue_storage
+            .record_batch_run(cl_links, run)
+            .await
+            .map_err(MegaError::Other)
+    }
+
+    /// Returns an innocent CL of a failed batch to the queue, keeping its place.
+    pub async fn requeue_item(&self, cl_link: &str) -> Result<bool, MegaError> {
+        self.merge_queue_storage
+            .requeue_item(cl_link)
+            .await
+            .map_err(MegaError::Other)
+    }
+
     /// Updates the status of a queue item.
     ///
     /// Returns true if update was successful, false if item was cancelled/not found.
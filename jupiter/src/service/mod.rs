@@ -5,4 +5,5 @@ pub mod import_service;
 pub mod issue_service;
 pub mod merge_queue_service;
 pub mod mono_service;
+pub mod notification_service;
 pub mod reviewer_service;
@@ -0,0 +1,380 @@
+//! Fan-out of in-app notifications.
+//!
+//! Notifications are a side effect of whatever raised them, so the
+//! producers here log failures instead of returning them.
+
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use callisto::sea_orm_active_enums::{
+    ConvTypeEnum, NotificationReasonEnum, SubscriptionTargetEnum,
+};
+use callisto::{mega_cl, mega_issue, notifications};
+use common::errors::MegaError;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect};
+use tokio::sync::broadcast;
+
+use crate::storage::base_storage::{BaseStorage, StorageConnector};
+use crate::storage::notification_storage::NotificationStorage;
+
+/// Longest summary stored with a notification, in characters.
+const SUMMARY_LIMIT: usize = 200;
+
+/// How often new notifications are looked up for the open streams.
+const STREAM_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// New notifications buffered for a stream that falls behind.
+const STREAM_CAPACITY: usize = 1024;
+
+#[derive(Clone)]
+pub struct NotificationService {
+    pub notification_storage: NotificationStorage,
+    stream: Arc<OnceLock<broadcast::Sender<notifications::Model>>>,
+}
+
+impl NotificationService {
+    pub fn new(base_storage: BaseStorage) -> Self {
+        Self {
+            notification_storage: NotificationStorage { base: base_storage },
+            stream: Arc::default(),
+        }
+    }
+
+    /// New notifications of every user, as they are saved.
+    ///
+    /// A single task polls the database for all open streams, so other
+    /// instances' notifications are seen too. It starts with the first
+    /// subscriber.
+    pub fn subscribe(&self) -> broadcast::Receiver<notifications::Model> {
+        self.stream
+            .get_or_init(|| {
+                let (sender, _) = broadcast::channel(STREAM_CAPACITY);
+                tokio::spawn(poll_notifications(
+                    self.notification_storage.clone(),
+                    sender.clone(),
+                ));
+                sender
+            })
+            .subscribe()
+    }
+
+    pub fn mock() -> Self {
+        Self::new(BaseStorage::mock())
+    }
+
+    /// Notifies the users mentioned in a new conversation on `link`, and
+    /// everyone following it.
+    pub async fn on_conversation(
+        &self,
+        link: &str,
+        actor: &str,
+        comment: Option<&str>,
+        conv_type: &ConvTypeEnum,
+    ) {
+        if let Err(e) = self
+            .notify_conversation(link, actor, comment, conv_type)
+            .await
+        {
+            tracing::warn!("Failed to send notifications for {link}: {e}");
+        }
+    }
+
+    /// Notifies new assignees of an issue or CL, who follow it from now on.
+    pub async fn on_assigned(&self, item_id: i64, item_type: &str, assignees: &[String]) {
+        let link = match self.item_link(item_id, item_type).await {
+            Ok(Some(link)) => link,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("Failed to find {item_type} {item_id}: {e}");
+                return;
+            }
+        };
+        let summary = format!("You were assigned to {link}");
+        if let Err(e) = self
+            .notify_and_follow(&link, assignees, NotificationReasonEnum::Assigned, summary)
+            .await
+        {
+            tracing::warn!("Failed to notify assignees of {link}: {e}");
+        }
+    }
+
+    /// Notifies users added as reviewers of a CL, who follow it from now on.
+    pub async fn on_review_requested(&self, cl_link: &str, reviewers: &[String]) {
+        let summary = format!("Your review was requested on {cl_link}");
+        if let Err(e) = self
+            .notify_and_follow(
+                cl_link,
+                reviewers,
+                NotificationReasonEnum::ReviewRequested,
+                summary,
+            )
+            .await
+        {
+            tracing::warn!("Failed to notify reviewers of {cl_link}: {e}");
+        }
+    }
+
+    async fn notify_conversation(
+        &self,
+        link: &str,
+        actor: &str,
+        comment: Option<&str>,
+        conv_type: &ConvTypeEnum,
+    ) -> Result<(), MegaError> {
+        let mut notified = HashSet::from([actor.to_owned()]);
+        let mut models = vec![];
+
+        // the comment is written by a user, other conversations describe
+        // what happened to the item
+        let summary = match (conv_type, comment) {
+            (ConvTypeEnum::Comment, _) | (_, None) => format!("{actor} commented on {link}"),
+            (_, Some(comment)) => truncate(comment),
+        };
+
+        // comments, reviews and approvals carry text written by the actor
+        if matches!(
+            conv_type,
+            ConvTypeEnum::Comment | ConvTypeEnum::Review | ConvTypeEnum::Approve
+        ) && let Some(comment) = comment
+        {
+            for user in parse_mentions(comment) {
+                if notified.insert(user.clone()) {
+                    models.push(notifications::Model::new(
+                        &user,
+                        NotificationReasonEnum::Mention,
+                        link,
+                        Some(actor),
+                        format!("{actor} mentioned you on {link}"),
+                    ));
+                }
+            }
+        }
+
+        let path = self.cl_path(link).await?;
+        for user in self
+            .notification_storage
+            .list_followers(link, path.as_deref())
+            .await?
+        {
+            if notified.insert(user.clone()) {
+                models.push(notifications::Model::new(
+                    &user,
+                    NotificationReasonEnum::Subscribed,
+                    link,
+                    Some(actor),
+                    summary.clone(),
+                ));
+            }
+        }
+        self.notification_storage.save_notifications(models).await
+    }
+
+    async fn notify_and_follow(
+        &self,
+        link: &str,
+        users: &[String],
+        reason: NotificationReasonEnum,
+        summary: String,
+    ) -> Result<(), MegaError> {
+        let mut models = vec![];
+        for user in users {
+            self.notification_storage
+                .subscribe(user, SubscriptionTargetEnum::Item, link)
+                .await?;
+            models.push(notifications::Model::new(
+                user,
+                reason.clone(),
+                link,
+                None,
+                summary.clone(),
+            ));
+        }
+        self.notification_storage.save_notifications(models).await
+    }
+
+    /// The path of a CL, so path followers hear about it. Issues have none.
+    async fn cl_path(&self, link: &str) -> Result<Option<String>, MegaError> {
+        Ok(mega_cl::Entity::find()
+            .select_only()
+            .column(mega_cl::Column::Path)
+            .filter(mega_cl::Column::Link.eq(link))
+            .into_tuple()
+            .one(self.notification_storage.get_connection())
+            .await?)
+    }
+
+    async fn item_link(&self, item_id: i64, item_type: &str) -> Result<Option<String>, MegaError> {
+        let conn = self.notification_storage.get_connection();
+        Ok(match item_type {
+            "issue" => {
+                mega_issue::Entity::find_by_id(item_id)
+                    .select_only()
+                    .column(mega_issue::Column::Link)
+                    .into_tuple()
+                    .one(conn)
+                    .await?
+            }
+            _ => {
+                mega_cl::Entity::find_by_id(item_id)
+                    .select_only()
+                    .column(mega_cl::Column::Link)
+                    .into_tuple()
+                    .one(conn)
+                    .await?
+            }
+        })
+    }
+}
+
+/// Usernames mentioned as `@name` in a comment. An `@` inside a word, as in
+/// an email address, is not a mention.
+pub fn parse_mentions(comment: &str) -> HashSet<String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let mut mentions = HashSet::new();
+    let mut prev: Option<char> = None;
+    for (i, c) in comment.char_indices() {
+        if c == '@' && !prev.is_some_and(|p| p.is_alphanumeric() || p == '.' || p == '_') {
+            let name: String = comment[i + 1..]
+                .chars()
+                .take_while(|&c| is_name_char(c))
+                .collect();
+            if !name.is_empty() {
+                mentions.insert(name);
+            }
+        }
+        prev = Some(c);
+    }
+    mentions
+}
+
+fn truncate(summary: &str) -> String {
+    match summary.char_indices().nth(SUMMARY_LIMIT) {
+        Some((end, _)) => format!("{}...", &summary[..end]),
+        None => summary.to_owned(),
+    }
+}
+
+/// Sends the notifications saved since the last poll to every stream.
+async fn poll_notifications(
+    storage: NotificationStorage,
+    sender: broadcast::Sender<notifications::Model>,
+) {
+    let mut since = chrono::Utc::now().naive_utc();
+    let mut interval = tokio::time::interval(STREAM_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        if sender.receiver_count() == 0 {
+            since = chrono::Utc::now().naive_utc();
+            continue;
+        }
+        match storage.notifications_since(since).await {
+            Ok(models) => {
+                for model in models {
+                    since = model.created_at;
+                    // no receiver left is fine, the next stream starts afresh
+                    let _ = sender.send(model);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to poll notifications: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use callisto::sea_orm_active_enums::{
+        ConvTypeEnum, NotificationReasonEnum, NotificationStateEnum, SubscriptionTargetEnum,
+    };
+    use common::model::Pagination;
+
+    use super::parse_mentions;
+    use crate::tests::test_storage;
+
+    #[test]
+    fn test_parse_mentions() {
+        let mentions = parse_mentions("<p>@alice and @bob-2, see mail@example.com</p>@carol");
+        assert_eq!(mentions.len(), 3);
+        assert!(mentions.contains("alice"));
+        assert!(mentions.contains("bob-2"));
+        assert!(mentions.contains("carol"));
+        assert!(parse_mentions("no one @ all").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_notification_fan_out() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = test_storage(temp_dir.path()).await;
+        let service = storage.notification_service.clone();
+        let notification_stg = storage.notification_storage();
+
+        notification_stg
+            .subscribe("dave", SubscriptionTargetEnum::Item, "ISSUE1")
+            .await
+            .unwrap();
+        service
+            .on_review_requested("ISSUE1", &["bob".to_owned()])
+            .await;
+        service
+            .on_conversation(
+                "ISSUE1",
+                "alice",
+                Some("<p>@bob @carol please take a look</p>"),
+                &ConvTypeEnum::Comment,
+            )
+            .await;
+
+        let page = Pagination::default();
+        let (bob, _) = notification_stg
+            .list_notifications("bob", None, page.clone())
+            .await
+            .unwrap();
+        assert_eq!(bob.len(), 2);
+        assert_eq!(bob[0].reason, NotificationReasonEnum::Mention);
+        assert_eq!(bob[1].reason, NotificationReasonEnum::ReviewRequested);
+
+        let (dave, _) = notification_stg
+            .list_notifications("dave", None, page.clone())
+            .await
+            .unwrap();
+        assert_eq!(dave.len(), 1);
+        assert_eq!(dave[0].reason, NotificationReasonEnum::Subscribed);
+        assert_eq!(dave[0].summary, "alice commented on ISSUE1");
+
+        // reviews are written by the reviewer too
+        service
+            .on_conversation(
+                "ISSUE1",
+                "bob",
+                Some("bob requested changes: @erin owns this file"),
+                &ConvTypeEnum::Review,
+            )
+            .await;
+        let (erin, _) = notification_stg
+            .list_notifications("erin", None, page.clone())
+            .await
+            .unwrap();
+        assert_eq!(erin.len(), 1);
+        assert_eq!(erin[0].reason, NotificationReasonEnum::Mention);
+
+        // the author isn't told about their own comment
+        assert_eq!(notification_stg.unread_count("alice").await.unwrap(), 0);
+
+        assert_eq!(notification_stg.unread_count("carol").await.unwrap(), 1);
+        notification_stg
+            .set_state("carol", &[], NotificationStateEnum::Read)
+            .await
+            .unwrap();
+        assert_eq!(notification_stg.unread_count("carol").await.unwrap(), 0);
+        notification_stg
+            .set_state("bob", &[bob[0].id], NotificationStateEnum::Done)
+            .await
+            .unwrap();
+        let (bob, total) = notification_stg
+            .list_notifications("bob", None, page)
+            .await
+            .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(bob[0].reason, NotificationReasonEnum::ReviewRequested);
+    }
+}
@@ -1,10 +1,16 @@
+use crate::service::notification_service::NotificationService;
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
+use crate::storage::cl_storage::latest_patchset;
+use crate::storage::webhook_storage::emit_event;
 use callisto::entity_ext::generate_id;
-use callisto::mega_cl_reviewer;
+use callisto::sea_orm_active_enums::ReviewStateEnum;
+use callisto::{mega_cl, mega_cl_reviewer};
+use common::enums::WebhookEvent;
 use common::errors::MegaError;
-use sea_orm::QueryFilter;
+use sea_orm::sea_query::Expr;
 use sea_orm::{ActiveModelTrait, EntityTrait, IntoActiveModel};
 use sea_orm::{ColumnTrait, Set};
+use sea_orm::{QueryFilter, QuerySelect};
 use std::ops::Deref;
 
 #[derive(Clone)]
@@ -30,6 +36,8 @@ impl ClReviewerStorage {
             created_at: now,
             updated_at: now,
             system_required: false,
+            approved_patchset: None,
+            review_state: ReviewStateEnum::Pending,
         }
     }
 
@@ -38,14 +46,17 @@ impl ClReviewerStorage {
         cl_link: &str,
         reviewers: Vec<String>,
     ) -> Result<(), MegaError> {
-        for reviewer in reviewers {
-            let new_reviewer = self.new_reviewer(cl_link, &reviewer);
+        for reviewer in &reviewers {
+            let new_reviewer = self.new_reviewer(cl_link, reviewer);
             let a_model: mega_cl_reviewer::ActiveModel = new_reviewer.into_active_model();
             a_model.insert(self.get_connection()).await.map_err(|e| {
                 tracing::error!("{}", e);
                 MegaError::Other(format!("reviewer {}", reviewer.clone()))
             })?;
         }
+        NotificationService::new(self.base.clone())
+            .on_review_requested(cl_link, &reviewers)
+            .await;
         Ok(())
     }
 
@@ -159,6 +170,24 @@ impl ClReviewerStorage {
         reviewer_username: &str,
         approved: bool,
     ) -> Result<(), MegaError> {
+        let state = if approved {
+            ReviewStateEnum::Approved
+        } else {
+            ReviewStateEnum::Pending
+        };
+        self.set_review_state(cl_link, reviewer_username, state)
+            .await
+    }
+
+    /// Records the reviewer's verdict. `approved` is kept in sync with the
+    /// state for callers that only know about approvals.
+    pub async fn set_review_state(
+        &self,
+        cl_link: &str,
+        reviewer_username: &str,
+        state: ReviewStateEnum,
+    ) -> Result<(), MegaError> {
+        let approved = state == ReviewStateEnum::Approved;
         let mut rev: mega_cl_reviewer::ActiveModel = mega_cl_reviewer::Entity::find()
             .filter(mega_cl_reviewer::Column::ClLink.eq(cl_link))
             .filter(mega_cl_reviewer::Column::Username.eq(reviewer_username))
@@ -171,13 +200,107 @@ impl ClReviewerStorage {
             .ok_or_else(|| MegaError::Other(format!("reviewer {} not found", reviewer_username)))?
             .into_active_model();
 
+        // approvals are given to the revision the reviewer looked at
+        let patchset = if approved {
+            latest_patchset(self.get_connection(), cl_link)
+                .await?
+                .map(|p| p.number)
+        } else {
+            None
+        };
         rev.approved = Set(approved);
+        rev.approved_patchset = Set(patchset);
+        rev.review_state = Set(state);
         rev.updated_at = Set(chrono::Utc::now().naive_utc());
-        rev.update(self.get_connection()).await.map_err(|e| {
+        let rev = rev.update(self.get_connection()).await.map_err(|e| {
             tracing::error!("{}", e);
             MegaError::Other(format!("fail to update reviewer {}", reviewer_username))
         })?;
 
+        // a reset to pending is a dismissal, not a review
+        if rev.review_state != ReviewStateEnum::Pending
+            && let Ok(Some(path)) = mega_cl::Entity::find()
+                .select_only()
+                .column(mega_cl::Column::Path)
+                .filter(mega_cl::Column::Link.eq(cl_link))
+                .into_tuple::<String>()
+                .one(self.get_connection())
+                .await
+        {
+            let data = serde_json::to_value(&rev).unwrap_or_default();
+            emit_event(self.get_connection(), WebhookEvent::ClReviewed, &path, data).await;
+        }
         Ok(())
     }
+
+    /// Moves an approval to `patchset` without asking the reviewer again.
+    pub async fn carry_approval(
+        &self,
+        cl_link: &str,
+        reviewer_username: &str,
+        patchset: i32,
+    ) -> Result<(), MegaError> {
+        mega_cl_reviewer::Entity::update_many()
+            .col_expr(
+                mega_cl_reviewer::Column::ApprovedPatchset,
+                Expr::value(patchset),
+            )
+            .filter(mega_cl_reviewer::Column::ClLink.eq(cl_link))
+            .filter(mega_cl_reviewer::Column::Username.eq(reviewer_username))
+            .exec(self.get_connection())
+            .await
+            .map_err(|e| {
+                tracing::error!("{}", e);
+                MegaError::Other(format!("fail to update reviewer {}", reviewer_username))
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use callisto::sea_orm_active_enums::ReviewStateEnum;
+
+    use crate::tests::test_storage;
+
+    #[tokio::test]
+    async fn test_review_state() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = test_storage(temp_dir.path()).await;
+        let reviewer_stg = storage.reviewer_storage();
+        reviewer_stg
+            .add_reviewers("CL1", vec!["alice".to_string(), "bob".to_string()])
+            .await
+            .unwrap();
+
+        reviewer_stg
+            .set_review_state("CL1", "alice", ReviewStateEnum::ChangesRequested)
+            .await
+            .unwrap();
+        reviewer_stg
+            .reviewer_change_state("CL1", "bob", true)
+            .await
+            .unwrap();
+        reviewer_stg.carry_approval("CL1", "bob", 3).await.unwrap();
+
+        let mut reviewers = reviewer_stg.list_reviewers("CL1").await.unwrap();
+        reviewers.sort_by(|a, b| a.username.cmp(&b.username));
+        assert_eq!(reviewers[0].review_state, ReviewStateEnum::ChangesRequested);
+        assert!(!reviewers[0].approved);
+        assert_eq!(reviewers[1].review_state, ReviewStateEnum::Approved);
+        assert!(reviewers[1].approved);
+        assert_eq!(reviewers[1].approved_patchset, Some(3));
+
+        // clearing the request is the same as withdrawing an approval
+        reviewer_stg
+            .reviewer_change_state("CL1", "alice", false)
+            .await
+            .unwrap();
+        let reviewers = reviewer_stg.list_reviewers("CL1").await.unwrap();
+        assert!(
+            reviewers
+                .iter()
+                .any(|r| r.username == "alice" && r.review_state == ReviewStateEnum::Pending)
+        );
+    }
 }
@@ -1,18 +1,20 @@
 use std::collections::HashMap;
 use std::ops::Deref;
 
-use callisto::sea_orm_active_enums::MergeStatusEnum;
+use callisto::sea_orm_active_enums::{CheckStatusEnum, CheckTypeEnum, MergeStatusEnum};
 use callisto::{
-    check_result, item_assignees, label, mega_cl, mega_conversation, path_check_configs,
+    branch_protection_rules, builds, check_result, item_assignees, label, mega_cl,
+    mega_cl_patchset, mega_conversation, path_check_configs, tasks,
 };
+use common::enums::WebhookEvent;
 use common::errors::MegaError;
 use common::model::Pagination;
 use git_internal::internal::object::commit::Commit;
 use sea_orm::prelude::Expr;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, JoinType,
-    PaginatorTrait, QueryFilter, QuerySelect, QueryTrait, Set,
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait, IntoActiveModel,
+    JoinType, PaginatorTrait, QueryFilter, QuerySelect, QueryTrait, Set, TransactionTrait,
 };
 use sea_orm::{QueryOrder, RelationTrait};
 
@@ -20,6 +22,7 @@ use crate::model::common::{ItemDetails, ListParams};
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
 use crate::storage::stg_common::combine_item_list;
 use crate::storage::stg_common::query_build::{apply_sort, filter_by_assignees, filter_by_labels};
+use crate::storage::webhook_storage::emit_event;
 
 #[derive(Clone)]
 pub struct ClStorage {
@@ -229,10 +232,11 @@ impl ClStorage {
             to_hash.to_owned(),
             username.to_owned(),
         );
-        let res = model
-            .into_active_model()
-            .insert(self.get_connection())
-            .await?;
+        let txn = self.get_connection().begin().await?;
+        let res = model.into_active_model().insert(&txn).await?;
+        add_patchset(&txn, link, from_hash, to_hash, username).await?;
+        txn.commit().await?;
+        emit_cl_event(self.get_connection(), WebhookEvent::ClOpened, &res).await;
         Ok(res.link)
     }
 
@@ -276,7 +280,8 @@ impl ClStorage {
         let mut a_model = model.into_active_model();
         a_model.status = Set(MergeStatusEnum::Closed);
         a_model.updated_at = Set(chrono::Utc::now().naive_utc());
-        a_model.update(self.get_connection()).await.unwrap();
+        let model = a_model.update(self.get_connection()).await?;
+        emit_cl_event(self.get_connection(), WebhookEvent::ClClosed, &model).await;
         Ok(())
     }
 
@@ -284,7 +289,8 @@ impl ClStorage {
         let mut a_model = model.into_active_model();
         a_model.status = Set(MergeStatusEnum::Open);
         a_model.updated_at = Set(chrono::Utc::now().naive_utc());
-        a_model.update(self.get_connection()).await.unwrap();
+        let model = a_model.update(self.get_connection()).await?;
+        emit_cl_event(self.get_connection(), WebhookEvent::ClReopened, &model).await;
         Ok(())
     }
 
@@ -304,36 +310,146 @@ impl ClStorage {
         let mut a_model = model.into_active_model();
         a_model.status = Set(MergeStatusEnum::Merged);
         a_model.updated_at = Set(chrono::Utc::now().naive_utc());
-        a_model.update(self.get_connection()).await.unwrap();
+        let model = a_model.update(self.get_connection()).await?;
+        emit_cl_event(self.get_connection(), WebhookEvent::ClMerged, &model).await;
+        Ok(())
+    }
+
+    /// Stacks the CL on `base_cl`, or unstacks it with `None`.
+    pub async fn set_base_cl(&self, link: &str, base_cl: Option<&str>) -> Result<(), MegaError> {
+        mega_cl::Entity::update_many()
+            .col_expr(mega_cl::Column::BaseCl, Expr::value(base_cl))
+            .col_expr(
+                mega_cl::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_cl::Column::Link.eq(link))
+            .exec(self.get_connection())
+            .await?;
         Ok(())
     }
 
+    /// CLs stacked directly on `link`, except closed ones.
+    pub async fn get_stacked_cls(&self, link: &str) -> Result<Vec<mega_cl::Model>, MegaError> {
+        Ok(mega_cl::Entity::find()
+            .filter(mega_cl::Column::BaseCl.eq(link))
+            .filter(mega_cl::Column::Status.ne(MergeStatusEnum::Closed))
+            .order_by_asc(mega_cl::Column::Id)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The CLs `cl` is stacked on, nearest first.
+    pub async fn get_cl_ancestors(
+        &self,
+        cl: &mega_cl::Model,
+    ) -> Result<Vec<mega_cl::Model>, MegaError> {
+        let mut ancestors: Vec<mega_cl::Model> = vec![];
+        let mut next = cl.base_cl.clone();
+        while let Some(link) = next {
+            // a cycle can only come from rows edited by hand, but don't spin on it
+            if link == cl.link || ancestors.iter().any(|a| a.link == link) {
+                break;
+            }
+            let Some(base) = self.get_cl(&link).await? else {
+                break;
+            };
+            next = base.base_cl.clone();
+            ancestors.push(base);
+        }
+        Ok(ancestors)
+    }
+
+    /// Every CL in the stack `cl` belongs to: its ancestors from the bottom
+    /// of the stack up, `cl` itself, then the CLs stacked on it.
+    pub async fn get_cl_stack(
+        &self,
+        cl: &mega_cl::Model,
+    ) -> Result<Vec<mega_cl::Model>, MegaError> {
+        let mut stack = self.get_cl_ancestors(cl).await?;
+        stack.reverse();
+        stack.push(cl.clone());
+
+        let mut pending = vec![cl.link.clone()];
+        while let Some(link) = pending.pop() {
+            for child in self.get_stacked_cls(&link).await? {
+                if stack.iter().any(|c| c.link == child.link) {
+                    continue;
+                }
+                pending.push(child.link.clone());
+                stack.push(child);
+            }
+        }
+        Ok(stack)
+    }
+
     pub async fn update_cl_to_hash(
         &self,
         model: mega_cl::Model,
         to_hash: &str,
+        pusher: &str,
     ) -> Result<(), MegaError> {
-        let mut a_model = model.into_active_model();
-        a_model.to_hash = Set(to_hash.to_owned());
-        a_model.updated_at = Set(chrono::Utc::now().naive_utc());
-        a_model.update(self.get_connection()).await.unwrap();
-        Ok(())
+        let from_hash = model.from_hash.clone();
+        self.update_cl_hash(model, &from_hash, to_hash, pusher)
+            .await
     }
 
+    /// Points the CL at a new revision, which is kept as its next patchset.
+    ///
+    /// Both are written in one transaction. Updating the CL row first makes
+    /// concurrent updates of the same CL wait, so they number their
+    /// patchsets one after the other.
     pub async fn update_cl_hash(
         &self,
         model: mega_cl::Model,
         from_hash: &str,
         to_hash: &str,
+        pusher: &str,
     ) -> Result<(), MegaError> {
+        let link = model.link.clone();
         let mut a_model = model.into_active_model();
         a_model.from_hash = Set(from_hash.to_owned());
         a_model.to_hash = Set(to_hash.to_owned());
         a_model.updated_at = Set(chrono::Utc::now().naive_utc());
-        a_model.update(self.get_connection()).await.unwrap();
+        let txn = self.get_connection().begin().await?;
+        let model = a_model.update(&txn).await?;
+        add_patchset(&txn, &link, from_hash, to_hash, pusher).await?;
+        txn.commit().await?;
+        emit_cl_event(self.get_connection(), WebhookEvent::ClUpdated, &model).await;
         Ok(())
     }
 
+    /// Every revision the CL has pointed at, oldest first.
+    pub async fn list_patchsets(
+        &self,
+        cl_link: &str,
+    ) -> Result<Vec<mega_cl_patchset::Model>, MegaError> {
+        Ok(mega_cl_patchset::Entity::find()
+            .filter(mega_cl_patchset::Column::ClLink.eq(cl_link))
+            .order_by_asc(mega_cl_patchset::Column::Number)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_patchset(
+        &self,
+        cl_link: &str,
+        number: i32,
+    ) -> Result<Option<mega_cl_patchset::Model>, MegaError> {
+        Ok(mega_cl_patchset::Entity::find()
+            .filter(mega_cl_patchset::Column::ClLink.eq(cl_link))
+            .filter(mega_cl_patchset::Column::Number.eq(number))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_latest_patchset(
+        &self,
+        cl_link: &str,
+    ) -> Result<Option<mega_cl_patchset::Model>, MegaError> {
+        latest_patchset(self.get_connection(), cl_link).await
+    }
+
     pub async fn update_cl_title(
         &self,
         model: mega_cl::Model,
@@ -358,6 +474,87 @@ impl ClStorage {
         Ok(models)
     }
 
+    /// Returns the branch protection rule with the longest path covering `path`.
+    pub async fn get_branch_protection_rule(
+        &self,
+        path: &str,
+    ) -> Result<Option<branch_protection_rules::Model>, MegaError> {
+        let rules = branch_protection_rules::Entity::find()
+            .all(self.get_connection())
+            .await?;
+        let path = std::path::Path::new(path);
+        Ok(rules
+            .into_iter()
+            .filter(|r| path.starts_with(&r.path))
+            .max_by_key(|r| r.path.len()))
+    }
+
+    /// Returns the branch protection rules of paths strictly below `path`.
+    pub async fn get_branch_protection_rules_below(
+        &self,
+        path: &str,
+    ) -> Result<Vec<branch_protection_rules::Model>, MegaError> {
+        let rules = self.list_branch_protection_rules().await?;
+        let path = std::path::Path::new(path);
+        Ok(rules
+            .into_iter()
+            .filter(|r| {
+                let rule_path = std::path::Path::new(&r.path);
+                rule_path != path && rule_path.starts_with(path)
+            })
+            .collect())
+    }
+
+    pub async fn list_branch_protection_rules(
+        &self,
+    ) -> Result<Vec<branch_protection_rules::Model>, MegaError> {
+        Ok(branch_protection_rules::Entity::find()
+            .order_by_asc(branch_protection_rules::Column::Path)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Deletes the rule of exactly `path`, returning whether there was one.
+    pub async fn delete_branch_protection_rule(&self, path: &str) -> Result<bool, MegaError> {
+        let res = branch_protection_rules::Entity::delete_many()
+            .filter(branch_protection_rules::Column::Path.eq(path))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    pub async fn save_branch_protection_rule(
+        &self,
+        model: branch_protection_rules::Model,
+    ) -> Result<(), MegaError> {
+        let existing = branch_protection_rules::Entity::find()
+            .filter(branch_protection_rules::Column::Path.eq(model.path.clone()))
+            .one(self.get_connection())
+            .await?;
+        match existing {
+            Some(rule) => {
+                let mut active = rule.into_active_model();
+                active.direct_push_users = Set(model.direct_push_users);
+                active.require_cl = Set(model.require_cl);
+                active.min_approvals = Set(model.min_approvals);
+                active.allow_force_push = Set(model.allow_force_push);
+                active.allow_deletion = Set(model.allow_deletion);
+                active.require_signed_push = Set(model.require_signed_push);
+                active.dismiss_stale_approvals = Set(model.dismiss_stale_approvals);
+                active.sticky_approvals = Set(model.sticky_approvals);
+                active.updated_at = Set(chrono::Utc::now().naive_utc());
+                active.update(self.get_connection()).await?;
+            }
+            None => {
+                model
+                    .into_active_model()
+                    .insert(self.get_connection())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn save_check_results(
         &self,
         models: Vec<check_result::Model>,
@@ -369,11 +566,14 @@ impl ClStorage {
                 OnConflict::columns(vec![
                     check_result::Column::ClLink,
                     check_result::Column::CheckTypeCode,
+                    check_result::Column::CheckName,
                 ])
                 .update_columns([
                     check_result::Column::CommitId,
                     check_result::Column::Status,
                     check_result::Column::Message,
+                    check_result::Column::Required,
+                    check_result::Column::UpdatedAt,
                 ])
                 .to_owned(),
             )
@@ -383,6 +583,30 @@ impl ClStorage {
         Ok(())
     }
 
+    /// Resolves a pending external check of the revision in `model`, leaving
+    /// results of other revisions and reported results alone. Returns whether
+    /// the check was still pending.
+    pub async fn resolve_pending_check(
+        &self,
+        model: check_result::Model,
+    ) -> Result<bool, MegaError> {
+        let res = check_result::Entity::update_many()
+            .col_expr(check_result::Column::Status, Expr::value(model.status))
+            .col_expr(check_result::Column::Message, Expr::value(model.message))
+            .col_expr(
+                check_result::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(check_result::Column::ClLink.eq(model.cl_link))
+            .filter(check_result::Column::CheckTypeCode.eq(model.check_type_code))
+            .filter(check_result::Column::CheckName.eq(model.check_name))
+            .filter(check_result::Column::CommitId.eq(model.commit_id))
+            .filter(check_result::Column::Status.eq(CheckStatusEnum::Pending))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+
     pub async fn get_check_result(
         &self,
         cl_link: &str,
@@ -394,6 +618,44 @@ impl ClStorage {
         Ok(models)
     }
 
+    pub async fn get_external_check_result(
+        &self,
+        cl_link: &str,
+        check_name: &str,
+    ) -> Result<Option<check_result::Model>, MegaError> {
+        let model = check_result::Entity::find()
+            .filter(check_result::Column::ClLink.eq(cl_link))
+            .filter(check_result::Column::CheckTypeCode.eq(CheckTypeEnum::External))
+            .filter(check_result::Column::CheckName.eq(check_name))
+            .one(self.get_connection())
+            .await?;
+        Ok(model)
+    }
+
+    /// Returns the latest Orion task triggered for a CL at the given commit,
+    /// together with its builds.
+    pub async fn get_latest_cl_task(
+        &self,
+        cl_id: i64,
+        commit_id: &str,
+    ) -> Result<Option<(tasks::Model, Vec<builds::Model>)>, MegaError> {
+        let task = tasks::Entity::find()
+            .filter(tasks::Column::ClId.eq(cl_id))
+            .filter(tasks::Column::CommitId.eq(commit_id))
+            .order_by_desc(tasks::Column::CreatedAt)
+            .one(self.get_connection())
+            .await?;
+        let Some(task) = task else {
+            return Ok(None);
+        };
+        let builds = builds::Entity::find()
+            .filter(builds::Column::TaskId.eq(task.id))
+            .order_by_asc(builds::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?;
+        Ok(Some((task, builds)))
+    }
+
     pub async fn save_cl_commits(&self, link: &str, commits: Vec<Commit>) -> Result<(), MegaError> {
         let mut save_models = vec![];
         for commit in commits {
@@ -412,3 +674,220 @@ impl ClStorage {
         Ok(())
     }
 }
+
+pub(crate) async fn latest_patchset<C: ConnectionTrait>(
+    conn: &C,
+    cl_link: &str,
+) -> Result<Option<mega_cl_patchset::Model>, MegaError> {
+    Ok(mega_cl_patchset::Entity::find()
+        .filter(mega_cl_patchset::Column::ClLink.eq(cl_link))
+        .order_by_desc(mega_cl_patchset::Column::Number)
+        .one(conn)
+        .await?)
+}
+
+/// Records `from_hash..to_hash` as the next patchset of `cl_link`.
+/// Patchsets are never updated once written.
+///
+/// Run it in the transaction that updates the CL, the next number is read
+/// from the latest patchset.
+pub(crate) async fn add_patchset<C: ConnectionTrait>(
+    conn: &C,
+    cl_link: &str,
+    from_hash: &str,
+    to_hash: &str,
+    pusher: &str,
+) -> Result<mega_cl_patchset::Model, MegaError> {
+    let number = latest_patchset(conn, cl_link)
+        .await?
+        .map_or(1, |latest| latest.number + 1);
+    let patchset = mega_cl_patchset::Model::new(cl_link, number, from_hash, to_hash, pusher);
+    Ok(patchset.into_active_model().insert(conn).await?)
+}
+
+pub(crate) async fn emit_cl_event<C: ConnectionTrait>(
+    conn: &C,
+    event: WebhookEvent,
+    cl: &mega_cl::Model,
+) {
+    let data = serde_json::to_value(cl).unwrap_or_default();
+    emit_event(conn, event, &cl.path, data).await
+}
+
+#[cfg(test)]
+mod test {
+    use callisto::sea_orm_active_enums::{CheckStatusEnum, CheckTypeEnum};
+    use callisto::{branch_protection_rules, check_result};
+    use tempfile::tempdir;
+
+    use super::add_patchset;
+    use crate::storage::base_storage::StorageConnector;
+    use crate::tests::test_storage;
+
+    #[tokio::test]
+    async fn test_branch_protection_rule_longest_prefix() {
+        let temp = tempdir().unwrap();
+        let storage = test_storage(&temp).await.cl_storage();
+        for path in ["/", "/project", "/project/mega"] {
+            let rule = branch_protection_rules::Model::new(path, vec![], true, 1, false, false);
+            storage.save_branch_protection_rule(rule).await.unwrap();
+        }
+
+        let rule = storage
+            .get_branch_protection_rule("/project/mega/src")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rule.path, "/project/mega");
+
+        let rule = storage
+            .get_branch_protection_rule("/project/megaphone")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rule.path, "/project");
+
+        let below: Vec<String> = storage
+            .get_branch_protection_rules_below("/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.path)
+            .collect();
+        assert_eq!(below, ["/project", "/project/mega"]);
+
+        assert!(
+            storage
+                .delete_branch_protection_rule("/project")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !storage
+                .delete_branch_protection_rule("/project")
+                .await
+                .unwrap()
+        );
+        let below = storage
+            .get_branch_protection_rules_below("/")
+            .await
+            .unwrap();
+        assert_eq!(below.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_save_check_results_keeps_one_row_per_check() {
+        let temp = tempdir().unwrap();
+        let storage = test_storage(&temp).await.cl_storage();
+        let builtin = |status| {
+            check_result::Model::new("/p", "CL1", "c1", CheckTypeEnum::CiStatus, status, "")
+        };
+        let external = |name, status| {
+            check_result::Model::new_external("/p", "CL1", "c1", name, false, status, "")
+        };
+        storage
+            .save_check_results(vec![
+                builtin(CheckStatusEnum::Pending),
+                external("scan", CheckStatusEnum::Pending),
+                external("lint", CheckStatusEnum::Pending),
+            ])
+            .await
+            .unwrap();
+        storage
+            .save_check_results(vec![
+                builtin(CheckStatusEnum::Passed),
+                external("scan", CheckStatusEnum::Failed),
+            ])
+            .await
+            .unwrap();
+
+        let results = storage.get_check_result("CL1").await.unwrap();
+        assert_eq!(results.len(), 3);
+        let scan = storage
+            .get_external_check_result("CL1", "scan")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(scan.status, CheckStatusEnum::Failed);
+        assert!(!scan.required);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_pending_check_of_current_revision_only() {
+        let temp = tempdir().unwrap();
+        let storage = test_storage(&temp).await.cl_storage();
+        let scan = |commit, status| {
+            check_result::Model::new_external("/p", "CL1", commit, "scan", true, status, "")
+        };
+        storage
+            .save_check_results(vec![scan("c2", CheckStatusEnum::Pending)])
+            .await
+            .unwrap();
+
+        // A late failure of the dispatch for c1 must not touch c2's result
+        let resolved = storage
+            .resolve_pending_check(scan("c1", CheckStatusEnum::Error))
+            .await
+            .unwrap();
+        assert!(!resolved);
+        let resolved = storage
+            .resolve_pending_check(scan("c2", CheckStatusEnum::Error))
+            .await
+            .unwrap();
+        assert!(resolved);
+
+        let result = storage
+            .get_external_check_result("CL1", "scan")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (result.commit_id.as_str(), result.status),
+            ("c2", CheckStatusEnum::Error)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cl_patchsets() {
+        let temp = tempdir().unwrap();
+        let storage = test_storage(&temp).await.cl_storage();
+        for (from_hash, to_hash, pusher) in [
+            ("base", "rev1", "alice"),
+            ("base", "rev2", "bob"),
+            ("base2", "rev3", "alice"),
+        ] {
+            add_patchset(storage.get_connection(), "CL1", from_hash, to_hash, pusher)
+                .await
+                .unwrap();
+        }
+        add_patchset(storage.get_connection(), "CL2", "base", "other", "bob")
+            .await
+            .unwrap();
+
+        let patchsets = storage.list_patchsets("CL1").await.unwrap();
+        let revisions: Vec<_> = patchsets
+            .iter()
+            .map(|p| {
+                (
+                    p.number,
+                    p.from_hash.as_str(),
+                    p.to_hash.as_str(),
+                    p.pusher.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            revisions,
+            vec![
+                (1, "base", "rev1", "alice"),
+                (2, "base", "rev2", "bob"),
+                (3, "base2", "rev3", "alice"),
+            ]
+        );
+        let latest = storage.get_latest_patchset("CL1").await.unwrap().unwrap();
+        assert_eq!(latest.number, 3);
+        let first = storage.get_patchset("CL2", 1).await.unwrap().unwrap();
+        assert_eq!(first.to_hash, "other");
+        assert!(storage.get_patchset("CL1", 4).await.unwrap().is_none());
+    }
+}
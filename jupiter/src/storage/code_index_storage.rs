@@ -0,0 +1,227 @@
+use std::ops::Deref;
+
+use callisto::entity_ext::generate_id;
+use callisto::{code_index_file, code_index_state, code_index_trigram};
+use common::errors::MegaError;
+use sea_orm::sea_query::Query;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait, prelude::Expr,
+};
+
+use crate::storage::base_storage::{BaseStorage, StorageConnector};
+
+/// Rows per insert when saving the trigrams of a file.
+const TRIGRAM_BATCH_SIZE: usize = 1000;
+
+/// Trigram index of the blobs on trunk, used by code search.
+#[derive(Clone)]
+pub struct CodeIndexStorage {
+    pub base: BaseStorage,
+}
+
+impl Deref for CodeIndexStorage {
+    type Target = BaseStorage;
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl CodeIndexStorage {
+    /// The trunk revision the index was last brought up to.
+    pub async fn indexed_state(&self) -> Result<Option<code_index_state::Model>, MegaError> {
+        Ok(code_index_state::Entity::find()
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_state(&self, tree_id: &str, commit_id: &str) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        match self.indexed_state().await? {
+            Some(state) => {
+                let mut state = state.into_active_model();
+                state.tree_id = Set(tree_id.to_owned());
+                state.commit_id = Set(commit_id.to_owned());
+                state.updated_at = Set(now);
+                state.update(self.get_connection()).await?;
+            }
+            None => {
+                code_index_state::Model {
+                    id: generate_id(),
+                    created_at: now,
+                    updated_at: now,
+                    tree_id: tree_id.to_owned(),
+                    commit_id: commit_id.to_owned(),
+                }
+                .into_active_model()
+                .insert(self.get_connection())
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces whatever is indexed at `file.path` with `file` and its
+    /// trigrams.
+    pub async fn index_file(
+        &self,
+        file: code_index_file::Model,
+        trigrams: &[i32],
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        remove_files(
+            &txn,
+            Condition::all().add(code_index_file::Column::Path.eq(&file.path)),
+        )
+        .await?;
+        let file = file.into_active_model().insert(&txn).await?;
+        for chunk in trigrams.chunks(TRIGRAM_BATCH_SIZE) {
+            code_index_trigram::Entity::insert_many(chunk.iter().map(|&trigram| {
+                code_index_trigram::ActiveModel {
+                    trigram: Set(trigram),
+                    file_id: Set(file.id),
+                }
+            }))
+            .exec_without_returning(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Drops the file at `path`, or every file under it if it is a directory.
+    pub async fn remove_path(&self, path: &str) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        remove_files(&txn, under_path(path)).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Indexed files containing every one of `trigrams`, ordered by path. An
+    /// empty `trigrams` matches every file.
+    pub async fn find_files(
+        &self,
+        trigrams: &[i32],
+        path: Option<&str>,
+        language: Option<&str>,
+        limit: u64,
+    ) -> Result<Vec<code_index_file::Model>, MegaError> {
+        let mut query = code_index_file::Entity::find();
+        if !trigrams.is_empty() {
+            let containing = Query::select()
+                .column(code_index_trigram::Column::FileId)
+                .from(code_index_trigram::Entity)
+                .and_where(code_index_trigram::Column::Trigram.is_in(trigrams.to_vec()))
+                .group_by_col(code_index_trigram::Column::FileId)
+                .and_having(
+                    Expr::col(code_index_trigram::Column::Trigram)
+                        .count()
+                        .eq(trigrams.len() as i64),
+                )
+                .to_owned();
+            query = query.filter(code_index_file::Column::Id.in_subquery(containing));
+        }
+        if let Some(path) = path {
+            query = query.filter(under_path(path));
+        }
+        if let Some(language) = language {
+            query = query.filter(code_index_file::Column::Language.eq(language.to_lowercase()));
+        }
+        Ok(query
+            .order_by_asc(code_index_file::Column::Path)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+}
+
+/// Matches `path` itself and everything below it.
+fn under_path(path: &str) -> Condition {
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        return Condition::all();
+    }
+    Condition::any()
+        .add(code_index_file::Column::Path.eq(path))
+        .add(code_index_file::Column::Path.starts_with(format!("{path}/")))
+}
+
+async fn remove_files<C: ConnectionTrait>(db: &C, condition: Condition) -> Result<(), MegaError> {
+    let ids: Vec<i64> = code_index_file::Entity::find()
+        .select_only()
+        .column(code_index_file::Column::Id)
+        .filter(condition)
+        .into_tuple()
+        .all(db)
+        .await?;
+    if ids.is_empty() {
+        return Ok(());
+    }
+    code_index_trigram::Entity::delete_many()
+        .filter(code_index_trigram::Column::FileId.is_in(ids.clone()))
+        .exec(db)
+        .await?;
+    code_index_file::Entity::delete_many()
+        .filter(code_index_file::Column::Id.is_in(ids))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use callisto::code_index_file;
+
+    use crate::tests::test_storage;
+
+    #[tokio::test]
+    async fn test_find_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = test_storage(temp_dir.path()).await;
+        let index_stg = storage.code_index_storage();
+
+        let main = code_index_file::Model::new("/project/src/main.rs", "b1", Some("rust"));
+        let lib = code_index_file::Model::new("/project/src/lib.rs", "b2", Some("rust"));
+        let readme = code_index_file::Model::new("/project-docs/README.md", "b3", None);
+        index_stg.index_file(main, &[1, 2, 3]).await.unwrap();
+        index_stg.index_file(lib, &[2, 3, 4]).await.unwrap();
+        index_stg.index_file(readme, &[2, 3]).await.unwrap();
+
+        let paths = |files: Vec<code_index_file::Model>| {
+            files.into_iter().map(|f| f.path).collect::<Vec<_>>()
+        };
+        let found = index_stg.find_files(&[2, 3], None, None, 10).await.unwrap();
+        assert_eq!(found.len(), 3);
+        let found = index_stg.find_files(&[1, 3], None, None, 10).await.unwrap();
+        assert_eq!(paths(found), ["/project/src/main.rs"]);
+        let found = index_stg
+            .find_files(&[2], Some("/project/"), Some("Rust"), 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            paths(found),
+            ["/project/src/lib.rs", "/project/src/main.rs"]
+        );
+
+        // re-indexing a path replaces its trigrams
+        let main = code_index_file::Model::new("/project/src/main.rs", "b4", Some("rust"));
+        index_stg.index_file(main, &[5]).await.unwrap();
+        assert!(
+            index_stg
+                .find_files(&[1], None, None, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        index_stg.remove_path("/project").await.unwrap();
+        let found = index_stg.find_files(&[], None, None, 10).await.unwrap();
+        assert_eq!(paths(found), ["/project-docs/README.md"]);
+
+        assert!(index_stg.indexed_state().await.unwrap().is_none());
+        index_stg.save_state("t1", "c1").await.unwrap();
+        index_stg.save_state("t2", "c2").await.unwrap();
+        let state = index_stg.indexed_state().await.unwrap().unwrap();
+        assert_eq!(state.tree_id, "t2");
+    }
+}
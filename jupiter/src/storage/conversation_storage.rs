@@ -4,9 +4,13 @@ use callisto::sea_orm_active_enums::ConvTypeEnum;
 use callisto::{mega_conversation, reactions};
 use common::errors::MegaError;
 use sea_orm::prelude::Expr;
-use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, Set};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set,
+};
 
-use crate::model::conv_dto::ConvWithReactions;
+use crate::model::conv_dto::{CommentAnchor, ConvWithReactions};
+use crate::service::notification_service::NotificationService;
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
 
 #[derive(Clone)]
@@ -32,9 +36,135 @@ impl ConversationStorage {
         let conversation = mega_conversation::Model::new(link, conv_type, comment, username);
         let conversation = conversation.into_active_model();
         let res = conversation.insert(self.get_connection()).await.unwrap();
+        self.notify(&res).await;
         Ok(res.id)
     }
 
+    /// Adds a comment anchored to lines of a file in the CL diff. It starts
+    /// a new thread that replies are attached to.
+    pub async fn add_inline_comment(
+        &self,
+        link: &str,
+        username: &str,
+        comment: String,
+        conv_type: ConvTypeEnum,
+        anchor: CommentAnchor,
+    ) -> Result<mega_conversation::Model, MegaError> {
+        let mut conversation =
+            mega_conversation::Model::new(link, conv_type, Some(comment), username);
+        conversation.file_path = Some(anchor.file_path);
+        conversation.diff_side = Some(anchor.side);
+        conversation.start_line = Some(anchor.start_line);
+        conversation.end_line = Some(anchor.end_line);
+        conversation.commit_id = Some(anchor.commit_id);
+        let res = conversation
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?;
+        self.notify(&res).await;
+        Ok(res)
+    }
+
+    /// Replies to the inline comment thread started by `parent_id`.
+    pub async fn add_reply(
+        &self,
+        link: &str,
+        parent_id: i64,
+        username: &str,
+        comment: String,
+        conv_type: ConvTypeEnum,
+    ) -> Result<mega_conversation::Model, MegaError> {
+        let parent = mega_conversation::Entity::find_by_id(parent_id)
+            .filter(mega_conversation::Column::Link.eq(link))
+            .filter(mega_conversation::Column::FilePath.is_not_null())
+            .filter(mega_conversation::Column::ParentId.is_null())
+            .one(self.get_connection())
+            .await?
+            .ok_or_else(|| MegaError::Other(format!("Inline comment {parent_id} not found")))?;
+
+        let mut conversation =
+            mega_conversation::Model::new(link, conv_type, Some(comment), username);
+        conversation.parent_id = Some(parent.id);
+        let res = conversation
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?;
+        self.notify(&res).await;
+        Ok(res)
+    }
+
+    async fn notify(&self, conversation: &mega_conversation::Model) {
+        NotificationService::new(self.base.clone())
+            .on_conversation(
+                &conversation.link,
+                &conversation.username,
+                conversation.comment.as_deref(),
+                &conversation.conv_type,
+            )
+            .await
+    }
+
+    /// Inline comments of a CL and their replies, oldest first.
+    pub async fn get_inline_comments(
+        &self,
+        link: &str,
+    ) -> Result<Vec<ConvWithReactions>, MegaError> {
+        let comments = mega_conversation::Entity::find()
+            .filter(mega_conversation::Column::Link.eq(link))
+            .filter(
+                Condition::any()
+                    .add(mega_conversation::Column::FilePath.is_not_null())
+                    .add(mega_conversation::Column::ParentId.is_not_null()),
+            )
+            .order_by_asc(mega_conversation::Column::CreatedAt)
+            .order_by_asc(mega_conversation::Column::Id)
+            .find_with_related(reactions::Entity)
+            .all(self.get_connection())
+            .await?;
+        Ok(comments
+            .into_iter()
+            .map(|(conversation, reactions)| ConvWithReactions {
+                conversation,
+                reactions,
+            })
+            .collect())
+    }
+
+    /// Moves an inline comment to the same lines in a newer revision.
+    pub async fn move_anchor(
+        &self,
+        id: i64,
+        start_line: i32,
+        end_line: i32,
+        commit_id: &str,
+    ) -> Result<(), MegaError> {
+        mega_conversation::Entity::update_many()
+            .col_expr(
+                mega_conversation::Column::StartLine,
+                Expr::value(start_line),
+            )
+            .col_expr(mega_conversation::Column::EndLine, Expr::value(end_line))
+            .col_expr(mega_conversation::Column::CommitId, Expr::value(commit_id))
+            .filter(mega_conversation::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Marks inline comments whose lines changed. They keep pointing at
+    /// the revision they were made against.
+    pub async fn mark_outdated(&self, ids: Vec<i64>) -> Result<(), MegaError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        mega_conversation::Entity::update_many()
+            .col_expr(mega_conversation::Column::Outdated, Expr::value(true))
+            .filter(mega_conversation::Column::Id.is_in(ids))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
     pub async fn update_comment(
         &self,
         comment_id: i64,
@@ -137,3 +267,80 @@ impl ConversationStorage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use callisto::sea_orm_active_enums::{ConvTypeEnum, DiffSideEnum};
+    use tempfile::tempdir;
+
+    use crate::model::conv_dto::CommentAnchor;
+    use crate::tests::test_storage;
+
+    #[tokio::test]
+    async fn test_inline_comment_thread() {
+        let temp = tempdir().unwrap();
+        let storage = test_storage(&temp).await.conversation_storage();
+        storage
+            .add_conversation(
+                "CL1",
+                "alice",
+                Some("LGTM".to_string()),
+                ConvTypeEnum::Comment,
+            )
+            .await
+            .unwrap();
+
+        let anchor = CommentAnchor {
+            file_path: "src/main.rs".to_string(),
+            side: DiffSideEnum::New,
+            start_line: 3,
+            end_line: 4,
+            commit_id: "aaa".to_string(),
+        };
+        let root = storage
+            .add_inline_comment(
+                "CL1",
+                "bob",
+                "nit".to_string(),
+                ConvTypeEnum::Review,
+                anchor,
+            )
+            .await
+            .unwrap();
+        assert_eq!(root.resolved, Some(false));
+        storage
+            .add_reply(
+                "CL1",
+                root.id,
+                "alice",
+                "done".to_string(),
+                ConvTypeEnum::Comment,
+            )
+            .await
+            .unwrap();
+        // only thread roots on the same CL can be replied to
+        assert!(
+            storage
+                .add_reply(
+                    "CL2",
+                    root.id,
+                    "alice",
+                    "x".to_string(),
+                    ConvTypeEnum::Comment
+                )
+                .await
+                .is_err()
+        );
+
+        let comments = storage.get_inline_comments("CL1").await.unwrap();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[1].conversation.parent_id, Some(root.id));
+
+        storage.move_anchor(root.id, 5, 6, "bbb").await.unwrap();
+        storage.mark_outdated(vec![root.id]).await.unwrap();
+        let root = &storage.get_inline_comments("CL1").await.unwrap()[0].conversation;
+        assert_eq!((root.start_line, root.end_line), (Some(5), Some(6)));
+        assert_eq!(root.commit_id.as_deref(), Some("bbb"));
+        assert!(root.outdated);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::storage::base_storage::BaseStorage;
 use crate::storage::base_storage::StorageConnector;
 use callisto::entity_ext::generate_id;
-use callisto::gpg_key;
+use callisto::{gpg_key, push_certificates};
 use chrono::Utc;
 use common::errors::MegaError;
 use pgp::composed::Deserializable;
@@ -98,6 +98,44 @@ impl GpgStorage {
             })?;
         Ok(res)
     }
+
+    /// Finds an unexpired key by its 16-hex-digit key ID.
+    pub async fn find_gpg_key_by_key_id(
+        &self,
+        key_id: &str,
+    ) -> Result<Option<gpg_key::Model>, MegaError> {
+        let now = Utc::now().naive_utc();
+
+        let res = gpg_key::Entity::find()
+            .filter(gpg_key::Column::KeyId.eq(key_id.to_uppercase()))
+            .filter(
+                Expr::col(gpg_key::Column::ExpiresAt)
+                    .is_null()
+                    .or(Expr::col(gpg_key::Column::ExpiresAt).gt(now)),
+            )
+            .one(self.get_connection())
+            .await
+            .map_err(|e| {
+                tracing::error!("{:?}", e);
+                MegaError::Other("Failed to get GPG key".to_string())
+            })?;
+        Ok(res)
+    }
+
+    pub async fn save_push_certificates(
+        &self,
+        models: Vec<push_certificates::Model>,
+    ) -> Result<(), MegaError> {
+        if models.is_empty() {
+            return Ok(());
+        }
+        let models: Vec<push_certificates::ActiveModel> =
+            models.into_iter().map(|m| m.into_active_model()).collect();
+        push_certificates::Entity::insert_many(models)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
 }
 
 #[test]
@@ -12,13 +12,16 @@ use sea_orm::{
 use callisto::{
     issue_cl_references, item_assignees, item_labels, label, mega_conversation, mega_issue,
 };
+use common::enums::WebhookEvent;
 use common::errors::MegaError;
 use common::model::Pagination;
 
 use crate::model::common::{ItemDetails, LabelAssigneeParams, ListParams};
+use crate::service::notification_service::NotificationService;
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
 use crate::storage::stg_common::combine_item_list;
 use crate::storage::stg_common::query_build::{apply_sort, filter_by_assignees, filter_by_labels};
+use crate::storage::webhook_storage::emit_event;
 
 #[derive(Clone)]
 pub struct IssueStorage {
@@ -176,6 +179,7 @@ impl IssueStorage {
             .into_active_model()
             .insert(self.get_connection())
             .await?;
+        self.emit_issue_event(WebhookEvent::IssueOpened, &res).await;
         Ok(res)
     }
 
@@ -189,6 +193,10 @@ impl IssueStorage {
             .filter(mega_issue::Column::Link.eq(link))
             .exec(self.get_connection())
             .await?;
+        if let Some(model) = self.get_issue(link).await? {
+            self.emit_issue_event(WebhookEvent::IssueEdited, &model)
+                .await;
+        }
         Ok(())
     }
 
@@ -196,7 +204,9 @@ impl IssueStorage {
         if let Some(model) = self.get_issue(link).await.unwrap() {
             let mut issue = model.into_active_model();
             issue.status = Set("closed".to_owned());
-            issue.update(self.get_connection()).await.unwrap();
+            let model = issue.update(self.get_connection()).await.unwrap();
+            self.emit_issue_event(WebhookEvent::IssueClosed, &model)
+                .await;
         };
         Ok(())
     }
@@ -205,11 +215,19 @@ impl IssueStorage {
         if let Some(model) = self.get_issue(link).await.unwrap() {
             let mut issue = model.into_active_model();
             issue.status = Set("open".to_owned());
-            issue.update(self.get_connection()).await.unwrap();
+            let model = issue.update(self.get_connection()).await.unwrap();
+            self.emit_issue_event(WebhookEvent::IssueReopened, &model)
+                .await;
         };
         Ok(())
     }
 
+    /// Issues aren't tied to a path, so only webhooks on `/` receive them.
+    async fn emit_issue_event(&self, event: WebhookEvent, issue: &mega_issue::Model) {
+        let data = serde_json::to_value(issue).unwrap_or_default();
+        emit_event(self.get_connection(), event, "/", data).await
+    }
+
     pub async fn new_label(
         &self,
         name: &str,
@@ -356,6 +374,10 @@ impl IssueStorage {
 
         txn.commit().await?;
 
+        NotificationService::new(self.base.clone())
+            .on_assigned(item_id, &item_type, &to_add)
+            .await;
+
         Ok(())
     }
 
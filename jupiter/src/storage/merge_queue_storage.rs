@@ -1,3 +1,4 @@
+use crate::model::merge_queue_dto::BatchRun;
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
 use callisto::merge_queue::{ActiveModel, Column, Entity, Model};
 use callisto::sea_orm_active_enums::{QueueFailureTypeEnum, QueueStatusEnum};
@@ -74,6 +75,8 @@ impl MergeQueueStorage {
             error_message: Set(None),
             created_at: Set(now.naive_utc()),
             updated_at: Set(now.naive_utc()),
+            batch_id: Set(None),
+            batch_history: Set(None),
         };
 
         new_item
@@ -163,6 +166,92 @@ impl MergeQueueStorage {
             .map_err(|e| format!("Failed to find waiting items: {}", e))
     }
 
+    /// Gets up to `limit` waiting items in queue order.
+    pub async fn get_next_waiting_items(&self, limit: u64) -> Result<Vec<Model>, String> {
+        Entity::find()
+            .filter(Column::Status.eq(QueueStatusEnum::Waiting))
+            .order_by_asc(Column::Position)
+            .limit(limit)
+            .all(self.get_connection())
+            .await
+            .map_err(|e| format!("Failed to find waiting items: {}", e))
+    }
+
+    /// Marks a waiting item as Testing in the speculative batch `batch_id`.
+    /// Returns false if the item was cancelled or is no longer waiting.
+    pub async fn assign_batch(&self, cl_link: &str, batch_id: i64) -> Result<bool, String> {
+        let Some(item) = self.find_item_by_cl_link(cl_link).await? else {
+            return Ok(false);
+        };
+        if !matches!(item.status, QueueStatusEnum::Waiting) {
+            return Ok(false);
+        }
+
+        let mut active_model: ActiveModel = item.into();
+        active_model.status = Set(QueueStatusEnum::Testing);
+        active_model.batch_id = Set(Some(batch_id));
+        active_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        active_model
+            .update(self.get_connection())
+            .await
+            .map_err(|e| format!("Failed to assign batch: {}", e))?;
+        Ok(true)
+    }
+
+    /// Appends a speculative test run to the history of every item in `cl_links`.
+    pub async fn record_batch_run(
+        &self,
+        cl_links: &[String],
+        run: &BatchRun,
+    ) -> Result<(), String> {
+        let db = self.get_connection();
+        let run = serde_json::to_value(run)
+            .map_err(|e| format!("Failed to serialize batch run: {}", e))?;
+
+        let items = Entity::find()
+            .filter(Column::ClLink.is_in(cl_links))
+            .all(db)
+            .await
+            .map_err(|e| format!("Failed to find batch items: {}", e))?;
+        for item in items {
+            let mut history = match item.batch_history.clone() {
+                Some(serde_json::Value::Array(runs)) => runs,
+                _ => Vec::new(),
+            };
+            history.push(run.clone());
+
+            let mut active_model: ActiveModel = item.into();
+            active_model.batch_history = Set(Some(serde_json::Value::Array(history)));
+            active_model
+                .update(db)
+                .await
+                .map_err(|e| format!("Failed to record batch run: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Puts an item that was tested in a failed batch back to Waiting.
+    ///
+    /// Unlike `move_item_to_tail` the position is kept, so an innocent CL
+    /// goes first in the next batch. Cancelled (Failed) items are skipped.
+    pub async fn requeue_item(&self, cl_link: &str) -> Result<bool, String> {
+        let Some(item) = self.find_item_by_cl_link(cl_link).await? else {
+            return Ok(false);
+        };
+        if matches!(item.status, QueueStatusEnum::Failed) {
+            return Ok(false);
+        }
+
+        let mut active_model: ActiveModel = item.into();
+        active_model.status = Set(QueueStatusEnum::Waiting);
+        active_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        active_model
+            .update(self.get_connection())
+            .await
+            .map_err(|e| format!("Failed to requeue item: {}", e))?;
+        Ok(true)
+    }
+
     /// Updates item status for normal workflow transitions.
     /// Returns false if item is already Failed (cancelled) - use retry_failed_item to re-queue.
     pub async fn update_item_status(
@@ -435,3 +524,53 @@ impl MergeQueueStorage {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use callisto::sea_orm_active_enums::QueueStatusEnum;
+    use tempfile::tempdir;
+
+    use crate::model::merge_queue_dto::BatchRun;
+    use crate::tests::test_storage;
+
+    #[tokio::test]
+    async fn test_batch_history_and_requeue() {
+        let temp = tempdir().unwrap();
+        let storage = test_storage(&temp).await.merge_queue_storage();
+        for link in ["CL1", "CL2", "CL3"] {
+            storage.add_to_queue(link.to_string()).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+
+        let waiting = storage.get_next_waiting_items(2).await.unwrap();
+        let links: Vec<String> = waiting.iter().map(|i| i.cl_link.clone()).collect();
+        assert_eq!(links, ["CL1", "CL2"]);
+        for link in &links {
+            assert!(storage.assign_batch(link, 42).await.unwrap());
+        }
+        // already testing
+        assert!(!storage.assign_batch("CL1", 43).await.unwrap());
+
+        let run = BatchRun {
+            batch_id: 42,
+            cl_links: links.clone(),
+            commit_id: Some("abc".to_string()),
+            passed: false,
+            message: "1 of 1 builds failed".to_string(),
+            tested_at: chrono::Utc::now().naive_utc(),
+        };
+        storage.record_batch_run(&links, &run).await.unwrap();
+        storage.record_batch_run(&links, &run).await.unwrap();
+
+        let cl2 = storage.get_cl_queue_status("CL2").await.unwrap().unwrap();
+        assert_eq!(cl2.status, QueueStatusEnum::Testing);
+        assert_eq!(cl2.batch_id, Some(42));
+        let history: Vec<BatchRun> = serde_json::from_value(cl2.batch_history.unwrap()).unwrap();
+        assert_eq!(history, vec![run.clone(), run]);
+
+        // requeued CLs keep their place ahead of CL3
+        assert!(storage.requeue_item("CL2").await.unwrap());
+        let next = storage.get_next_waiting_item().await.unwrap().unwrap();
+        assert_eq!(next.cl_link, "CL2");
+    }
+}
@@ -2,6 +2,7 @@ pub mod base_storage;
 pub mod buck_storage;
 pub mod cl_reviewer_storage;
 pub mod cl_storage;
+pub mod code_index_storage;
 pub mod commit_binding_storage;
 pub mod conversation_storage;
 pub mod dynamic_sidebar_storage;
@@ -13,9 +14,12 @@ pub mod lfs_db_storage;
 pub mod merge_queue_storage;
 pub mod mono_storage;
 pub mod note_storage;
+pub mod notification_storage;
 pub mod stg_common;
+pub mod symbol_storage;
 pub mod user_storage;
 pub mod vault_storage;
+pub mod webhook_storage;
 
 use common::errors::MegaError;
 use std::sync::{Arc, LazyLock, Weak};
@@ -32,6 +36,7 @@ use crate::service::import_service::ImportService;
 use crate::service::issue_service::IssueService;
 use crate::service::merge_queue_service::MergeQueueService;
 use crate::service::mono_service::MonoService;
+use crate::service::notification_service::NotificationService;
 use crate::storage::conversation_storage::ConversationStorage;
 use crate::storage::dynamic_sidebar_storage::DynamicSidebarStorage;
 use crate::storage::init::database_connection;
@@ -39,12 +44,15 @@ use crate::storage::{
     buck_storage::BuckStorage, cl_storage::ClStorage, commit_binding_storage::CommitBindingStorage,
     git_db_storage::GitDbStorage, gpg_storage::GpgStorage, issue_storage::IssueStorage,
     lfs_db_storage::LfsDbStorage, merge_queue_storage::MergeQueueStorage,
-    mono_storage::MonoStorage, user_storage::UserStorage, vault_storage::VaultStorage,
+    mono_storage::MonoStorage, notification_storage::NotificationStorage,
+    user_storage::UserStorage, vault_storage::VaultStorage, webhook_storage::WebhookStorage,
 };
 
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
 use crate::storage::cl_reviewer_storage::ClReviewerStorage;
+use crate::storage::code_index_storage::CodeIndexStorage;
 use crate::storage::note_storage::NoteStorage;
+use crate::storage::symbol_storage::SymbolStorage;
 
 #[derive(Clone)]
 pub struct AppService {
@@ -64,6 +72,10 @@ pub struct AppService {
     pub merge_queue_storage: MergeQueueStorage,
     pub buck_storage: BuckStorage,
     pub dynamic_sidebar_storage: DynamicSidebarStorage,
+    pub webhook_storage: WebhookStorage,
+    pub notification_storage: NotificationStorage,
+    pub code_index_storage: CodeIndexStorage,
+    pub symbol_storage: SymbolStorage,
 }
 
 impl AppService {
@@ -86,6 +98,10 @@ impl AppService {
             merge_queue_storage: MergeQueueStorage::new(mock.clone()),
             buck_storage: BuckStorage { base: mock.clone() },
             dynamic_sidebar_storage: DynamicSidebarStorage { base: mock.clone() },
+            webhook_storage: WebhookStorage { base: mock.clone() },
+            notification_storage: NotificationStorage { base: mock.clone() },
+            code_index_storage: CodeIndexStorage { base: mock.clone() },
+            symbol_storage: SymbolStorage { base: mock.clone() },
         })
     }
 }
@@ -99,6 +115,7 @@ pub struct Storage {
     pub buck_service: BuckService,
     pub mono_service: MonoService,
     pub import_service: ImportService,
+    pub notification_service: NotificationService,
     pub git_service: GitService,
     pub config: Weak<Config>,
 }
@@ -125,6 +142,10 @@ impl Storage {
         let merge_queue_storage = MergeQueueStorage::new(base.clone());
         let buck_storage = BuckStorage { base: base.clone() };
         let dynamic_sidebar_storage = DynamicSidebarStorage { base: base.clone() };
+        let webhook_storage = WebhookStorage { base: base.clone() };
+        let notification_storage = NotificationStorage { base: base.clone() };
+        let code_index_storage = CodeIndexStorage { base: base.clone() };
+        let symbol_storage = SymbolStorage { base: base.clone() };
 
         let git_service = GitService {
             obj_storage: ObjectStorageFactory::create(ObjectStorageConfig::from_config(
@@ -179,6 +200,10 @@ impl Storage {
             merge_queue_storage: merge_queue_storage.clone(),
             buck_storage,
             dynamic_sidebar_storage,
+            webhook_storage,
+            notification_storage,
+            code_index_storage,
+            symbol_storage,
         };
         let merge_queue_service = MergeQueueService::new(base.clone());
         let buck_service = BuckService::new(
@@ -201,6 +226,7 @@ impl Storage {
             git_service,
             mono_service,
             import_service,
+            notification_service: NotificationService::new(base.clone()),
         })
     }
 
@@ -285,6 +311,22 @@ impl Storage {
         self.app_service.dynamic_sidebar_storage.clone()
     }
 
+    pub fn webhook_storage(&self) -> WebhookStorage {
+        self.app_service.webhook_storage.clone()
+    }
+
+    pub fn notification_storage(&self) -> NotificationStorage {
+        self.app_service.notification_storage.clone()
+    }
+
+    pub fn code_index_storage(&self) -> CodeIndexStorage {
+        self.app_service.code_index_storage.clone()
+    }
+
+    pub fn symbol_storage(&self) -> SymbolStorage {
+        self.app_service.symbol_storage.clone()
+    }
+
     pub fn mock() -> Self {
         // During test time, we don't need a AppContext,
         // Put config in a leaked static variable thus the weak reference will always be valid.
@@ -300,6 +342,7 @@ impl Storage {
             git_service: GitService::mock(),
             mono_service: MonoService::mock(),
             import_service: ImportService::mock(),
+            notification_service: NotificationService::mock(),
         }
     }
 }
@@ -23,6 +23,7 @@ use git_internal::internal::object::commit::Commit;
 use git_internal::internal::object::tree::Tree;
 
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
+use crate::storage::cl_storage::add_patchset;
 use crate::storage::commit_binding_storage::CommitBindingStorage;
 use crate::storage::user_storage::UserStorage;
 use crate::utils::converter::IntoMegaModel;
@@ -604,6 +605,7 @@ impl MonoStorage {
         cl_active.updated_at = Set(chrono::Utc::now().naive_utc());
 
         cl_active.update(conn).await?;
+        add_patchset(conn, cl_link, from_hash, to_hash, &cl.username).await?;
 
         Ok(cl)
     }
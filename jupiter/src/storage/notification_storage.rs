@@ -0,0 +1,195 @@
+use std::ops::Deref;
+use std::path::Path;
+
+use callisto::sea_orm_active_enums::{NotificationStateEnum, SubscriptionTargetEnum};
+use callisto::{notification_subscriptions, notifications};
+use common::errors::MegaError;
+use common::model::Pagination;
+use sea_orm::prelude::Expr;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ColumnTrait, DbErr, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use crate::storage::base_storage::{BaseStorage, StorageConnector};
+
+#[derive(Clone)]
+pub struct NotificationStorage {
+    pub base: BaseStorage,
+}
+
+impl Deref for NotificationStorage {
+    type Target = BaseStorage;
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl NotificationStorage {
+    pub async fn save_notifications(
+        &self,
+        models: Vec<notifications::Model>,
+    ) -> Result<(), MegaError> {
+        if models.is_empty() {
+            return Ok(());
+        }
+        notifications::Entity::insert_many(models.into_iter().map(|m| m.into_active_model()))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// A user's notifications, newest first. Without `state` every
+    /// notification except the ones marked done is listed.
+    pub async fn list_notifications(
+        &self,
+        username: &str,
+        state: Option<NotificationStateEnum>,
+        page: Pagination,
+    ) -> Result<(Vec<notifications::Model>, u64), MegaError> {
+        let query =
+            notifications::Entity::find().filter(notifications::Column::Username.eq(username));
+        let query = match state {
+            Some(state) => query.filter(notifications::Column::State.eq(state)),
+            None => query.filter(notifications::Column::State.ne(NotificationStateEnum::Done)),
+        };
+        let paginator = query
+            .order_by_desc(notifications::Column::CreatedAt)
+            .order_by_desc(notifications::Column::Id)
+            .paginate(self.get_connection(), page.per_page);
+        let num_items = paginator.num_items().await?;
+        Ok(paginator
+            .fetch_page(page.page.saturating_sub(1))
+            .await
+            .map(|m| (m, num_items))?)
+    }
+
+    pub async fn unread_count(&self, username: &str) -> Result<u64, MegaError> {
+        Ok(notifications::Entity::find()
+            .filter(notifications::Column::Username.eq(username))
+            .filter(notifications::Column::State.eq(NotificationStateEnum::Unread))
+            .count(self.get_connection())
+            .await?)
+    }
+
+    /// Notifications of every user created after `since`, oldest first.
+    pub async fn notifications_since(
+        &self,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<notifications::Model>, MegaError> {
+        Ok(notifications::Entity::find()
+            .filter(notifications::Column::CreatedAt.gt(since))
+            .order_by_asc(notifications::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Moves the given notifications of a user to `state`. With no ids every
+    /// unread notification is moved.
+    pub async fn set_state(
+        &self,
+        username: &str,
+        ids: &[i64],
+        state: NotificationStateEnum,
+    ) -> Result<u64, MegaError> {
+        let query = notifications::Entity::update_many()
+            .col_expr(notifications::Column::State, Expr::value(state))
+            .col_expr(
+                notifications::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(notifications::Column::Username.eq(username));
+        let query = if ids.is_empty() {
+            query.filter(notifications::Column::State.eq(NotificationStateEnum::Unread))
+        } else {
+            query.filter(notifications::Column::Id.is_in(ids.to_vec()))
+        };
+        Ok(query.exec(self.get_connection()).await?.rows_affected)
+    }
+
+    /// Follows a path or an issue or CL. Following twice is a no-op.
+    pub async fn subscribe(
+        &self,
+        username: &str,
+        target_type: SubscriptionTargetEnum,
+        target: &str,
+    ) -> Result<(), MegaError> {
+        let model = notification_subscriptions::Model::new(username, target_type, target);
+        let res = notification_subscriptions::Entity::insert(model.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    notification_subscriptions::Column::Username,
+                    notification_subscriptions::Column::TargetType,
+                    notification_subscriptions::Column::Target,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await;
+        match res {
+            Ok(_) | Err(DbErr::RecordNotInserted) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn unsubscribe(
+        &self,
+        username: &str,
+        target_type: SubscriptionTargetEnum,
+        target: &str,
+    ) -> Result<(), MegaError> {
+        notification_subscriptions::Entity::delete_many()
+            .filter(notification_subscriptions::Column::Username.eq(username))
+            .filter(notification_subscriptions::Column::TargetType.eq(target_type))
+            .filter(notification_subscriptions::Column::Target.eq(target))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_subscriptions(
+        &self,
+        username: &str,
+    ) -> Result<Vec<notification_subscriptions::Model>, MegaError> {
+        Ok(notification_subscriptions::Entity::find()
+            .filter(notification_subscriptions::Column::Username.eq(username))
+            .order_by_asc(notification_subscriptions::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Users following `link` itself, or a path `path` is under.
+    pub async fn list_followers(
+        &self,
+        link: &str,
+        path: Option<&str>,
+    ) -> Result<Vec<String>, MegaError> {
+        let mut followers: Vec<String> = notification_subscriptions::Entity::find()
+            .select_only()
+            .column(notification_subscriptions::Column::Username)
+            .filter(notification_subscriptions::Column::TargetType.eq(SubscriptionTargetEnum::Item))
+            .filter(notification_subscriptions::Column::Target.eq(link))
+            .into_tuple()
+            .all(self.get_connection())
+            .await?;
+        if let Some(path) = path {
+            let path_subs = notification_subscriptions::Entity::find()
+                .filter(
+                    notification_subscriptions::Column::TargetType.eq(SubscriptionTargetEnum::Path),
+                )
+                .all(self.get_connection())
+                .await?;
+            followers.extend(
+                path_subs
+                    .into_iter()
+                    .filter(|sub| Path::new(path).starts_with(&sub.target))
+                    .map(|sub| sub.username),
+            );
+        }
+        followers.sort();
+        followers.dedup();
+        Ok(followers)
+    }
+}
@@ -132,6 +132,13 @@ mod tests {
             updated_at: chrono::Utc::now().naive_utc(),
             username: String::from("benjamin_747"),
             resolved: None,
+            parent_id: None,
+            file_path: None,
+            diff_side: None,
+            start_line: None,
+            end_line: None,
+            commit_id: None,
+            outdated: false,
         };
 
         let item_labels = vec![(issue.clone(), vec![label])];
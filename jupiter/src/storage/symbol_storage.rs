@@ -0,0 +1,290 @@
+use std::ops::Deref;
+
+use callisto::code_symbols;
+use common::errors::MegaError;
+use sea_orm::{
+    ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+
+use crate::storage::base_storage::{BaseStorage, StorageConnector};
+
+/// Rows per insert when saving the symbols of an index.
+const SYMBOL_BATCH_SIZE: usize = 500;
+
+/// Definitions and references of code symbols, stored per commit.
+#[derive(Clone)]
+pub struct SymbolStorage {
+    pub base: BaseStorage,
+}
+
+impl Deref for SymbolStorage {
+    type Target = BaseStorage;
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl SymbolStorage {
+    /// Replaces what the indexer `source` found under `root` at `commit_id`
+    /// with `symbols`, so uploading an index for a project again doesn't
+    /// duplicate it. Other indexers' symbols are kept.
+    pub async fn replace_symbols(
+        &self,
+        commit_id: &str,
+        root: &str,
+        source: &str,
+        symbols: Vec<code_symbols::Model>,
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        code_symbols::Entity::delete_many()
+            .filter(code_symbols::Column::CommitId.eq(commit_id))
+            .filter(code_symbols::Column::Source.eq(source))
+            .filter(under_path(root))
+            .exec(&txn)
+            .await?;
+        for chunk in symbols.chunks(SYMBOL_BATCH_SIZE) {
+            code_symbols::Entity::insert_many(
+                chunk.iter().cloned().map(code_symbols::ActiveModel::from),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// What the indexer `source` found under `root` at `commit_id`.
+    pub async fn symbols_under(
+        &self,
+        commit_id: &str,
+        root: &str,
+        source: &str,
+    ) -> Result<Vec<code_symbols::Model>, MegaError> {
+        Ok(code_symbols::Entity::find()
+            .filter(code_symbols::Column::CommitId.eq(commit_id))
+            .filter(code_symbols::Column::Source.eq(source))
+            .filter(under_path(root))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Whether anything has been indexed at `commit_id` under `path`.
+    pub async fn is_indexed(&self, commit_id: &str, path: &str) -> Result<bool, MegaError> {
+        Ok(code_symbols::Entity::find()
+            .filter(code_symbols::Column::CommitId.eq(commit_id))
+            .filter(under_path(path))
+            .one(self.get_connection())
+            .await?
+            .is_some())
+    }
+
+    /// The innermost occurrence covering the 0-based `line` and `character`
+    /// of the file at `path`.
+    pub async fn occurrence_at(
+        &self,
+        commit_id: &str,
+        path: &str,
+        line: i32,
+        character: i32,
+    ) -> Result<Option<code_symbols::Model>, MegaError> {
+        let candidates = code_symbols::Entity::find()
+            .filter(code_symbols::Column::CommitId.eq(commit_id))
+            .filter(code_symbols::Column::Path.eq(path))
+            .filter(code_symbols::Column::StartLine.lte(line))
+            .filter(code_symbols::Column::EndLine.gte(line))
+            .all(self.get_connection())
+            .await?;
+        Ok(candidates
+            .into_iter()
+            .filter(|s| {
+                (s.start_line, s.start_character) <= (line, character)
+                    && (line, character) <= (s.end_line, s.end_character)
+            })
+            .max_by_key(|s| (s.start_line, s.start_character)))
+    }
+
+    /// Occurrences of any of `symbols` at `commit_id`, definitions only if
+    /// `definition` is set, references only otherwise.
+    pub async fn find_occurrences(
+        &self,
+        commit_id: &str,
+        symbols: &[String],
+        definition: bool,
+        limit: u64,
+    ) -> Result<Vec<code_symbols::Model>, MegaError> {
+        if symbols.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(code_symbols::Entity::find()
+            .filter(code_symbols::Column::CommitId.eq(commit_id))
+            .filter(code_symbols::Column::Symbol.is_in(symbols.to_vec()))
+            .filter(code_symbols::Column::IsDefinition.eq(definition))
+            .order_by_asc(code_symbols::Column::Path)
+            .order_by_asc(code_symbols::Column::StartLine)
+            .order_by_asc(code_symbols::Column::StartCharacter)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Definitions at `commit_id` whose short name is `name`.
+    pub async fn definitions_named(
+        &self,
+        commit_id: &str,
+        name: &str,
+        limit: u64,
+    ) -> Result<Vec<code_symbols::Model>, MegaError> {
+        Ok(code_symbols::Entity::find()
+            .filter(code_symbols::Column::CommitId.eq(commit_id))
+            .filter(code_symbols::Column::Name.eq(name))
+            .filter(code_symbols::Column::IsDefinition.eq(true))
+            .order_by_asc(code_symbols::Column::Path)
+            .order_by_asc(code_symbols::Column::StartLine)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+}
+
+/// Matches `path` itself and everything below it.
+fn under_path(path: &str) -> Condition {
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        return Condition::all();
+    }
+    Condition::any()
+        .add(code_symbols::Column::Path.eq(path))
+        .add(code_symbols::Column::Path.starts_with(format!("{path}/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use callisto::code_symbols;
+
+    use crate::tests::test_storage;
+
+    fn occurrence(
+        path: &str,
+        symbol: &str,
+        is_definition: bool,
+        line: i32,
+        range: (i32, i32),
+    ) -> code_symbols::Model {
+        let now = chrono::Utc::now().naive_utc();
+        code_symbols::Model {
+            created_at: now,
+            updated_at: now,
+            id: common::utils::generate_id(),
+            commit_id: "c1".to_owned(),
+            path: path.to_owned(),
+            symbol: symbol.to_owned(),
+            name: symbol.rsplit("::").next().unwrap().to_owned(),
+            kind: None,
+            is_definition,
+            start_line: line,
+            start_character: range.0,
+            end_line: line,
+            end_character: range.1,
+            source: "scip".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_symbol_queries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = test_storage(temp_dir.path()).await;
+        let symbol_stg = storage.symbol_storage();
+
+        symbol_stg
+            .replace_symbols(
+                "c1",
+                "/project",
+                "scip",
+                vec![
+                    occurrence("/project/src/lib.rs", "crate::parse", true, 3, (7, 12)),
+                    occurrence("/project/src/main.rs", "crate::parse", false, 10, (4, 9)),
+                    occurrence("/project/src/main.rs", "crate::run", false, 10, (0, 20)),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(symbol_stg.is_indexed("c1", "/project/src").await.unwrap());
+        assert!(!symbol_stg.is_indexed("c2", "/project").await.unwrap());
+        let under_src = symbol_stg
+            .symbols_under("c1", "/project/src", "scip")
+            .await
+            .unwrap();
+        assert_eq!(under_src.len(), 3);
+        assert!(
+            symbol_stg
+                .symbols_under("c1", "/project", "tree-sitter")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // the innermost occurrence wins
+        let at = symbol_stg
+            .occurrence_at("c1", "/project/src/main.rs", 10, 6)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(at.symbol, "crate::parse");
+        let defs = symbol_stg
+            .find_occurrences("c1", &[at.symbol], true, 10)
+            .await
+            .unwrap();
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].path, "/project/src/lib.rs");
+        let named = symbol_stg
+            .definitions_named("c1", "parse", 10)
+            .await
+            .unwrap();
+        assert_eq!(named, defs);
+
+        // uploading again replaces the earlier index
+        symbol_stg
+            .replace_symbols(
+                "c1",
+                "/project/",
+                "scip",
+                vec![occurrence(
+                    "/project/src/main.rs",
+                    "crate::parse",
+                    false,
+                    1,
+                    (0, 5),
+                )],
+            )
+            .await
+            .unwrap();
+        let refs = symbol_stg
+            .find_occurrences("c1", &["crate::parse".to_owned()], false, 10)
+            .await
+            .unwrap();
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].start_line, 1);
+        assert!(
+            symbol_stg
+                .find_occurrences("c1", &["crate::parse".to_owned()], true, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        // another indexer under the same root leaves them alone
+        let mut tree_sitter = occurrence("/project/src/lib.rs", "parse", true, 3, (7, 12));
+        tree_sitter.source = "tree-sitter".to_owned();
+        symbol_stg
+            .replace_symbols("c1", "/project", "tree-sitter", vec![tree_sitter])
+            .await
+            .unwrap();
+        assert_eq!(
+            symbol_stg
+                .symbols_under("c1", "/project", "scip")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
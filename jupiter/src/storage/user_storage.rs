@@ -1,13 +1,20 @@
 use std::ops::Deref;
+use std::path::Path;
 
+use chrono::NaiveDateTime;
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, ModelTrait, QueryFilter,
+    prelude::Expr,
 };
-use uuid::Uuid;
 
 use callisto::{access_token, ssh_keys};
-use common::{errors::MegaError, utils::generate_id};
+use common::{enums::TokenScope, errors::MegaError, utils::generate_id};
 
+use crate::model::token_dto::{AuthorizedToken, join_scopes};
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
 
 #[derive(Debug, Clone)]
@@ -79,17 +86,34 @@ impl UserStorage {
         Ok(res)
     }
 
-    pub async fn generate_token(&self, username: String) -> Result<String, MegaError> {
-        let token_str = Uuid::new_v4().to_string();
+    /// Issues a token and returns it with the raw token string, which is
+    /// not stored and can't be shown again.
+    pub async fn generate_token(
+        &self,
+        username: String,
+        scopes: &[TokenScope],
+        path_prefix: Option<String>,
+        expires_at: NaiveDateTime,
+    ) -> Result<(access_token::Model, String), MegaError> {
+        let token_str = format!("{TOKEN_PREFIX}{}", hex::encode(random_bytes::<20>()));
+        let salt = generate_salt();
         let model = access_token::Model {
             id: generate_id(),
             username,
-            token: token_str.clone(),
             created_at: chrono::Utc::now().naive_utc(),
+            token_prefix: token_str[..TOKEN_PREFIX_LEN].to_owned(),
+            token_hash: hash_token(&salt, &token_str),
+            salt,
+            scopes: join_scopes(scopes),
+            path_prefix,
+            expires_at: Some(expires_at),
+            last_used_at: None,
         };
-        let a_model = model.into_active_model();
-        a_model.insert(self.get_connection()).await.unwrap();
-        Ok(token_str.to_owned())
+        let model = model
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?;
+        Ok((model, token_str))
     }
 
     pub async fn delete_token(&self, username: String, id: i64) -> Result<(), MegaError> {
@@ -104,6 +128,31 @@ impl UserStorage {
         Ok(())
     }
 
+    /// Deletes the user's tokens limited to `path_prefix` or a path below
+    /// it. Tokens for the whole monorepo are left alone.
+    pub async fn revoke_tokens(&self, username: &str, path_prefix: &str) -> Result<u64, MegaError> {
+        let ids: Vec<i64> = self
+            .list_token(username.to_owned())
+            .await?
+            .into_iter()
+            .filter(|token| {
+                token
+                    .path_prefix
+                    .as_ref()
+                    .is_some_and(|p| Path::new(p).starts_with(path_prefix))
+            })
+            .map(|token| token.id)
+            .collect();
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let res = access_token::Entity::delete_many()
+            .filter(access_token::Column::Id.is_in(ids))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+
     pub async fn list_token(
         &self,
         username: String,
@@ -115,37 +164,95 @@ impl UserStorage {
         Ok(res)
     }
 
-    pub async fn check_token(&self, username: &str, token: &str) -> Result<bool, MegaError> {
-        let res = access_token::Entity::find()
-            .filter(access_token::Column::Username.eq(username))
-            .filter(access_token::Column::Token.eq(token))
-            .one(self.get_connection())
-            .await?;
-        match res {
-            Some(_) => Ok(true),
-            None => Ok(false),
+    /// Looks up an unexpired token and records that it was used.
+    pub async fn verify_token(&self, token: &str) -> Result<Option<AuthorizedToken>, MegaError> {
+        let Some(prefix) = token.get(..TOKEN_PREFIX_LEN) else {
+            return Ok(None);
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let found = access_token::Entity::find()
+            .filter(access_token::Column::TokenPrefix.eq(prefix))
+            .all(self.get_connection())
+            .await?
+            .into_iter()
+            .find(|model| hash_token(&model.salt, token) == model.token_hash);
+        let Some(model) = found else {
+            return Ok(None);
+        };
+        if model.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Ok(None);
         }
-    }
 
-    pub async fn find_user_by_token(&self, token: &str) -> Result<Option<String>, MegaError> {
-        let res = access_token::Entity::find()
-            .filter(access_token::Column::Token.eq(token))
-            .one(self.get_connection())
+        access_token::Entity::update_many()
+            .col_expr(access_token::Column::LastUsedAt, Expr::value(now))
+            .filter(access_token::Column::Id.eq(model.id))
+            .exec(self.get_connection())
             .await?;
-        match res {
-            Some(token_model) => Ok(Some(token_model.username)),
-            None => Ok(None),
-        }
+        Ok(Some(model.into()))
     }
 }
 
+/// Length of the leading part of a token that is stored in clear to find
+/// it again.
+pub const TOKEN_PREFIX_LEN: usize = 12;
+
+const TOKEN_PREFIX: &str = "mega_";
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random source failed");
+    bytes
+}
+
+fn generate_salt() -> String {
+    hex::encode(random_bytes::<16>())
+}
+
+fn hash_token(salt: &str, token: &str) -> String {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(salt.as_bytes());
+    ctx.update(token.as_bytes());
+    hex::encode(ctx.finish())
+}
+
 #[cfg(test)]
 mod test {
+    use std::path::Path;
+
+    use common::enums::TokenScope;
     use uuid::Uuid;
 
+    use super::{generate_salt, hash_token};
+    use crate::model::token_dto::{AuthorizedToken, parse_scopes};
+
     #[test]
     fn token_format() {
         let uuid = Uuid::new_v4().to_string();
         println!("{uuid:?}");
     }
+
+    #[test]
+    fn test_scoped_token() {
+        let token = "mega_0123456789abcdef";
+        let salt = generate_salt();
+        let hash = hash_token(&salt, token);
+        assert_eq!(hash, hash_token(&salt, token));
+        assert_ne!(hash, hash_token(&generate_salt(), token));
+        assert!(!hash.contains(token));
+
+        let authorized = AuthorizedToken {
+            id: 1,
+            username: "alice".to_owned(),
+            scopes: parse_scopes("repo:write,unknown"),
+            path_prefix: Some("/project/mega".to_owned()),
+        };
+        assert_eq!(authorized.scopes, vec![TokenScope::RepoWrite]);
+        assert!(authorized.allows(TokenScope::RepoRead));
+        assert!(!authorized.allows(TokenScope::ClWrite));
+        assert!(authorized.covers_path(Path::new("/project/mega/src")));
+        assert!(!authorized.covers_path(Path::new("/project/megalith")));
+        assert!(TokenScope::Lfs.granted_by(&[TokenScope::Admin]));
+    }
 }
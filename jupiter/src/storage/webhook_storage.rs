@@ -0,0 +1,347 @@
+use std::ops::Deref;
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, QuerySelect, Set,
+};
+
+use callisto::sea_orm_active_enums::DeliveryStatusEnum;
+use callisto::{webhook, webhook_delivery};
+use common::enums::WebhookEvent;
+use common::errors::MegaError;
+
+use crate::storage::base_storage::{BaseStorage, StorageConnector};
+
+#[derive(Clone)]
+pub struct WebhookStorage {
+    pub base: BaseStorage,
+}
+
+impl Deref for WebhookStorage {
+    type Target = BaseStorage;
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl WebhookStorage {
+    pub async fn create_webhook(&self, model: webhook::Model) -> Result<webhook::Model, MegaError> {
+        Ok(model
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?)
+    }
+
+    /// The webhooks created by `username`.
+    pub async fn list_webhooks(&self, username: &str) -> Result<Vec<webhook::Model>, MegaError> {
+        Ok(webhook::Entity::find()
+            .filter(webhook::Column::CreatedBy.eq(username))
+            .order_by_asc(webhook::Column::PathPrefix)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_webhook(&self, id: i64) -> Result<Option<webhook::Model>, MegaError> {
+        Ok(webhook::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Deletes the webhook together with its delivery log.
+    pub async fn delete_webhook(&self, id: i64) -> Result<(), MegaError> {
+        webhook_delivery::Entity::delete_many()
+            .filter(webhook_delivery::Column::WebhookId.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        webhook::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Records `event` for every webhook subscribed to it under `path`.
+    pub async fn emit(&self, event: WebhookEvent, path: &str, data: serde_json::Value) {
+        emit_event(self.get_connection(), event, path, data).await
+    }
+
+    /// The most recent deliveries of a webhook, newest first.
+    pub async fn list_deliveries(
+        &self,
+        webhook_id: i64,
+        limit: u64,
+    ) -> Result<Vec<webhook_delivery::Model>, MegaError> {
+        Ok(webhook_delivery::Entity::find()
+            .filter(webhook_delivery::Column::WebhookId.eq(webhook_id))
+            .order_by_desc(webhook_delivery::Column::CreatedAt)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_delivery(
+        &self,
+        id: i64,
+    ) -> Result<Option<webhook_delivery::Model>, MegaError> {
+        Ok(webhook_delivery::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Sends the payload of a past delivery again, as a new delivery.
+    pub async fn redeliver(&self, id: i64) -> Result<webhook_delivery::Model, MegaError> {
+        let delivery = self
+            .get_delivery(id)
+            .await?
+            .ok_or_else(|| MegaError::Other(format!("Delivery {id} not found")))?;
+        let model =
+            webhook_delivery::Model::new(delivery.webhook_id, &delivery.event, delivery.payload);
+        Ok(model
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?)
+    }
+
+    /// Deliveries that are not on the retry queue yet, and queued ones
+    /// whose attempt is overdue since `stale_before`, which were lost by a
+    /// worker that stopped half way.
+    pub async fn unscheduled_deliveries(
+        &self,
+        stale_before: NaiveDateTime,
+        limit: u64,
+    ) -> Result<Vec<webhook_delivery::Model>, MegaError> {
+        Ok(webhook_delivery::Entity::find()
+            .filter(
+                Condition::any()
+                    .add(webhook_delivery::Column::Status.eq(DeliveryStatusEnum::Pending))
+                    .add(
+                        Condition::all()
+                            .add(webhook_delivery::Column::Status.eq(DeliveryStatusEnum::Queued))
+                            .add(webhook_delivery::Column::NextAttemptAt.lt(stale_before)),
+                    ),
+            )
+            .order_by_asc(webhook_delivery::Column::CreatedAt)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn mark_queued(
+        &self,
+        delivery: webhook_delivery::Model,
+        next_attempt_at: NaiveDateTime,
+    ) -> Result<(), MegaError> {
+        let mut a_model = delivery.into_active_model();
+        a_model.status = Set(DeliveryStatusEnum::Queued);
+        a_model.next_attempt_at = Set(Some(next_attempt_at));
+        a_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        a_model.update(self.get_connection()).await?;
+        Ok(())
+    }
+
+    pub async fn record_success(
+        &self,
+        delivery: webhook_delivery::Model,
+        response_status: i32,
+    ) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let attempts = delivery.attempts + 1;
+        let mut a_model = delivery.into_active_model();
+        a_model.status = Set(DeliveryStatusEnum::Delivered);
+        a_model.attempts = Set(attempts);
+        a_model.response_status = Set(Some(response_status));
+        a_model.last_error = Set(None);
+        a_model.next_attempt_at = Set(None);
+        a_model.delivered_at = Set(Some(now));
+        a_model.updated_at = Set(now);
+        a_model.update(self.get_connection()).await?;
+        Ok(())
+    }
+
+    /// Records a failed attempt. Without `retry_at` the delivery is given up.
+    pub async fn record_failure(
+        &self,
+        delivery: webhook_delivery::Model,
+        response_status: Option<i32>,
+        error: String,
+        retry_at: Option<NaiveDateTime>,
+    ) -> Result<(), MegaError> {
+        let attempts = delivery.attempts + 1;
+        let mut a_model = delivery.into_active_model();
+        a_model.status = Set(match retry_at {
+            Some(_) => DeliveryStatusEnum::Queued,
+            None => DeliveryStatusEnum::Failed,
+        });
+        a_model.attempts = Set(attempts);
+        a_model.response_status = Set(response_status);
+        a_model.last_error = Set(Some(error));
+        a_model.next_attempt_at = Set(retry_at);
+        a_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        a_model.update(self.get_connection()).await?;
+        Ok(())
+    }
+}
+
+pub fn join_events(events: &[WebhookEvent]) -> String {
+    events
+        .iter()
+        .map(|event| event.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Events are stored comma separated. Unknown ones are dropped.
+pub fn parse_events(events: &str) -> Vec<WebhookEvent> {
+    events
+        .split(',')
+        .filter_map(|event| event.parse().ok())
+        .collect()
+}
+
+fn subscribed(hook: &webhook::Model, event: WebhookEvent, path: &str) -> bool {
+    hook.active
+        && (!event.is_path_scoped() || Path::new(path).starts_with(&hook.path_prefix))
+        && parse_events(&hook.events).contains(&event)
+}
+
+/// Records `event` for every webhook subscribed to it under `path`, to be
+/// sent by the delivery worker.
+///
+/// Webhooks are a side effect of whatever raised the event, so failures
+/// are only logged.
+pub(crate) async fn emit_event<C: ConnectionTrait>(
+    db: &C,
+    event: WebhookEvent,
+    path: &str,
+    data: serde_json::Value,
+) {
+    if let Err(e) = enqueue_event(db, event, path, data).await {
+        tracing::warn!("Failed to record {} event for {path}: {e}", event.as_str());
+    }
+}
+
+async fn enqueue_event<C: ConnectionTrait>(
+    db: &C,
+    event: WebhookEvent,
+    path: &str,
+    data: serde_json::Value,
+) -> Result<(), MegaError> {
+    let hooks: Vec<webhook::Model> = webhook::Entity::find()
+        .filter(webhook::Column::Active.eq(true))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|hook| subscribed(hook, event, path))
+        .collect();
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let payload = serde_json::json!({
+        "event": event.as_str(),
+        "path": path,
+        "timestamp": chrono::Utc::now().timestamp(),
+        "data": data,
+    })
+    .to_string();
+    for hook in hooks {
+        webhook_delivery::Model::new(hook.id, event.as_str(), payload.clone())
+            .into_active_model()
+            .insert(db)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use callisto::sea_orm_active_enums::DeliveryStatusEnum;
+    use callisto::webhook;
+    use common::enums::WebhookEvent;
+
+    use super::{join_events, subscribed};
+    use crate::tests::test_storage;
+
+    #[tokio::test]
+    async fn test_webhook_deliveries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = test_storage(temp_dir.path()).await;
+        let webhook_stg = storage.webhook_storage();
+
+        let hook = webhook_stg
+            .create_webhook(webhook::Model::new(
+                "/project",
+                "https://ci.example.com/hook",
+                "secret",
+                join_events(&[WebhookEvent::ClMerged, WebhookEvent::Push]),
+                "alice",
+            ))
+            .await
+            .unwrap();
+        assert!(subscribed(&hook, WebhookEvent::Push, "/project/mega"));
+        assert!(!subscribed(&hook, WebhookEvent::Push, "/projects"));
+        assert!(!subscribed(&hook, WebhookEvent::ClOpened, "/project"));
+        // issues aren't under any path
+        let issues = webhook::Model::new(
+            "/project",
+            "https://ci.example.com/hook",
+            "secret",
+            join_events(&[WebhookEvent::IssueOpened]),
+            "alice",
+        );
+        assert!(subscribed(&issues, WebhookEvent::IssueOpened, "/"));
+
+        webhook_stg
+            .emit(
+                WebhookEvent::ClMerged,
+                "/project/mega",
+                serde_json::json!({ "link": "CL1" }),
+            )
+            .await;
+        webhook_stg
+            .emit(WebhookEvent::ClMerged, "/doc", serde_json::json!({}))
+            .await;
+        let deliveries = webhook_stg.list_deliveries(hook.id, 10).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&deliveries[0].payload).unwrap();
+        assert_eq!(payload["event"], "cl.merged");
+        assert_eq!(payload["data"]["link"], "CL1");
+
+        let now = chrono::Utc::now().naive_utc();
+        let pending = webhook_stg.unscheduled_deliveries(now, 10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        webhook_stg
+            .mark_queued(pending[0].clone(), now + chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        assert!(
+            webhook_stg
+                .unscheduled_deliveries(now, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let queued = webhook_stg
+            .get_delivery(pending[0].id)
+            .await
+            .unwrap()
+            .unwrap();
+        webhook_stg
+            .record_failure(queued, Some(500), "Internal Server Error".to_owned(), None)
+            .await
+            .unwrap();
+        let failed = webhook_stg
+            .get_delivery(pending[0].id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(failed.status, DeliveryStatusEnum::Failed);
+        assert_eq!(failed.attempts, 1);
+
+        let again = webhook_stg.redeliver(failed.id).await.unwrap();
+        assert_eq!(again.status, DeliveryStatusEnum::Pending);
+        assert_eq!(again.payload, failed.payload);
+    }
+}
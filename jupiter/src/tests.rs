@@ -1,4 +1,4 @@
-use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm::{ConnectOptions, ConnectionTrait, Database, DatabaseConnection};
 use tracing::log;
 
 use common::config::Config;
@@ -14,17 +14,21 @@ use crate::service::import_service::ImportService;
 use crate::service::issue_service::IssueService;
 use crate::service::merge_queue_service::MergeQueueService;
 use crate::service::mono_service::MonoService;
+use crate::service::notification_service::NotificationService;
 use crate::storage::base_storage::{BaseStorage, StorageConnector};
+use crate::storage::code_index_storage::CodeIndexStorage;
 use crate::storage::dynamic_sidebar_storage::DynamicSidebarStorage;
 use crate::storage::gpg_storage::GpgStorage;
 use crate::storage::merge_queue_storage::MergeQueueStorage;
 use crate::storage::note_storage::NoteStorage;
+use crate::storage::symbol_storage::SymbolStorage;
 use crate::storage::{AppService, Storage};
 use crate::storage::{
     buck_storage::BuckStorage, cl_reviewer_storage::ClReviewerStorage, cl_storage::ClStorage,
     commit_binding_storage::CommitBindingStorage, conversation_storage::ConversationStorage,
     git_db_storage::GitDbStorage, issue_storage::IssueStorage, lfs_db_storage::LfsDbStorage,
-    mono_storage::MonoStorage, user_storage::UserStorage, vault_storage::VaultStorage,
+    mono_storage::MonoStorage, notification_storage::NotificationStorage,
+    user_storage::UserStorage, vault_storage::VaultStorage, webhook_storage::WebhookStorage,
 };
 
 pub async fn test_db_connection(temp_dir: impl AsRef<Path>) -> DatabaseConnection {
@@ -67,9 +71,19 @@ pub async fn test_storage(temp_dir: impl AsRef<Path>) -> Storage {
         merge_queue_storage: MergeQueueStorage::new(base.clone()),
         buck_storage: BuckStorage { base: base.clone() },
         dynamic_sidebar_storage: DynamicSidebarStorage { base: base.clone() },
+        webhook_storage: WebhookStorage { base: base.clone() },
+        notification_storage: NotificationStorage { base: base.clone() },
+        code_index_storage: CodeIndexStorage { base: base.clone() },
+        symbol_storage: SymbolStorage { base: base.clone() },
     };
 
     apply_migrations(&connection, true).await.unwrap();
+    // SQLite never got the `username` column on `mega_cl`, see the patchset
+    // migration, so add it here for tests that create CLs.
+    connection
+        .execute_unprepared("ALTER TABLE mega_cl ADD COLUMN username TEXT NOT NULL DEFAULT ''")
+        .await
+        .unwrap();
 
     Storage {
         app_service: Arc::new(svc),
@@ -81,5 +95,6 @@ pub async fn test_storage(temp_dir: impl AsRef<Path>) -> Storage {
         git_service: GitService::mock(),
         mono_service: MonoService::mock(),
         import_service: ImportService::mock(),
+        notification_service: NotificationService::new(base),
     }
 }
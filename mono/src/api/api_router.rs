@@ -17,9 +17,10 @@ use crate::api::{
     error::ApiError,
     notes::note_router,
     router::{
-        buck_router, cl_router, commit_router, conv_router, dynamic_sidebar_router, gpg_router,
-        issue_router, label_router, merge_queue_router, preview_router, repo_router,
-        reviewer_router, tag_router, user_router,
+        archive_router, buck_router, cl_router, commit_router, conv_router, dynamic_sidebar_router,
+        gpg_router, issue_router, label_router, merge_queue_router, notification_router,
+        preview_router, protection_router, repo_router, reviewer_router, search_router,
+        symbol_router, tag_router, user_router, webhook_router,
     },
 };
 use crate::server::http_server::SYSTEM_COMMON;
@@ -29,9 +30,11 @@ pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
         .routes(routes!(life_cycle_check))
         .route("/file/blob/{object_id}", get(get_blob_file))
         .route("/file/tree", get(get_tree_file))
+        .merge(archive_router::routers())
         .merge(preview_router::routers())
         .merge(cl_router::routers())
         .merge(reviewer_router::routers())
+        .merge(protection_router::routers())
         .merge(gpg_router::routers())
         .merge(user_router::routers())
         .merge(issue_router::routers())
@@ -44,6 +47,10 @@ pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
         .merge(repo_router::routers())
         .merge(dynamic_sidebar_router::routers())
         .merge(buck_router::routers())
+        .merge(webhook_router::routers())
+        .merge(notification_router::routers())
+        .merge(search_router::routers())
+        .merge(symbol_router::routers())
 }
 
 /// Health Check
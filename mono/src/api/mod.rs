@@ -25,7 +25,8 @@ use common::errors::ProtocolError;
 use jupiter::storage::{
     Storage, cl_storage::ClStorage, conversation_storage::ConversationStorage,
     dynamic_sidebar_storage::DynamicSidebarStorage, issue_storage::IssueStorage,
-    user_storage::UserStorage,
+    notification_storage::NotificationStorage, user_storage::UserStorage,
+    webhook_storage::WebhookStorage,
 };
 use jupiter::storage::{gpg_storage::GpgStorage, note_storage::NoteStorage};
 pub mod api_common;
@@ -118,6 +119,11 @@ impl MonoApiServiceState {
         self.into()
     }
 
+    /// Whether `username` is the system admin set in `monorepo.admin`.
+    fn is_admin(&self, username: &str) -> bool {
+        self.storage.config().monorepo.admin == username
+    }
+
     fn issue_stg(&self) -> IssueStorage {
         self.storage.issue_storage()
     }
@@ -146,6 +152,14 @@ impl MonoApiServiceState {
         self.storage.dynamic_sidebar_storage()
     }
 
+    fn webhook_stg(&self) -> WebhookStorage {
+        self.storage.webhook_storage()
+    }
+
+    fn notification_stg(&self) -> NotificationStorage {
+        self.storage.notification_storage()
+    }
+
     async fn api_handler(&self, path: &Path) -> Result<Box<dyn ApiHandler>, ProtocolError> {
         // Normalize path to ensure it has a root component
         let path = if path.has_root() {
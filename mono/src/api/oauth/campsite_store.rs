@@ -3,7 +3,7 @@ use std::sync::Arc;
 use anyhow::Context;
 use async_trait::async_trait;
 use http::header::COOKIE;
-use jupiter::storage::user_storage::UserStorage;
+use jupiter::storage::{cl_storage::ClStorage, user_storage::UserStorage};
 use reqwest::Client;
 use reqwest::Url;
 use tower_sessions::{
@@ -56,6 +56,14 @@ impl CampsiteApiStore {
         }
     }
 
+    /// The path of the CL with `link`, if it exists.
+    pub async fn cl_path(&self, link: &str) -> anyhow::Result<Option<String>> {
+        let cl_storage = ClStorage {
+            base: self.user_storage.base.clone(),
+        };
+        Ok(cl_storage.get_cl(link).await?.map(|cl| cl.path))
+    }
+
     // Custom method to load user from external API
     pub async fn load_user_from_api(
         &self,
@@ -87,9 +95,11 @@ impl CampsiteApiStore {
     }
 
     pub async fn load_user_from_token(&self, token: String) -> anyhow::Result<Option<LoginUser>> {
-        if let Some(username) = self.user_storage.find_user_by_token(&token).await? {
+        if let Some(authorized) = self.user_storage.verify_token(&token).await? {
             let user = LoginUser {
-                username,
+                username: authorized.username,
+                token_scopes: Some(authorized.scopes),
+                token_path_prefix: authorized.path_prefix,
                 ..Default::default()
             };
             return Ok(Some(user));
@@ -1,8 +1,8 @@
 use anyhow::Context;
 use axum::{
     RequestPartsExt,
-    extract::{FromRef, FromRequestParts, Query, State},
-    http::{HeaderMap, StatusCode, header::SET_COOKIE},
+    extract::{FromRef, FromRequestParts, OriginalUri, Query, State},
+    http::{HeaderMap, Method, StatusCode, header::SET_COOKIE},
     response::{IntoResponse, Redirect, Response},
     routing::get,
 };
@@ -12,16 +12,17 @@ use axum_extra::{
     typed_header::TypedHeaderRejectionReason,
 };
 use chrono::{Duration, Utc};
-use http::request::Parts;
+use http::{Uri, request::Parts};
 use oauth2::{
     AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, RedirectUrl, Scope,
     TokenResponse, TokenUrl,
 };
-use std::sync::Arc;
+use std::{collections::HashMap, path::Path, sync::Arc};
 use tower_sessions::session::Id;
 use tower_sessions::{MemoryStore, Session, SessionStore};
 
 use common::config::OauthConfig;
+use common::enums::TokenScope;
 use model::{GitHubUserJson, LoginUser, OauthCallbackParams};
 use utoipa_axum::router::OpenApiRouter;
 
@@ -196,13 +197,122 @@ impl IntoResponse for AuthRedirect {
     }
 }
 
+/// Whether `path` is the route `prefix` or one below it.
+fn is_under(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The scope a personal access token needs for an API request.
+fn required_scope(method: &Method, path: &str) -> TokenScope {
+    let path = path.strip_prefix("/api/v1").unwrap_or(path);
+    let under = |prefix: &str| is_under(path, prefix);
+    if under("/lfs") || under("/info/lfs") {
+        TokenScope::Lfs
+    } else if under("/user/ssh") || under("/user/token") || under("/gpg") || under("/webhook") {
+        TokenScope::Admin
+    } else if method.is_safe() {
+        TokenScope::RepoRead
+    } else if [
+        "/cl",
+        "/conversation",
+        "/issue",
+        "/label",
+        "/merge-queue",
+        "/notifications",
+    ]
+    .into_iter()
+    .any(under)
+    {
+        TokenScope::ClWrite
+    } else {
+        TokenScope::RepoWrite
+    }
+}
+
+/// Routes that don't touch monorepo content.
+const PATH_FREE_ROUTES: [&str; 9] = [
+    "/user",
+    "/gpg",
+    "/issue",
+    "/label",
+    "/notifications",
+    "/sidebar",
+    "/lfs",
+    "/info/lfs",
+    "/status",
+];
+
+/// Routes whose handlers limit their results to the token's path themselves.
+const SELF_SCOPED_ROUTES: [&str; 4] = [
+    "/search",
+    "/symbols/definition",
+    "/symbols/references",
+    "/symbols/index",
+];
+
+/// The link of the CL a route works on, if any.
+fn cl_link(path: &str) -> Option<&str> {
+    let rest = path
+        .strip_prefix("/cl/")
+        .or_else(|| path.strip_prefix("/buck/session/"))
+        .or_else(|| {
+            ["remove", "status", "retry"]
+                .into_iter()
+                .find_map(|op| path.strip_prefix(&format!("/merge-queue/{op}/")))
+        })?;
+    rest.split('/').next().filter(|link| !link.is_empty())
+}
+
+/// Rejects requests outside the part of the monorepo a token is limited to.
+///
+/// The target is the CL's path for CL routes and the `path` or `root` query
+/// parameter otherwise. Requests without a target are refused.
+async fn check_token_path(
+    store: &CampsiteApiStore,
+    uri: &Uri,
+    prefix: &str,
+) -> Result<(), Response> {
+    let path = uri.path();
+    let path = path.strip_prefix("/api/v1").unwrap_or(path);
+    if PATH_FREE_ROUTES
+        .into_iter()
+        .chain(SELF_SCOPED_ROUTES)
+        .any(|route| is_under(path, route))
+    {
+        return Ok(());
+    }
+    let target = match cl_link(path) {
+        Some(link) => store.cl_path(link).await.map_err(|e| {
+            tracing::error!("Error loading CL {link}: {e:?}");
+            ApiError::internal(anyhow::anyhow!("Internal server error")).into_response()
+        })?,
+        None => Query::<HashMap<String, String>>::try_from_uri(uri)
+            .ok()
+            .and_then(|Query(mut query)| query.remove("path").or_else(|| query.remove("root"))),
+    };
+    match target {
+        Some(target) if Path::new(&target).starts_with(prefix) => Ok(()),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            format!("Token is limited to {prefix}"),
+        )
+            .into_response()),
+        None => Err((
+            StatusCode::FORBIDDEN,
+            format!("Token is limited to {prefix} and can't be used for this request"),
+        )
+            .into_response()),
+    }
+}
+
 impl<S> FromRequestParts<S> for LoginUser
 where
     CampsiteApiStore: FromRef<S>,
     S: Send + Sync,
 {
     // If anything goes wrong or no session is found, redirect to the auth page
-    type Rejection = AuthRedirect;
+    type Rejection = Response;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let store = CampsiteApiStore::from_ref(state);
@@ -213,14 +323,32 @@ where
         {
             let token = bearer.token().to_string();
             match store.load_user_from_token(token).await {
-                Ok(Some(user)) => return Ok(user),
+                Ok(Some(user)) => {
+                    let uri = match parts.extensions.get::<OriginalUri>() {
+                        Some(OriginalUri(uri)) => uri.clone(),
+                        None => parts.uri.clone(),
+                    };
+                    let scope = required_scope(&parts.method, uri.path());
+                    let granted = user.token_scopes.as_deref().unwrap_or_default();
+                    if !scope.granted_by(granted) {
+                        return Err((
+                            StatusCode::FORBIDDEN,
+                            format!("Token is missing the {} scope", scope.as_str()),
+                        )
+                            .into_response());
+                    }
+                    if let Some(prefix) = user.token_path_prefix.as_deref() {
+                        check_token_path(&store, &uri, prefix).await?;
+                    }
+                    return Ok(user);
+                }
                 Ok(None) => {
                     tracing::error!("Invalid or expired bearer token");
-                    return Err(AuthRedirect);
+                    return Err(AuthRedirect.into_response());
                 }
                 Err(e) => {
                     tracing::error!("Error validating bearer token: {:?}", e);
-                    return Err(AuthRedirect);
+                    return Err(AuthRedirect.into_response());
                 }
             }
         }
@@ -235,21 +363,78 @@ where
                     _ => panic!("unexpected error getting Cookie header(s): {e}"),
                 },
                 _ => panic!("unexpected error getting cookies: {e}"),
-            })?;
+            })
+            .map_err(IntoResponse::into_response)?;
 
-        let session_cookie = cookies.get(CAMPSITE_API_COOKIE).ok_or(AuthRedirect)?;
+        let session_cookie = cookies
+            .get(CAMPSITE_API_COOKIE)
+            .ok_or(AuthRedirect.into_response())?;
 
         // Load user from external API
         match store.load_user_from_api(session_cookie.to_string()).await {
             Ok(Some(user)) => Ok(user),
             Ok(None) => {
                 tracing::error!("Invalid or expired session cookie");
-                Err(AuthRedirect)
+                Err(AuthRedirect.into_response())
             }
             Err(e) => {
                 tracing::error!("Error loading user from cookie session: {:?}", e);
-                Err(AuthRedirect)
+                Err(AuthRedirect.into_response())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(
+            required_scope(&Method::GET, "/api/v1/cl/ABC/detail"),
+            TokenScope::RepoRead
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v1/cl/ABC/merge"),
+            TokenScope::ClWrite
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v1/create-entry"),
+            TokenScope::RepoWrite
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/api/v1/user/token/list"),
+            TokenScope::Admin
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/api/v1/webhook/list"),
+            TokenScope::Admin
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v1/notifications/mark"),
+            TokenScope::ClWrite
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/api/v1/user"),
+            TokenScope::RepoRead
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v1/lfs/locks"),
+            TokenScope::Lfs
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v1/clone"),
+            TokenScope::RepoWrite
+        );
+    }
+
+    #[test]
+    fn test_cl_link() {
+        assert_eq!(cl_link("/cl/ABC/detail"), Some("ABC"));
+        assert_eq!(cl_link("/buck/session/ABC/manifest"), Some("ABC"));
+        assert_eq!(cl_link("/merge-queue/status/ABC"), Some("ABC"));
+        assert_eq!(cl_link("/merge-queue/list"), None);
+        assert_eq!(cl_link("/tree"), None);
+    }
+}
@@ -1,3 +1,4 @@
+use common::enums::TokenScope;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -29,6 +30,8 @@ impl From<GitHubUserJson> for LoginUser {
             email: value.email.unwrap_or_default(),
             avatar_url: value.avatar_url,
             campsite_user_id: String::new(),
+            token_scopes: None,
+            token_path_prefix: None,
         }
     }
 }
@@ -48,6 +51,8 @@ impl From<CampsiteUserJson> for LoginUser {
             email: value.email.unwrap_or_default(),
             avatar_url: value.avatar_url,
             campsite_user_id: value.id,
+            token_scopes: None,
+            token_path_prefix: None,
         }
     }
 }
@@ -58,4 +63,19 @@ pub struct LoginUser {
     pub username: String,
     pub avatar_url: String,
     pub email: String,
+    /// Set when the user authenticated with a personal access token.
+    #[serde(skip)]
+    pub token_scopes: Option<Vec<TokenScope>>,
+    /// The part of the monorepo that token is limited to, if any.
+    #[serde(skip)]
+    pub token_path_prefix: Option<String>,
+}
+
+impl LoginUser {
+    /// Whether the user's token, if any, reaches `path`.
+    pub fn covers_path(&self, path: &str) -> bool {
+        self.token_path_prefix
+            .as_deref()
+            .is_none_or(|prefix| std::path::Path::new(path).starts_with(prefix))
+    }
 }
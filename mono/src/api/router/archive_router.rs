@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    response::Response,
+    routing::get,
+};
+use http::{HeaderMap, StatusCode, header};
+use serde::Deserialize;
+use utoipa_axum::router::OpenApiRouter;
+
+use ceres::api_service::archive_ops::{self, ArchiveFormat};
+
+use crate::api::MonoApiServiceState;
+use crate::api::error::ApiError;
+
+#[derive(Debug, Default, Deserialize)]
+struct ArchiveQuery {
+    /// Replace LFS pointers with the files they point to.
+    #[serde(default)]
+    lfs: bool,
+}
+
+pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
+    OpenApiRouter::new()
+        .route("/archive/{name}", get(root_archive))
+        .route("/archive/{commit}/{*path}", get(tree_archive))
+}
+
+/// Archive of the whole monorepo, e.g. `/archive/{commit}.tar.gz`.
+async fn root_archive(
+    state: State<MonoApiServiceState>,
+    Path(name): Path<String>,
+    Query(query): Query<ArchiveQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let (commit, format) = ArchiveFormat::from_name(&name)
+        .ok_or_else(|| ApiError::not_found(anyhow::anyhow!("Unknown archive format")))?;
+    archive(state, commit, "/", format, query, headers).await
+}
+
+/// Archive of a directory, e.g. `/archive/{commit}/project/src.zip`.
+async fn tree_archive(
+    state: State<MonoApiServiceState>,
+    Path((commit, path)): Path<(String, String)>,
+    Query(query): Query<ArchiveQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let (path, format) = ArchiveFormat::from_name(&path)
+        .ok_or_else(|| ApiError::not_found(anyhow::anyhow!("Unknown archive format")))?;
+    archive(state, &commit, path, format, query, headers).await
+}
+
+async fn archive(
+    state: State<MonoApiServiceState>,
+    commit: &str,
+    path: &str,
+    format: ArchiveFormat,
+    query: ArchiveQuery,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let path = PathBuf::from("/").join(path.trim_start_matches('/'));
+    let handler = state.api_handler(&path).await?;
+    let source = archive_ops::find_archive_source(handler.as_ref(), commit, &path)
+        .await?
+        .ok_or_else(|| {
+            ApiError::not_found(anyhow::anyhow!("{} not found at {commit}", path.display()))
+        })?;
+
+    // the same tree always makes the same files, though the timestamps and
+    // top directory follow the commit, hence a weak tag
+    let lfs = if query.lfs { "-lfs" } else { "" };
+    let etag = format!("W/\"{}{lfs}.{}\"", source.tree.id, format.extension());
+    let fresh = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    if fresh {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag)
+            .body(Body::empty())
+            .map_err(ApiError::internal);
+    }
+
+    let file_name = content_disposition(&format!("{}.{}", source.prefix, format.extension()));
+    let stream = archive_ops::archive_stream(handler, source, format, query.lfs);
+    Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(header::CONTENT_DISPOSITION, file_name)
+        .header(header::ETAG, etag)
+        .body(Body::from_stream(stream))
+        .map_err(ApiError::internal)
+}
+
+/// An attachment header for `name`, which comes from the repository path
+/// and so may hold quotes or control characters.
+fn content_disposition(name: &str) -> String {
+    let mut quoted = String::with_capacity(name.len());
+    for c in name.chars().filter(|c| !c.is_control()) {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    format!("attachment; filename=\"{quoted}\"")
+}
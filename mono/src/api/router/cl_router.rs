@@ -2,23 +2,30 @@ use std::path::PathBuf;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
 };
-use callisto::sea_orm_active_enums::{ConvTypeEnum, MergeStatusEnum};
+use axum_extra::{
+    TypedHeader,
+    headers::{Authorization, authorization::Bearer},
+};
+use callisto::sea_orm_active_enums::{ConvTypeEnum, DiffSideEnum, MergeStatusEnum};
+use ceres::merge_checker::{CheckerRegistry, external_checker::ExternalChecks};
 use ceres::model::change_list::{
-    CLDetailRes, ClFilesRes, Condition, FilesChangedPage, MergeBoxRes, MuiTreeNode,
+    CLDetailRes, ClFilesQuery, ClFilesRes, Condition, ExternalCheckPayload, FilesChangedPage,
+    InterdiffPayload, MergeBoxRes, MuiTreeNode, PatchsetItem, UpdateBaseClPayload,
     UpdateClStatusPayload,
 };
 use common::{
     errors::MegaError,
-    model::{CommonPage, CommonResult, PageParams},
+    model::{CommonPage, CommonResult, DiffItem, PageParams},
 };
+use http::StatusCode;
 use jupiter::service::cl_service::CLService;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use ceres::model::{
     change_list::{AssigneeUpdatePayload, ListPayload},
-    conversation::ContentPayload,
+    conversation::{ContentPayload, InlineCommentPayload, InlineThread},
     issue::ItemRes,
     label::LabelUpdatePayload,
 };
@@ -40,15 +47,22 @@ pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
             .routes(routes!(merge))
             .routes(routes!(merge_box))
             .routes(routes!(merge_no_auth))
+            .routes(routes!(recheck))
+            .routes(routes!(report_external_check))
             .routes(routes!(close_cl))
             .routes(routes!(reopen_cl))
             .routes(routes!(cl_mui_tree))
             .routes(routes!(cl_files_changed_by_page))
             .routes(routes!(cl_files_list))
+            .routes(routes!(cl_patchsets))
+            .routes(routes!(cl_interdiff))
             .routes(routes!(save_comment))
+            .routes(routes!(save_inline_comment))
+            .routes(routes!(reply_inline_comment))
             .routes(routes!(labels))
             .routes(routes!(assignees))
             .routes(routes!(edit_title))
+            .routes(routes!(update_base_cl))
             .routes(routes!(update_cl_status)),
     )
 }
@@ -194,6 +208,103 @@ async fn merge_no_auth(
     ))))
 }
 
+/// Re-run merge checks of a Change List
+///
+/// Called by orion-server when a build finishes, authenticated with the
+/// token in its `MONO_API_TOKEN`.
+#[utoipa::path(
+    post,
+    params(
+        ("link", description = "CL link"),
+    ),
+    path = "/{link}/recheck",
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn recheck(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let model = state
+        .cl_stg()
+        .get_cl(&link)
+        .await?
+        .ok_or(MegaError::Other("CL Not Found".to_string()))?;
+
+    if model.status != MergeStatusEnum::Open {
+        return Ok(Json(CommonResult::success(None)));
+    }
+
+    let check_reg = CheckerRegistry::new(state.storage.clone().into(), user.username);
+    check_reg.run_checks(model.into()).await?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+/// Report the status of an external check
+///
+/// Called by the service behind a check declared in `external_checks`, which
+/// must send the check's token as a bearer token.
+#[utoipa::path(
+    post,
+    params(
+        ("link", description = "CL link"),
+        ("name", description = "Name of the external check"),
+    ),
+    path = "/{link}/checks/{name}",
+    request_body = ExternalCheckPayload,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn report_external_check(
+    Path((link, name)): Path<(String, String)>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<ExternalCheckPayload>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let checks = ExternalChecks::new(state.storage.clone().into());
+    let check = checks
+        .find(&name)
+        .ok_or_else(|| ApiError::not_found(anyhow::anyhow!("Unknown check: {name}")))?;
+    if !ExternalChecks::verify_token(&check, auth.as_ref().map(|TypedHeader(a)| a.token())) {
+        return Err(ApiError::with_status(
+            StatusCode::UNAUTHORIZED,
+            anyhow::anyhow!("Invalid token for check {name}"),
+        ));
+    }
+
+    let model = state
+        .cl_stg()
+        .get_cl(&link)
+        .await?
+        .ok_or_else(|| ApiError::not_found(anyhow::anyhow!("CL Not Found")))?;
+    if model.status != MergeStatusEnum::Open {
+        return Err(ApiError::bad_request(anyhow::anyhow!(
+            "CL is not in Open status, current status: {:?}",
+            model.status
+        )));
+    }
+    if model.to_hash != payload.commit_id {
+        return Err(ApiError::with_status(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!(
+                "Outdated result for {}, the CL is now at {}",
+                payload.commit_id,
+                model.to_hash
+            ),
+        ));
+    }
+
+    checks
+        .report(&check, &model.into(), payload.status, &payload.message)
+        .await?;
+    Ok(Json(CommonResult::success(None)))
+}
+
 /// Fetch CL list
 #[utoipa::path(
     post,
@@ -289,17 +400,32 @@ async fn cl_files_changed_by_page(
         .monorepo()
         .paged_content_diff(&link, json.pagination)
         .await?;
+    let comments = state.conv_stg().get_inline_comments(&link).await?;
+    // this endpoint doesn't require login, so there is no viewer reaction
+    let comments = InlineThread::group(comments, "")
+        .into_iter()
+        .filter(|thread| {
+            items
+                .iter()
+                .any(|item| item.path == thread.anchor.file_path)
+        })
+        .collect();
     let res = CommonResult::success(Some(FilesChangedPage {
         page: CommonPage { total, items },
+        comments,
     }));
     Ok(Json(res))
 }
 
 /// Get Change List file list
+///
+/// With `commit`, lists the files that commit changes instead, which is how
+/// build workers mount a merge queue speculative commit of the CL.
 #[utoipa::path(
     get,
     params(
         ("link", description = "CL link"),
+        ClFilesQuery,
     ),
     path = "/{link}/files-list",
     responses(
@@ -309,6 +435,7 @@ async fn cl_files_changed_by_page(
 )]
 async fn cl_files_list(
     Path(link): Path<String>,
+    Query(query): Query<ClFilesQuery>,
     state: State<MonoApiServiceState>,
 ) -> Result<Json<CommonResult<Vec<ClFilesRes>>>, ApiError> {
     let cl = state
@@ -318,8 +445,19 @@ async fn cl_files_list(
         .ok_or(MegaError::Other("CL Not Found".to_string()))?;
 
     let stg = state.monorepo();
-    let old_files = stg.get_commit_blobs(&cl.from_hash).await?;
-    let new_files = stg.get_commit_blobs(&cl.to_hash).await?;
+    let (old_hash, new_hash) = match query.commit {
+        Some(commit) => {
+            if !stg.is_cl_commit(&cl, &commit).await? {
+                return Err(ApiError::not_found(anyhow::anyhow!(
+                    "Commit {commit} is not a revision of CL {link}"
+                )));
+            }
+            (stg.first_parent(&commit).await?, commit)
+        }
+        None => (stg.cl_diff_base(&cl).await?, cl.to_hash.clone()),
+    };
+    let old_files = stg.get_commit_blobs(&old_hash).await?;
+    let new_files = stg.get_commit_blobs(&new_hash).await?;
     let cl_diff_files = stg.cl_files_list(old_files, new_files.clone()).await?; // TODO
 
     let cl_base = PathBuf::from(cl.path);
@@ -334,6 +472,65 @@ async fn cl_files_list(
     Ok(Json(CommonResult::success(Some(res))))
 }
 
+/// List every revision pushed to the Change List
+#[utoipa::path(
+    get,
+    params(
+        ("link", description = "CL link"),
+    ),
+    path = "/{link}/patchsets",
+    responses(
+        (status = 200, body = CommonResult<Vec<PatchsetItem>>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn cl_patchsets(
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<PatchsetItem>>>, ApiError> {
+    let patchsets = state
+        .cl_stg()
+        .list_patchsets(&link)
+        .await?
+        .into_iter()
+        .map(PatchsetItem::from)
+        .collect();
+    Ok(Json(CommonResult::success(Some(patchsets))))
+}
+
+/// Diff two patchsets of the Change List in Pagination
+#[utoipa::path(
+    post,
+    params(
+        ("link", description = "CL link"),
+    ),
+    path = "/{link}/interdiff",
+    request_body = PageParams<InterdiffPayload>,
+    responses(
+        (status = 200, body = CommonResult<CommonPage<DiffItem>>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn cl_interdiff(
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<PageParams<InterdiffPayload>>,
+) -> Result<Json<CommonResult<CommonPage<DiffItem>>>, ApiError> {
+    let (items, total) = state
+        .monorepo()
+        .paged_interdiff(
+            &link,
+            json.additional.old,
+            json.additional.new,
+            json.pagination,
+        )
+        .await?;
+    Ok(Json(CommonResult::success(Some(CommonPage {
+        total,
+        items,
+    }))))
+}
+
 /// Get Merge Box to check merge status
 #[utoipa::path(
     get,
@@ -393,30 +590,165 @@ async fn save_comment(
     state: State<MonoApiServiceState>,
     Json(payload): Json<ContentPayload>,
 ) -> Result<Json<CommonResult<()>>, ApiError> {
-    let conv_type = if state
-        .storage
-        .reviewer_storage()
-        .is_reviewer(&link, &user.username)
+    let conv_type = comment_type(&state, &link, &user.username).await?;
+    state
+        .conv_stg()
+        .add_conversation(
+            &link,
+            &user.username,
+            Some(payload.content.clone()),
+            conv_type,
+        )
+        .await?;
+    api_common::comment::check_comment_ref(user, state, &payload.content, &link).await
+}
+
+/// Add a comment on lines of a file in the Change List diff
+#[utoipa::path(
+    post,
+    params(
+        ("link", description = "CL link"),
+    ),
+    path = "/{link}/inline-comment",
+    request_body = InlineCommentPayload,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn save_inline_comment(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<InlineCommentPayload>,
+) -> Result<Json<CommonResult<()>>, ApiError> {
+    let cl = state
+        .cl_stg()
+        .get_cl(&link)
         .await?
-    {
-        // If user is the reviewer for this cl, then the comment if of type review
-        ConvTypeEnum::Review
-    } else {
-        ConvTypeEnum::Comment
+        .ok_or(MegaError::Other("CL Not Found".to_string()))?;
+    let anchor = payload.anchor();
+    let current = match anchor.side {
+        DiffSideEnum::Old => state.monorepo().cl_diff_base(&cl).await?,
+        DiffSideEnum::New => cl.to_hash.clone(),
     };
+    if anchor.commit_id != current {
+        return Err(ApiError::with_status(
+            StatusCode::CONFLICT,
+            MegaError::Other("The CL has been updated, reload the diff to comment".to_string()),
+        ));
+    }
+    if anchor.start_line < 1 || anchor.end_line < anchor.start_line {
+        return Err(ApiError::bad_request(MegaError::Other(
+            "Invalid line range".to_string(),
+        )));
+    }
 
+    let conv_type = comment_type(&state, &link, &user.username).await?;
     state
         .conv_stg()
-        .add_conversation(
+        .add_inline_comment(
             &link,
             &user.username,
-            Some(payload.content.clone()),
+            payload.content.clone(),
             conv_type,
+            anchor,
         )
         .await?;
     api_common::comment::check_comment_ref(user, state, &payload.content, &link).await
 }
 
+/// Reply to an inline comment on Change List
+#[utoipa::path(
+    post,
+    params(
+        ("link", description = "CL link"),
+        ("comment_id", description = "ID of the inline comment that started the thread"),
+    ),
+    path = "/{link}/inline-comment/{comment_id}/reply",
+    request_body = ContentPayload,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn reply_inline_comment(
+    user: LoginUser,
+    Path((link, comment_id)): Path<(String, i64)>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<ContentPayload>,
+) -> Result<Json<CommonResult<()>>, ApiError> {
+    let conv_type = comment_type(&state, &link, &user.username).await?;
+    state
+        .conv_stg()
+        .add_reply(
+            &link,
+            comment_id,
+            &user.username,
+            payload.content.clone(),
+            conv_type,
+        )
+        .await?;
+    api_common::comment::check_comment_ref(user, state, &payload.content, &link).await
+}
+
+/// Comments by reviewers of the CL are reviews.
+async fn comment_type(
+    state: &MonoApiServiceState,
+    link: &str,
+    username: &str,
+) -> Result<ConvTypeEnum, MegaError> {
+    if state
+        .storage
+        .reviewer_storage()
+        .is_reviewer(link, username)
+        .await?
+    {
+        Ok(ConvTypeEnum::Review)
+    } else {
+        Ok(ConvTypeEnum::Comment)
+    }
+}
+
+/// Stack the Change List on another CL, or unstack it
+///
+/// Only the CL author or the monorepo admin can change it.
+#[utoipa::path(
+    post,
+    params(
+        ("link", description = "CL link"),
+    ),
+    path = "/{link}/base",
+    request_body = UpdateBaseClPayload,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn update_base_cl(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<UpdateBaseClPayload>,
+) -> Result<Json<CommonResult<()>>, ApiError> {
+    let cl = state
+        .cl_stg()
+        .get_cl(&link)
+        .await?
+        .ok_or_else(|| ApiError::not_found(anyhow::anyhow!("CL Not Found")))?;
+    if cl.username != user.username && !state.is_admin(&user.username) {
+        return Err(ApiError::with_status(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("Only the CL author or a maintainer can change its base"),
+        ));
+    }
+    state
+        .monorepo()
+        .set_base_cl(&link, payload.base_cl.as_deref())
+        .await?;
+    Ok(Json(CommonResult::success(None)))
+}
+
 /// Edit CL title
 #[utoipa::path(
     post,
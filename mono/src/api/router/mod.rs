@@ -1,3 +1,4 @@
+pub mod archive_router;
 pub mod buck_router;
 pub mod cl_router;
 pub mod commit_router;
@@ -8,8 +9,13 @@ pub mod issue_router;
 pub mod label_router;
 pub mod lfs_router;
 pub mod merge_queue_router;
+pub mod notification_router;
 pub mod preview_router;
+pub mod protection_router;
 pub mod repo_router;
 pub mod reviewer_router;
+pub mod search_router;
+pub mod symbol_router;
 pub mod tag_router;
 pub mod user_router;
+pub mod webhook_router;
@@ -0,0 +1,194 @@
+use std::convert::Infallible;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use ceres::model::notification::{
+    MarkNotifications, NotificationInfo, NotificationList, NotificationQuery, Subscription,
+};
+use common::model::{CommonResult, Pagination};
+
+use crate::api::MonoApiServiceState;
+use crate::api::{error::ApiError, oauth::model::LoginUser};
+use crate::server::http_server::NOTIFICATION_TAG;
+
+pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
+    OpenApiRouter::new().nest(
+        "/notifications",
+        OpenApiRouter::new()
+            .routes(routes!(list_notifications))
+            .routes(routes!(mark_notifications))
+            .routes(routes!(notification_stream))
+            .routes(routes!(list_subscriptions))
+            .routes(routes!(subscribe))
+            .routes(routes!(unsubscribe)),
+    )
+}
+
+/// List the notifications of the current user, newest first
+#[utoipa::path(
+    get,
+    path = "/list",
+    params(NotificationQuery),
+    responses(
+        (status = 200, body = CommonResult<NotificationList>, content_type = "application/json")
+    ),
+    tag = NOTIFICATION_TAG
+)]
+async fn list_notifications(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Query(query): Query<NotificationQuery>,
+) -> Result<Json<CommonResult<NotificationList>>, ApiError> {
+    let default = Pagination::default();
+    let page = Pagination {
+        page: query.page.unwrap_or(default.page),
+        per_page: query.per_page.unwrap_or(default.per_page),
+    };
+    let stg = state.notification_stg();
+    let (items, total) = stg
+        .list_notifications(&user.username, query.state.map(|s| s.into()), page)
+        .await?;
+    let unread = stg.unread_count(&user.username).await?;
+    Ok(Json(CommonResult::success(Some(NotificationList {
+        total,
+        unread,
+        items: items.into_iter().map(|x| x.into()).collect(),
+    }))))
+}
+
+/// Mark notifications of the current user as read, unread or done
+#[utoipa::path(
+    post,
+    path = "/mark",
+    request_body = MarkNotifications,
+    responses(
+        (status = 200, body = CommonResult<u64>, content_type = "application/json")
+    ),
+    tag = NOTIFICATION_TAG
+)]
+async fn mark_notifications(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<MarkNotifications>,
+) -> Result<Json<CommonResult<u64>>, ApiError> {
+    let res = state
+        .notification_stg()
+        .set_state(&user.username, &payload.ids, payload.state.into())
+        .await?;
+    Ok(Json(CommonResult::success(Some(res))))
+}
+
+/// Stream new notifications of the current user as server-sent events
+#[utoipa::path(
+    get,
+    path = "/stream",
+    responses(
+        (status = 200, description = "`notification` events carrying a NotificationInfo", content_type = "text/event-stream")
+    ),
+    tag = NOTIFICATION_TAG
+)]
+async fn notification_stream(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut notifications = state.storage.notification_service.subscribe();
+    let stream = async_stream::stream! {
+        loop {
+            let model = match notifications.recv().await {
+                Ok(model) => model,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(
+                        "Notification stream of {} skipped {skipped} notifications",
+                        user.username
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if model.username != user.username {
+                continue;
+            }
+            match Event::default()
+                .event("notification")
+                .json_data(NotificationInfo::from(model))
+            {
+                Ok(event) => yield Ok(event),
+                Err(e) => tracing::warn!("Failed to encode notification: {e}"),
+            }
+        }
+    };
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// List the paths, issues and CLs the current user follows
+#[utoipa::path(
+    get,
+    path = "/subscriptions",
+    responses(
+        (status = 200, body = CommonResult<Vec<Subscription>>, content_type = "application/json")
+    ),
+    tag = NOTIFICATION_TAG
+)]
+async fn list_subscriptions(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<Subscription>>>, ApiError> {
+    let res = state
+        .notification_stg()
+        .list_subscriptions(&user.username)
+        .await?;
+    Ok(Json(CommonResult::success(Some(
+        res.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+/// Follow a path, issue or CL
+#[utoipa::path(
+    post,
+    path = "/subscribe",
+    request_body = Subscription,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = NOTIFICATION_TAG
+)]
+async fn subscribe(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<Subscription>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    state
+        .notification_stg()
+        .subscribe(&user.username, payload.target_type.into(), &payload.target)
+        .await?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+/// Stop following a path, issue or CL
+#[utoipa::path(
+    post,
+    path = "/unsubscribe",
+    request_body = Subscription,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = NOTIFICATION_TAG
+)]
+async fn unsubscribe(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<Subscription>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    state
+        .notification_stg()
+        .unsubscribe(&user.username, payload.target_type.into(), &payload.target)
+        .await?;
+    Ok(Json(CommonResult::success(None)))
+}
@@ -0,0 +1,116 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use ceres::model::branch_protection::{BranchProtectionQuery, BranchProtectionRule};
+use common::model::CommonResult;
+
+use crate::api::MonoApiServiceState;
+use crate::api::{error::ApiError, oauth::model::LoginUser};
+use crate::server::http_server::PROTECTION_TAG;
+
+pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
+    OpenApiRouter::new().nest(
+        "/protection",
+        OpenApiRouter::new()
+            .routes(routes!(list_rules))
+            .routes(routes!(save_rule, delete_rule)),
+    )
+}
+
+/// List all branch protection rules
+#[utoipa::path(
+    get,
+    path = "/list",
+    responses(
+        (status = 200, body = CommonResult<Vec<BranchProtectionRule>>, content_type = "application/json")
+    ),
+    tag = PROTECTION_TAG
+)]
+async fn list_rules(
+    _: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<BranchProtectionRule>>>, ApiError> {
+    let rules = state.cl_stg().list_branch_protection_rules().await?;
+    Ok(Json(CommonResult::success(Some(
+        rules.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+/// Create the rule of a path, or replace it
+///
+/// Only the system admin may change branch protection.
+#[utoipa::path(
+    post,
+    path = "/rule",
+    request_body = BranchProtectionRule,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = PROTECTION_TAG
+)]
+async fn save_rule(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<BranchProtectionRule>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    require_admin(&state, &user)?;
+    if !payload.path.starts_with('/') {
+        return Err(ApiError::bad_request(anyhow::anyhow!(
+            "Path must be absolute: {}",
+            payload.path
+        )));
+    }
+    if payload.min_approvals < 0 {
+        return Err(ApiError::bad_request(anyhow::anyhow!(
+            "min_approvals can't be negative"
+        )));
+    }
+    state
+        .cl_stg()
+        .save_branch_protection_rule(payload.into())
+        .await?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+/// Delete the rule of a path
+#[utoipa::path(
+    delete,
+    path = "/rule",
+    params(BranchProtectionQuery),
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = PROTECTION_TAG
+)]
+async fn delete_rule(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Query(query): Query<BranchProtectionQuery>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    require_admin(&state, &user)?;
+    if !state
+        .cl_stg()
+        .delete_branch_protection_rule(&query.path)
+        .await?
+    {
+        return Err(ApiError::not_found(anyhow::anyhow!(
+            "No branch protection rule for {}",
+            query.path
+        )));
+    }
+    Ok(Json(CommonResult::success(None)))
+}
+
+fn require_admin(state: &MonoApiServiceState, user: &LoginUser) -> Result<(), ApiError> {
+    if !state.is_admin(&user.username) {
+        return Err(ApiError::with_status(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("Only the system admin can change branch protection"),
+        ));
+    }
+    Ok(())
+}
@@ -4,8 +4,8 @@ use axum::{
 };
 use callisto::sea_orm_active_enums::{ConvTypeEnum, MergeStatusEnum};
 use ceres::model::change_list::{
-    ChangeReviewStatePayload, ChangeReviewerStatePayload, ReviewerInfo, ReviewerPayload,
-    ReviewersResponse,
+    ChangeReviewStatePayload, ChangeReviewerStatePayload, ReviewState, ReviewerInfo,
+    ReviewerPayload, ReviewersResponse, SubmitReviewPayload,
 };
 use common::{errors::MegaError, model::CommonResult};
 use utoipa_axum::{router::OpenApiRouter, routes};
@@ -25,6 +25,7 @@ pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
             .routes(routes!(remove_reviewers))
             .routes(routes!(list_reviewers))
             .routes(routes!(reviewer_approve))
+            .routes(routes!(submit_review))
             .routes(routes!(review_resolve)),
     )
 }
@@ -151,6 +152,8 @@ async fn list_reviewers(
             username: r.username,
             approved: r.approved,
             system_required: r.system_required,
+            approved_patchset: r.approved_patchset,
+            review_state: r.review_state.into(),
         })
         .collect();
 
@@ -206,6 +209,61 @@ async fn reviewer_approve(
     Ok(Json(CommonResult::success(None)))
 }
 
+/// Approve, request changes or comment on a CL as one of its reviewers
+///
+/// Requested changes block the merge until the same reviewer submits
+/// another review.
+#[utoipa::path(
+    post,
+    params (
+        ("link", description = "the cl link")
+    ),
+    path = "/{link}/reviewer/review",
+    request_body = SubmitReviewPayload,
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = CL_TAG
+)]
+async fn submit_review(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<SubmitReviewPayload>,
+) -> Result<Json<CommonResult<()>>, ApiError> {
+    let res = state.cl_stg().get_cl(&link).await?;
+    let model = res.ok_or(MegaError::Other("CL Not Found".to_string()))?;
+
+    if model.status == MergeStatusEnum::Draft {
+        return Err(ApiError::from(MegaError::Other(
+            ERR_CL_NOT_READY_FOR_REVIEW.to_owned(),
+        )));
+    }
+
+    state
+        .storage
+        .reviewer_storage()
+        .set_review_state(&link, &user.username, payload.state.into())
+        .await?;
+
+    let (verdict, conv_type) = match payload.state {
+        ReviewState::Approved => ("approved the CL", ConvTypeEnum::Approve),
+        ReviewState::ChangesRequested => ("requested changes", ConvTypeEnum::Review),
+        ReviewState::Commented => ("reviewed the CL", ConvTypeEnum::Review),
+        ReviewState::Pending => ("withdrew their review", ConvTypeEnum::Review),
+    };
+    let comment = match payload.comment.filter(|c| !c.trim().is_empty()) {
+        Some(comment) => format!("{} {verdict}: {comment}", user.username),
+        None => format!("{} {verdict}", user.username),
+    };
+    state
+        .conv_stg()
+        .add_conversation(&link, &user.username, Some(comment), conv_type)
+        .await?;
+
+    Ok(Json(CommonResult::success(None)))
+}
+
 #[utoipa::path(
     post,
     params (
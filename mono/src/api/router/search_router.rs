@@ -0,0 +1,42 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use ceres::api_service::search_ops::search_code;
+use ceres::model::search::{CodeSearchQuery, CodeSearchResult};
+use common::model::CommonResult;
+
+use crate::api::MonoApiServiceState;
+use crate::api::{error::ApiError, oauth::model::LoginUser};
+use crate::server::http_server::SEARCH_TAG;
+
+pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
+    OpenApiRouter::new().nest(
+        "/search",
+        OpenApiRouter::new().routes(routes!(search_code_handler)),
+    )
+}
+
+/// Search the contents of trunk
+///
+/// Matches are reported per file, ordered by path, with the matching lines.
+/// A token limited to a path only finds files under it.
+#[utoipa::path(
+    get,
+    path = "/code",
+    params(CodeSearchQuery),
+    responses(
+        (status = 200, body = CommonResult<CodeSearchResult>, content_type = "application/json")
+    ),
+    tag = SEARCH_TAG
+)]
+async fn search_code_handler(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Query(query): Query<CodeSearchQuery>,
+) -> Result<Json<CommonResult<CodeSearchResult>>, ApiError> {
+    let res = search_code(&state.storage, &query, user.token_path_prefix.as_deref()).await?;
+    Ok(Json(CommonResult::success(Some(res))))
+}
@@ -0,0 +1,148 @@
+use axum::{
+    Json,
+    body::{Body, to_bytes},
+    extract::{Query, Request, State},
+};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use ceres::api_service::symbol_ops;
+use ceres::model::symbol::{
+    IndexSymbolsPayload, SymbolIndexResult, SymbolLocation, SymbolQuery, SymbolUploadQuery,
+};
+use common::model::CommonResult;
+
+use crate::api::MonoApiServiceState;
+use crate::api::{error::ApiError, oauth::model::LoginUser};
+use crate::server::http_server::SYMBOL_TAG;
+
+/// Largest SCIP or LSIF index accepted by an upload.
+const MAX_INDEX_SIZE: usize = 256 * 1024 * 1024;
+
+pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
+    OpenApiRouter::new().nest(
+        "/symbols",
+        OpenApiRouter::new()
+            .routes(routes!(definition))
+            .routes(routes!(references))
+            .routes(routes!(upload_index))
+            .routes(routes!(index_symbols)),
+    )
+}
+
+/// Go to definition
+///
+/// Resolves a name, or the identifier at a 0-based position in a file, at
+/// a commit and returns where it is defined.
+#[utoipa::path(
+    get,
+    path = "/definition",
+    params(SymbolQuery),
+    responses(
+        (status = 200, body = CommonResult<Vec<SymbolLocation>>, content_type = "application/json")
+    ),
+    tag = SYMBOL_TAG
+)]
+async fn definition(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Query(query): Query<SymbolQuery>,
+) -> Result<Json<CommonResult<Vec<SymbolLocation>>>, ApiError> {
+    let res =
+        symbol_ops::find_definitions(&state.monorepo(), &query, user.token_path_prefix.as_deref())
+            .await?;
+    Ok(Json(CommonResult::success(Some(res))))
+}
+
+/// Find references
+///
+/// Resolves a name, or the identifier at a 0-based position in a file, at
+/// a commit and returns where it is used.
+#[utoipa::path(
+    get,
+    path = "/references",
+    params(SymbolQuery),
+    responses(
+        (status = 200, body = CommonResult<Vec<SymbolLocation>>, content_type = "application/json")
+    ),
+    tag = SYMBOL_TAG
+)]
+async fn references(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Query(query): Query<SymbolQuery>,
+) -> Result<Json<CommonResult<Vec<SymbolLocation>>>, ApiError> {
+    let res =
+        symbol_ops::find_references(&state.monorepo(), &query, user.token_path_prefix.as_deref())
+            .await?;
+    Ok(Json(CommonResult::success(Some(res))))
+}
+
+/// Upload a SCIP or LSIF index
+///
+/// Stores the symbols of an index built at `commit` from the directory
+/// `root`, replacing whatever was indexed there before.
+#[utoipa::path(
+    post,
+    path = "/upload",
+    params(SymbolUploadQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = CommonResult<SymbolIndexResult>, content_type = "application/json"),
+        (status = 400, description = "The index could not be parsed"),
+        (status = 413, description = "Index too large"),
+    ),
+    tag = SYMBOL_TAG
+)]
+async fn upload_index(
+    _: LoginUser,
+    state: State<MonoApiServiceState>,
+    Query(query): Query<SymbolUploadQuery>,
+    req: Request<Body>,
+) -> Result<Json<CommonResult<SymbolIndexResult>>, ApiError> {
+    let data = to_bytes(req.into_body(), MAX_INDEX_SIZE)
+        .await
+        .map_err(|e| ApiError::with_status(StatusCode::PAYLOAD_TOO_LARGE, e))?;
+    let occurrences = symbol_ops::upload_index(
+        &state.monorepo(),
+        &query.commit,
+        &query.root,
+        query.format,
+        &data,
+    )
+    .await?;
+    Ok(Json(CommonResult::success(Some(SymbolIndexResult {
+        occurrences,
+    }))))
+}
+
+/// Index Rust code
+///
+/// Runs the built-in tree-sitter indexer over the Rust files under a
+/// directory at a commit. CL pushes do this on their own.
+#[utoipa::path(
+    post,
+    path = "/index",
+    request_body = IndexSymbolsPayload,
+    responses(
+        (status = 200, body = CommonResult<SymbolIndexResult>, content_type = "application/json")
+    ),
+    tag = SYMBOL_TAG
+)]
+async fn index_symbols(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<IndexSymbolsPayload>,
+) -> Result<Json<CommonResult<SymbolIndexResult>>, ApiError> {
+    if !user.covers_path(&payload.path) {
+        return Err(ApiError::with_status(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("Token is limited to another path"),
+        ));
+    }
+    let occurrences =
+        symbol_ops::index_rust(&state.monorepo(), &payload.commit, &payload.path).await?;
+    Ok(Json(CommonResult::success(Some(SymbolIndexResult {
+        occurrences,
+    }))))
+}
@@ -6,13 +6,18 @@ use axum::{
 use russh::keys::{HashAlg, parse_public_key_base64};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use ceres::model::user::{AddSSHKey, ListSSHKey, ListToken};
+use ceres::model::user::{
+    AddSSHKey, GenerateToken, GeneratedToken, ListSSHKey, ListToken, RevokeTokens,
+};
 use common::{errors::MegaError, model::CommonResult};
 
 use crate::api::MonoApiServiceState;
 use crate::api::{error::ApiError, oauth::model::LoginUser};
 use crate::server::http_server::USER_TAG;
 
+const DEFAULT_TOKEN_LIFETIME_DAYS: u32 = 90;
+const MAX_TOKEN_LIFETIME_DAYS: u32 = 366;
+
 pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
     OpenApiRouter::new().nest(
         "/user",
@@ -23,7 +28,8 @@ pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
             .routes(routes!(remove_key))
             .routes(routes!(generate_token))
             .routes(routes!(list_token))
-            .routes(routes!(remove_token)),
+            .routes(routes!(remove_token))
+            .routes(routes!(revoke_tokens)),
     )
 }
 
@@ -118,21 +124,54 @@ async fn list_key(
     ))))
 }
 
-/// Generate Token For http push
+/// Generate a personal access token
+///
+/// The token is only returned once, only a hash of it is stored.
 #[utoipa::path(
     post,
     path = "/token/generate",
+    request_body = GenerateToken,
     responses(
-        (status = 200, body = CommonResult<String>, content_type = "application/json")
+        (status = 200, body = CommonResult<GeneratedToken>, content_type = "application/json")
     ),
     tag = USER_TAG
 )]
 async fn generate_token(
     user: LoginUser,
     state: State<MonoApiServiceState>,
-) -> Result<Json<CommonResult<String>>, ApiError> {
-    let res = state.user_stg().generate_token(user.username).await?;
-    Ok(Json(CommonResult::success(Some(res))))
+    Json(payload): Json<GenerateToken>,
+) -> Result<Json<CommonResult<GeneratedToken>>, ApiError> {
+    if payload.scopes.is_empty() {
+        return Err(ApiError::bad_request(anyhow::anyhow!(
+            "A token needs at least one scope"
+        )));
+    }
+    let days = payload
+        .expires_in_days
+        .unwrap_or(DEFAULT_TOKEN_LIFETIME_DAYS);
+    if days > MAX_TOKEN_LIFETIME_DAYS {
+        return Err(ApiError::bad_request(anyhow::anyhow!(
+            "A token can be valid for at most {MAX_TOKEN_LIFETIME_DAYS} days"
+        )));
+    }
+    let expires_at = chrono::Utc::now()
+        .naive_utc()
+        .checked_add_signed(chrono::Duration::days(days.into()))
+        .ok_or_else(|| ApiError::bad_request(anyhow::anyhow!("Invalid token lifetime")))?;
+    let (model, token) = state
+        .user_stg()
+        .generate_token(
+            user.username,
+            &payload.scopes,
+            payload.path_prefix,
+            expires_at,
+        )
+        .await?;
+    Ok(Json(CommonResult::success(Some(GeneratedToken {
+        id: model.id,
+        token,
+        expires_at: expires_at.and_utc().timestamp(),
+    }))))
 }
 
 /// Delete User's http push token
@@ -156,6 +195,28 @@ async fn remove_token(
     Ok(Json(CommonResult::success(None)))
 }
 
+/// Revoke the tokens limited to a path prefix or a path below it
+#[utoipa::path(
+    post,
+    path = "/token/revoke",
+    request_body = RevokeTokens,
+    responses(
+        (status = 200, body = CommonResult<u64>, content_type = "application/json")
+    ),
+    tag = USER_TAG
+)]
+async fn revoke_tokens(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<RevokeTokens>,
+) -> Result<Json<CommonResult<u64>>, ApiError> {
+    let revoked = state
+        .user_stg()
+        .revoke_tokens(&user.username, &payload.path_prefix)
+        .await?;
+    Ok(Json(CommonResult::success(Some(revoked))))
+}
+
 /// Get User's push token list
 #[utoipa::path(
     get,
@@ -0,0 +1,175 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use callisto::webhook;
+use ceres::api_service::webhook_ops::check_webhook_url;
+use ceres::model::webhook::{CreateWebhook, DeliveryInfo, DeliveryQuery, WebhookInfo};
+use common::{errors::MegaError, model::CommonResult};
+use jupiter::storage::webhook_storage::join_events;
+
+use crate::api::MonoApiServiceState;
+use crate::api::{error::ApiError, oauth::model::LoginUser};
+use crate::server::http_server::WEBHOOK_TAG;
+
+const DEFAULT_DELIVERY_LIMIT: u64 = 50;
+
+pub fn routers() -> OpenApiRouter<MonoApiServiceState> {
+    OpenApiRouter::new().nest(
+        "/webhook",
+        OpenApiRouter::new()
+            .routes(routes!(create_webhook))
+            .routes(routes!(list_webhooks))
+            .routes(routes!(delete_webhook))
+            .routes(routes!(list_deliveries))
+            .routes(routes!(redeliver)),
+    )
+}
+
+/// Subscribe a URL to events under a path
+#[utoipa::path(
+    post,
+    path = "/new",
+    request_body = CreateWebhook,
+    responses(
+        (status = 200, body = CommonResult<WebhookInfo>, content_type = "application/json")
+    ),
+    tag = WEBHOOK_TAG
+)]
+async fn create_webhook(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<CreateWebhook>,
+) -> Result<Json<CommonResult<WebhookInfo>>, ApiError> {
+    if payload.events.is_empty() {
+        return Err(ApiError::from(MegaError::Other(
+            "A webhook needs at least one event".to_string(),
+        )));
+    }
+    check_webhook_url(&payload.url)
+        .await
+        .map_err(ApiError::bad_request)?;
+    let model = webhook::Model::new(
+        &payload.path_prefix,
+        &payload.url,
+        &payload.secret,
+        join_events(&payload.events),
+        &user.username,
+    );
+    let res = state.webhook_stg().create_webhook(model).await?;
+    Ok(Json(CommonResult::success(Some(res.into()))))
+}
+
+/// List your webhooks
+#[utoipa::path(
+    get,
+    path = "/list",
+    responses(
+        (status = 200, body = CommonResult<Vec<WebhookInfo>>, content_type = "application/json")
+    ),
+    tag = WEBHOOK_TAG
+)]
+async fn list_webhooks(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<WebhookInfo>>>, ApiError> {
+    let res = state.webhook_stg().list_webhooks(&user.username).await?;
+    Ok(Json(CommonResult::success(Some(
+        res.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+/// Delete a webhook and its delivery log
+#[utoipa::path(
+    delete,
+    params(
+        ("id", description = "A numeric ID representing a webhook"),
+    ),
+    path = "/{id}",
+    responses(
+        (status = 200, body = CommonResult<String>, content_type = "application/json")
+    ),
+    tag = WEBHOOK_TAG
+)]
+async fn delete_webhook(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Path(id): Path<i64>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    owned_webhook(&state, &user, id).await?;
+    state.webhook_stg().delete_webhook(id).await?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+/// List the recent deliveries of a webhook, newest first
+#[utoipa::path(
+    get,
+    params(
+        ("id", description = "A numeric ID representing a webhook"),
+        DeliveryQuery,
+    ),
+    path = "/{id}/deliveries",
+    responses(
+        (status = 200, body = CommonResult<Vec<DeliveryInfo>>, content_type = "application/json")
+    ),
+    tag = WEBHOOK_TAG
+)]
+async fn list_deliveries(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Path(id): Path<i64>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<CommonResult<Vec<DeliveryInfo>>>, ApiError> {
+    owned_webhook(&state, &user, id).await?;
+    let res = state
+        .webhook_stg()
+        .list_deliveries(id, query.limit.unwrap_or(DEFAULT_DELIVERY_LIMIT))
+        .await?;
+    Ok(Json(CommonResult::success(Some(
+        res.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+/// Send the payload of a past delivery again
+#[utoipa::path(
+    post,
+    params(
+        ("id", description = "A numeric ID representing a delivery"),
+    ),
+    path = "/delivery/{id}/redeliver",
+    responses(
+        (status = 200, body = CommonResult<DeliveryInfo>, content_type = "application/json")
+    ),
+    tag = WEBHOOK_TAG
+)]
+async fn redeliver(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Path(id): Path<i64>,
+) -> Result<Json<CommonResult<DeliveryInfo>>, ApiError> {
+    let delivery = state
+        .webhook_stg()
+        .get_delivery(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(anyhow::anyhow!("Delivery {id} not found")))?;
+    owned_webhook(&state, &user, delivery.webhook_id).await?;
+    let res = state.webhook_stg().redeliver(id).await?;
+    Ok(Json(CommonResult::success(Some(res.into()))))
+}
+
+/// The webhook with `id`, if `user` created it. Others' webhooks are
+/// reported as missing.
+async fn owned_webhook(
+    state: &MonoApiServiceState,
+    user: &LoginUser,
+    id: i64,
+) -> Result<webhook::Model, ApiError> {
+    state
+        .webhook_stg()
+        .get_webhook(id)
+        .await?
+        .filter(|hook| hook.created_by == user.username)
+        .ok_or_else(|| ApiError::not_found(anyhow::anyhow!("Webhook {id} not found")))
+}
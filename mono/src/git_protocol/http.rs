@@ -10,6 +10,7 @@ use tokio::io::AsyncReadExt;
 use tokio_stream::StreamExt;
 
 use ceres::protocol::{ServiceType, SmartProtocol, smart};
+use common::enums::TokenScope;
 use common::errors::ProtocolError;
 use common::model::InfoRefsParams;
 
@@ -141,7 +142,10 @@ pub async fn git_receive_pack(
     req: Request<Body>,
     mut pack_protocol: SmartProtocol,
 ) -> Result<Response<Body>, ProtocolError> {
-    if pack_protocol.enable_http_auth(state) && !pack_protocol.http_auth(state, req.headers()).await
+    if pack_protocol.enable_http_auth(state)
+        && !pack_protocol
+            .http_auth(state, req.headers(), TokenScope::RepoWrite)
+            .await
     {
         return auth_failed();
     }
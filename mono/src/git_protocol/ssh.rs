@@ -13,9 +13,9 @@ use russh::{Channel, ChannelId};
 use tokio::io::AsyncReadExt;
 
 use ceres::lfs::lfs_structs::Link;
-use ceres::protocol::ServiceType;
 use ceres::protocol::smart::{self};
-use ceres::protocol::{SmartProtocol, TransportProtocol};
+use ceres::protocol::v2;
+use ceres::protocol::{ProtocolVersion, ServiceType, SmartProtocol, TransportProtocol};
 use tokio::sync::Mutex;
 
 use crate::git_protocol::http::search_subsequence;
@@ -29,6 +29,7 @@ pub struct SshServer {
     pub smart_protocol: Option<SmartProtocol>,
     pub state: ProtocolApiState,
     pub data_combined: BytesMut,
+    pub protocol_version: ProtocolVersion,
 }
 
 impl server::Server for SshServer {
@@ -56,6 +57,21 @@ impl server::Handler for SshServer {
         Ok(true)
     }
 
+    /// Git passes the requested wire protocol in `GIT_PROTOCOL`, sent before
+    /// the exec request.
+    async fn env_request(
+        &mut self,
+        _channel: ChannelId,
+        variable_name: &str,
+        variable_value: &str,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        if variable_name == "GIT_PROTOCOL" {
+            self.protocol_version = ProtocolVersion::from_git_protocol(variable_value);
+        }
+        Ok(())
+    }
+
     /// # Executes a request on the SSH server.
     ///
     /// This function processes the received data from the specified channel and performs the
@@ -83,6 +99,7 @@ impl server::Handler for SshServer {
         let path = command[1];
         let path = path.replace(".git", "").replace('\'', "");
         let mut smart_protocol = SmartProtocol::new(PathBuf::from(&path), TransportProtocol::Ssh);
+        smart_protocol.protocol_version = self.protocol_version;
         match command[0] {
             "git-upload-pack" | "git-receive-pack" => {
                 smart_protocol.service_type = Some(ServiceType::from_str(command[0]).unwrap());
@@ -153,7 +170,10 @@ impl server::Handler for SshServer {
         data: &[u8],
         session: &mut Session,
     ) -> Result<(), Self::Error> {
-        let smart_protocol = self.smart_protocol.as_mut().unwrap();
+        // Nothing to serve once a failed request closed the channel
+        let Some(smart_protocol) = self.smart_protocol.as_mut() else {
+            return Ok(());
+        };
         tracing::info!(
             "receiving data length:{}",
             // String::from_utf8_lossy(data),
@@ -161,8 +181,22 @@ impl server::Handler for SshServer {
         );
         let service_type = smart_protocol.service_type.unwrap();
         match service_type {
+            ServiceType::UploadPack if smart_protocol.protocol_version == ProtocolVersion::V2 => {
+                // v2 clients may send several command requests over one session
+                self.data_combined.extend_from_slice(data);
+                while let Some(len) = v2::request_len(&self.data_combined) {
+                    let request = self.data_combined.split_to(len);
+                    if &request[..] != smart::PKT_LINE_END_MARKER
+                        && !self.handle_upload_pack(channel, &request, session).await
+                    {
+                        return Ok(());
+                    }
+                }
+            }
             ServiceType::UploadPack => {
-                self.handle_upload_pack(channel, data, session).await;
+                if !self.handle_upload_pack(channel, data, session).await {
+                    return Ok(());
+                }
             }
             ServiceType::ReceivePack => {
                 self.data_combined.extend_from_slice(data);
@@ -194,13 +228,33 @@ impl server::Handler for SshServer {
 }
 
 impl SshServer {
-    async fn handle_upload_pack(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) {
+    /// Returns false if the request failed and the channel was closed.
+    async fn handle_upload_pack(
+        &mut self,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) -> bool {
         let smart_protocol = self.smart_protocol.as_mut().unwrap();
 
-        let (mut send_pack_data, buf) = smart_protocol
+        let (mut send_pack_data, buf) = match smart_protocol
             .git_upload_pack(&self.state, &mut Bytes::copy_from_slice(data))
             .await
-            .unwrap();
+        {
+            Ok(res) => res,
+            Err(e) => {
+                // report the error to the client instead of dropping the connection
+                tracing::warn!("upload-pack failed: {e}");
+                let mut err = BytesMut::new();
+                smart::add_pkt_line_string(&mut err, format!("ERR {e}\n"));
+                let _ = session.data(channel, err.to_vec().into());
+                let _ = session.exit_status_request(channel, 1);
+                let _ = session.close(channel);
+                self.smart_protocol = None;
+                self.data_combined.clear();
+                return false;
+            }
+        };
 
         tracing::info!("buf is {:?}", buf);
         session
@@ -223,6 +277,7 @@ impl SshServer {
         session
             .data(channel, smart::PKT_LINE_END_MARKER.to_vec().into())
             .unwrap();
+        true
     }
 
     async fn handle_receive_pack(&mut self, channel: ChannelId, session: &mut Session) {
@@ -5,6 +5,7 @@ use std::sync::Arc;
 
 use bytes::BytesMut;
 use ceres::api_service::{cache::GitObjectCache, state::ProtocolApiState};
+use ceres::protocol::ProtocolVersion;
 use clap::Args;
 use context::AppContext;
 use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
@@ -70,6 +71,7 @@ pub async fn start_server(ctx: AppContext, command: &SshOptions) {
         id: 0,
         smart_protocol: None,
         data_combined: BytesMut::new(),
+        protocol_version: ProtocolVersion::default(),
     };
     let server_url = format!("{host}:{ssh_port}");
     let addr = SocketAddr::from_str(&server_url).unwrap();
@@ -107,7 +107,9 @@ const AdditionalCheckItemComponent = ({ check }: AdditionalCheckItemProps) => (
     <div className='mr-3 mt-0.5 flex-shrink-0'>{getStatusIcon(check.result)}</div>
     <div className='min-w-0 flex-1'>
       <div className='flex items-center justify-between'>
-        <h5 className='text-sm font-medium text-gray-900'>{ADDITIONAL_CHECK_LABELS[check.type]}</h5>
+        <h5 className='text-sm font-medium text-gray-900'>
+          {check.type === 'External' ? check.display_name : ADDITIONAL_CHECK_LABELS[check.type]}
+        </h5>
         <span
           className={`rounded-full px-2 py-1 text-xs font-medium ${
             check.result === 'PASSED'
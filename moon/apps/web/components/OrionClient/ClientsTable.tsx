@@ -142,7 +142,7 @@ function mapApiStatusToUiStatus(coreStatus: CoreWorkerStatus, phase: TaskPhase |
 
   if (coreStatus === CoreWorkerStatus.Busy) {
     if (phase === TaskPhase.DownloadingSource) return 'downloading'
-    if (phase === TaskPhase.RunningBuild) return 'running'
+    if (phase === TaskPhase.RunningBuild || phase === TaskPhase.RunningTests) return 'running'
     return 'busy'
   }
 
@@ -3152,7 +3152,8 @@ export enum CheckType {
   ClSync = 'ClSync',
   MergeConflict = 'MergeConflict',
   CiStatus = 'CiStatus',
-  CodeReview = 'CodeReview'
+  CodeReview = 'CodeReview',
+  External = 'External'
 }
 
 /** Chunk download object information */
@@ -3849,13 +3850,18 @@ export type Condition = {
   description: string
   display_name: string
   message: string
+  /** Optional conditions are shown but do not block merging. */
+  required: boolean
   result: ConditionResult
   type: CheckType
 }
 
 export enum ConditionResult {
   FAILED = 'FAILED',
-  PASSED = 'PASSED'
+  PASSED = 'PASSED',
+  PENDING = 'PENDING',
+  SKIPPED = 'SKIPPED',
+  ERROR = 'ERROR'
 }
 
 export type ContentPayload = {
@@ -4445,7 +4451,8 @@ export type RequestObject = {
 
 export enum RequirementsState {
   UNMERGEABLE = 'UNMERGEABLE',
-  MERGEABLE = 'MERGEABLE'
+  MERGEABLE = 'MERGEABLE',
+  PENDING = 'PENDING'
 }
 
 /** Response object for LFS batch operations */
@@ -4668,6 +4675,7 @@ export type BuildRequest = {
 
 export type CommonPageOrionClientInfo = {
   items: {
+    capabilities: WorkerCapabilities
     client_id: string
     hostname: string
     /** @format date-time */
@@ -4724,6 +4732,7 @@ export type LogSegment = {
 }
 
 export type OrionClientInfo = {
+  capabilities: WorkerCapabilities
   client_id: string
   hostname: string
   /** @format date-time */
@@ -4733,6 +4742,23 @@ export type OrionClientInfo = {
   start_time: string
 }
 
+export type WorkerCapabilities = {
+  arch: string
+  /**
+   * @format int32
+   * @min 0
+   */
+  cpus: number
+  /** Custom labels such as `gpu-free` or `large-disk`. */
+  labels?: string[]
+  /**
+   * @format int64
+   * @min 0
+   */
+  memory_mb: number
+  os: string
+}
+
 /**
  * Additional query parameters for querying Orion clients.
  * When no extra conditions are required, this struct can be left empty.
@@ -4790,7 +4816,8 @@ export type TaskInfoDTO = {
 /** Task phase when in buck2 build */
 export enum TaskPhase {
   DownloadingSource = 'DownloadingSource',
-  RunningBuild = 'RunningBuild'
+  RunningBuild = 'RunningBuild',
+  RunningTests = 'RunningTests'
 }
 
 /** Request structure for creating a task */
@@ -4811,6 +4838,8 @@ export enum TaskStatusEnum {
   Interrupted = 'Interrupted',
   Failed = 'Failed',
   Completed = 'Completed',
+  /** Superseded by a newer build of the same CL */
+  Cancelled = 'Cancelled',
   NotFound = 'NotFound'
 }
 
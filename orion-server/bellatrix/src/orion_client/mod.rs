@@ -38,12 +38,27 @@ pub struct BuildInfo {
     pub changes: Vec<Status<ProjectRelativePath>>,
 }
 
+/// Where a build request comes from, merge queue builds get a higher priority
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildOrigin {
+    #[default]
+    Cl,
+    MergeQueue,
+}
+
 #[derive(Serialize, Debug)]
 pub struct OrionBuildRequest {
     pub cl_link: String,
     pub repo: String,
     pub cl: i64,
     pub builds: Vec<BuildInfo>,
+    pub commit_id: String,
+    pub origin: BuildOrigin,
+    /// CL label names, used by Orion to set the build priority
+    pub labels: Vec<String>,
+    /// CL author, used by Orion to share workers fairly
+    pub username: String,
 }
 
 #[derive(Clone)]
@@ -1,9 +1,12 @@
+use crate::artifact;
 use crate::auto_retry::AutoRetryJudger;
 use crate::common::model::{CommonPage, PageParams};
 use crate::log::log_service::{LogEvent, LogService};
-use crate::model::{builds, tasks};
+use crate::model::{builds, tasks, test_results};
+use crate::routing::RoutingRules;
 use crate::scheduler::{
-    self, BuildInfo, BuildRequest, TaskQueueStats, TaskScheduler, WorkerInfo, WorkerStatus,
+    self, BuildInfo, BuildRequest, TaskOrigin, TaskPriority, TaskQueueStats, TaskScheduler,
+    WorkerInfo, WorkerStatus,
 };
 use anyhow::Result;
 use axum::extract::Query;
@@ -22,10 +25,15 @@ use chrono::{FixedOffset, Utc};
 use dashmap::DashMap;
 use futures::stream::select;
 use futures_util::{SinkExt, Stream, StreamExt};
-use orion::ws::{TaskPhase, WSMessage};
+use jupiter::object_storage::ObjectStorage;
+use orion::capability::{TaskRequirements, WorkerCapabilities};
+use orion::ws::{TaskPhase, TestCaseResult, WSMessage};
 use rand::Rng;
 use sea_orm::prelude::DateTimeUtc;
-use sea_orm::{ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter as _};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter as _, QueryOrder,
+    TransactionTrait,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::convert::Infallible;
@@ -63,6 +71,8 @@ pub enum TaskStatusEnum {
     Interrupted, // Task was interrupted, exit code is None
     Failed,
     Completed,
+    /// Superseded by a newer build of the same CL
+    Cancelled,
     #[default]
     NotFound,
 }
@@ -76,6 +86,21 @@ pub struct TaskRequest {
     pub task_name: Option<String>,
     pub template: Option<Value>,
     pub builds: Vec<scheduler::BuildRequest>,
+    /// Commit the builds run against, used by mono to match results to a CL revision
+    #[serde(default)]
+    pub commit_id: Option<String>,
+    /// Explicit priority, overrides the one derived from `labels` and `origin`
+    #[serde(default)]
+    pub priority: Option<TaskPriority>,
+    #[serde(default)]
+    pub origin: TaskOrigin,
+    /// Labels of the CL, `hotfix`, `urgent`, `critical` or `priority:<level>` set the
+    /// priority, up to `high`
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Author of the CL, workers are shared fairly between authors
+    #[serde(default)]
+    pub username: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, ToSchema)]
@@ -93,6 +118,7 @@ pub struct AppState {
     pub scheduler: TaskScheduler,
     pub conn: DatabaseConnection,
     pub log_service: LogService,
+    pub artifact_storage: Arc<dyn ObjectStorage>,
 }
 
 impl AppState {
@@ -101,15 +127,24 @@ impl AppState {
         conn: DatabaseConnection,
         queue_config: Option<crate::scheduler::TaskQueueConfig>,
         log_service: LogService,
+        artifact_storage: Arc<dyn ObjectStorage>,
+        routing: RoutingRules,
     ) -> Self {
         let workers = Arc::new(DashMap::new());
         let active_builds = Arc::new(DashMap::new());
-        let scheduler = TaskScheduler::new(conn.clone(), workers, active_builds, queue_config);
+        let scheduler = TaskScheduler::new(
+            conn.clone(),
+            workers,
+            active_builds,
+            queue_config,
+            Arc::new(routing),
+        );
 
         Self {
             scheduler,
             conn,
             log_service,
+            artifact_storage,
         }
     }
 }
@@ -123,6 +158,12 @@ pub fn routers() -> Router<AppState> {
         .route("/task-output/{id}", get(task_output_handler))
         .route("/task-history-output", get(task_history_output_handler))
         .route("/tasks/{cl}", get(tasks_handler))
+        .route("/tasks/{cl}/tests", get(task_tests_handler))
+        .route(
+            "/builds/{build_id}/artifacts",
+            get(artifact::build_artifacts_handler).post(artifact::upload_artifact_handler),
+        )
+        .route("/artifacts/{id}", get(artifact::download_artifact_handler))
         .route("/queue-stats", get(queue_stats_handler))
         .route("/orion-clients-info", post(get_orion_clients_info))
         .route(
@@ -294,6 +335,21 @@ pub async fn task_handler(
     State(state): State<AppState>,
     Json(req): Json<TaskRequest>,
 ) -> impl IntoResponse {
+    // A new revision of the CL makes the builds of its earlier revisions stale
+    if req.origin == TaskOrigin::Cl {
+        let superseded = state
+            .scheduler
+            .supersede_cl_builds(req.cl, req.commit_id.as_deref())
+            .await;
+        if !superseded.is_empty() {
+            tracing::info!(
+                "Cancelled {} superseded builds of CL {}",
+                superseded.len(),
+                req.cl
+            );
+        }
+    }
+
     // create task id
     let task_id = Uuid::now_v7();
 
@@ -306,6 +362,7 @@ pub async fn task_handler(
         req.task_name.clone(),
         req.template.clone(),
         chrono::Utc::now().into(),
+        req.commit_id.clone(),
         &state.conn,
     )
     .await
@@ -320,9 +377,25 @@ pub async fn task_handler(
             .into_response();
     }
 
+    // Worker capabilities the builds of this repository need
+    let requirements = state.scheduler.routing.requirements_for(&req.repo);
+
+    let priority = TaskPriority::for_request(req.priority, req.origin, &req.labels);
+    // Merge queue builds test a speculative commit rather than the CL itself
+    let build_commit = match req.origin {
+        TaskOrigin::Cl => None,
+        TaskOrigin::MergeQueue => req.commit_id.clone(),
+    };
+
     for build in &req.builds {
-        // Check if there are idle workers available
-        if state.scheduler.has_idle_workers() {
+        // Dispatch directly only if nothing is waiting, queued builds go first by priority
+        let queue_empty = state.scheduler.get_queue_stats().await.total_queued == 0;
+        if queue_empty
+            && !state
+                .scheduler
+                .get_idle_workers_for(&requirements)
+                .is_empty()
+        {
             // Have idle workers, directly dispatch task (keep original logic)
             let result: BuildResult = handle_immediate_task_dispatch(
                 state.clone(),
@@ -332,29 +405,46 @@ pub async fn task_handler(
                 req.cl,
                 build.clone(),
                 String::new(),
+                requirements.clone(),
+                priority,
+                req.username.clone(),
+                build_commit.clone(),
+                req.origin,
             )
             .await;
             results.push(result);
         } else {
             // No idle workers, add task to queue
-            match state
-                .scheduler
-                .enqueue_task(
-                    task_id,
-                    &req.cl_link,
-                    build.clone(),
-                    req.repo.clone(),
-                    req.cl,
-                )
-                .await
-            {
+            let pending_task = scheduler::PendingTask {
+                task_id,
+                cl_link: req.cl_link.clone(),
+                build_id: Uuid::now_v7(),
+                repo: req.repo.clone(),
+                cl: req.cl,
+                request: build.clone(),
+                created_at: std::time::Instant::now(),
+                requirements: requirements.clone(),
+                blocked_reason: None,
+                priority,
+                username: req.username.clone(),
+                commit_id: build_commit.clone(),
+                origin: req.origin,
+            };
+            match state.scheduler.enqueue_task(pending_task).await {
                 Ok(build_id) => {
-                    tracing::info!("Build {}/{} queued for later processing", task_id, build_id);
+                    tracing::info!(
+                        "Build {}/{} queued for later processing with {} priority",
+                        task_id,
+                        build_id,
+                        priority.as_str()
+                    );
                     let result: BuildResult = BuildResult {
                         build_id: build_id.to_string(),
                         status: "queued".to_string(),
-                        message: "Task queued for processing when workers become available"
-                            .to_string(),
+                        message: format!(
+                            "Task queued: {}",
+                            state.scheduler.blocked_reason(&requirements)
+                        ),
                     };
                     results.push(result);
                 }
@@ -385,6 +475,7 @@ pub async fn task_handler(
 ///
 /// # Note
 /// The `target` field is deprecated, only remain for compatibility reasons.
+#[allow(clippy::too_many_arguments)]
 async fn handle_immediate_task_dispatch(
     state: AppState,
     task_id: Uuid,
@@ -393,9 +484,14 @@ async fn handle_immediate_task_dispatch(
     cl: i64,
     req: BuildRequest,
     target: String,
+    requirements: TaskRequirements,
+    priority: TaskPriority,
+    username: Option<String>,
+    commit_id: Option<String>,
+    origin: TaskOrigin,
 ) -> BuildResult {
-    // Find all idle workers
-    let idle_workers = state.scheduler.get_idle_workers();
+    // Find the idle workers meeting the requirements
+    let idle_workers = state.scheduler.get_idle_workers_for(&requirements);
 
     // Return error if no workers are available (this shouldn't happen theoretically since we already checked)
     if idle_workers.is_empty() {
@@ -422,9 +518,17 @@ async fn handle_immediate_task_dispatch(
         changes: req.changes.clone(),
         start_at: chrono::Utc::now(),
         cl: cl.to_string(),
+        cl_link: cl_link.to_string(),
         _worker_id: chosen_id.clone(),
         auto_retry_judger: AutoRetryJudger::new(),
         retry_count: 0,
+        requirements,
+        priority,
+        username,
+        commit_id: commit_id.clone(),
+        origin,
+        queued_at: std::time::Instant::now(),
+        upload_token: Uuid::new_v4().simple().to_string(),
     };
 
     // Use the model's insert_build method for direct insertion
@@ -452,6 +556,8 @@ async fn handle_immediate_task_dispatch(
         repo: repo.to_string(),
         changes: req.changes.clone(),
         cl_link: cl_link.to_string(),
+        commit_id,
+        upload_token: build_info.upload_token.clone(),
     };
 
     // Send task to the selected worker
@@ -626,9 +732,17 @@ async fn process_message(
                     id,
                     hostname,
                     orion_version,
+                    capabilities,
                 } = ws_msg
                 {
-                    tracing::info!("Worker from {who} registered as: {id}");
+                    tracing::info!(
+                        "Worker from {who} registered as: {id} ({}/{}, {} cpus, {} MB, labels {:?})",
+                        capabilities.os,
+                        capabilities.arch,
+                        capabilities.cpus,
+                        capabilities.memory_mb,
+                        capabilities.labels
+                    );
                     state.scheduler.workers.insert(
                         id.clone(),
                         WorkerInfo {
@@ -638,6 +752,7 @@ async fn process_message(
                             start_time: chrono::Utc::now(),
                             hostname,
                             orion_version,
+                            capabilities,
                         },
                     );
                     *worker_id = Some(id);
@@ -706,6 +821,22 @@ async fn process_message(
                         build_info.auto_retry_judger.judge_by_output(&output);
                     }
                 }
+                WSMessage::TestResults { id, results } => {
+                    tracing::info!(
+                        "Received {} test results for build {id} from worker {current_worker_id}",
+                        results.len()
+                    );
+                    if let Some(build_info) = state.scheduler.active_builds.get(&id) {
+                        let task_id = build_info.task_id.clone();
+                        drop(build_info);
+                        if let Err(e) = save_test_results(&state.conn, &task_id, &id, results).await
+                        {
+                            tracing::error!("Failed to save test results for build {id}: {e}");
+                        }
+                    } else {
+                        tracing::warn!("Received test results for unknown build: {id}");
+                    }
+                }
                 WSMessage::BuildComplete {
                     id,
                     success,
@@ -761,6 +892,8 @@ async fn process_message(
                                 repo: build_info.repo,
                                 changes: build_info.changes,
                                 cl_link: build_info.cl,
+                                commit_id: build_info.commit_id,
+                                upload_token: build_info.upload_token,
                             };
                             let worker_id = build_info._worker_id;
                             if let Some(worker) = state.scheduler.workers.get_mut(&worker_id)
@@ -794,6 +927,7 @@ async fn process_message(
                                     )
                                     .exec(&state.conn)
                                     .await;
+                                notify_cl_recheck(build_info.cl_link);
 
                                 // Notify scheduler to process queued tasks
                                 state.scheduler.notify_task_available();
@@ -827,6 +961,7 @@ async fn process_message(
                                 .filter(builds::Column::Id.eq(id.parse::<uuid::Uuid>().unwrap()))
                                 .exec(&state.conn)
                                 .await;
+                            notify_cl_recheck(build_info.cl_link);
 
                             // Mark the worker as idle or error depending on whether the task succeeds.
                             if let Some(mut worker) =
@@ -842,10 +977,73 @@ async fn process_message(
                             // After worker becomes idle, notify to process queued tasks
                             state.scheduler.notify_task_available();
                         }
+                    } else if let Some(mut worker) = state
+                        .scheduler
+                        .workers
+                        .get_mut(current_worker_id)
+                        .filter(|w| matches!(&w.status, WorkerStatus::Busy { task_id, .. } if task_id == &id))
+                    {
+                        // The build was cancelled, the worker is free once it has stopped
+                        tracing::info!("Cancelled build {id} stopped on worker {current_worker_id}");
+                        worker.status = WorkerStatus::Idle;
+                        drop(worker);
+                        state.scheduler.notify_task_available();
                     } else {
                         tracing::error!("Not found build: {id}");
                     }
                 }
+                WSMessage::TaskRejected {
+                    id,
+                    requirements,
+                    reason,
+                } => {
+                    tracing::warn!("Build {id} rejected by worker {current_worker_id}: {reason}");
+                    if let Some((_, build_info)) = state.scheduler.active_builds.remove(&id) {
+                        // The worker found requirements in the target labels, queue the build
+                        // again so it waits for a worker that meets all of them
+                        let mut merged = build_info.requirements.clone();
+                        merged.merge(&requirements);
+                        let (Ok(task_id), Ok(build_id)) =
+                            (build_info.task_id.parse(), build_info.build_id.parse())
+                        else {
+                            tracing::error!("Invalid ids for rejected build {id}");
+                            return ControlFlow::Continue(());
+                        };
+                        // Dispatch records the build again once a worker takes it
+                        if let Err(e) = builds::Entity::delete_by_id(build_id)
+                            .exec(&state.conn)
+                            .await
+                        {
+                            tracing::error!("Failed to delete rejected build {id}: {e}");
+                        }
+                        state
+                            .scheduler
+                            .requeue_task(scheduler::PendingTask {
+                                task_id,
+                                cl_link: build_info.cl_link,
+                                build_id,
+                                repo: build_info.repo,
+                                cl: build_info.cl.parse().unwrap_or_default(),
+                                request: BuildRequest {
+                                    changes: build_info.changes,
+                                },
+                                created_at: build_info.queued_at,
+                                requirements: merged,
+                                blocked_reason: Some(reason),
+                                priority: build_info.priority,
+                                username: build_info.username,
+                                commit_id: build_info.commit_id,
+                                origin: build_info.origin,
+                            })
+                            .await;
+                    } else {
+                        tracing::warn!("Received rejection for unknown build: {id}");
+                    }
+                    if let Some(mut worker) = state.scheduler.workers.get_mut(current_worker_id) {
+                        worker.status = WorkerStatus::Idle;
+                    }
+                    state.scheduler.notify_task_available();
+                }
                 WSMessage::TaskPhaseUpdate { id, phase } => {
                     tracing::info!(
                         "Task phase updated by orion worker {current_worker_id} with: {phase:?}"
@@ -895,6 +1093,56 @@ async fn process_message(
     ControlFlow::Continue(())
 }
 
+/// Stores the test results of a build, replacing those of an earlier attempt
+/// of the same build.
+async fn save_test_results(
+    db: &DatabaseConnection,
+    task_id: &str,
+    build_id: &str,
+    results: Vec<TestCaseResult>,
+) -> Result<(), anyhow::Error> {
+    let task_id = task_id.parse::<Uuid>()?;
+    let build_id = build_id.parse::<Uuid>()?;
+    let txn = db.begin().await?;
+    test_results::Entity::delete_many()
+        .filter(test_results::Column::BuildId.eq(build_id))
+        .exec(&txn)
+        .await?;
+    for chunk in results.chunks(500) {
+        test_results::Entity::insert_many(
+            chunk
+                .iter()
+                .cloned()
+                .map(|result| test_results::Model::create_test_result(task_id, build_id, result)),
+        )
+        .exec_without_returning(&txn)
+        .await?;
+    }
+    txn.commit().await?;
+    Ok(())
+}
+
+/// Asks mono to re-run the merge checks of a CL after one of its builds finished,
+/// so the CI status in the merge box follows the build result.
+fn notify_cl_recheck(cl_link: String) {
+    tokio::spawn(async move {
+        let url = crate::buck2::cl_recheck_endpoint(&cl_link);
+        let mut req = reqwest::Client::new().post(&url);
+        if let Some(token) = crate::buck2::mono_api_token() {
+            req = req.bearer_auth(token);
+        }
+        match req.send().await {
+            Ok(res) if res.status().is_success() => {
+                tracing::debug!("Requested merge check re-run for CL {cl_link}");
+            }
+            Ok(res) => {
+                tracing::warn!("Failed to re-run checks for CL {cl_link}: {}", res.status());
+            }
+            Err(e) => tracing::warn!("Failed to re-run checks for CL {cl_link}: {e}"),
+        }
+    });
+}
+
 /// Data transfer object for build information in API responses
 #[derive(Debug, Serialize, ToSchema)]
 pub struct BuildDTO {
@@ -933,7 +1181,9 @@ impl BuildDTO {
 
     /// Determine build status based on database fields and active builds
     pub fn determine_status(model: &builds::Model, is_active: bool) -> TaskStatusEnum {
-        if is_active {
+        if model.cancelled {
+            TaskStatusEnum::Cancelled
+        } else if is_active {
             TaskStatusEnum::Building
         } else if model.end_at.is_none() {
             // Not in active_builds and end_at is None => still queued (pending)
@@ -953,6 +1203,7 @@ impl BuildDTO {
 pub struct TaskInfoDTO {
     pub task_id: String,
     pub cl_id: i64,
+    pub commit_id: Option<String>,
     pub task_name: Option<String>,
     pub template: Option<serde_json::Value>,
     pub created_at: String,
@@ -964,6 +1215,7 @@ impl TaskInfoDTO {
         Self {
             task_id: model.id.to_string(),
             cl_id: model.cl_id,
+            commit_id: model.commit_id,
             task_name: model.task_name,
             template: model.template,
             created_at: model.created_at.with_timezone(&Utc).to_rfc3339(),
@@ -1051,6 +1303,112 @@ pub async fn tasks_handler(
     }
 }
 
+/// Result of one test case run for a CL
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TestResultDTO {
+    pub task_id: String,
+    pub build_id: String,
+    #[serde(flatten)]
+    pub result: TestCaseResult,
+    pub created_at: String,
+}
+
+impl TestResultDTO {
+    fn from_model(model: test_results::Model) -> Self {
+        let status = model.test_status();
+        Self {
+            task_id: model.task_id.to_string(),
+            build_id: model.build_id.to_string(),
+            result: TestCaseResult {
+                target: model.target,
+                name: model.name,
+                status,
+                duration_ms: model.duration_ms.map(|ms| ms as u64),
+                message: model.message,
+            },
+            created_at: model.created_at.with_timezone(&Utc).to_rfc3339(),
+        }
+    }
+}
+
+/// Which run of a CL to list the test results of
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskTestsQuery {
+    /// A build of the CL, instead of its latest run.
+    pub build_id: Option<Uuid>,
+    /// Only runs at this commit of the CL.
+    pub commit: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/tasks/{cl}/tests",
+    params(
+        ("cl" = i64, Path, description = "CL number to filter test results by"),
+        ("build_id" = Option<String>, Query, description = "Build to list the results of, instead of the latest run"),
+        ("commit" = Option<String>, Query, description = "Only consider runs at this commit"),
+    ),
+    responses(
+    (status = 200, description = "Per-test results of the latest run of the CL, or of the given build", body = [TestResultDTO]),
+    (status = 500, description = "Internal error", body = serde_json::Value)
+    )
+)]
+/// Return the test case results of the latest run of a CL, ordered by target and test name
+///
+/// A run is every build of one task, so a CL pushed again doesn't mix the
+/// results of its old revision with the new one.
+pub async fn task_tests_handler(
+    State(state): State<AppState>,
+    Path(cl): Path<i64>,
+    Query(params): Query<TaskTestsQuery>,
+) -> Result<Json<Vec<TestResultDTO>>, (StatusCode, Json<serde_json::Value>)> {
+    let db = &state.conn;
+    let results = async {
+        let mut cl_tasks = tasks::Entity::find().filter(tasks::Column::ClId.eq(cl));
+        if let Some(commit) = &params.commit {
+            cl_tasks = cl_tasks.filter(tasks::Column::CommitId.eq(commit));
+        }
+        let results = match params.build_id {
+            Some(build_id) => {
+                let task_ids: Vec<Uuid> =
+                    cl_tasks.all(db).await?.into_iter().map(|t| t.id).collect();
+                test_results::Entity::find()
+                    .filter(test_results::Column::TaskId.is_in(task_ids))
+                    .filter(test_results::Column::BuildId.eq(build_id))
+            }
+            None => {
+                let Some(task) = cl_tasks
+                    .order_by_desc(tasks::Column::CreatedAt)
+                    .one(db)
+                    .await?
+                else {
+                    return Ok(vec![]);
+                };
+                test_results::Entity::find().filter(test_results::Column::TaskId.eq(task.id))
+            }
+        };
+        results
+            .order_by_asc(test_results::Column::Target)
+            .order_by_asc(test_results::Column::Name)
+            .all(db)
+            .await
+    }
+    .await;
+
+    match results {
+        Ok(models) => Ok(Json(
+            models.into_iter().map(TestResultDTO::from_model).collect(),
+        )),
+        Err(e) => {
+            tracing::error!("Failed to fetch test results: {e}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"message": "Failed to fetch test results"})),
+            ))
+        }
+    }
+}
+
 async fn find_caused_by_next_line_in_content(content: &str) -> Option<String> {
     let mut last_was_caused = false;
 
@@ -1077,6 +1435,7 @@ pub struct OrionClientInfo {
     pub start_time: DateTimeUtc,
     #[schema(value_type = String, format = "date-time")]
     pub last_heartbeat: DateTimeUtc,
+    pub capabilities: WorkerCapabilities,
 }
 
 impl OrionClientInfo {
@@ -1087,6 +1446,7 @@ impl OrionClientInfo {
             orion_version: worker.orion_version.clone(),
             start_time: worker.start_time,
             last_heartbeat: worker.last_heartbeat,
+            capabilities: worker.capabilities.clone(),
         }
     }
 }
@@ -0,0 +1,476 @@
+//! Build artifacts uploaded by workers after a successful build.
+//!
+//! Artifacts are kept in object storage under [`ObjectNamespace::Artifact`] and
+//! linked to their `builds` row. Each one expires after a retention period,
+//! after which a background task removes it.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use futures::StreamExt;
+use jupiter::object_storage::factory::{ObjectStorageConfig, ObjectStorageFactory};
+use jupiter::object_storage::{
+    ObjectByteStream, ObjectKey, ObjectMeta, ObjectNamespace, ObjectStorage,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_stream::wrappers::ReceiverStream;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::api::AppState;
+use crate::model::artifacts;
+
+/// Days an artifact is kept when `ARTIFACT_RETENTION_DAYS` is not set
+const DEFAULT_RETENTION_DAYS: i64 = 7;
+/// Largest accepted artifact in bytes when `ARTIFACT_MAX_SIZE` is not set
+const DEFAULT_MAX_SIZE: i64 = 1 << 30;
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+const PURGE_BATCH_SIZE: u64 = 500;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: impl Into<String>) -> ApiError {
+    (
+        status,
+        Json(serde_json::json!({ "message": message.into() })),
+    )
+}
+
+/// Creates the artifact object storage from the environment.
+///
+/// `ARTIFACT_STORAGE_TYPE` selects `local` (default, under `ARTIFACT_DIR`) or
+/// `s3` (bucket `ARTIFACT_BUCKET`, using the AWS credentials of the log store).
+pub async fn init_artifact_storage() -> Arc<dyn ObjectStorage> {
+    let storage_type =
+        std::env::var("ARTIFACT_STORAGE_TYPE").unwrap_or_else(|_| "local".to_string());
+    let config = match storage_type.as_str() {
+        "local" => ObjectStorageConfig::Fs {
+            root: std::env::var("ARTIFACT_DIR")
+                .unwrap_or_else(|_| "/tmp/artifacts".to_string())
+                .into(),
+        },
+        "s3" => ObjectStorageConfig::S3 {
+            config: ::common::config::S3Config {
+                region: std::env::var("AWS_DEFAULT_REGION")
+                    .expect("AWS_DEFAULT_REGION must be set for S3"),
+                bucket: std::env::var("ARTIFACT_BUCKET")
+                    .unwrap_or_else(|_| "orion-artifacts".to_string()),
+                access_key_id: std::env::var("AWS_ACCESS_KEY_ID")
+                    .expect("AWS_ACCESS_KEY_ID must be set for S3"),
+                secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY")
+                    .expect("AWS_SECRET_ACCESS_KEY must be set for S3"),
+                endpoint_url: std::env::var("S3_ENDPOINT_URL").unwrap_or_default(),
+            },
+        },
+        other => panic!(
+            "Unsupported ARTIFACT_STORAGE_TYPE: {}. Supported values: [local, s3]",
+            other
+        ),
+    };
+    ObjectStorageFactory::create(config)
+        .await
+        .expect("Failed to initialize artifact storage")
+}
+
+fn retention() -> chrono::Duration {
+    let days = std::env::var("ARTIFACT_RETENTION_DAYS")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    chrono::Duration::days(days)
+}
+
+fn max_size() -> i64 {
+    std::env::var("ARTIFACT_MAX_SIZE")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE)
+}
+
+/// Checks that `build_id` is running and that the request carries the upload
+/// token its worker received with the task.
+fn authorize_upload(state: &AppState, build_id: Uuid, headers: &HeaderMap) -> Result<(), ApiError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Upload token is required"))?;
+    let build = state
+        .scheduler
+        .active_builds
+        .get(&build_id.to_string())
+        .ok_or_else(|| api_error(StatusCode::CONFLICT, "Build is not running"))?;
+    if build.upload_token.is_empty() || build.upload_token != token {
+        return Err(api_error(StatusCode::UNAUTHORIZED, "Invalid upload token"));
+    }
+    Ok(())
+}
+
+/// `Content-Disposition` value for downloading a file called `name`, with
+/// characters that could break out of the quoted file name escaped or dropped.
+fn content_disposition(name: &str) -> String {
+    let mut quoted = String::with_capacity(name.len());
+    for c in name.chars().filter(|c| !c.is_control()) {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    format!("attachment; filename=\"{quoted}\"")
+}
+
+fn object_key(id: Uuid) -> ObjectKey {
+    ObjectKey {
+        namespace: ObjectNamespace::Artifact,
+        key: id.simple().to_string(),
+    }
+}
+
+/// Query of an artifact upload
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ArtifactUploadQuery {
+    /// Target that declared the output, e.g. `root//foo:bar`
+    pub target: String,
+    /// File name of the output
+    pub name: String,
+}
+
+/// Artifact information in API responses
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArtifactDTO {
+    pub id: String,
+    pub build_id: String,
+    pub target: String,
+    pub name: String,
+    pub size: i64,
+    pub created_at: String,
+    pub expires_at: String,
+}
+
+impl ArtifactDTO {
+    fn from_model(model: artifacts::Model) -> Self {
+        Self {
+            id: model.id.to_string(),
+            build_id: model.build_id.to_string(),
+            target: model.target,
+            name: model.name,
+            size: model.size,
+            created_at: model.created_at.with_timezone(&Utc).to_rfc3339(),
+            expires_at: model.expires_at.with_timezone(&Utc).to_rfc3339(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/builds/{build_id}/artifacts",
+    params(
+        ("build_id" = String, Path, description = "Build that produced the artifact"),
+        ArtifactUploadQuery
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "Artifact stored", body = ArtifactDTO),
+        (status = 400, description = "Invalid artifact name or body length", body = serde_json::Value),
+        (status = 401, description = "Missing or invalid upload token", body = serde_json::Value),
+        (status = 409, description = "Build is not running", body = serde_json::Value),
+        (status = 411, description = "Content-Length missing", body = serde_json::Value),
+        (status = 413, description = "Artifact exceeds the size limit", body = serde_json::Value),
+        (status = 500, description = "Internal error", body = serde_json::Value)
+    )
+)]
+/// Store an output artifact of a running build, streamed as the request body.
+///
+/// Only the worker running the build may upload, authenticated by the upload
+/// token it received with the task.
+pub async fn upload_artifact_handler(
+    State(state): State<AppState>,
+    Path(build_id): Path<Uuid>,
+    Query(query): Query<ArtifactUploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<ArtifactDTO>, ApiError> {
+    authorize_upload(&state, build_id, &headers)?;
+    if query.name.is_empty()
+        || query.name.contains(['/', '\\'])
+        || query.name.chars().any(char::is_control)
+    {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Artifact name must be a plain file name",
+        ));
+    }
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok())
+        .ok_or_else(|| api_error(StatusCode::LENGTH_REQUIRED, "Content-Length is required"))?;
+    if declared > max_size() {
+        return Err(api_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Artifact exceeds the size limit",
+        ));
+    }
+
+    // Object streams must be `Sync`, which the request body is not. The
+    // forwarding task counts the bytes and fails the stream once the body
+    // outgrows its Content-Length.
+    let (tx, rx) = tokio::sync::mpsc::channel(8);
+    let mut data = body.into_data_stream();
+    let forward = tokio::spawn(async move {
+        let mut received = 0i64;
+        while let Some(chunk) = data.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                    return Err(api_error(StatusCode::BAD_REQUEST, "Failed to read body"));
+                }
+            };
+            received += chunk.len() as i64;
+            if received > declared {
+                let _ = tx
+                    .send(Err(std::io::Error::other("body exceeds Content-Length")))
+                    .await;
+                return Err(api_error(
+                    StatusCode::BAD_REQUEST,
+                    "Body is longer than Content-Length",
+                ));
+            }
+            if tx.send(Ok(chunk)).await.is_err() {
+                break;
+            }
+        }
+        Ok(received)
+    });
+    let stream: ObjectByteStream = Box::pin(ReceiverStream::new(rx));
+
+    let id = Uuid::now_v7();
+    let meta = ObjectMeta {
+        size: declared,
+        content_type: Some("application/octet-stream".to_string()),
+        ..Default::default()
+    };
+    let stored = state
+        .artifact_storage
+        .put(&object_key(id), stream, meta)
+        .await;
+    let received = forward.await.unwrap_or_else(|e| {
+        tracing::error!("Artifact upload of build {build_id} panicked: {e}");
+        Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to store artifact",
+        ))
+    });
+    let size = match (stored, received) {
+        (_, Err(e)) => Err(e),
+        (Err(e), _) => {
+            tracing::error!("Failed to store artifact of build {build_id}: {e}");
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store artifact",
+            ))
+        }
+        (Ok(()), Ok(n)) if n < declared => Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Body is shorter than Content-Length",
+        )),
+        (Ok(()), Ok(n)) => Ok(n),
+    };
+    let size = match size {
+        Ok(size) => size,
+        Err(e) => {
+            // Drop whatever part of the object was written
+            let _ = state.artifact_storage.delete(&object_key(id)).await;
+            return Err(e);
+        }
+    };
+
+    let now = Utc::now();
+    let model = artifacts::ActiveModel {
+        id: Set(id),
+        build_id: Set(build_id),
+        target: Set(query.target),
+        name: Set(query.name),
+        size: Set(size),
+        created_at: Set(now.into()),
+        expires_at: Set((now + retention()).into()),
+    }
+    .insert(&state.conn)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to insert artifact of build {build_id}: {e}");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to record artifact",
+        )
+    })?;
+
+    tracing::info!(
+        "Stored artifact {} ({} bytes) of build {}",
+        model.name,
+        model.size,
+        build_id
+    );
+    Ok(Json(ArtifactDTO::from_model(model)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/builds/{build_id}/artifacts",
+    params(
+        ("build_id" = String, Path, description = "Build whose artifacts to list")
+    ),
+    responses(
+        (status = 200, description = "Unexpired artifacts of the build", body = [ArtifactDTO]),
+        (status = 500, description = "Internal error", body = serde_json::Value)
+    )
+)]
+/// List the artifacts of a build that have not expired yet
+pub async fn build_artifacts_handler(
+    State(state): State<AppState>,
+    Path(build_id): Path<Uuid>,
+) -> Result<Json<Vec<ArtifactDTO>>, ApiError> {
+    let models = artifacts::Entity::find()
+        .filter(artifacts::Column::BuildId.eq(build_id))
+        .filter(artifacts::Column::ExpiresAt.gt(Utc::now()))
+        .order_by_asc(artifacts::Column::Target)
+        .order_by_asc(artifacts::Column::Name)
+        .all(&state.conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch artifacts of build {build_id}: {e}");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to fetch artifacts",
+            )
+        })?;
+    Ok(Json(
+        models.into_iter().map(ArtifactDTO::from_model).collect(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/artifacts/{id}",
+    params(
+        ("id" = String, Path, description = "Artifact ID")
+    ),
+    responses(
+        (status = 200, description = "Artifact content", content_type = "application/octet-stream"),
+        (status = 404, description = "Artifact not found or expired", body = serde_json::Value),
+        (status = 500, description = "Internal error", body = serde_json::Value)
+    )
+)]
+/// Download an artifact
+pub async fn download_artifact_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let model = artifacts::Entity::find_by_id(id)
+        .one(&state.conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch artifact {id}: {e}");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to fetch artifact",
+            )
+        })?
+        .filter(|m| m.expires_at.with_timezone(&Utc) > Utc::now())
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Artifact not found"))?;
+
+    let (stream, _) = state
+        .artifact_storage
+        .get(&object_key(id))
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to read artifact {id}: {e}");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read artifact")
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, model.size.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                content_disposition(&model.name),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Background task that removes expired artifacts from storage and database
+pub async fn start_artifact_purge_task(state: AppState) {
+    loop {
+        tokio::time::sleep(PURGE_INTERVAL).await;
+        match purge_expired_artifacts(&state).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Purged {n} expired artifacts"),
+            Err(e) => tracing::error!("Failed to purge expired artifacts: {e}"),
+        }
+    }
+}
+
+async fn purge_expired_artifacts(state: &AppState) -> Result<usize, sea_orm::DbErr> {
+    let mut purged = 0;
+    loop {
+        let expired = artifacts::Entity::find()
+            .filter(artifacts::Column::ExpiresAt.lte(Utc::now()))
+            .limit(PURGE_BATCH_SIZE)
+            .all(&state.conn)
+            .await?;
+        if expired.is_empty() {
+            return Ok(purged);
+        }
+
+        let mut ids = Vec::with_capacity(expired.len());
+        for artifact in expired {
+            // Keep the row if the object could not be removed, so it is retried
+            match state
+                .artifact_storage
+                .delete(&object_key(artifact.id))
+                .await
+            {
+                Ok(()) => ids.push(artifact.id),
+                Err(e) => tracing::warn!("Failed to delete artifact {}: {e}", artifact.id),
+            }
+        }
+        if ids.is_empty() {
+            return Ok(purged);
+        }
+        purged += ids.len();
+        artifacts::Entity::delete_many()
+            .filter(artifacts::Column::Id.is_in(ids))
+            .exec(&state.conn)
+            .await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(content_disposition("app"), "attachment; filename=\"app\"");
+        assert_eq!(
+            content_disposition("a\"b\\c\r\nd"),
+            "attachment; filename=\"a\\\"b\\\\cd\""
+        );
+    }
+}
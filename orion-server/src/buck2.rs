@@ -77,6 +77,18 @@ pub fn file_blob_endpoint() -> String {
     format!("{}/api/v1/file/blob", base_url())
 }
 
+/// Personal access token orion-server authenticates to mono with, if any
+pub fn mono_api_token() -> Option<String> {
+    std::env::var("MONO_API_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty())
+}
+
+/// Get the endpoint that re-runs the merge checks of a CL
+pub fn cl_recheck_endpoint(cl_link: &str) -> String {
+    format!("{}/api/v1/cl/{cl_link}/recheck", base_url())
+}
+
 /// Execute buck2 targets //... command in the given directory and return the last line string
 #[allow(dead_code)]
 pub fn get_buck2_targets_last_line(directory: &str) -> Result<String, Box<dyn std::error::Error>> {
@@ -1,8 +1,10 @@
 pub mod api;
+pub mod artifact;
 pub mod auto_retry;
 pub mod buck2;
 pub mod common;
 pub mod log;
 pub mod model;
+pub mod routing;
 pub mod scheduler;
 pub mod server;
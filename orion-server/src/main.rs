@@ -1,9 +1,11 @@
 mod api;
+mod artifact;
 mod auto_retry;
 mod buck2;
 mod common;
 mod log;
 mod model;
+mod routing;
 mod scheduler;
 mod server;
 
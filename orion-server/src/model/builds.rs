@@ -22,6 +22,8 @@ pub struct Model {
     pub output_file: String,
     pub created_at: DateTimeWithTimeZone,
     pub retry_count: u32,
+    /// Set when the build was superseded by a newer build of the same CL
+    pub cancelled: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -66,6 +68,7 @@ impl Model {
             output_file: Set(format!("./logs/{}", build_id)),
             created_at: Set(now),
             retry_count: Set(0),
+            cancelled: Set(false),
         }
     }
 
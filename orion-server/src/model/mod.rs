@@ -1,2 +1,5 @@
+pub mod artifacts;
 pub mod builds;
+pub mod pending_tasks;
 pub mod tasks;
+pub mod test_results;
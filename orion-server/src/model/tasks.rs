@@ -15,6 +15,7 @@ pub struct Model {
     #[sea_orm(column_type = "JsonBinary", nullable)]
     pub template: Option<Json>,
     pub created_at: DateTimeWithTimeZone,
+    pub commit_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -62,6 +63,7 @@ impl Model {
         task_name: Option<String>,
         template: Option<Json>,
         created_at: DateTimeWithTimeZone,
+        commit_id: Option<String>,
     ) -> ActiveModel {
         ActiveModel {
             id: Set(task_id),
@@ -69,6 +71,7 @@ impl Model {
             task_name: Set(task_name),
             template: Set(template),
             created_at: Set(created_at),
+            commit_id: Set(commit_id),
         }
     }
 
@@ -79,9 +82,11 @@ impl Model {
         task_name: Option<String>,
         template: Option<Json>,
         created_at: DateTimeWithTimeZone,
+        commit_id: Option<String>,
         db: &impl ConnectionTrait,
     ) -> Result<Model, DbErr> {
-        let task_model = Self::create_task(task_id, cl_id, task_name, template, created_at);
+        let task_model =
+            Self::create_task(task_id, cl_id, task_name, template, created_at, commit_id);
         task_model.insert(db).await
     }
 }
@@ -0,0 +1,79 @@
+//! Per-path worker requirements for the builds of a repository.
+//!
+//! Rules are read from the JSON file named by `ORION_ROUTING_RULES`, e.g.
+//! `[{"path": "/project/gpu", "labels": ["gpu-free"], "min_memory_mb": 32768}]`.
+//! Every rule whose `path` contains the build's repository applies.
+
+use orion::capability::TaskRequirements;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoutingRule {
+    /// Repository path the rule covers, including everything below it
+    pub path: String,
+    #[serde(flatten)]
+    pub requirements: TaskRequirements,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RoutingRules(Vec<RoutingRule>);
+
+impl RoutingRules {
+    /// Loads the rules from `ORION_ROUTING_RULES`; no rules if it is unset.
+    pub fn load() -> Self {
+        let Ok(file) = std::env::var("ORION_ROUTING_RULES") else {
+            return Self::default();
+        };
+        let content = std::fs::read_to_string(&file)
+            .unwrap_or_else(|e| panic!("Failed to read ORION_ROUTING_RULES file {file}: {e}"));
+        let rules: Vec<RoutingRule> = serde_json::from_str(&content)
+            .unwrap_or_else(|e| panic!("Invalid ORION_ROUTING_RULES file {file}: {e}"));
+        tracing::info!("Loaded {} routing rules from {}", rules.len(), file);
+        Self(rules)
+    }
+
+    /// Combined requirements of the rules covering `repo`
+    pub fn requirements_for(&self, repo: &str) -> TaskRequirements {
+        let mut requirements = TaskRequirements::default();
+        for rule in &self.0 {
+            let path = rule.path.trim_end_matches('/');
+            let covered = match repo.strip_prefix(path) {
+                Some(rest) => path.is_empty() || rest.is_empty() || rest.starts_with('/'),
+                None => false,
+            };
+            if covered {
+                requirements.merge(&rule.requirements);
+            }
+        }
+        requirements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requirements_for() {
+        let rules: Vec<RoutingRule> = serde_json::from_str(
+            r#"[
+                {"path": "/", "os": "linux"},
+                {"path": "/project/gpu/", "labels": ["gpu-free"], "min_cpus": 8},
+                {"path": "/project/gpu/train", "min_cpus": 32}
+            ]"#,
+        )
+        .unwrap();
+        let rules = RoutingRules(rules);
+
+        let req = rules.requirements_for("/project/gpu/train");
+        assert_eq!(req.os.as_deref(), Some("linux"));
+        assert_eq!(req.min_cpus, Some(32));
+        assert_eq!(req.labels, ["gpu-free"]);
+
+        let req = rules.requirements_for("/project/gpu-legacy");
+        assert_eq!(req.min_cpus, None);
+        assert!(req.labels.is_empty());
+
+        assert!(RoutingRules::default().requirements_for("/any").is_empty());
+    }
+}
@@ -1,16 +1,19 @@
 use crate::api::CoreWorkerStatus;
 use crate::auto_retry::AutoRetryJudger;
 use crate::log::log_service::LogService;
-use crate::model::builds;
+use crate::model::{builds, pending_tasks, tasks};
+use crate::routing::RoutingRules;
 use chrono::FixedOffset;
 use dashmap::DashMap;
+use orion::capability::{TaskRequirements, WorkerCapabilities};
 use orion::repo::sapling::status::{ProjectRelativePath, Status};
 use orion::ws::{TaskPhase, WSMessage};
-use rand::Rng;
-use sea_orm::ActiveModelTrait;
+use rand::seq::SliceRandom;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
 use sea_orm::{ActiveValue::Set, DatabaseConnection, prelude::DateTimeUtc};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, Notify, mpsc::UnboundedSender};
@@ -34,6 +37,132 @@ pub struct PendingTask {
     pub cl: i64,
     pub request: BuildRequest,
     pub created_at: Instant,
+    /// Worker capabilities the build needs
+    pub requirements: TaskRequirements,
+    /// Why the build is still queued, set once a dispatch attempt skipped it
+    pub blocked_reason: Option<String>,
+    pub priority: TaskPriority,
+    /// Author of the CL, used for fair-share between users
+    pub username: Option<String>,
+    /// Speculative merge queue commit to build instead of the CL's latest revision
+    pub commit_id: Option<String>,
+    pub origin: TaskOrigin,
+}
+
+/// Dispatch priority of a queued build
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskPriority {
+    /// Documentation, refactoring, non-critical work
+    Low,
+    #[default]
+    Normal,
+    /// Merge queue builds and other work blocking a merge
+    High,
+    /// Hotfixes and urgent production issues
+    Critical,
+}
+
+/// Where a build request comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskOrigin {
+    /// A CL was created or updated
+    #[default]
+    Cl,
+    /// Speculative build of a merge queue batch
+    MergeQueue,
+}
+
+impl TaskOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cl => "cl",
+            Self::MergeQueue => "merge_queue",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [Self::Cl, Self::MergeQueue]
+            .into_iter()
+            .find(|origin| origin.as_str() == s)
+    }
+}
+
+impl TaskPriority {
+    const LEVELS: [TaskPriority; 4] = [Self::Low, Self::Normal, Self::High, Self::Critical];
+
+    /// Priority of a new build: an explicit priority or a `priority:<level>` CL
+    /// label wins, otherwise `High` for `hotfix`, `urgent` or `critical` labels
+    /// and merge queue builds, and `Normal` for the rest.
+    ///
+    /// CL authors set their own labels, so labels go no higher than `High`;
+    /// `Critical` is only given through the API.
+    pub fn for_request(explicit: Option<Self>, origin: TaskOrigin, labels: &[String]) -> Self {
+        if let Some(priority) = explicit {
+            return priority;
+        }
+        let labels: Vec<String> = labels.iter().map(|label| label.to_lowercase()).collect();
+        let from_label = labels.iter().find_map(|label| {
+            label
+                .strip_prefix("priority:")
+                .and_then(|level| Self::parse(level.trim()))
+        });
+        if let Some(priority) = from_label {
+            return priority.min(Self::High);
+        }
+        if labels
+            .iter()
+            .any(|label| matches!(label.as_str(), "hotfix" | "urgent" | "critical"))
+        {
+            return Self::High;
+        }
+        match origin {
+            TaskOrigin::Cl => Self::Normal,
+            TaskOrigin::MergeQueue => Self::High,
+        }
+    }
+
+    /// This priority raised by `levels` for the time a build waited, capped at
+    /// `High` so waiting builds never compete with `Critical` ones
+    pub fn raised(self, levels: u64) -> Self {
+        let index = (self as usize).saturating_add(usize::try_from(levels).unwrap_or(usize::MAX));
+        self.max(Self::LEVELS[index.min(Self::High as usize)])
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+            Self::Critical => "critical",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::LEVELS.into_iter().find(|level| level.as_str() == s)
+    }
+}
+
+/// How queued builds are grouped when sharing the workers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FairShareKey {
+    /// By CL author, falling back to the repository path
+    #[default]
+    User,
+    /// By repository path
+    Path,
+}
+
+impl FairShareKey {
+    fn key(self, username: Option<&str>, repo: &str) -> String {
+        match (self, username) {
+            (Self::User, Some(username)) => format!("user:{username}"),
+            _ => format!("path:{repo}"),
+        }
+    }
 }
 
 /// Task queue configuration
@@ -41,26 +170,61 @@ pub struct PendingTask {
 pub struct TaskQueueConfig {
     /// Maximum queue length
     pub max_queue_size: usize,
-    /// Maximum wait time for tasks in queue
-    pub max_wait_time: Duration,
+    /// Maximum wait time for tasks in queue, after which they are cancelled.
+    /// `None` keeps them queued until a worker takes them.
+    pub max_wait_time: Option<Duration>,
     /// Queue cleanup interval
     pub cleanup_interval: Duration,
+    /// Waiting time after which a queued build is raised one priority level
+    pub aging_interval: Duration,
+    /// Grouping used to share workers between users or paths
+    pub fair_share: FairShareKey,
 }
 
 impl Default for TaskQueueConfig {
     fn default() -> Self {
         Self {
             max_queue_size: 1000,
-            max_wait_time: Duration::from_secs(300), // 5 minutes
+            max_wait_time: None,
             cleanup_interval: Duration::from_secs(30), // Cleanup every 30 seconds
+            aging_interval: Duration::from_secs(60),   // One level per minute waited
+            fair_share: FairShareKey::User,
         }
     }
 }
 
-/// Simple FIFO task queue
+impl TaskQueueConfig {
+    /// Default configuration with `ORION_FAIR_SHARE` (`user` or `path`),
+    /// `ORION_QUEUE_AGING_SECS` and `ORION_QUEUE_MAX_WAIT_SECS` applied
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(value) = std::env::var("ORION_FAIR_SHARE") {
+            config.fair_share = match value.as_str() {
+                "path" => FairShareKey::Path,
+                "user" => FairShareKey::User,
+                other => panic!("Invalid ORION_FAIR_SHARE: {other}, expected user or path"),
+            };
+        }
+        if let Ok(value) = std::env::var("ORION_QUEUE_AGING_SECS") {
+            let secs: u64 = value
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid ORION_QUEUE_AGING_SECS {value}: {e}"));
+            config.aging_interval = Duration::from_secs(secs.max(1));
+        }
+        if let Ok(value) = std::env::var("ORION_QUEUE_MAX_WAIT_SECS") {
+            let secs: u64 = value
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid ORION_QUEUE_MAX_WAIT_SECS {value}: {e}"));
+            config.max_wait_time = Some(Duration::from_secs(secs));
+        }
+        config
+    }
+}
+
+/// Task queue ordered by priority, aging and fair-share
 #[derive(Debug)]
 pub struct TaskQueue {
-    /// Queue storage (FIFO)
+    /// Queue storage in arrival order
     queue: VecDeque<PendingTask>,
     /// Queue configuration
     config: TaskQueueConfig,
@@ -85,18 +249,48 @@ impl TaskQueue {
         Ok(())
     }
 
-    /// Remove task-bound build from the front of queue
+    /// Remove the task-bound build to dispatch next
+    #[allow(dead_code)]
     pub fn dequeue(&mut self) -> Option<PendingTask> {
-        self.queue.pop_front()
+        let order = self.dispatch_order(Instant::now());
+        let index = next_index(&order, &HashMap::new(), &vec![false; order.len()])?;
+        self.queue.remove(index)
+    }
+
+    /// Sort key of every queued build: the priority raised one level per
+    /// `aging_interval` waited so low priority builds cannot starve, the
+    /// fair-share group, and the arrival time.
+    fn dispatch_order(&self, now: Instant) -> Vec<(Reverse<TaskPriority>, String, Instant)> {
+        let aging_secs = self.config.aging_interval.as_secs().max(1);
+        self.queue
+            .iter()
+            .map(|task| {
+                let waited = now.saturating_duration_since(task.created_at).as_secs();
+                (
+                    Reverse(task.priority.raised(waited / aging_secs)),
+                    self.config
+                        .fair_share
+                        .key(task.username.as_deref(), &task.repo),
+                    task.created_at,
+                )
+            })
+            .collect()
     }
 
     /// Clean up expired task-bound build
+    ///
+    /// Builds blocked on worker capabilities are kept: they wait for a
+    /// matching worker to join, however long that takes.
     pub fn cleanup_expired(&mut self) -> Vec<PendingTask> {
+        let Some(max_wait_time) = self.config.max_wait_time else {
+            return Vec::new();
+        };
         let now = Instant::now();
         let mut expired_tasks = Vec::new();
 
         self.queue.retain(|task| {
-            if now.duration_since(task.created_at) > self.config.max_wait_time {
+            if task.blocked_reason.is_none() && now.duration_since(task.created_at) > max_wait_time
+            {
                 expired_tasks.push(task.clone());
                 false
             } else {
@@ -107,24 +301,126 @@ impl TaskQueue {
         expired_tasks
     }
 
+    /// Remove the queued builds `pick` assigns a worker to, in dispatch order.
+    ///
+    /// Builds are offered by effective priority, then to the fair-share group with
+    /// the fewest `running` builds, then by arrival. `running` is counted up as
+    /// builds are taken so a single group cannot take every idle worker. `pick`
+    /// returns the chosen worker id, or the reason the build has to wait, which is
+    /// recorded on the build left in the queue.
+    pub fn take_matching(
+        &mut self,
+        mut running: HashMap<String, usize>,
+        mut pick: impl FnMut(&PendingTask) -> Result<String, String>,
+    ) -> Vec<(PendingTask, String)> {
+        let order = self.dispatch_order(Instant::now());
+        let mut visited = vec![false; order.len()];
+        let mut chosen = Vec::new();
+        while let Some(index) = next_index(&order, &running, &visited) {
+            visited[index] = true;
+            match pick(&self.queue[index]) {
+                Ok(worker_id) => {
+                    *running.entry(order[index].1.clone()).or_default() += 1;
+                    chosen.push((index, worker_id));
+                }
+                Err(reason) => self.queue[index].blocked_reason = Some(reason),
+            }
+        }
+
+        let mut slots: Vec<Option<PendingTask>> = self.queue.drain(..).map(Some).collect();
+        let taken = chosen
+            .into_iter()
+            .filter_map(|(index, worker_id)| slots[index].take().map(|task| (task, worker_id)))
+            .collect();
+        self.queue = slots.into_iter().flatten().collect();
+        taken
+    }
+
+    /// Put a build back in the queue, e.g. after a worker rejected it
+    pub fn requeue(&mut self, task: PendingTask) {
+        self.queue.push_back(task);
+    }
+
+    /// Fair-share group of a build
+    pub fn share_key(&self, username: Option<&str>, repo: &str) -> String {
+        self.config.fair_share.key(username, repo)
+    }
+
+    /// Remove the queued builds of `cl` made for a CL revision, except those
+    /// of the tasks in `keep`
+    pub fn remove_cl(&mut self, cl: i64, keep: &HashSet<Uuid>) -> Vec<PendingTask> {
+        let mut removed = Vec::new();
+        self.queue.retain(|task| {
+            if task.cl == cl && task.origin == TaskOrigin::Cl && !keep.contains(&task.task_id) {
+                removed.push(task.clone());
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
     /// Get queue statistics
     pub fn get_stats(&self) -> TaskQueueStats {
         TaskQueueStats {
             total_queued: self.queue.len(),
             oldest_task_age_seconds: self
                 .queue
-                .front()
-                .map(|task| Instant::now().duration_since(task.created_at).as_secs()),
+                .iter()
+                .map(|task| Instant::now().duration_since(task.created_at).as_secs())
+                .max(),
+            blocked: self
+                .queue
+                .iter()
+                .filter_map(|task| {
+                    task.blocked_reason.as_ref().map(|reason| BlockedTask {
+                        build_id: task.build_id.to_string(),
+                        cl: task.cl,
+                        reason: reason.clone(),
+                    })
+                })
+                .collect(),
         }
     }
 }
 
+/// Index of the next build in `order` among those not `visited`
+fn next_index(
+    order: &[(Reverse<TaskPriority>, String, Instant)],
+    running: &HashMap<String, usize>,
+    visited: &[bool],
+) -> Option<usize> {
+    order
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !visited[*index])
+        .min_by_key(|(_, (priority, key, created_at))| {
+            (
+                *priority,
+                running.get(key).copied().unwrap_or(0),
+                *created_at,
+            )
+        })
+        .map(|(index, _)| index)
+}
+
 /// Queue statistics
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct TaskQueueStats {
     pub total_queued: usize,
     /// Age of oldest task in seconds
     pub oldest_task_age_seconds: Option<u64>,
+    /// Queued builds no idle worker could take, with the reason
+    pub blocked: Vec<BlockedTask>,
+}
+
+/// Queued build waiting for a worker that meets its requirements
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BlockedTask {
+    pub build_id: String,
+    pub cl: i64,
+    pub reason: String,
 }
 
 /// Information about an active build task
@@ -137,9 +433,19 @@ pub struct BuildInfo {
     pub start_at: DateTimeUtc,
     pub changes: Vec<Status<ProjectRelativePath>>,
     pub cl: String,
+    pub cl_link: String,
     pub _worker_id: String,
     pub auto_retry_judger: AutoRetryJudger,
     pub retry_count: u32,
+    pub requirements: TaskRequirements,
+    pub priority: TaskPriority,
+    pub username: Option<String>,
+    pub commit_id: Option<String>,
+    pub origin: TaskOrigin,
+    /// When the build was first queued, kept if it has to wait again
+    pub queued_at: Instant,
+    /// Secret sent to the worker with the task, required to upload artifacts
+    pub upload_token: String,
 }
 
 /// Status of a worker node
@@ -176,6 +482,7 @@ pub struct WorkerInfo {
     pub hostname: String,
     pub start_time: DateTimeUtc,
     pub orion_version: String,
+    pub capabilities: WorkerCapabilities,
 }
 
 /// Task scheduler - manages task queue and worker assignment
@@ -191,6 +498,8 @@ pub struct TaskScheduler {
     pub active_builds: Arc<DashMap<String, BuildInfo>>,
     /// Database connection
     pub conn: DatabaseConnection,
+    /// Per-path worker requirements
+    pub routing: Arc<RoutingRules>,
 }
 
 /// Log segment read result
@@ -234,6 +543,7 @@ impl TaskScheduler {
         workers: Arc<DashMap<String, WorkerInfo>>,
         active_builds: Arc<DashMap<String, BuildInfo>>,
         queue_config: Option<TaskQueueConfig>,
+        routing: Arc<RoutingRules>,
     ) -> Self {
         let config = queue_config.unwrap_or_default();
         Self {
@@ -242,33 +552,24 @@ impl TaskScheduler {
             workers,
             active_builds,
             conn,
+            routing,
         }
     }
 
-    /// Add task-bound build to queue
-    pub async fn enqueue_task(
-        &self,
-        task_id: Uuid,
-        cl_link: &str,
-        request: BuildRequest,
-        repo: String,
-        cl: i64,
-    ) -> Result<Uuid, String> {
-        let build_id = Uuid::now_v7();
-
-        let pending_task = PendingTask {
-            task_id,
-            cl_link: cl_link.to_string(),
-            build_id,
-            request,
-            created_at: Instant::now(),
-            repo,
-            cl,
-        };
+    /// Add task-bound build to queue, persisting it so it survives a restart
+    pub async fn enqueue_task(&self, task: PendingTask) -> Result<Uuid, String> {
+        let build_id = task.build_id;
+        // Persist before the build becomes visible to dispatch, which deletes
+        // the row once a worker takes it
+        let model = pending_tasks::Model::create_pending_task(&task);
+        if let Err(e) = model.insert(&self.conn).await {
+            tracing::error!("Failed to persist queued build {}: {}", build_id, e);
+        }
 
-        {
-            let mut queue = self.pending_tasks.lock().await;
-            queue.enqueue(pending_task)?;
+        let enqueued = self.pending_tasks.lock().await.enqueue(task);
+        if let Err(e) = enqueued {
+            self.forget_pending_tasks(vec![build_id]).await;
+            return Err(e);
         }
 
         // Notify that there's a new task to process
@@ -276,23 +577,228 @@ impl TaskScheduler {
         Ok(build_id)
     }
 
+    /// Load the builds queued before a restart, called once before the queue
+    /// manager starts. Returns the number of restored builds.
+    pub async fn restore_pending_tasks(&self) -> usize {
+        let models = match pending_tasks::Entity::find()
+            .order_by_asc(pending_tasks::Column::CreatedAt)
+            .all(&self.conn)
+            .await
+        {
+            Ok(models) => models,
+            Err(e) => {
+                tracing::error!("Failed to load queued builds: {}", e);
+                return 0;
+            }
+        };
+
+        let mut dropped = Vec::new();
+        let mut restored = 0;
+        {
+            let mut queue = self.pending_tasks.lock().await;
+            for model in models {
+                let build_id = model.build_id;
+                match model.into_pending_task() {
+                    Some(task) => match queue.enqueue(task) {
+                        Ok(()) => restored += 1,
+                        Err(e) => {
+                            tracing::warn!("Dropping queued build {}: {}", build_id, e);
+                            dropped.push(build_id);
+                        }
+                    },
+                    None => {
+                        tracing::warn!("Dropping unreadable queued build {}", build_id);
+                        dropped.push(build_id);
+                    }
+                }
+            }
+        }
+        self.forget_pending_tasks(dropped).await;
+
+        if restored > 0 {
+            self.task_notifier.notify_one();
+        }
+        restored
+    }
+
+    /// Delete the persisted copies of builds that left the queue
+    async fn forget_pending_tasks(&self, build_ids: Vec<Uuid>) {
+        if build_ids.is_empty() {
+            return;
+        }
+        let res = pending_tasks::Entity::delete_many()
+            .filter(pending_tasks::Column::BuildId.is_in(build_ids))
+            .exec(&self.conn)
+            .await;
+        if let Err(e) = res {
+            tracing::error!("Failed to delete persisted queued builds: {}", e);
+        }
+    }
+
     /// Get queue statistics
     pub async fn get_queue_stats(&self) -> TaskQueueStats {
         let queue = self.pending_tasks.lock().await;
         queue.get_stats()
     }
 
-    /// Clean up expired task-bound builds
+    /// Clean up expired task-bound builds, which are recorded as cancelled
     pub async fn cleanup_expired_tasks(&self) -> Vec<PendingTask> {
-        let mut queue = self.pending_tasks.lock().await;
-        queue.cleanup_expired()
+        let expired = {
+            let mut queue = self.pending_tasks.lock().await;
+            queue.cleanup_expired()
+        };
+        self.forget_pending_tasks(expired.iter().map(|task| task.build_id).collect())
+            .await;
+        for task in &expired {
+            self.record_cancelled_build(task).await;
+        }
+        expired
     }
 
-    /// Check if there are available workers
-    pub fn has_idle_workers(&self) -> bool {
-        self.workers
+    /// Keep a record of a queued build cancelled before it ran, so its task
+    /// shows what happened to it
+    async fn record_cancelled_build(&self, task: &PendingTask) {
+        let now = chrono::Utc::now().with_timezone(&FixedOffset::east_opt(0).unwrap());
+        let res = builds::ActiveModel {
+            id: Set(task.build_id),
+            task_id: Set(task.task_id),
+            exit_code: Set(None),
+            start_at: Set(now),
+            end_at: Set(Some(now)),
+            repo: Set(task.repo.clone()),
+            target: Set("//...".to_string()),
+            args: Set(None),
+            output_file: Set(build_output_file(task.task_id, &task.repo, task.build_id)),
+            created_at: Set(now),
+            retry_count: Set(0),
+            cancelled: Set(true),
+        }
+        .insert(&self.conn)
+        .await;
+        if let Err(e) = res {
+            tracing::error!("Failed to record cancelled build {}: {}", task.build_id, e);
+        }
+    }
+
+    /// Cancel the queued and running builds of `cl` made for a revision other
+    /// than `commit_id`, called before a newer revision of the CL is built so
+    /// stale builds stop holding workers. Merge queue builds are left alone.
+    ///
+    /// Running builds are sent `CancelTask`; their worker becomes idle again once
+    /// it reports the build complete. Returns the ids of the cancelled builds.
+    pub async fn supersede_cl_builds(&self, cl: i64, commit_id: Option<&str>) -> Vec<Uuid> {
+        let now = chrono::Utc::now().with_timezone(&FixedOffset::east_opt(0).unwrap());
+        let mut cancelled = Vec::new();
+
+        // Builds of tasks for the same revision are not stale
+        let current: HashSet<Uuid> = match commit_id {
+            Some(commit_id) => tasks::Entity::find()
+                .filter(tasks::Column::ClId.eq(cl))
+                .filter(tasks::Column::CommitId.eq(commit_id))
+                .all(&self.conn)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Failed to load the tasks of CL {}: {}", cl, e);
+                    vec![]
+                })
+                .into_iter()
+                .map(|task| task.id)
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        let queued = {
+            let mut queue = self.pending_tasks.lock().await;
+            queue.remove_cl(cl, &current)
+        };
+        self.forget_pending_tasks(queued.iter().map(|task| task.build_id).collect())
+            .await;
+        for task in queued {
+            // Queued builds have no record yet
+            self.record_cancelled_build(&task).await;
+            cancelled.push(task.build_id);
+        }
+
+        let cl = cl.to_string();
+        let running: Vec<String> = self
+            .active_builds
             .iter()
-            .any(|entry| matches!(entry.value().status, WorkerStatus::Idle))
+            .filter(|entry| {
+                let build = entry.value();
+                build.cl == cl
+                    && build.origin == TaskOrigin::Cl
+                    && build
+                        .task_id
+                        .parse()
+                        .is_ok_and(|task_id| !current.contains(&task_id))
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+        for build_id in running {
+            let Some((_, build_info)) = self.active_builds.remove(&build_id) else {
+                continue;
+            };
+            let sent = self
+                .workers
+                .get(&build_info._worker_id)
+                .is_some_and(|worker| {
+                    worker
+                        .sender
+                        .send(WSMessage::CancelTask {
+                            id: build_id.clone(),
+                        })
+                        .is_ok()
+                });
+            if !sent {
+                tracing::warn!(
+                    "Failed to send CancelTask for build {} to worker {}",
+                    build_id,
+                    build_info._worker_id
+                );
+            }
+
+            let Ok(id) = build_id.parse::<Uuid>() else {
+                continue;
+            };
+            let res = builds::Entity::update_many()
+                .set(builds::ActiveModel {
+                    end_at: Set(Some(now)),
+                    cancelled: Set(true),
+                    ..Default::default()
+                })
+                .filter(builds::Column::Id.eq(id))
+                .exec(&self.conn)
+                .await;
+            if let Err(e) = res {
+                tracing::error!("Failed to mark build {} as cancelled: {}", build_id, e);
+            }
+            cancelled.push(id);
+        }
+
+        cancelled
+    }
+
+    /// Put a build rejected by its worker back in the queue
+    pub async fn requeue_task(&self, task: PendingTask) {
+        let build_id = task.build_id;
+        let model = pending_tasks::Model::create_pending_task(&task);
+        if let Err(e) = model.insert(&self.conn).await {
+            tracing::error!("Failed to persist requeued build {}: {}", build_id, e);
+        }
+        self.pending_tasks.lock().await.requeue(task);
+        self.task_notifier.notify_one();
+    }
+
+    /// Running builds of each fair-share group
+    fn running_shares(&self, queue: &TaskQueue) -> HashMap<String, usize> {
+        let mut running = HashMap::new();
+        for entry in self.active_builds.iter() {
+            let build = entry.value();
+            *running
+                .entry(queue.share_key(build.username.as_deref(), &build.repo))
+                .or_default() += 1;
+        }
+        running
     }
 
     /// Get list of idle workers
@@ -304,38 +810,74 @@ impl TaskScheduler {
             .collect()
     }
 
-    /// Try to dispatch queued task-bound builds (concurrent safe)
-    pub async fn process_pending_tasks(&self) {
-        // Get available workers
-        let idle_workers = self.get_idle_workers();
-        if idle_workers.is_empty() {
-            return;
+    /// Get list of idle workers meeting `requirements`
+    pub fn get_idle_workers_for(&self, requirements: &TaskRequirements) -> Vec<String> {
+        self.workers
+            .iter()
+            .filter(|entry| {
+                matches!(entry.value().status, WorkerStatus::Idle)
+                    && requirements.is_met_by(&entry.value().capabilities)
+            })
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Why a build with `requirements` cannot be dispatched right now
+    pub fn blocked_reason(&self, requirements: &TaskRequirements) -> String {
+        if requirements.is_empty() {
+            return "Waiting for an idle worker".to_string();
         }
+        let matched = self
+            .workers
+            .iter()
+            .any(|entry| requirements.is_met_by(&entry.value().capabilities));
+        if matched {
+            "Waiting for a matching worker to become idle".to_string()
+        } else {
+            format!("No connected worker meets the requirements: {requirements}")
+        }
+    }
 
-        // Process tasks in batches, up to the number of idle workers
-        let max_tasks = idle_workers.len();
-        let mut tasks_to_dispatch = Vec::with_capacity(max_tasks);
+    /// Try to dispatch queued task-bound builds (concurrent safe)
+    pub async fn process_pending_tasks(&self) {
+        // Get available workers in random order to spread the load; the queue is
+        // still walked without any so every waiting build gets its reason
+        let mut idle_workers = self.get_idle_workers();
+        idle_workers.shuffle(&mut rand::rng());
 
-        // Batch dequeue tasks
-        {
+        // Assign each queued build the first idle worker meeting its requirements,
+        // builds without one stay queued with the reason
+        let tasks_to_dispatch = {
             let mut queue = self.pending_tasks.lock().await;
-            for _ in 0..max_tasks {
-                if let Some(task) = queue.dequeue() {
-                    tasks_to_dispatch.push(task);
-                } else {
-                    break;
+            let running = self.running_shares(&queue);
+            queue.take_matching(running, |task| {
+                let position = idle_workers.iter().position(|id| {
+                    self.workers
+                        .get(id)
+                        .is_some_and(|worker| task.requirements.is_met_by(&worker.capabilities))
+                });
+                match position {
+                    Some(index) => Ok(idle_workers.swap_remove(index)),
+                    None => Err(self.blocked_reason(&task.requirements)),
                 }
-            }
-        }
+            })
+        };
+        self.forget_pending_tasks(
+            tasks_to_dispatch
+                .iter()
+                .map(|(task, _)| task.build_id)
+                .collect(),
+        )
+        .await;
 
         // Dispatch tasks concurrently
         if !tasks_to_dispatch.is_empty() {
             let dispatch_futures: Vec<_> = tasks_to_dispatch
                 .into_iter()
-                .map(|task| {
+                .map(|(task, worker_id)| {
                     let scheduler = self.clone();
                     tokio::spawn(async move {
-                        if let Err(e) = scheduler.dispatch_task(task).await {
+                        if let Err(e) = scheduler.dispatch_task(task, worker_id).await {
                             tracing::error!("Failed to dispatch queued task: {}", e);
                         }
                     })
@@ -349,20 +891,12 @@ impl TaskScheduler {
         }
     }
 
-    /// Dispatch single task
-    async fn dispatch_task(&self, pending_task: PendingTask) -> Result<(), String> {
-        let idle_workers = self.get_idle_workers();
-        if idle_workers.is_empty() {
-            return Err("No idle workers available".to_string());
-        }
-
-        // Randomly select an idle worker
-        let chosen_index = {
-            let mut rng = rand::rng();
-            rng.random_range(0..idle_workers.len())
-        };
-        let chosen_id = idle_workers[chosen_index].clone();
-
+    /// Dispatch single task to the chosen worker
+    async fn dispatch_task(
+        &self,
+        pending_task: PendingTask,
+        chosen_id: String,
+    ) -> Result<(), String> {
         // Create build information
         let build_info = BuildInfo {
             task_id: pending_task.task_id.to_string(),
@@ -371,9 +905,17 @@ impl TaskScheduler {
             start_at: chrono::Utc::now(),
             changes: pending_task.request.changes.clone(),
             cl: pending_task.cl.to_string(),
+            cl_link: pending_task.cl_link.clone(),
             _worker_id: chosen_id.clone(),
             auto_retry_judger: AutoRetryJudger::new(),
             retry_count: 0,
+            requirements: pending_task.requirements.clone(),
+            priority: pending_task.priority,
+            username: pending_task.username.clone(),
+            commit_id: pending_task.commit_id.clone(),
+            origin: pending_task.origin,
+            queued_at: pending_task.created_at,
+            upload_token: Uuid::new_v4().simple().to_string(),
         };
 
         // Insert build record
@@ -388,16 +930,16 @@ impl TaskScheduler {
             repo: Set(build_info.repo.clone()),
             target: Set("//...".to_string()),
             args: Set(None),
-            output_file: Set(format!(
-                "{}/{}/{}.log",
+            output_file: Set(build_output_file(
                 pending_task.task_id,
-                LogService::last_segment(&pending_task.repo),
-                pending_task.build_id
+                &pending_task.repo,
+                pending_task.build_id,
             )),
             created_at: Set(build_info
                 .start_at
                 .with_timezone(&FixedOffset::east_opt(0).unwrap())),
             retry_count: Set(0),
+            cancelled: Set(false),
         }
         .insert(&self.conn)
         .await;
@@ -410,6 +952,8 @@ impl TaskScheduler {
             repo: pending_task.repo,
             cl_link: pending_task.cl_link.to_string(),
             changes: pending_task.request.changes.clone(),
+            commit_id: pending_task.commit_id.clone(),
+            upload_token: build_info.upload_token.clone(),
         };
 
         // Send task to worker
@@ -507,6 +1051,16 @@ impl TaskScheduler {
     }
 }
 
+/// Log file of a build, relative to the log store root
+fn build_output_file(task_id: Uuid, repo: &str, build_id: Uuid) -> String {
+    format!(
+        "{}/{}/{}.log",
+        task_id,
+        LogService::last_segment(repo),
+        build_id
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -526,6 +1080,12 @@ mod tests {
             repo: "/test/repo".to_string(),
             cl: 123456,
             cl_link: "test".to_string(),
+            requirements: TaskRequirements::default(),
+            blocked_reason: None,
+            priority: TaskPriority::Normal,
+            username: None,
+            commit_id: None,
+            origin: TaskOrigin::Cl,
         };
 
         let task2 = PendingTask {
@@ -536,6 +1096,12 @@ mod tests {
             repo: "/test2/repo".to_string(),
             cl: 123457,
             cl_link: "test".to_string(),
+            requirements: TaskRequirements::default(),
+            blocked_reason: None,
+            priority: TaskPriority::Normal,
+            username: None,
+            commit_id: None,
+            origin: TaskOrigin::Cl,
         };
 
         // Test FIFO behavior
@@ -556,8 +1122,9 @@ mod tests {
     fn test_queue_capacity() {
         let config = TaskQueueConfig {
             max_queue_size: 2,
-            max_wait_time: Duration::from_secs(60),
+            max_wait_time: Some(Duration::from_secs(60)),
             cleanup_interval: Duration::from_secs(30),
+            ..Default::default()
         };
         let mut queue = TaskQueue::new(config);
 
@@ -569,6 +1136,12 @@ mod tests {
             repo: "/test/repo".to_string(),
             cl: 123456,
             cl_link: "test".to_string(),
+            requirements: TaskRequirements::default(),
+            blocked_reason: None,
+            priority: TaskPriority::Normal,
+            username: None,
+            commit_id: None,
+            origin: TaskOrigin::Cl,
         };
 
         // Fill queue to capacity
@@ -578,4 +1151,227 @@ mod tests {
         // Should fail when full
         assert!(queue.enqueue(task).is_err());
     }
+
+    /// Test that builds waiting for a capable worker don't expire
+    #[test]
+    fn test_cleanup_expired_keeps_blocked() {
+        let mut queue = TaskQueue::new(TaskQueueConfig {
+            max_wait_time: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        let task = |blocked_reason: Option<&str>| PendingTask {
+            task_id: Uuid::now_v7(),
+            build_id: Uuid::now_v7(),
+            request: BuildRequest { changes: vec![] },
+            created_at: Instant::now() - Duration::from_secs(120),
+            repo: "/test/repo".to_string(),
+            cl: 1,
+            cl_link: "test".to_string(),
+            requirements: TaskRequirements::default(),
+            blocked_reason: blocked_reason.map(str::to_string),
+            priority: TaskPriority::Normal,
+            username: None,
+            commit_id: None,
+            origin: TaskOrigin::Cl,
+        };
+        let stuck = task(None);
+        let blocked = task(Some("No worker has label gpu"));
+        queue.enqueue(stuck.clone()).unwrap();
+        queue.enqueue(blocked.clone()).unwrap();
+
+        let expired = queue.cleanup_expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].build_id, stuck.build_id);
+        assert_eq!(queue.get_stats().total_queued, 1);
+    }
+
+    /// Test removing the queued builds of a CL
+    #[test]
+    fn test_queue_remove_cl() {
+        let mut queue = TaskQueue::new(TaskQueueConfig::default());
+        let task = |cl| PendingTask {
+            task_id: Uuid::now_v7(),
+            build_id: Uuid::now_v7(),
+            request: BuildRequest { changes: vec![] },
+            created_at: Instant::now(),
+            repo: "/test/repo".to_string(),
+            cl,
+            cl_link: "test".to_string(),
+            requirements: TaskRequirements::default(),
+            blocked_reason: None,
+            priority: TaskPriority::Normal,
+            username: None,
+            commit_id: None,
+            origin: TaskOrigin::Cl,
+        };
+        let (first, other, second) = (task(1), task(2), task(1));
+        queue.enqueue(first.clone()).unwrap();
+        queue.enqueue(other.clone()).unwrap();
+        queue.enqueue(second.clone()).unwrap();
+
+        let mut speculative = task(1);
+        speculative.origin = TaskOrigin::MergeQueue;
+        let same_revision = task(1);
+        queue.enqueue(speculative.clone()).unwrap();
+        queue.enqueue(same_revision.clone()).unwrap();
+
+        let keep = HashSet::from([same_revision.task_id]);
+        let removed: Vec<_> = queue
+            .remove_cl(1, &keep)
+            .iter()
+            .map(|t| t.build_id)
+            .collect();
+        assert_eq!(removed, [first.build_id, second.build_id]);
+        let left: Vec<_> = std::iter::from_fn(|| queue.dequeue())
+            .map(|t| t.build_id)
+            .collect();
+        assert_eq!(
+            left,
+            [other.build_id, speculative.build_id, same_revision.build_id]
+        );
+    }
+
+    /// Test that builds without a matching worker stay queued with the reason
+    #[test]
+    fn test_queue_take_matching() {
+        let mut queue = TaskQueue::new(TaskQueueConfig::default());
+        let task = |labels: &[&str]| PendingTask {
+            task_id: Uuid::now_v7(),
+            build_id: Uuid::now_v7(),
+            request: BuildRequest { changes: vec![] },
+            created_at: Instant::now(),
+            repo: "/test/repo".to_string(),
+            cl: 1,
+            cl_link: "test".to_string(),
+            requirements: TaskRequirements {
+                labels: labels.iter().map(|l| l.to_string()).collect(),
+                ..Default::default()
+            },
+            blocked_reason: None,
+            priority: TaskPriority::Normal,
+            username: None,
+            commit_id: None,
+            origin: TaskOrigin::Cl,
+        };
+        let (gpu, plain) = (task(&["gpu-free"]), task(&[]));
+        queue.enqueue(gpu.clone()).unwrap();
+        queue.enqueue(plain.clone()).unwrap();
+
+        let taken = queue.take_matching(HashMap::new(), |task| {
+            if task.requirements.is_empty() {
+                Ok("worker-1".to_string())
+            } else {
+                Err("no gpu".to_string())
+            }
+        });
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].0.build_id, plain.build_id);
+        assert_eq!(taken[0].1, "worker-1");
+
+        let stats = queue.get_stats();
+        assert_eq!(stats.total_queued, 1);
+        assert_eq!(stats.blocked[0].build_id, gpu.build_id.to_string());
+        assert_eq!(stats.blocked[0].reason, "no gpu");
+    }
+
+    /// Test dispatch order by priority, aging and fair-share
+    #[test]
+    fn test_queue_priority_and_fair_share() {
+        let mut queue = TaskQueue::new(TaskQueueConfig {
+            aging_interval: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let task = |priority, user: &str, waited: u64| PendingTask {
+            task_id: Uuid::now_v7(),
+            build_id: Uuid::now_v7(),
+            request: BuildRequest { changes: vec![] },
+            created_at: Instant::now() - Duration::from_secs(waited),
+            repo: "/test/repo".to_string(),
+            cl: 1,
+            cl_link: "test".to_string(),
+            requirements: TaskRequirements::default(),
+            blocked_reason: None,
+            priority,
+            username: Some(user.to_string()),
+            commit_id: None,
+            origin: TaskOrigin::Cl,
+        };
+        let big_cl: Vec<_> = (0..3)
+            .map(|_| task(TaskPriority::Normal, "alice", 5))
+            .collect();
+        let bob = task(TaskPriority::Normal, "bob", 1);
+        let hotfix = task(TaskPriority::Critical, "carol", 0);
+        // Waited two aging intervals, raised from Low to High
+        let aged = task(TaskPriority::Low, "dave", 130);
+        for t in big_cl.iter().chain([&bob, &hotfix, &aged]) {
+            queue.enqueue(t.clone()).unwrap();
+        }
+
+        let taken: Vec<_> = queue
+            .take_matching(HashMap::new(), |_| Ok("worker".to_string()))
+            .into_iter()
+            .map(|(task, _)| task.build_id)
+            .collect();
+        assert_eq!(
+            taken,
+            [
+                hotfix.build_id,
+                aged.build_id,
+                big_cl[0].build_id,
+                bob.build_id,
+                big_cl[1].build_id,
+                big_cl[2].build_id,
+            ]
+        );
+
+        // Bob goes first while alice already has builds running
+        let mut queue = TaskQueue::new(TaskQueueConfig::default());
+        queue.enqueue(big_cl[0].clone()).unwrap();
+        queue.enqueue(bob.clone()).unwrap();
+        let running = HashMap::from([(queue.share_key(Some("alice"), "/test/repo"), 2)]);
+        let taken = queue.take_matching(running, |_| Ok("worker".to_string()));
+        assert_eq!(taken[0].0.build_id, bob.build_id);
+    }
+
+    /// Test deriving the priority of a build request
+    #[test]
+    fn test_task_priority_for_request() {
+        let labels = |l: &[&str]| l.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            TaskPriority::for_request(None, TaskOrigin::Cl, &[]),
+            TaskPriority::Normal
+        );
+        assert_eq!(
+            TaskPriority::for_request(None, TaskOrigin::MergeQueue, &[]),
+            TaskPriority::High
+        );
+        // labels are set by the CL author, only the API makes a build critical
+        assert_eq!(
+            TaskPriority::for_request(None, TaskOrigin::Cl, &labels(&["Hotfix"])),
+            TaskPriority::High
+        );
+        assert_eq!(
+            TaskPriority::for_request(None, TaskOrigin::Cl, &labels(&["priority:critical"])),
+            TaskPriority::High
+        );
+        assert_eq!(
+            TaskPriority::for_request(Some(TaskPriority::Critical), TaskOrigin::Cl, &[]),
+            TaskPriority::Critical
+        );
+        assert_eq!(
+            TaskPriority::for_request(None, TaskOrigin::Cl, &labels(&["priority:low"])),
+            TaskPriority::Low
+        );
+        assert_eq!(
+            TaskPriority::for_request(
+                Some(TaskPriority::Low),
+                TaskOrigin::MergeQueue,
+                &labels(&["urgent"])
+            ),
+            TaskPriority::Low
+        );
+        // aging stops below critical
+        assert_eq!(TaskPriority::Low.raised(5), TaskPriority::High);
+        assert_eq!(TaskPriority::Critical.raised(1), TaskPriority::Critical);
+    }
 }
@@ -15,9 +15,12 @@ use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::api::{self, AppState};
+use crate::artifact;
 use crate::log::log_service::LogService;
 use crate::log::store::{LogStore, local_log_store, s3_log_store};
 use crate::model::builds;
+use crate::routing::RoutingRules;
+use crate::scheduler::TaskQueueConfig;
 /// OpenAPI documentation configuration
 #[derive(OpenApi)]
 #[openapi(
@@ -27,6 +30,10 @@ use crate::model::builds;
         api::task_output_handler,
         api::task_history_output_handler,
         api::tasks_handler,
+        api::task_tests_handler,
+        artifact::upload_artifact_handler,
+        artifact::build_artifacts_handler,
+        artifact::download_artifact_handler,
         api::get_orion_clients_info,
         api::get_orion_client_status_by_id
     ),
@@ -37,6 +44,15 @@ use crate::model::builds;
             api::TaskStatusEnum,
             api::BuildDTO,
             api::TaskInfoDTO,
+            api::TestResultDTO,
+            artifact::ArtifactDTO,
+            orion::ws::TestCaseResult,
+            orion::ws::TestStatus,
+            orion::capability::WorkerCapabilities,
+            orion::capability::TaskRequirements,
+            crate::scheduler::BlockedTask,
+            crate::scheduler::TaskPriority,
+            crate::scheduler::TaskOrigin,
             api::OrionClientInfo,
             api::OrionClientStatus,
             api::CoreWorkerStatus,
@@ -107,12 +123,33 @@ pub async fn start_server(port: u16) {
         log_service_clone.watch_logs().await;
     });
 
-    let state = AppState::new(conn, None, log_service);
+    if crate::buck2::mono_api_token().is_none() {
+        tracing::error!(
+            "MONO_API_TOKEN is not set: mono will reject the check re-runs requested after \
+             builds finish, so the CI status of CLs will not update"
+        );
+    }
+
+    let artifact_storage = artifact::init_artifact_storage().await;
+    let state = AppState::new(
+        conn,
+        Some(TaskQueueConfig::from_env()),
+        log_service,
+        artifact_storage,
+        RoutingRules::load(),
+    );
 
     // Start background health check task
     tokio::spawn(start_health_check_task(state.clone()));
 
+    // Start expired artifact cleanup
+    tokio::spawn(artifact::start_artifact_purge_task(state.clone()));
+
     // Start queue manager
+    let restored = state.scheduler.restore_pending_tasks().await;
+    if restored > 0 {
+        tracing::info!("Restored {} queued builds", restored);
+    }
     tokio::spawn(api::start_queue_manager(state.clone()));
 
     let origins: Vec<HeaderValue> = std::env::var("ALLOWED_CORS_ORIGINS")
@@ -14,6 +14,10 @@ pub struct BuildRequest {
     pub cl: String,
     /// Commit changes
     pub changes: Vec<Status<ProjectRelativePath>>,
+    /// Commit of the CL to build instead of its latest revision
+    pub commit_id: Option<String>,
+    /// Secret authorizing artifact uploads for this build
+    pub upload_token: String,
 }
 
 /// Result of a build operation containing status and metadata.
@@ -57,6 +61,8 @@ pub async fn buck_build(
             id_str.clone(),
             req.repo,
             req.cl,
+            req.commit_id,
+            req.upload_token,
             sender.clone(),
             req.changes,
         )
@@ -84,6 +90,8 @@ pub async fn buck_build(
                     message,
                 }
             }
+            // The server re-queues rejected tasks, they have no completion to report
+            Err(e) if e.is::<buck_controller::TaskRejected>() => return,
             Err(e) => {
                 let error_msg = format!("Build execution failed: {e}");
                 tracing::error!("[Task {}] {}", id_str, error_msg);
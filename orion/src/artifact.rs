@@ -0,0 +1,174 @@
+//! Upload of build outputs to orion-server, so the binaries built for a CL
+//! can be downloaded by testers.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use td_util_buck::types::TargetLabel;
+use tokio::process::Command;
+
+/// HTTP base address of orion-server.
+///
+/// Taken from `SERVER_HTTP`, or derived from the `SERVER_WS` websocket address.
+fn server_http_base() -> String {
+    if let Ok(base) = std::env::var("SERVER_HTTP") {
+        return base.trim_end_matches('/').to_string();
+    }
+    let ws = std::env::var("SERVER_WS").unwrap_or_else(|_| "ws://127.0.0.1:8004/ws".to_string());
+    http_base_from_ws(&ws)
+}
+
+fn http_base_from_ws(ws: &str) -> String {
+    let http = if let Some(rest) = ws.strip_prefix("wss://") {
+        format!("https://{rest}")
+    } else if let Some(rest) = ws.strip_prefix("ws://") {
+        format!("http://{rest}")
+    } else {
+        ws.to_string()
+    };
+    let http = http.trim_end_matches('/');
+    http.strip_suffix("/ws").unwrap_or(http).to_string()
+}
+
+/// Parses the output of `buck2 build --show-full-output`, one
+/// `<target> <path>` pair per line. Targets without outputs are skipped.
+fn parse_show_output(stdout: &str) -> Vec<(String, PathBuf)> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let (target, path) = line.trim().split_once(' ')?;
+            let path = path.trim();
+            (!path.is_empty()).then(|| (target.to_string(), PathBuf::from(path)))
+        })
+        .collect()
+}
+
+/// Resolves the default outputs of `targets`, which must already be built.
+async fn find_outputs(
+    mount_point: &str,
+    targets: &[TargetLabel],
+) -> Result<Vec<(String, PathBuf)>, Box<dyn Error + Send + Sync>> {
+    let output = Command::new("buck2")
+        .arg("build")
+        .args(targets)
+        .arg("--show-full-output")
+        .current_dir(mount_point)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(format!(
+            "buck2 --show-full-output failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    Ok(parse_show_output(&String::from_utf8_lossy(&output.stdout)))
+}
+
+async fn upload_file(
+    client: &reqwest::Client,
+    build_id: &str,
+    upload_token: &str,
+    target: &str,
+    path: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or("output has no file name")?;
+    let file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+
+    let res = client
+        .post(format!(
+            "{}/builds/{}/artifacts",
+            server_http_base(),
+            build_id
+        ))
+        .query(&[("target", target), ("name", name)])
+        .bearer_auth(upload_token)
+        .header(reqwest::header::CONTENT_LENGTH, size)
+        .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+        .body(reqwest::Body::from(file))
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        return Err(format!("upload rejected with {}", res.status()).into());
+    }
+    Ok(())
+}
+
+/// Uploads the output files of `targets` as artifacts of build `id`, using the
+/// upload token the server sent with the task.
+///
+/// Failures are logged and do not fail the build; directory outputs are skipped.
+pub async fn upload_outputs(
+    id: &str,
+    upload_token: &str,
+    mount_point: &str,
+    targets: &[TargetLabel],
+) {
+    let outputs = match find_outputs(mount_point, targets).await {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            tracing::error!("[Task {}] Failed to find build outputs: {}", id, e);
+            return;
+        }
+    };
+
+    let client = reqwest::Client::new();
+    for (target, path) in outputs {
+        if !path.is_file() {
+            tracing::debug!("[Task {}] Skipping non-file output {:?}", id, path);
+            continue;
+        }
+        match upload_file(&client, id, upload_token, &target, &path).await {
+            Ok(()) => tracing::info!("[Task {}] Uploaded artifact {:?} of {}", id, path, target),
+            Err(e) => tracing::error!(
+                "[Task {}] Failed to upload artifact {:?} of {}: {}",
+                id,
+                path,
+                target,
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_show_output() {
+        let stdout = "root//app:server /repo/buck-out/v2/gen/root/app/__server__/server\n\
+                      root//lib:empty\n\
+                      \n\
+                      root//app:cli /repo/buck-out/v2/gen/root/app/__cli__/cli\n";
+        assert_eq!(
+            parse_show_output(stdout),
+            [
+                (
+                    "root//app:server".to_string(),
+                    PathBuf::from("/repo/buck-out/v2/gen/root/app/__server__/server")
+                ),
+                (
+                    "root//app:cli".to_string(),
+                    PathBuf::from("/repo/buck-out/v2/gen/root/app/__cli__/cli")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_http_base_from_ws() {
+        assert_eq!(
+            http_base_from_ws("ws://127.0.0.1:8004/ws"),
+            "http://127.0.0.1:8004"
+        );
+        assert_eq!(
+            http_base_from_ws("wss://orion.example.com/ws/"),
+            "https://orion.example.com"
+        );
+    }
+}
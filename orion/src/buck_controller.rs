@@ -1,16 +1,20 @@
+use crate::artifact;
+use crate::capability::{TaskRequirements, WorkerCapabilities};
 use crate::repo::diff;
 use crate::repo::sapling::status::Status;
-use crate::ws::{TaskPhase, WSMessage};
+use crate::ws::{TaskPhase, TestCaseResult, TestStatus, WSMessage};
 use once_cell::sync::Lazy;
 use serde_json::{Value, json};
 use td_util_buck::types::{ProjectRelativePath, TargetLabel};
 // Import complete Error trait for better error handling
 use crate::repo::changes::Changes;
 use anyhow::anyhow;
+use std::collections::HashMap;
 use std::error::Error;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
 use td_util::{command::spawn, file_io::file_writer};
 use td_util_buck::{
     cells::CellInfo,
@@ -20,6 +24,7 @@ use td_util_buck::{
 use tokio::io::AsyncBufReadExt;
 use tokio::process::Command;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
 use tokio::time::Duration;
 
 #[allow(dead_code)]
@@ -28,6 +33,20 @@ static PROJECT_ROOT: Lazy<String> =
 
 const MOUNT_TIMEOUT_SECS: u64 = 7200;
 
+/// Cancel signals of the builds currently running on this worker, by task id.
+static RUNNING_BUILDS: Lazy<Mutex<HashMap<String, oneshot::Sender<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Stops the running build `id`.
+///
+/// The buck2 child is killed and the Antares mount released through
+/// [`MountGuard`] as the build future is dropped. Returns `false` if no such
+/// build is running.
+pub fn cancel_build(id: &str) -> bool {
+    let sender = RUNNING_BUILDS.lock().unwrap().remove(id);
+    sender.is_some_and(|sender| sender.send(()).is_ok())
+}
+
 /// Mounts filesystem via remote API for repository access.
 ///
 /// Initiates mount request and polls for completion with exponential backoff.
@@ -162,6 +181,7 @@ pub async fn mount_fs(
 /// # Arguments
 /// - `repo`: The repository path to mount, e.g., `"my_repo"`.
 /// - `cl`: Optional changelist ID. If provided, mounts the specified CL; otherwise, mounts the latest version.
+/// - `commit`: Optional commit of the CL to mount instead of its latest revision.
 ///
 /// # Returns
 /// Returns a tuple `(mountpoint, mount_id)`:
@@ -181,7 +201,7 @@ pub async fn mount_fs(
 ///
 /// #[tokio::main]
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
-///     let (mountpoint, mount_id) = mount_antares_fs("my_repo", Some("12345")).await?;
+///     let (mountpoint, mount_id) = mount_antares_fs("job1", "my_repo", Some("12345"), None).await?;
 ///     println!("Mounted at {} with id {}", mountpoint, mount_id);
 ///     Ok(())
 /// }
@@ -195,11 +215,13 @@ pub async fn mount_antares_fs(
     job_id: &str,
     repo: &str,
     cl: Option<&str>,
+    commit: Option<&str>,
 ) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
     tracing::debug!(
-        "Preparing to mount Antares FS for repo: {}, cl: {:?}",
+        "Preparing to mount Antares FS for repo: {}, cl: {:?}, commit: {:?}",
         repo,
-        cl
+        cl,
+        commit
     );
 
     let client = reqwest::Client::builder()
@@ -211,7 +233,7 @@ pub async fn mount_antares_fs(
         })?;
 
     let mount_payload = if let Some(cl_id) = cl {
-        json!({ "path": repo, "cl": cl_id, "job_id": job_id })
+        json!({ "path": repo, "cl": cl_id, "commit": commit, "job_id": job_id })
     } else {
         json!({ "path": repo ,"job_id": job_id })
     };
@@ -416,6 +438,31 @@ fn get_repo_targets(file_name: &str, repo_path: &Path) -> anyhow::Result<Targets
     Targets::from_file(Path::new(file_name))
 }
 
+/// Targets affected by a change.
+struct AffectedTargets {
+    /// Every affected target, all of them are built.
+    build: Vec<TargetLabel>,
+    /// The affected targets whose rule is a test rule, run with `buck2 test`.
+    test: Vec<TargetLabel>,
+    /// The affected binary targets, whose outputs are uploaded as artifacts.
+    artifact: Vec<TargetLabel>,
+    /// Worker requirements declared by `orion:` labels of the affected targets.
+    requirements: TaskRequirements,
+}
+
+/// Returned by [`build`] when the worker lacks capabilities the affected
+/// targets require; the task has been handed back with `TaskRejected`.
+#[derive(Debug)]
+pub struct TaskRejected;
+
+impl std::fmt::Display for TaskRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("task rejected, worker lacks required capabilities")
+    }
+}
+
+impl Error for TaskRejected {}
+
 /// Run buck2-change-detector to get targets to build.
 ///
 /// # Note
@@ -423,7 +470,7 @@ fn get_repo_targets(file_name: &str, repo_path: &Path) -> anyhow::Result<Targets
 async fn get_build_targets(
     mount_point: &str,
     mega_changes: Vec<Status<ProjectRelativePath>>,
-) -> anyhow::Result<Vec<TargetLabel>> {
+) -> anyhow::Result<AffectedTargets> {
     tracing::info!("Get cells at {:?}", mount_point);
     let mount_path = PathBuf::from(mount_point);
     let mut buck2 = Buck2::with_root("buck2".to_string(), mount_path.clone());
@@ -449,11 +496,27 @@ async fn get_build_targets(
     let immediate = diff::immediate_target_changes(&base, &diff, &changes, false);
     let recursive = diff::recursive_target_changes(&diff, &changes, &immediate, None, |_| true);
 
-    Ok(recursive
-        .into_iter()
-        .flatten()
-        .map(|(target, _)| target.label())
-        .collect())
+    let mut targets = AffectedTargets {
+        build: Vec::new(),
+        test: Vec::new(),
+        artifact: Vec::new(),
+        requirements: TaskRequirements::default(),
+    };
+    for (target, _) in recursive.into_iter().flatten() {
+        targets
+            .requirements
+            .merge(&TaskRequirements::from_target_labels(
+                target.labels.iter().map(|l| l.as_str()),
+            ));
+        let rule = target.rule_type.short();
+        if rule.ends_with("_test") {
+            targets.test.push(target.label());
+        } else if rule.ends_with("_binary") {
+            targets.artifact.push(target.label());
+        }
+        targets.build.push(target.label());
+    }
+    Ok(targets)
 }
 
 /// RAII guard for automatically unmounting Antares filesystem when dropped
@@ -491,7 +554,9 @@ impl Drop for MountGuard {
 /// 1. Mount repository filesystem via remote API
 /// 2. Execute buck build command with specified target and arguments  
 /// 3. Stream build output in real-time via WebSocket
-/// 4. Return final build status
+/// 4. If the build succeeded, upload the outputs of affected binary targets
+///    and run the affected test targets, reporting per-test results via WebSocket
+/// 5. Return final build (or test) status
 ///
 /// # Arguments
 /// * `id` - Build task identifier for logging and tracking
@@ -499,38 +564,71 @@ impl Drop for MountGuard {
 /// * `target` - Buck build target specification  
 /// * `args` - Additional command-line arguments for buck
 /// * `cl` - Change List context identifier
+/// * `commit_id` - Commit of the CL to build instead of its latest revision
 /// * `sender` - WebSocket channel for streaming build output
 /// * `changes` - Commit's file change information
 ///
 /// # Returns
-/// Process exit status indicating build success or failure
+/// Process exit status indicating build success or failure, or an error if the
+/// build was cancelled via [`cancel_build`]
 pub async fn build(
     id: String,
     repo: String,
     cl: String,
+    commit_id: Option<String>,
+    upload_token: String,
+    sender: UnboundedSender<WSMessage>,
+    changes: Vec<Status<ProjectRelativePath>>,
+) -> Result<ExitStatus, Box<dyn Error + Send + Sync>> {
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    RUNNING_BUILDS.lock().unwrap().insert(id.clone(), cancel_tx);
+
+    let result = tokio::select! {
+        result = run_build(id.clone(), repo, cl, commit_id, upload_token, sender, changes) => result,
+        Ok(()) = cancel_rx => {
+            tracing::info!("[Task {}] Build cancelled", id);
+            Err("Build cancelled".into())
+        }
+    };
+    RUNNING_BUILDS.lock().unwrap().remove(&id);
+    result
+}
+
+async fn run_build(
+    id: String,
+    repo: String,
+    cl: String,
+    commit_id: Option<String>,
+    upload_token: String,
     sender: UnboundedSender<WSMessage>,
     changes: Vec<Status<ProjectRelativePath>>,
 ) -> Result<ExitStatus, Box<dyn Error + Send + Sync>> {
     tracing::info!("[Task {}] Building in repo '{}'", id, repo);
 
-    let (mount_point, mount_id) = mount_antares_fs(&id, &repo, Some(&cl)).await?;
+    let (mount_point, mount_id) =
+        mount_antares_fs(&id, &repo, Some(&cl), commit_id.as_deref()).await?;
     let _mount_guard = MountGuard::new(mount_id.clone(), id.clone());
     let targets = get_build_targets(&mount_point, changes).await?;
 
     tracing::info!("[Task {}] Filesystem mounted successfully.", id);
 
+    let unmet = targets.requirements.unmet(WorkerCapabilities::local());
+    if !unmet.is_empty() {
+        let reason = format!("Worker lacks {}", unmet.join(", "));
+        tracing::info!("[Task {}] Rejecting task: {}", id, reason);
+        sender.send(WSMessage::TaskRejected {
+            id: id.clone(),
+            requirements: targets.requirements,
+            reason,
+        })?;
+        return Err(Box::new(TaskRejected));
+    }
+
     let mut cmd = Command::new("buck2");
-    let cmd = cmd
-        .arg("build")
-        .args(&targets)
+    cmd.arg("build")
+        .args(&targets.build)
         .arg("--verbose=2")
-        .current_dir(mount_point)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-
-    tracing::debug!("[Task {}] Executing command: {:?}", id, cmd);
-
-    let mut child = cmd.spawn()?;
+        .current_dir(&mount_point);
 
     if let Err(e) = sender.send(WSMessage::TaskPhaseUpdate {
         id: id.clone(),
@@ -539,6 +637,73 @@ pub async fn build(
         tracing::error!("Failed to send RunningBuild phase update: {}", e);
     }
 
+    let status = run_streaming(&id, cmd, &sender).await?;
+    if !status.success() {
+        return Ok(status);
+    }
+
+    if !targets.artifact.is_empty() {
+        artifact::upload_outputs(&id, &upload_token, &mount_point, &targets.artifact).await;
+    }
+    if targets.test.is_empty() {
+        return Ok(status);
+    }
+
+    if let Err(e) = sender.send(WSMessage::TaskPhaseUpdate {
+        id: id.clone(),
+        phase: TaskPhase::RunningTests,
+    }) {
+        tracing::error!("Failed to send RunningTests phase update: {}", e);
+    }
+
+    // buck2 writes its event stream, including one `TestResult` per test case,
+    // as JSON lines when the log file has a `.json-lines` extension.
+    let event_log = std::env::temp_dir().join(format!("buck2-test-{id}.json-lines"));
+    let mut cmd = Command::new("buck2");
+    cmd.arg("test")
+        .args(&targets.test)
+        .arg("--event-log")
+        .arg(&event_log)
+        .current_dir(&mount_point);
+
+    let status = run_streaming(&id, cmd, &sender).await?;
+    let results = match std::fs::File::open(&event_log) {
+        Ok(file) => parse_test_events(BufReader::new(file)),
+        Err(e) => {
+            tracing::error!("[Task {}] Failed to open buck2 event log: {}", id, e);
+            Vec::new()
+        }
+    };
+    let _ = std::fs::remove_file(&event_log);
+
+    tracing::info!("[Task {}] Collected {} test results", id, results.len());
+    if sender
+        .send(WSMessage::TestResults {
+            id: id.clone(),
+            results,
+        })
+        .is_err()
+    {
+        return Err("WebSocket connection lost during test.".into());
+    }
+    Ok(status)
+}
+
+/// Runs a buck2 command, forwarding every stdout/stderr line as build output.
+async fn run_streaming(
+    id: &str,
+    mut cmd: Command,
+    sender: &UnboundedSender<WSMessage>,
+) -> Result<ExitStatus, Box<dyn Error + Send + Sync>> {
+    // Dropping the future on cancellation must not leave buck2 running
+    cmd.stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    tracing::debug!("[Task {}] Executing command: {:?}", id, cmd);
+
+    let mut child = cmd.spawn()?;
+
     let stdout = child.stdout.take().unwrap();
     let stderr = child.stderr.take().unwrap();
     let mut stdout_reader = tokio::io::BufReader::new(stdout).lines();
@@ -549,7 +714,7 @@ pub async fn build(
             result = stdout_reader.next_line() => {
                 match result {
                     Ok(Some(line)) => {
-                        if sender.send(WSMessage::BuildOutput { id: id.clone(), output: line }).is_err() {
+                        if sender.send(WSMessage::BuildOutput { id: id.to_string(), output: line }).is_err() {
                             child.kill().await?;
                             return Err("WebSocket connection lost during build.".into());
                         }
@@ -564,7 +729,7 @@ pub async fn build(
             result = stderr_reader.next_line() => {
                 match result {
                     Ok(Some(line)) => {
-                        if sender.send(WSMessage::BuildOutput { id: id.clone(), output: line }).is_err() {
+                        if sender.send(WSMessage::BuildOutput { id: id.to_string(), output: line }).is_err() {
                             child.kill().await?;
                             return Err("WebSocket connection lost during build.".into());
                         }
@@ -588,6 +753,82 @@ pub async fn build(
     Ok(status)
 }
 
+/// Extracts the `TestResult` events from a buck2 JSON-lines event log.
+///
+/// Lines that are not test results, or cannot be parsed, are skipped.
+fn parse_test_events(reader: impl BufRead) -> Vec<TestCaseResult> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<Value>(&line).ok())
+        .filter_map(|event| {
+            let result = event
+                .pointer("/Event/data/Instant/data/TestResult")?
+                .clone();
+            test_case_result(&result)
+        })
+        .collect()
+}
+
+fn test_case_result(result: &Value) -> Option<TestCaseResult> {
+    // buck2 serializes the protobuf enum either by number or by name.
+    let status = match &result["status"] {
+        Value::Number(n) => match n.as_i64()? {
+            1 => TestStatus::Pass,
+            2 => TestStatus::Fail,
+            3 => TestStatus::Skip,
+            4 => TestStatus::Omitted,
+            5 => TestStatus::Fatal,
+            6 => TestStatus::Timeout,
+            // NOT_SET and the test listing statuses are not test outcomes.
+            0 | 9 | 10 => return None,
+            _ => TestStatus::Unknown,
+        },
+        Value::String(s) => match s.as_str() {
+            "PASS" => TestStatus::Pass,
+            "FAIL" => TestStatus::Fail,
+            "SKIP" => TestStatus::Skip,
+            "OMITTED" => TestStatus::Omitted,
+            "FATAL" => TestStatus::Fatal,
+            "TIMEOUT" => TestStatus::Timeout,
+            "NOT_SET" | "LISTING_SUCCESS" | "LISTING_FAILED" => return None,
+            _ => TestStatus::Unknown,
+        },
+        _ => return None,
+    };
+
+    let label = &result["target_label"]["label"];
+    let target = match (label["package"].as_str(), label["name"].as_str()) {
+        (Some(package), Some(name)) => format!("{package}:{name}"),
+        _ => String::new(),
+    };
+
+    let duration = &result["duration"];
+    let duration_ms = duration["secs"]
+        .as_u64()
+        .or_else(|| duration["seconds"].as_u64())
+        .map(|secs| secs * 1000 + duration["nanos"].as_u64().unwrap_or(0) / 1_000_000);
+
+    // Passing tests keep no message, their output is in the build log.
+    let message = if status == TestStatus::Pass {
+        None
+    } else {
+        [&result["msg"]["msg"], &result["details"]]
+            .into_iter()
+            .filter_map(Value::as_str)
+            .find(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    Some(TestCaseResult {
+        target,
+        name: result["name"].as_str()?.to_string(),
+        status,
+        duration_ms,
+        message,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::TcpListener;
@@ -613,7 +854,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let result = mount_antares_fs("job1", &mock_server.uri(), None)
+        let result = mount_antares_fs("job1", &mock_server.uri(), None, None)
             .await
             .unwrap();
         assert_eq!(result.0, "/mock/mountpoint");
@@ -634,7 +875,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let result = mount_antares_fs("job1", &mock_server.uri(), None).await;
+        let result = mount_antares_fs("job1", &mock_server.uri(), None, None).await;
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
@@ -656,7 +897,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let result = mount_antares_fs("job1", &mock_server.uri(), None).await;
+        let result = mount_antares_fs("job1", &mock_server.uri(), None, None).await;
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
@@ -744,4 +985,65 @@ mod tests {
         .await
         .unwrap();
     }
+
+    #[tokio::test]
+    async fn test_cancel_build() {
+        assert!(!cancel_build("unknown_task"));
+
+        let (tx, rx) = oneshot::channel();
+        RUNNING_BUILDS
+            .lock()
+            .unwrap()
+            .insert("running_task".to_string(), tx);
+        assert!(cancel_build("running_task"));
+        assert!(rx.await.is_ok());
+        assert!(!cancel_build("running_task"));
+    }
+
+    #[test]
+    fn test_parse_test_events() {
+        let test_result = |status: Value, name: &str, msg: &str| {
+            json!({"Event": {"data": {"Instant": {"data": {"TestResult": {
+                "name": name,
+                "status": status,
+                "msg": {"msg": msg},
+                "duration": {"secs": 1, "nanos": 250_000_000},
+                "details": "",
+                "target_label": {"label": {"package": "root//foo", "name": "bar_test"}},
+            }}}}}})
+        };
+        let log = [
+            json!({"command_line_args": ["buck2", "test"]}),
+            test_result(json!(1), "passes", "ignored"),
+            test_result(json!("FAIL"), "breaks", "assertion failed"),
+            test_result(json!(9), "listing", ""),
+            json!({"Event": {"data": {"SpanStart": {}}}}),
+        ]
+        .iter()
+        .map(Value::to_string)
+        .chain(["not json".to_string()])
+        .collect::<Vec<_>>()
+        .join("\n");
+
+        let results = parse_test_events(log.as_bytes());
+        assert_eq!(
+            results,
+            [
+                TestCaseResult {
+                    target: "root//foo:bar_test".to_string(),
+                    name: "passes".to_string(),
+                    status: TestStatus::Pass,
+                    duration_ms: Some(1250),
+                    message: None,
+                },
+                TestCaseResult {
+                    target: "root//foo:bar_test".to_string(),
+                    name: "breaks".to_string(),
+                    status: TestStatus::Fail,
+                    duration_ms: Some(1250),
+                    message: Some("assertion failed".to_string()),
+                },
+            ]
+        );
+    }
 }
@@ -0,0 +1,248 @@
+//! Worker capabilities and the requirements tasks place on them.
+//!
+//! Workers advertise their capabilities when they register. Requirements come
+//! from orion-server's per-path routing rules and from `orion:` labels on the
+//! affected buck targets, e.g. `orion:os=linux`, `orion:cpus=16`,
+//! `orion:memory_mb=32768` or a custom `orion:gpu-free`.
+
+use std::fmt;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Prefix of the buck target labels that express worker requirements.
+const LABEL_PREFIX: &str = "orion:";
+
+/// What a worker offers, sent with its registration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct WorkerCapabilities {
+    pub os: String,
+    pub arch: String,
+    pub cpus: u32,
+    pub memory_mb: u64,
+    /// Custom labels such as `gpu-free` or `large-disk`.
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+static LOCAL: Lazy<WorkerCapabilities> = Lazy::new(|| WorkerCapabilities {
+    os: std::env::consts::OS.to_string(),
+    arch: std::env::consts::ARCH.to_string(),
+    cpus: std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1),
+    memory_mb: total_memory_mb().unwrap_or(0),
+    labels: std::env::var("ORION_WORKER_LABELS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect(),
+});
+
+impl WorkerCapabilities {
+    /// Capabilities of this machine, custom labels are read from `ORION_WORKER_LABELS`.
+    pub fn local() -> &'static WorkerCapabilities {
+        &LOCAL
+    }
+}
+
+fn total_memory_mb() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kb = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb / 1024)
+}
+
+/// What a task needs from the worker that runs it. Unset fields match any worker.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct TaskRequirements {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_cpus: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_memory_mb: Option<u64>,
+    /// Custom labels the worker must have.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
+
+impl TaskRequirements {
+    /// Collects the requirements expressed by `orion:` target labels.
+    /// Malformed numeric values are ignored.
+    pub fn from_target_labels<'a>(labels: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut req = TaskRequirements::default();
+        for label in labels {
+            let Some(label) = label.strip_prefix(LABEL_PREFIX) else {
+                continue;
+            };
+            let parsed = match label.split_once('=') {
+                Some(("os", os)) => TaskRequirements {
+                    os: Some(os.to_string()),
+                    ..Default::default()
+                },
+                Some(("arch", arch)) => TaskRequirements {
+                    arch: Some(arch.to_string()),
+                    ..Default::default()
+                },
+                Some(("cpus", cpus)) => TaskRequirements {
+                    min_cpus: cpus.parse().ok(),
+                    ..Default::default()
+                },
+                Some(("memory_mb", mb)) => TaskRequirements {
+                    min_memory_mb: mb.parse().ok(),
+                    ..Default::default()
+                },
+                _ => TaskRequirements {
+                    labels: vec![label.to_string()],
+                    ..Default::default()
+                },
+            };
+            req.merge(&parsed);
+        }
+        req
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == TaskRequirements::default()
+    }
+
+    /// Adds `other` to these requirements, keeping the stricter bound of each.
+    /// A conflicting `os` or `arch` is overridden by `other`.
+    pub fn merge(&mut self, other: &TaskRequirements) {
+        if other.os.is_some() {
+            self.os.clone_from(&other.os);
+        }
+        if other.arch.is_some() {
+            self.arch.clone_from(&other.arch);
+        }
+        self.min_cpus = self.min_cpus.max(other.min_cpus);
+        self.min_memory_mb = self.min_memory_mb.max(other.min_memory_mb);
+        for label in &other.labels {
+            if !self.labels.contains(label) {
+                self.labels.push(label.clone());
+            }
+        }
+    }
+
+    /// The requirements `caps` does not meet, empty if the worker can run the task.
+    pub fn unmet(&self, caps: &WorkerCapabilities) -> Vec<String> {
+        let mut unmet = Vec::new();
+        if let Some(os) = &self.os
+            && *os != caps.os
+        {
+            unmet.push(format!("os={os}"));
+        }
+        if let Some(arch) = &self.arch
+            && *arch != caps.arch
+        {
+            unmet.push(format!("arch={arch}"));
+        }
+        if let Some(cpus) = self.min_cpus
+            && caps.cpus < cpus
+        {
+            unmet.push(format!("cpus>={cpus}"));
+        }
+        if let Some(mb) = self.min_memory_mb
+            && caps.memory_mb < mb
+        {
+            unmet.push(format!("memory_mb>={mb}"));
+        }
+        for label in &self.labels {
+            if !caps.labels.contains(label) {
+                unmet.push(format!("label {label}"));
+            }
+        }
+        unmet
+    }
+
+    /// Used by the server to pick a worker
+    #[allow(dead_code)]
+    pub fn is_met_by(&self, caps: &WorkerCapabilities) -> bool {
+        self.unmet(caps).is_empty()
+    }
+}
+
+impl fmt::Display for TaskRequirements {
+    /// Lists the requirements as they appear in [`TaskRequirements::unmet`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&self.unmet(&WorkerCapabilities::default()).join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn worker(labels: &[&str]) -> WorkerCapabilities {
+        WorkerCapabilities {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            cpus: 16,
+            memory_mb: 65536,
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_from_target_labels() {
+        let req = TaskRequirements::from_target_labels([
+            "ci:skip_target",
+            "orion:os=linux",
+            "orion:cpus=8",
+            "orion:cpus=32",
+            "orion:memory_mb=not-a-number",
+            "orion:gpu-free",
+            "orion:gpu-free",
+        ]);
+        assert_eq!(
+            req,
+            TaskRequirements {
+                os: Some("linux".to_string()),
+                min_cpus: Some(32),
+                labels: vec!["gpu-free".to_string()],
+                ..Default::default()
+            }
+        );
+        assert!(TaskRequirements::from_target_labels(["ci:overwrite"]).is_empty());
+    }
+
+    #[test]
+    fn test_unmet() {
+        let req = TaskRequirements {
+            os: Some("linux".to_string()),
+            min_cpus: Some(32),
+            labels: vec!["gpu-free".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            req.unmet(&worker(&["large-disk"])),
+            ["cpus>=32", "label gpu-free"]
+        );
+        assert!(!req.is_met_by(&worker(&["gpu-free"])));
+
+        let req = TaskRequirements {
+            min_cpus: Some(16),
+            labels: vec!["gpu-free".to_string()],
+            ..Default::default()
+        };
+        assert!(req.is_met_by(&worker(&["large-disk", "gpu-free"])));
+        assert!(TaskRequirements::default().is_met_by(&WorkerCapabilities::default()));
+        assert_eq!(req.to_string(), "cpus>=16, label gpu-free");
+    }
+}
@@ -1,5 +1,7 @@
 mod api;
+mod artifact;
 mod buck_controller;
+pub mod capability;
 pub mod repo;
 mod util;
 pub mod ws;
@@ -1,6 +1,8 @@
 // Orion worker client modules
 mod api;
+mod artifact;
 mod buck_controller;
+mod capability;
 pub mod repo;
 mod util;
 mod ws;
@@ -1,4 +1,6 @@
 use crate::api::{BuildRequest, buck_build};
+use crate::buck_controller;
+use crate::capability::{TaskRequirements, WorkerCapabilities};
 use crate::repo::sapling::status::Status;
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
@@ -19,6 +21,32 @@ use uuid::Uuid;
 pub enum TaskPhase {
     DownloadingSource,
     RunningBuild,
+    RunningTests,
+}
+
+/// Outcome of a single test case as reported by `buck2 test`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStatus {
+    Pass,
+    Fail,
+    Skip,
+    Omitted,
+    Fatal,
+    Timeout,
+    Unknown,
+}
+
+/// Structured result of one test case.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct TestCaseResult {
+    /// Test target the case belongs to, e.g. `root//foo:bar_test`.
+    pub target: String,
+    pub name: String,
+    pub status: TestStatus,
+    pub duration_ms: Option<u64>,
+    /// Failure message or captured output, if any.
+    pub message: Option<String>,
 }
 
 /// Message protocol for WebSocket communication between worker and server.
@@ -30,6 +58,8 @@ pub enum WSMessage {
         id: String,
         hostname: String,
         orion_version: String,
+        #[serde(default)]
+        capabilities: WorkerCapabilities,
     },
     Heartbeat,
     // Sent when a task is in the build process and its execution phase changes.
@@ -42,10 +72,22 @@ pub enum WSMessage {
         success: bool,
         message: String,
     },
+    // Sent instead of building when the affected targets require capabilities
+    // this worker lacks, so the server can route the task elsewhere.
+    TaskRejected {
+        id: String,
+        requirements: TaskRequirements,
+        reason: String,
+    },
     BuildOutput {
         id: String,
         output: String,
     },
+    // Sent once the affected test targets have run, before BuildComplete.
+    TestResults {
+        id: String,
+        results: Vec<TestCaseResult>,
+    },
     BuildComplete {
         id: String,
         success: bool,
@@ -58,6 +100,16 @@ pub enum WSMessage {
         repo: String,
         cl_link: String,
         changes: Vec<Status<ProjectRelativePath>>,
+        // Speculative merge queue commit to build instead of the CL's latest revision.
+        #[serde(default)]
+        commit_id: Option<String>,
+        // Secret the worker presents when uploading artifacts of this build.
+        #[serde(default)]
+        upload_token: String,
+    },
+    // Sent when a newer build of the same CL supersedes a running one.
+    CancelTask {
+        id: String,
     },
 }
 
@@ -132,6 +184,7 @@ async fn handle_connection(
                 id: worker_id_clone,
                 hostname: hostname_clone,
                 orion_version,
+                capabilities: WorkerCapabilities::local().clone(),
             })
             .is_err()
         {
@@ -192,7 +245,8 @@ async fn handle_connection(
 /// Processes incoming server messages and handles task execution.
 ///
 /// Handles different message types including Task assignments and connection management.
-/// For Task messages, spawns build processes and sends acknowledgments.
+/// For Task messages, spawns build processes and sends acknowledgments; CancelTask
+/// messages stop a running build.
 ///
 /// # Arguments
 /// * `msg` - WebSocket message received from server
@@ -216,6 +270,8 @@ async fn process_server_message(
                             repo,
                             cl_link: cl,
                             changes,
+                            commit_id,
+                            upload_token,
                         } => {
                             tracing::info!("Received task: id={}", id);
                             tokio::spawn(async move {
@@ -233,7 +289,13 @@ async fn process_server_message(
 
                                 let build_result = buck_build(
                                     task_id_uuid,
-                                    BuildRequest { repo, cl, changes },
+                                    BuildRequest {
+                                        repo,
+                                        cl,
+                                        changes,
+                                        commit_id,
+                                        upload_token,
+                                    },
                                     sender.clone(),
                                 )
                                 .await;
@@ -247,6 +309,13 @@ async fn process_server_message(
                                 }
                             });
                         }
+                        WSMessage::CancelTask { id } => {
+                            if buck_controller::cancel_build(&id) {
+                                tracing::info!("Cancelling task: id={}", id);
+                            } else {
+                                tracing::warn!("Received cancel for unknown task: id={}", id);
+                            }
+                        }
                         // Log unexpected message types
                         _ => {
                             tracing::warn!("Received unexpected message from server: {:?}", ws_msg);
@@ -237,6 +237,10 @@ pub struct CreateMountRequest {
     /// Optional CL (changelist) identifier for the CL layer
     #[serde(default)]
     pub cl: Option<String>,
+    /// Optional commit of the CL to build the CL layer from instead of its
+    /// latest revision, e.g. a merge queue speculative commit
+    #[serde(default)]
+    pub commit: Option<String>,
 }
 
 /// Request payload for building/rebuilding a CL layer.
@@ -910,11 +914,14 @@ impl AntaresService for AntaresServiceImpl {
         // 4. Build CL layer if cl is provided
         if let Some(ref cl_link) = request.cl {
             if let Some(ref cl_dir_path) = cl_dir {
-                build_cl_layer(cl_link, cl_dir_path.clone(), &request.path)
-                    .await
-                    .map_err(|e| {
-                        ServiceError::Internal(format!("failed to build CL layer: {}", e))
-                    })?;
+                build_cl_layer(
+                    cl_link,
+                    request.commit.as_deref(),
+                    cl_dir_path.clone(),
+                    &request.path,
+                )
+                .await
+                .map_err(|e| ServiceError::Internal(format!("failed to build CL layer: {}", e)))?;
                 tracing::info!(
                     "Built CL layer for {} with link {} at {:?}",
                     request.path,
@@ -1191,7 +1198,7 @@ impl AntaresService for AntaresServiceImpl {
         drop(mounts);
 
         // Build the CL layer
-        build_cl_layer(&cl_link, cl_dir_path.clone(), &repo_path)
+        build_cl_layer(&cl_link, None, cl_dir_path.clone(), &repo_path)
             .await
             .map_err(|e| ServiceError::Internal(format!("failed to build CL layer: {}", e)))?;
 
@@ -1772,6 +1779,7 @@ mod tests {
                         build_id: None,
                         path: format!("/project/path{}", i),
                         cl: None,
+                        commit: None,
                     })
                     .await
                 })
@@ -1796,6 +1804,7 @@ mod tests {
             build_id: None,
             path: "/third-party/mega".into(),
             cl: Some("CL123".into()),
+            commit: None,
         };
 
         // First mount should succeed
@@ -1816,6 +1825,7 @@ mod tests {
             build_id: None,
             path: "/third-party/mega".into(),
             cl: Some("CL123".into()),
+            commit: None,
         };
 
         let first = service.create_mount(request.clone()).await.unwrap();
@@ -1834,6 +1844,7 @@ mod tests {
             build_id: None,
             path: "/third-party/mega".into(),
             cl: Some("CL123".into()),
+            commit: None,
         };
 
         let first = service.create_mount(request.clone()).await.unwrap();
@@ -1858,12 +1869,14 @@ mod tests {
             build_id: None,
             path: "/third-party/mega".into(),
             cl: Some("CL123".into()),
+            commit: None,
         };
         let req2 = CreateMountRequest {
             job_id: Some("job-b".into()),
             build_id: None,
             path: "/third-party/mega".into(),
             cl: Some("CL123".into()),
+            commit: None,
         };
 
         let r1 = service.create_mount(req1).await;
@@ -1886,6 +1899,7 @@ mod tests {
                 build_id: None,
                 path: "/third-party/mega".into(),
                 cl: None,
+                commit: None,
             })
             .await
             .unwrap();
@@ -1912,6 +1926,7 @@ mod tests {
                 build_id: None,
                 path: "/third-party/mega".into(),
                 cl: Some("CL1".into()),
+                commit: None,
             })
             .await;
         assert!(result1.is_ok());
@@ -1923,6 +1938,7 @@ mod tests {
                 build_id: None,
                 path: "/third-party/mega".into(),
                 cl: Some("CL2".into()),
+                commit: None,
             })
             .await;
         assert!(result2.is_ok());
@@ -1949,6 +1965,7 @@ mod tests {
                     build_id: None,
                     path: format!("/concurrent-path-{}", i),
                     cl: None,
+                    commit: None,
                 };
                 svc.create_mount(request).await
             });
@@ -1993,6 +2010,7 @@ mod tests {
             build_id: None,
             path: "/test-concurrent-ops".to_string(),
             cl: None,
+            commit: None,
         };
         let created = service.create_mount(request).await.unwrap();
         let mount_id = created.mount_id;
@@ -2028,6 +2046,7 @@ mod tests {
                 build_id: None,
                 path: "/third-party/mega".into(),
                 cl: None,
+                commit: None,
             })
             .await
             .unwrap();
@@ -2050,6 +2069,7 @@ mod tests {
                 build_id: None,
                 path: "/third-party/mega".into(),
                 cl: None,
+                commit: None,
             })
             .await
             .unwrap();
@@ -2086,6 +2106,7 @@ mod tests {
                 build_id: None,
                 path: "/third-party/mega".into(),
                 cl: Some("CL123".into()),
+                commit: None,
             })
             .await
             .unwrap();
@@ -2110,6 +2131,7 @@ mod tests {
                 build_id: None,
                 path: "/third-party/mega".into(),
                 cl: None,
+                commit: None,
             })
             .await
             .unwrap();
@@ -2133,6 +2155,7 @@ mod tests {
                 build_id: None,
                 path: "/test/path".into(),
                 cl: None,
+                commit: None,
             })
             .await
             .unwrap();
@@ -2173,6 +2196,7 @@ mod tests {
                 build_id: None,
                 path: "/test/path".into(),
                 cl: Some("CL123".into()),
+                commit: None,
             })
             .await
             .unwrap();
@@ -289,7 +289,7 @@ async fn perform_mount_task(state: ScoState, req: MountRequest) -> Result<MountI
     // Handle Change List (CL) layer if provided
     if let Some(m) = &req.cl {
         let cl_store_path = PathBuf::from(&store_path).join("cl").join(m);
-        if let Err(e) = cl::build_cl_layer(m, cl_store_path, &req.path).await {
+        if let Err(e) = cl::build_cl_layer(m, None, cl_store_path, &req.path).await {
             return Err(format!("Failed to build cl layer: {e}"));
         }
     }
@@ -69,6 +69,7 @@ impl std::error::Error for ClLayerError {}
 ///
 /// # Arguments
 /// * `link` - Unique identifier for the CL
+/// * `commit` - Commit of the CL to use instead of its latest revision
 /// * `cl_path` - Directory where CL layer files will be stored
 /// * `repo_path` - Repository path to filter files (only files under this path are processed)
 ///
@@ -77,6 +78,7 @@ impl std::error::Error for ClLayerError {}
 /// * `Err(ClLayerError)` on failure with specific error type
 pub async fn build_cl_layer(
     link: &str,
+    commit: Option<&str>,
     cl_path: PathBuf,
     repo_path: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -86,7 +88,7 @@ pub async fn build_cl_layer(
         repo_path
     );
 
-    let files_list = fetch_files_list(link)
+    let files_list = fetch_files_list(link, commit)
         .await
         .map_err(|e| ClLayerError::FetchError(e.to_string()))?;
 
@@ -277,13 +279,22 @@ fn sanitize_relative_path(p: &str) -> Result<PathBuf, ()> {
 /// - `link`: unique identifier for the CL (used in the path)
 ///
 /// Returns `FilesListResp` on success, or `reqwest::Error` on failure.
-async fn fetch_files_list(link: &str) -> Result<FilesListResp, reqwest::Error> {
+async fn fetch_files_list(
+    link: &str,
+    commit: Option<&str>,
+) -> Result<FilesListResp, reqwest::Error> {
     let url = format!("{}/api/v1/cl/{}/files-list", config::base_url(), link);
-    tracing::debug!("Fetching CL files list from: {}", url);
+    tracing::debug!(
+        "Fetching CL files list from: {} (commit: {:?})",
+        url,
+        commit
+    );
 
-    Client::new()
-        .get(&url)
-        .send()
+    let mut req = Client::new().get(&url);
+    if let Some(commit) = commit {
+        req = req.query(&[("commit", commit)]);
+    }
+    req.send()
         .await?
         .error_for_status()?
         .json::<FilesListResp>()